@@ -0,0 +1,5 @@
+-- Mark the dictionary version used for parsing
+ALTER TABLE dictionaries ADD COLUMN IF NOT EXISTS active BOOLEAN NOT NULL DEFAULT FALSE;
+
+-- At most one dictionary can be active
+CREATE UNIQUE INDEX IF NOT EXISTS idx_dictionaries_single_active ON dictionaries(active) WHERE active;
@@ -0,0 +1,3 @@
+-- Transport protocol used to reach each peer
+ALTER TABLE peers ADD COLUMN IF NOT EXISTS transport VARCHAR(8) NOT NULL DEFAULT 'tcp'
+    CHECK (transport IN ('tcp', 'sctp'));
@@ -1,6 +1,74 @@
-use crate::models::{PeerConfig, VirtualRouter};
+use crate::models::{PeerConfig, PeerExportRow, VirtualRouter};
 use anyhow::Result;
-use sqlx::{postgres::PgPoolOptions, Pool, Postgres};
+use sqlx::postgres::{PgConnectOptions, PgPoolOptions};
+use sqlx::{Pool, Postgres};
+use std::str::FromStr;
+use std::time::Duration;
+use tokio_stream::wrappers::ReceiverStream;
+use tokio_stream::StreamExt;
+
+/// Connection pool settings
+#[derive(Debug, Clone)]
+pub struct PoolSettings {
+    /// Upper bound on open connections
+    pub max_connections: u32,
+
+    /// Connections kept open while idle
+    pub min_connections: u32,
+
+    /// Time a query waits for a free connection before failing
+    pub acquire_timeout: Duration,
+
+    /// Prepared statements kept per connection
+    ///
+    /// Queries are prepared once per connection and reused by their SQL
+    /// text, so the repository's fixed query strings hit this cache.
+    pub statement_cache_capacity: usize,
+}
+
+impl Default for PoolSettings {
+    fn default() -> Self {
+        Self {
+            max_connections: 5,
+            min_connections: 0,
+            acquire_timeout: Duration::from_secs(30),
+            statement_cache_capacity: 100,
+        }
+    }
+}
+
+impl PoolSettings {
+    /// Read overrides from `DB_MAX_CONNECTIONS`, `DB_MIN_CONNECTIONS`,
+    /// `DB_ACQUIRE_TIMEOUT_MS` and `DB_STATEMENT_CACHE_SIZE`
+    pub fn from_env() -> Self {
+        fn var<T: FromStr>(name: &str) -> Option<T> {
+            std::env::var(name).ok().and_then(|v| v.parse().ok())
+        }
+
+        let defaults = Self::default();
+        Self {
+            max_connections: var("DB_MAX_CONNECTIONS").unwrap_or(defaults.max_connections),
+            min_connections: var("DB_MIN_CONNECTIONS").unwrap_or(defaults.min_connections),
+            acquire_timeout: var("DB_ACQUIRE_TIMEOUT_MS")
+                .map(Duration::from_millis)
+                .unwrap_or(defaults.acquire_timeout),
+            statement_cache_capacity: var("DB_STATEMENT_CACHE_SIZE")
+                .unwrap_or(defaults.statement_cache_capacity),
+        }
+    }
+
+    fn pool_options(&self) -> PgPoolOptions {
+        PgPoolOptions::new()
+            .max_connections(self.max_connections)
+            .min_connections(self.min_connections)
+            .acquire_timeout(self.acquire_timeout)
+    }
+
+    fn connect_options(&self, database_url: &str) -> Result<PgConnectOptions> {
+        Ok(PgConnectOptions::from_str(database_url)?
+            .statement_cache_capacity(self.statement_cache_capacity))
+    }
+}
 
 #[derive(Clone)]
 pub struct PostgresRepository {
@@ -9,9 +77,14 @@ pub struct PostgresRepository {
 
 impl PostgresRepository {
     pub async fn new(database_url: &str) -> Result<Self> {
-        let pool = PgPoolOptions::new()
-            .max_connections(5)
-            .connect(database_url)
+        Self::with_settings(database_url, &PoolSettings::default()).await
+    }
+
+    /// Connect with explicit pool settings and run migrations
+    pub async fn with_settings(database_url: &str, settings: &PoolSettings) -> Result<Self> {
+        let pool = settings
+            .pool_options()
+            .connect_with(settings.connect_options(database_url)?)
             .await?;
 
         // Run migrations
@@ -20,6 +93,21 @@ impl PostgresRepository {
         Ok(Self { pool })
     }
 
+    /// Create the pool without connecting; connections open on first use
+    ///
+    /// Migrations are not run.
+    pub fn connect_lazy(database_url: &str, settings: &PoolSettings) -> Result<Self> {
+        let pool = settings
+            .pool_options()
+            .connect_lazy_with(settings.connect_options(database_url)?);
+        Ok(Self { pool })
+    }
+
+    /// Maximum number of connections the pool will open
+    pub fn max_connections(&self) -> u32 {
+        self.pool.options().get_max_connections()
+    }
+
     pub async fn get_all_vrs(&self) -> Vec<VirtualRouter> {
         sqlx::query_as::<_, VirtualRouter>(
             "SELECT id, hostname, realm, timeout_ms FROM virtual_routers",
@@ -76,15 +164,53 @@ impl PostgresRepository {
     }
 
     pub async fn get_all_peers(&self) -> Vec<PeerConfig> {
-        sqlx::query_as::<_, PeerConfig>("SELECT hostname, realm, ip_address, port FROM peers")
-            .fetch_all(&self.pool)
-            .await
-            .unwrap_or_default()
+        sqlx::query_as::<_, PeerConfig>(
+            "SELECT hostname, realm, ip_address, port, transport FROM peers",
+        )
+        .fetch_all(&self.pool)
+        .await
+        .unwrap_or_default()
+    }
+
+    /// Get the peers serving a Virtual Router
+    pub async fn get_peers_for_vr(&self, vr_id: &str) -> Vec<PeerConfig> {
+        sqlx::query_as::<_, PeerConfig>(
+            "SELECT hostname, realm, ip_address, port, transport FROM peers WHERE virtual_router_id = $1",
+        )
+        .bind(vr_id)
+        .fetch_all(&self.pool)
+        .await
+        .unwrap_or_default()
+    }
+
+    /// Stream every peer with its Virtual Router, ordered by hostname
+    ///
+    /// Rows are fetched by a background task and handed over through a small
+    /// channel, so a slow reader holds back the query instead of buffering it.
+    pub fn stream_peers_for_export(&self) -> ReceiverStream<Result<PeerExportRow, sqlx::Error>> {
+        let (tx, rx) = tokio::sync::mpsc::channel(64);
+        let pool = self.pool.clone();
+
+        tokio::spawn(async move {
+            let mut rows = sqlx::query_as::<_, PeerExportRow>(
+                "SELECT hostname, realm, ip_address, port, virtual_router_id AS vr_id FROM peers ORDER BY hostname",
+            )
+            .fetch(&pool);
+
+            while let Some(row) = rows.next().await {
+                let failed = row.is_err();
+                if tx.send(row).await.is_err() || failed {
+                    break;
+                }
+            }
+        });
+
+        ReceiverStream::new(rx)
     }
 
     pub async fn get_peer(&self, hostname: &str) -> Option<PeerConfig> {
         sqlx::query_as::<_, PeerConfig>(
-            "SELECT hostname, realm, ip_address, port FROM peers WHERE hostname = $1",
+            "SELECT hostname, realm, ip_address, port, transport FROM peers WHERE hostname = $1",
         )
         .bind(hostname)
         .fetch_optional(&self.pool)
@@ -94,12 +220,13 @@ impl PostgresRepository {
 
     pub async fn add_peer(&self, peer: PeerConfig) -> bool {
         sqlx::query(
-            "INSERT INTO peers (hostname, realm, ip_address, port) VALUES ($1, $2, $3, $4) ON CONFLICT (hostname) DO UPDATE SET realm = $2, ip_address = $3, port = $4"
+            "INSERT INTO peers (hostname, realm, ip_address, port, transport) VALUES ($1, $2, $3, $4, $5) ON CONFLICT (hostname) DO UPDATE SET realm = $2, ip_address = $3, port = $4, transport = $5"
         )
         .bind(&peer.hostname)
         .bind(&peer.realm)
         .bind(&peer.ip_address)
         .bind(peer.port)
+        .bind(&peer.transport)
         .execute(&self.pool)
         .await
         .is_ok()
@@ -117,7 +244,7 @@ impl PostgresRepository {
     // Dictionary management methods
     pub async fn list_dictionaries(&self) -> Vec<crate::models::Dictionary> {
         sqlx::query_as::<_, crate::models::Dictionary>(
-            "SELECT id, name, version, xml_content, created_at FROM dictionaries ORDER BY created_at DESC"
+            "SELECT id, name, version, xml_content, active, created_at FROM dictionaries ORDER BY created_at DESC"
         )
         .fetch_all(&self.pool)
         .await
@@ -126,7 +253,7 @@ impl PostgresRepository {
 
     pub async fn get_dictionary(&self, id: i32) -> Option<crate::models::Dictionary> {
         sqlx::query_as::<_, crate::models::Dictionary>(
-            "SELECT id, name, version, xml_content, created_at FROM dictionaries WHERE id = $1",
+            "SELECT id, name, version, xml_content, active, created_at FROM dictionaries WHERE id = $1",
         )
         .bind(id)
         .fetch_optional(&self.pool)
@@ -134,6 +261,37 @@ impl PostgresRepository {
         .unwrap_or(None)
     }
 
+    pub async fn get_active_dictionary(&self) -> Option<crate::models::Dictionary> {
+        sqlx::query_as::<_, crate::models::Dictionary>(
+            "SELECT id, name, version, xml_content, active, created_at FROM dictionaries WHERE active",
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .unwrap_or(None)
+    }
+
+    /// Make a dictionary the only active one, returning false if it does not exist
+    pub async fn activate_dictionary(&self, id: i32) -> bool {
+        let Ok(mut tx) = self.pool.begin().await else {
+            return false;
+        };
+
+        let deactivated =
+            sqlx::query("UPDATE dictionaries SET active = FALSE WHERE active AND id <> $1")
+                .bind(id)
+                .execute(&mut *tx)
+                .await;
+        let activated = sqlx::query("UPDATE dictionaries SET active = TRUE WHERE id = $1")
+            .bind(id)
+            .execute(&mut *tx)
+            .await
+            .map(|result| result.rows_affected() > 0)
+            .unwrap_or(false);
+
+        // Dropping the transaction without commit rolls it back
+        deactivated.is_ok() && activated && tx.commit().await.is_ok()
+    }
+
     pub async fn save_dictionary(
         &self,
         name: String,
@@ -227,6 +385,12 @@ impl PostgresRepository {
             .unwrap_or(false)
     }
 
+    /// Delete every routing rule of a VR in one transaction, returning the count deleted
+    pub async fn delete_routing_rules_for_vr(&self, vr_id: &str) -> Option<u64> {
+        self.delete_all_for_vr("DELETE FROM routing_rules WHERE vr_id = $1", vr_id)
+            .await
+    }
+
     // Manipulation rule management methods
     pub async fn list_manipulation_rules(
         &self,
@@ -293,4 +457,54 @@ impl PostgresRepository {
             .map(|result| result.rows_affected() > 0)
             .unwrap_or(false)
     }
+
+    /// Delete every manipulation rule of a VR in one transaction, returning the count deleted
+    pub async fn delete_manipulation_rules_for_vr(&self, vr_id: &str) -> Option<u64> {
+        self.delete_all_for_vr("DELETE FROM manipulation_rules WHERE vr_id = $1", vr_id)
+            .await
+    }
+
+    async fn delete_all_for_vr(&self, statement: &str, vr_id: &str) -> Option<u64> {
+        let mut tx = self.pool.begin().await.ok()?;
+        let deleted = sqlx::query(statement)
+            .bind(vr_id)
+            .execute(&mut *tx)
+            .await
+            .ok()?
+            .rows_affected();
+        tx.commit().await.ok()?;
+        Some(deleted)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_pool_settings_are_honored() {
+        let settings = PoolSettings {
+            max_connections: 17,
+            min_connections: 0,
+            acquire_timeout: Duration::from_millis(250),
+            statement_cache_capacity: 500,
+        };
+
+        let repo =
+            PostgresRepository::connect_lazy("postgres://user@localhost/cdde", &settings).unwrap();
+        assert_eq!(repo.max_connections(), 17);
+        assert_eq!(
+            repo.pool.options().get_acquire_timeout(),
+            Duration::from_millis(250)
+        );
+
+        let repo = PostgresRepository::connect_lazy(
+            "postgres://user@localhost/cdde",
+            &PoolSettings::default(),
+        )
+        .unwrap();
+        assert_eq!(repo.max_connections(), 5);
+
+        assert!(PostgresRepository::connect_lazy("not a url", &settings).is_err());
+    }
 }
@@ -0,0 +1,68 @@
+use crate::db::PostgresRepository;
+use crate::models::Dictionary;
+use cdde_diameter_dict::DictionaryManager;
+
+/// Load the active dictionary into the manager, as done on startup
+///
+/// Its AVPs are tagged with its id, so deleting it later unloads them.
+/// Returns the dictionary loaded, if one is active.
+pub async fn load_active_dictionary(
+    repository: &PostgresRepository,
+    manager: &DictionaryManager,
+) -> Result<Option<Dictionary>, String> {
+    let Some(dict) = repository.get_active_dictionary().await else {
+        return Ok(None);
+    };
+
+    manager
+        .replace_dictionary(&dict.id.to_string(), &dict.xml_content)
+        .map_err(|e| format!("{}: {e}", dict.name))?;
+    Ok(Some(dict))
+}
+
+/// Mark dictionary `id` active and make its AVPs the ones that resolve
+///
+/// The dictionary is loaded before it is marked active, so one that fails
+/// to load leaves both the database and the manager as they were.
+/// Returns `Ok(false)` when no dictionary has this id.
+pub async fn activate_dictionary(
+    repository: &PostgresRepository,
+    manager: &DictionaryManager,
+    id: i32,
+) -> Result<bool, String> {
+    let Some(dict) = repository.get_dictionary(id).await else {
+        return Ok(false);
+    };
+    let previous = repository.get_active_dictionary().await;
+
+    manager.replace_dictionary(&id.to_string(), &dict.xml_content)?;
+    if repository.activate_dictionary(id).await {
+        return Ok(true);
+    }
+
+    // Not committed: go back to the dictionary still marked active
+    match previous {
+        Some(previous) => {
+            manager.replace_dictionary(&previous.id.to_string(), &previous.xml_content)?
+        }
+        None => {
+            manager.unload_dictionary(&id.to_string());
+        }
+    }
+    Ok(false)
+}
+
+/// Delete dictionary `id`, removing its AVPs from the manager if loaded
+///
+/// Returns whether a dictionary was deleted.
+pub async fn delete_dictionary(
+    repository: &PostgresRepository,
+    manager: &DictionaryManager,
+    id: i32,
+) -> bool {
+    if !repository.delete_dictionary(id).await {
+        return false;
+    }
+    manager.unload_dictionary(&id.to_string());
+    true
+}
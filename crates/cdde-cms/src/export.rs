@@ -0,0 +1,65 @@
+use crate::db::PostgresRepository;
+use crate::models::PeerExportRow;
+use tokio_stream::{Stream, StreamExt};
+
+/// Header row of the peer inventory export
+pub const PEERS_CSV_HEADER: &str = "hostname,realm,ip,port,vr_id\n";
+
+/// Quote a CSV field when it contains a separator, quote or line break
+fn csv_field(value: &str) -> std::borrow::Cow<'_, str> {
+    if value.contains([',', '"', '\r', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\"")).into()
+    } else {
+        value.into()
+    }
+}
+
+/// Render one peer as a CSV line
+pub fn peer_csv_line(peer: &PeerExportRow) -> String {
+    format!(
+        "{},{},{},{},{}\n",
+        csv_field(&peer.hostname),
+        csv_field(&peer.realm),
+        csv_field(&peer.ip_address),
+        peer.port,
+        csv_field(peer.vr_id.as_deref().unwrap_or_default())
+    )
+}
+
+/// Peer inventory as CSV lines, header first, read from the database as it is sent
+pub fn peers_csv(
+    repository: &PostgresRepository,
+) -> impl Stream<Item = Result<String, sqlx::Error>> + Send + 'static {
+    let rows = repository
+        .stream_peers_for_export()
+        .map(|row| row.map(|peer| peer_csv_line(&peer)));
+
+    tokio_stream::once(Ok(PEERS_CSV_HEADER.to_string())).chain(rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_peer_csv_line() {
+        let mut peer = PeerExportRow {
+            hostname: "hss1.example.com".to_string(),
+            realm: "example.com".to_string(),
+            ip_address: "192.0.2.10".to_string(),
+            port: 3868,
+            vr_id: Some("vr1".to_string()),
+        };
+        assert_eq!(
+            peer_csv_line(&peer),
+            "hss1.example.com,example.com,192.0.2.10,3868,vr1\n"
+        );
+
+        peer.realm = "odd,\"realm\"".to_string();
+        peer.vr_id = None;
+        assert_eq!(
+            peer_csv_line(&peer),
+            "hss1.example.com,\"odd,\"\"realm\"\"\",192.0.2.10,3868,\n"
+        );
+    }
+}
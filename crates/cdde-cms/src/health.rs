@@ -0,0 +1,171 @@
+use crate::models::PeerConfig;
+use cdde_core::PeerHealthRegistry;
+use cdde_proto::routing_update_service_server::RoutingUpdateService;
+use cdde_proto::{PeerStatus, PeerStatusRequest, UpdateResponse};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use tonic::{Request, Response, Status};
+use tracing::debug;
+use utoipa::ToSchema;
+
+/// Diameter state of a peer as last reported by the DPA
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub enum PeerState {
+    Up,
+    Down,
+    /// No status reported since the CMS started
+    #[default]
+    Unknown,
+}
+
+/// Peer entry of the health summary
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct PeerHealthSummary {
+    #[schema(example = "peer1.example.com")]
+    pub hostname: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub realm: Option<String>,
+    /// Whether the peer is in the CMS configuration
+    pub configured: bool,
+    pub state: PeerState,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub latency_ms: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error_rate: Option<f64>,
+}
+
+/// Keeps the CMS's view of peer states current from DPA notifications
+pub struct PeerStatusListener {
+    health: Arc<PeerHealthRegistry>,
+}
+
+impl PeerStatusListener {
+    /// Create a listener updating the given registry
+    pub fn new(health: Arc<PeerHealthRegistry>) -> Self {
+        Self { health }
+    }
+}
+
+#[tonic::async_trait]
+impl RoutingUpdateService for PeerStatusListener {
+    async fn update_peer_status(
+        &self,
+        request: Request<PeerStatusRequest>,
+    ) -> Result<Response<UpdateResponse>, Status> {
+        let request = request.into_inner();
+        let status = PeerStatus::try_from(request.current_status)
+            .map_err(|_| Status::invalid_argument("unknown peer status"))?;
+
+        debug!(peer = %request.peer_node_id, status = ?status, "Peer status update");
+        self.health
+            .set_down(&request.peer_node_id, status == PeerStatus::Down);
+
+        Ok(Response::new(UpdateResponse {
+            success: true,
+            message: String::new(),
+        }))
+    }
+}
+
+/// Combine the configured peers with the states reported for them
+///
+/// Configured peers nobody reported on are `Unknown`; peers reported but not
+/// configured are listed too so they are not hidden from operators.
+pub fn peer_health_summary(
+    peers: Vec<PeerConfig>,
+    health: &PeerHealthRegistry,
+) -> Vec<PeerHealthSummary> {
+    let mut summary: BTreeMap<String, PeerHealthSummary> = peers
+        .into_iter()
+        .map(|peer| {
+            (
+                peer.hostname.clone(),
+                PeerHealthSummary {
+                    hostname: peer.hostname,
+                    realm: Some(peer.realm),
+                    configured: true,
+                    ..Default::default()
+                },
+            )
+        })
+        .collect();
+
+    for (hostname, observed) in health.snapshot() {
+        let entry = summary
+            .entry(hostname.clone())
+            .or_insert_with(|| PeerHealthSummary {
+                hostname,
+                ..Default::default()
+            });
+        entry.state = if observed.down {
+            PeerState::Down
+        } else {
+            PeerState::Up
+        };
+        entry.latency_ms = Some(observed.latency_ms);
+        entry.error_rate = Some(observed.error_rate);
+    }
+
+    summary.into_values().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cdde_core::HealthThresholds;
+
+    fn peer(hostname: &str) -> PeerConfig {
+        PeerConfig {
+            hostname: hostname.to_string(),
+            realm: "example.com".to_string(),
+            ip_address: "192.0.2.1".to_string(),
+            port: 3868,
+            transport: "tcp".to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_reported_states_in_summary() {
+        let health = Arc::new(PeerHealthRegistry::new(HealthThresholds::default()));
+        let listener = PeerStatusListener::new(health.clone());
+
+        for (peer, status) in [
+            ("hss1.example.com", PeerStatus::Down),
+            ("hss2.example.com", PeerStatus::Up),
+            ("rogue.example.com", PeerStatus::Up),
+        ] {
+            listener
+                .update_peer_status(Request::new(PeerStatusRequest {
+                    peer_node_id: peer.to_string(),
+                    current_status: status as i32,
+                    virtual_router_ids: vec![],
+                }))
+                .await
+                .unwrap();
+        }
+
+        let summary = peer_health_summary(
+            vec![
+                peer("hss1.example.com"),
+                peer("hss2.example.com"),
+                peer("hss3.example.com"),
+            ],
+            &health,
+        );
+        let states: Vec<_> = summary
+            .iter()
+            .map(|peer| (peer.hostname.as_str(), peer.state, peer.configured))
+            .collect();
+
+        assert_eq!(
+            states,
+            vec![
+                ("hss1.example.com", PeerState::Down, true),
+                ("hss2.example.com", PeerState::Up, true),
+                ("hss3.example.com", PeerState::Unknown, true),
+                ("rogue.example.com", PeerState::Up, false),
+            ]
+        );
+    }
+}
@@ -1,8 +1,13 @@
 // Library exports for cdde-cms
-pub use crate::db::PostgresRepository;
+pub use crate::db::{PoolSettings, PostgresRepository};
+pub use crate::dictionaries::{activate_dictionary, delete_dictionary, load_active_dictionary};
+pub use crate::export::{peer_csv_line, peers_csv, PEERS_CSV_HEADER};
 pub use crate::models::{
-    Dictionary, DictionaryAvp, ManipulationRule, PeerConfig, RoutingRule, VirtualRouter,
+    Dictionary, DictionaryAvp, ManipulationRule, PeerConfig, PeerExportRow, RoutingRule,
+    VirtualRouter,
 };
 
 mod db;
+mod dictionaries;
+mod export;
 mod models;
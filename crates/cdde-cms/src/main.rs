@@ -2,12 +2,17 @@ mod api;
 mod models;
 
 mod db;
+mod dictionaries;
 mod error;
+mod export;
+mod health;
 
-pub use db::PostgresRepository;
+pub use db::{PoolSettings, PostgresRepository};
 pub use error::AppError;
+pub use health::{peer_health_summary, PeerHealthSummary, PeerState, PeerStatusListener};
 pub use models::{
-    Dictionary, DictionaryAvp, ManipulationRule, PeerConfig, RoutingRule, VirtualRouter,
+    Dictionary, DictionaryAvp, ManipulationRule, PeerConfig, PeerExportRow, RoutingRule,
+    VirtualRouter,
 };
 
 use tracing::{error, info};
@@ -30,19 +35,50 @@ async fn main() {
 
     // Initialize repository
     let database_url = std::env::var("DATABASE_URL").expect("DATABASE_URL must be set");
-    let repository = match PostgresRepository::new(&database_url).await {
-        Ok(repo) => repo,
-        Err(e) => {
-            error!("Failed to connect to database: {}", e);
-            return;
-        }
-    };
+    let repository =
+        match PostgresRepository::with_settings(&database_url, &PoolSettings::from_env()).await {
+            Ok(repo) => repo,
+            Err(e) => {
+                error!("Failed to connect to database: {}", e);
+                return;
+            }
+        };
 
     // Initialize dictionary manager
     let dictionary_manager = std::sync::Arc::new(cdde_diameter_dict::DictionaryManager::new());
+    match dictionaries::load_active_dictionary(&repository, &dictionary_manager).await {
+        Ok(Some(dict)) => info!("Loaded active dictionary {} ({})", dict.name, dict.version),
+        Ok(None) => {}
+        Err(e) => error!("Failed to load active dictionary {}", e),
+    }
+
+    // Peer states reported by the DPA
+    let health = std::sync::Arc::new(cdde_core::PeerHealthRegistry::default());
+    let status_addr =
+        std::env::var("STATUS_BIND_ADDR").unwrap_or_else(|_| "[::]:50053".to_string());
+    match status_addr.parse() {
+        Ok(addr) => {
+            info!("Starting peer status service on {}", status_addr);
+            let listener = PeerStatusListener::new(health.clone());
+            tokio::spawn(async move {
+                if let Err(e) = tonic::transport::Server::builder()
+                    .add_service(
+                        cdde_proto::routing_update_service_server::RoutingUpdateServiceServer::new(
+                            listener,
+                        ),
+                    )
+                    .serve(addr)
+                    .await
+                {
+                    error!("Peer status service error: {}", e);
+                }
+            });
+        }
+        Err(e) => error!("Invalid STATUS_BIND_ADDR {}: {}", status_addr, e),
+    }
 
     // Create API router
-    let api_router = api::create_router(repository, dictionary_manager);
+    let api_router = api::create_router(repository, dictionary_manager, health);
 
     // Swagger UI
     use api::ApiDoc;
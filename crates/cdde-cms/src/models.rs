@@ -1,6 +1,6 @@
 use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
-use validator::Validate;
+use validator::{Validate, ValidationError};
 
 /// Virtual Router configuration
 #[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow, Validate, ToSchema)]
@@ -46,6 +46,33 @@ pub struct PeerConfig {
     #[validate(range(min = 1, max = 65535, message = "Port must be between 1 and 65535"))]
     #[schema(example = 3868)]
     pub port: i32,
+
+    /// Transport protocol, `tcp` or `sctp`
+    #[serde(default = "default_transport")]
+    #[validate(custom = "validate_transport")]
+    #[schema(example = "tcp")]
+    pub transport: String,
+}
+
+fn default_transport() -> String {
+    "tcp".to_string()
+}
+
+fn validate_transport(transport: &str) -> Result<(), ValidationError> {
+    match transport {
+        "tcp" | "sctp" => Ok(()),
+        _ => Err(ValidationError::new("transport must be tcp or sctp")),
+    }
+}
+
+/// Peer row exported to operations, with its Virtual Router
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct PeerExportRow {
+    pub hostname: String,
+    pub realm: String,
+    pub ip_address: String,
+    pub port: i32,
+    pub vr_id: Option<String>,
 }
 
 /// Dictionary metadata
@@ -59,6 +86,9 @@ pub struct Dictionary {
     pub version: String,
     #[schema(example = "<dictionary>...</dictionary>")]
     pub xml_content: String,
+    /// Whether this version is the one loaded for parsing
+    #[serde(default)]
+    pub active: bool,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub created_at: Option<chrono::DateTime<chrono::Utc>>,
 }
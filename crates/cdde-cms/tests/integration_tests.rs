@@ -81,6 +81,7 @@ async fn test_peer_crud_operations() {
         realm: "example.com".to_string(),
         ip_address: "192.168.1.10".to_string(),
         port: 3868,
+        transport: "sctp".to_string(),
     };
 
     assert!(repo.add_peer(peer.clone()).await, "Failed to create peer");
@@ -92,6 +93,7 @@ async fn test_peer_crud_operations() {
     assert_eq!(fetched_peer.hostname, "peer.example.com");
     assert_eq!(fetched_peer.ip_address, "192.168.1.10");
     assert_eq!(fetched_peer.port, 3868);
+    assert_eq!(fetched_peer.transport, "sctp");
 
     // Test LIST
     let peers = repo.get_all_peers().await;
@@ -210,3 +212,243 @@ async fn test_routing_rule_operations() {
     // Cleanup
     repo.delete_vr("test_vr").await;
 }
+
+#[tokio::test]
+#[ignore]
+async fn test_bulk_delete_routing_rules() {
+    let db_url = get_test_db_url();
+    let repo = PostgresRepository::new(&db_url)
+        .await
+        .expect("Failed to create repository");
+
+    let vr = VirtualRouter {
+        id: "test_vr_bulk".to_string(),
+        hostname: "bulk-host.example.com".to_string(),
+        realm: "example.com".to_string(),
+        timeout_ms: 3000,
+    };
+    repo.add_vr(vr).await;
+
+    for priority in [10, 20, 30] {
+        let rule = cdde_cms::RoutingRule {
+            id: 0,
+            vr_id: "test_vr_bulk".to_string(),
+            priority,
+            realm: Some("example.realm".to_string()),
+            application_id: None,
+            destination_host: None,
+            target_pool: format!("pool{priority}"),
+            created_at: None,
+        };
+        assert!(repo.create_routing_rule(rule).await.is_some());
+    }
+    assert_eq!(repo.list_routing_rules("test_vr_bulk").await.len(), 3);
+
+    // All three go in one call
+    assert_eq!(
+        repo.delete_routing_rules_for_vr("test_vr_bulk").await,
+        Some(3)
+    );
+    assert!(repo.list_routing_rules("test_vr_bulk").await.is_empty());
+
+    // Deleting again is not an error, just nothing to remove
+    assert_eq!(
+        repo.delete_routing_rules_for_vr("test_vr_bulk").await,
+        Some(0)
+    );
+
+    // Cleanup
+    repo.delete_vr("test_vr_bulk").await;
+}
+
+#[tokio::test]
+#[ignore]
+async fn test_activating_dictionary_version() {
+    let db_url = get_test_db_url();
+    let repo = PostgresRepository::new(&db_url)
+        .await
+        .expect("Failed to create repository");
+
+    let v1 = repo
+        .save_dictionary(
+            "versioned-dict-v1".to_string(),
+            "1.0".to_string(),
+            r#"<dictionary><avp name="Versioned-AVP-V1" code="10101" type="Unsigned32"/></dictionary>"#
+                .to_string(),
+        )
+        .await
+        .expect("Failed to save v1");
+    let v2 = repo
+        .save_dictionary(
+            "versioned-dict-v2".to_string(),
+            "2.0".to_string(),
+            r#"<dictionary><avp name="Versioned-AVP-V2" code="10101" type="UTF8String"/></dictionary>"#
+                .to_string(),
+        )
+        .await
+        .expect("Failed to save v2");
+
+    let manager = cdde_diameter_dict::DictionaryManager::new();
+    let load_active = |dict: cdde_cms::Dictionary| {
+        manager
+            .replace_dynamic_dictionary(&dict.xml_content)
+            .unwrap();
+    };
+
+    assert!(repo.activate_dictionary(v1).await);
+    load_active(repo.get_active_dictionary().await.unwrap());
+    assert_eq!(manager.lookup(10101).unwrap().name, "Versioned-AVP-V1");
+
+    // Activating v2 deactivates v1 and changes what resolves
+    assert!(repo.activate_dictionary(v2).await);
+    let active = repo.get_active_dictionary().await.unwrap();
+    assert_eq!(active.id, v2);
+    assert!(!repo.get_dictionary(v1).await.unwrap().active);
+    load_active(active);
+    assert_eq!(manager.lookup(10101).unwrap().name, "Versioned-AVP-V2");
+
+    assert!(!repo.activate_dictionary(-1).await);
+    assert_eq!(repo.get_active_dictionary().await.unwrap().id, v2);
+
+    // Cleanup
+    repo.delete_dictionary(v1).await;
+    repo.delete_dictionary(v2).await;
+}
+
+#[tokio::test]
+#[ignore]
+async fn test_peer_csv_export() {
+    use tokio_stream::StreamExt;
+
+    let db_url = get_test_db_url();
+    let repo = PostgresRepository::new(&db_url)
+        .await
+        .expect("Failed to create repository");
+
+    let peer = PeerConfig {
+        hostname: "csv-peer.example.com".to_string(),
+        realm: "example.com".to_string(),
+        ip_address: "192.0.2.44".to_string(),
+        port: 3868,
+        transport: "tcp".to_string(),
+    };
+    repo.add_peer(peer).await;
+
+    let csv: Vec<String> = cdde_cms::peers_csv(&repo)
+        .collect::<Result<_, _>>()
+        .await
+        .expect("Failed to export peers");
+
+    assert_eq!(csv[0], cdde_cms::PEERS_CSV_HEADER);
+    assert!(csv.contains(&"csv-peer.example.com,example.com,192.0.2.44,3868,\n".to_string()));
+
+    // Cleanup
+    repo.delete_peer("csv-peer.example.com").await;
+}
+
+#[tokio::test]
+#[ignore]
+async fn test_deleting_dictionary_unloads_its_avps() {
+    let db_url = get_test_db_url();
+    let repo = PostgresRepository::new(&db_url)
+        .await
+        .expect("Failed to create repository");
+
+    let id = repo
+        .save_dictionary(
+            "deleted-dict".to_string(),
+            "1.0".to_string(),
+            r#"<dictionary><avp name="Deleted-AVP" code="10201" type="Unsigned32"/></dictionary>"#
+                .to_string(),
+        )
+        .await
+        .expect("Failed to save dictionary");
+
+    let manager = cdde_diameter_dict::DictionaryManager::new();
+    assert!(cdde_cms::activate_dictionary(&repo, &manager, id)
+        .await
+        .unwrap());
+    assert_eq!(manager.lookup(10201).unwrap().name, "Deleted-AVP");
+
+    assert!(cdde_cms::delete_dictionary(&repo, &manager, id).await);
+    assert!(manager.lookup(10201).is_none());
+    assert!(repo.get_dictionary(id).await.is_none());
+
+    // Deleting again finds nothing
+    assert!(!cdde_cms::delete_dictionary(&repo, &manager, id).await);
+}
+
+#[tokio::test]
+#[ignore]
+async fn test_dictionary_loaded_on_restart_is_unloaded_when_deleted() {
+    let db_url = get_test_db_url();
+    let repo = PostgresRepository::new(&db_url)
+        .await
+        .expect("Failed to create repository");
+
+    let id = repo
+        .save_dictionary(
+            "restarted-dict".to_string(),
+            "1.0".to_string(),
+            r#"<dictionary><avp name="Restarted-AVP" code="10202" type="Unsigned32"/></dictionary>"#
+                .to_string(),
+        )
+        .await
+        .expect("Failed to save dictionary");
+    assert!(repo.activate_dictionary(id).await);
+
+    // A restarted CMS starts from an empty manager and loads the active dictionary
+    let manager = cdde_diameter_dict::DictionaryManager::new();
+    let loaded = cdde_cms::load_active_dictionary(&repo, &manager)
+        .await
+        .unwrap()
+        .expect("No active dictionary");
+    assert_eq!(loaded.id, id);
+    assert_eq!(manager.lookup(10202).unwrap().name, "Restarted-AVP");
+
+    assert!(cdde_cms::delete_dictionary(&repo, &manager, id).await);
+    assert!(manager.lookup(10202).is_none());
+}
+
+#[tokio::test]
+#[ignore]
+async fn test_dictionary_failing_to_load_is_not_activated() {
+    let db_url = get_test_db_url();
+    let repo = PostgresRepository::new(&db_url)
+        .await
+        .expect("Failed to create repository");
+
+    let good = repo
+        .save_dictionary(
+            "good-dict".to_string(),
+            "1.0".to_string(),
+            r#"<dictionary><avp name="Good-AVP" code="10203" type="Unsigned32"/></dictionary>"#
+                .to_string(),
+        )
+        .await
+        .expect("Failed to save dictionary");
+    let bad = repo
+        .save_dictionary(
+            "bad-dict".to_string(),
+            "1.0".to_string(),
+            "<dictionary>".to_string(),
+        )
+        .await
+        .expect("Failed to save dictionary");
+
+    let manager = cdde_diameter_dict::DictionaryManager::new();
+    assert!(cdde_cms::activate_dictionary(&repo, &manager, good)
+        .await
+        .unwrap());
+    assert!(cdde_cms::activate_dictionary(&repo, &manager, bad)
+        .await
+        .is_err());
+
+    // The database still names the dictionary the manager has loaded
+    assert_eq!(repo.get_active_dictionary().await.unwrap().id, good);
+    assert_eq!(manager.lookup(10203).unwrap().name, "Good-AVP");
+
+    // Cleanup
+    assert!(cdde_cms::delete_dictionary(&repo, &manager, bad).await);
+    assert!(cdde_cms::delete_dictionary(&repo, &manager, good).await);
+}
@@ -1,6 +1,7 @@
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use thiserror::Error;
-use validator::Validate;
+use validator::{Validate, ValidationErrors};
 
 /// Configuration error
 #[derive(Error, Debug)]
@@ -8,12 +9,17 @@ pub enum ConfigError {
     #[error("Failed to load config: {0}")]
     LoadError(String),
 
+    /// Field-level validator output, keyed by field name
     #[error("Validation error: {0}")]
-    ValidationError(String),
+    ValidationError(#[from] ValidationErrors),
 }
 
 /// Common application configuration
+///
+/// Missing fields take their value from `Default`, so a file only needs to
+/// list what it overrides.
 #[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+#[serde(default)]
 pub struct AppConfig {
     #[validate(length(min = 1))]
     pub service_name: String,
@@ -21,6 +27,12 @@ pub struct AppConfig {
     pub log_level: String,
     #[validate(range(min = 1, max = 65535))]
     pub metrics_port: u16,
+    #[validate(nested)]
+    pub peer_health: PeerHealthConfig,
+    #[validate(nested)]
+    pub dfl: DflConfig,
+    #[validate(nested)]
+    pub dcr: DcrConfig,
 }
 
 impl Default for AppConfig {
@@ -29,29 +41,264 @@ impl Default for AppConfig {
             service_name: "cdde".to_string(),
             log_level: "info".to_string(),
             metrics_port: 9090,
+            peer_health: PeerHealthConfig::default(),
+            dfl: DflConfig::default(),
+            dcr: DcrConfig::default(),
         }
     }
 }
 
-/// Load configuration from file
-pub fn load_config<T>(path: &str) -> Result<T, ConfigError>
+/// Thresholds for scoring peer health in routing
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+#[serde(default)]
+pub struct PeerHealthConfig {
+    /// Answer latency considered slow, in milliseconds
+    #[validate(range(min = 1))]
+    pub latency_threshold_ms: u64,
+    /// Error ratio considered unhealthy
+    #[validate(range(exclusive_min = 0.0, max = 1.0))]
+    pub error_rate_threshold: f64,
+    /// Weight of the newest observation in the rolling averages
+    #[validate(range(exclusive_min = 0.0, max = 1.0))]
+    pub smoothing: f64,
+}
+
+impl Default for PeerHealthConfig {
+    fn default() -> Self {
+        Self {
+            latency_threshold_ms: 500,
+            error_rate_threshold: 0.1,
+            smoothing: 0.2,
+        }
+    }
+}
+
+/// Transaction timeouts used by the DFL
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+#[serde(default)]
+pub struct DflConfig {
+    /// Overall lifetime of a transaction, in milliseconds
+    #[validate(range(min = 1))]
+    pub session_timeout_ms: u64,
+    /// Time allowed for the first answer, in milliseconds
+    #[validate(range(min = 1))]
+    pub answer_timeout_ms: u64,
+    /// Answers slower than this are logged, in milliseconds
+    #[validate(range(min = 1))]
+    pub slow_threshold_ms: u64,
+}
+
+impl Default for DflConfig {
+    fn default() -> Self {
+        Self {
+            session_timeout_ms: 30_000,
+            answer_timeout_ms: 5_000,
+            slow_threshold_ms: 1_000,
+        }
+    }
+}
+
+/// Message handling settings of the DCR
+#[derive(Debug, Clone, Default, Serialize, Deserialize, Validate)]
+#[serde(default)]
+pub struct DcrConfig {
+    /// Virtual routers that only forward explicitly allowed AVPs
+    pub avp_allowlists: Vec<AvpAllowlistConfig>,
+
+    /// Answer requests for commands no route names with
+    /// DIAMETER_COMMAND_UNSUPPORTED instead of DIAMETER_REALM_NOT_SERVED
+    pub strict_commands: bool,
+
+    /// Result-Code for requests whose every candidate peer is draining,
+    /// DIAMETER_TOO_BUSY if unset
+    pub draining_result_code: Option<u32>,
+
+    /// Peer selection strategy of virtual routers not using round robin
+    pub peer_selection: Vec<PeerSelectionConfig>,
+
+    /// Order in which routes are tried
+    pub route_precedence: RoutePrecedence,
+
+    /// Requests served at once on one DFL connection, others wait
+    pub concurrency_limit_per_connection: Option<usize>,
+
+    /// HTTP/2 streams a DFL connection may open at once
+    pub max_concurrent_streams: Option<u32>,
+
+    /// Requests processed at once before new ones are refused with
+    /// RESOURCE_EXHAUSTED
+    pub max_in_flight: Option<usize>,
+
+    /// Where requests given up as undeliverable are recorded, nowhere if unset
+    pub dead_letter: Option<DeadLetterConfig>,
+
+    /// DPA peer relay the DCR delivers routed requests through, retrying on
+    /// other peers; without one the DFL delivers them
+    pub relay_endpoint: Option<String>,
+
+    /// Origin-Host of answers and Proxy-Host of relayed requests; must
+    /// differ between DCR instances
+    pub origin_host: Option<String>,
+
+    /// Origin-Realm of answers generated by the DCR
+    pub origin_realm: Option<String>,
+
+    /// DSL manipulation of routed requests, none if unset
+    pub manipulation: Option<ManipulationConfig>,
+}
+
+/// DSL rules the DCR applies to routed requests
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ManipulationConfig {
+    /// JSON file holding the list of rules
+    pub rules_path: String,
+
+    /// Applications the rules apply to, others are relayed untouched;
+    /// every application if empty
+    #[serde(default)]
+    pub applications: Vec<u32>,
+}
+
+/// Record of requests the DCR could not deliver
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DeadLetterConfig {
+    pub sink: DeadLetterSinkConfig,
+    /// Records written per second at most, further ones are only counted
+    pub max_per_second: u32,
+}
+
+impl Default for DeadLetterConfig {
+    fn default() -> Self {
+        Self {
+            sink: DeadLetterSinkConfig::Log,
+            max_per_second: 10,
+        }
+    }
+}
+
+/// Destination of dead-letter records
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum DeadLetterSinkConfig {
+    /// Structured warnings in the service log
+    Log,
+    /// JSON lines appended to a file
+    File { path: String },
+}
+
+/// Order in which the DCR tries its routes
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RoutePrecedence {
+    /// By numeric priority alone
+    #[default]
+    Priority,
+    /// Destination-Host routes, then the other conditions, then the
+    /// default route (RFC 6733 section 6.1), by priority within each
+    HostRealmDefault,
+}
+
+/// How a virtual router picks a peer within a pool
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PeerSelectionConfig {
+    pub vr_id: String,
+    pub strategy: PeerSelectionStrategy,
+
+    /// Relative weight of each peer for the weighted strategy, 1 if unlisted
+    #[serde(default)]
+    pub weights: HashMap<String, u32>,
+}
+
+/// Load distribution over the peers of a pool
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PeerSelectionStrategy {
+    /// Always the first available peer, the others only as fallback
+    FirstMatch,
+    /// Each peer in turn
+    RoundRobin,
+    /// Peers in turn, in proportion to their weights
+    Weighted,
+    /// The same peer for every request of a session
+    ConsistentHash,
+}
+
+/// AVPs a virtual router in whitelist mode may forward
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AvpAllowlistConfig {
+    pub vr_id: String,
+    pub avps: Vec<AllowedAvp>,
+}
+
+/// AVP identified by its code and vendor, `None` for IETF AVPs
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct AllowedAvp {
+    pub code: u32,
+    #[serde(default)]
+    pub vendor_id: Option<u32>,
+}
+
+/// `CDDE_`-prefixed environment variables, with `__` separating nested keys
+///
+/// `CDDE_DFL__SESSION_TIMEOUT_MS` sets `dfl.session_timeout_ms`.
+fn env_source() -> config::Environment {
+    config::Environment::with_prefix("CDDE")
+        .prefix_separator("_")
+        .separator("__")
+}
+
+/// Start a builder whose lowest layer is `T::default()`
+fn defaults_builder<T>() -> Result<config::ConfigBuilder<config::builder::DefaultState>, ConfigError>
+where
+    T: Default + Serialize,
+{
+    let defaults = config::Config::try_from(&T::default())
+        .map_err(|e| ConfigError::LoadError(e.to_string()))?;
+    Ok(config::Config::builder().add_source(defaults))
+}
+
+/// Build, deserialize and validate the layered configuration
+fn finish<T>(
+    builder: config::ConfigBuilder<config::builder::DefaultState>,
+) -> Result<T, ConfigError>
 where
     T: for<'de> Deserialize<'de> + Validate,
 {
-    let config: T = config::Config::builder()
-        .add_source(config::File::with_name(path))
-        .add_source(config::Environment::with_prefix("CDDE"))
+    let config: T = builder
         .build()
         .map_err(|e| ConfigError::LoadError(e.to_string()))?
         .try_deserialize()
         .map_err(|e| ConfigError::LoadError(e.to_string()))?;
 
-    config
-        .validate()
-        .map_err(|e| ConfigError::ValidationError(e.to_string()))?;
+    config.validate()?;
     Ok(config)
 }
 
+/// Load configuration from file
+///
+/// Layers, lowest first: `T::default()`, the file, then the environment.
+pub fn load_config<T>(path: &str) -> Result<T, ConfigError>
+where
+    T: for<'de> Deserialize<'de> + Serialize + Default + Validate,
+{
+    finish(
+        defaults_builder::<T>()?
+            .add_source(config::File::with_name(path))
+            .add_source(env_source()),
+    )
+}
+
+/// Load configuration from environment variables only
+///
+/// Variables override `T::default()`.
+pub fn load_from_env<T>() -> Result<T, ConfigError>
+where
+    T: for<'de> Deserialize<'de> + Serialize + Default + Validate,
+{
+    finish(defaults_builder::<T>()?.add_source(env_source()))
+}
+
 /// Load configuration from YAML string (for testing)
 pub fn load_from_yaml<T>(yaml: &str) -> Result<T, ConfigError>
 where
@@ -59,15 +306,53 @@ where
 {
     let config: T =
         serde_yaml::from_str(yaml).map_err(|e| ConfigError::LoadError(e.to_string()))?;
-    config
-        .validate()
-        .map_err(|e| ConfigError::ValidationError(e.to_string()))?;
+    config.validate()?;
     Ok(config)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::sync::{Mutex, MutexGuard};
+
+    /// Serializes tests that read the process environment
+    static ENV_LOCK: Mutex<()> = Mutex::new(());
+
+    /// Environment variables set for one test, restored when dropped
+    struct EnvGuard {
+        previous: Vec<(&'static str, Option<String>)>,
+        _lock: MutexGuard<'static, ()>,
+    }
+
+    impl EnvGuard {
+        /// Take the environment, setting `vars` on top of it
+        fn set(vars: &[(&'static str, &str)]) -> Self {
+            let lock = ENV_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+            let previous = vars
+                .iter()
+                .map(|(name, value)| {
+                    let previous = std::env::var(name).ok();
+                    std::env::set_var(name, value);
+                    (*name, previous)
+                })
+                .collect();
+            Self {
+                previous,
+                _lock: lock,
+            }
+        }
+    }
+
+    impl Drop for EnvGuard {
+        fn drop(&mut self) {
+            for (name, previous) in &self.previous {
+                match previous {
+                    Some(value) => std::env::set_var(name, value),
+                    None => std::env::remove_var(name),
+                }
+            }
+        }
+    }
 
     #[test]
     fn test_default_config() {
@@ -88,6 +373,33 @@ metrics_port: 8080
         assert_eq!(config.service_name, "test-service");
         assert_eq!(config.log_level, "debug");
         assert_eq!(config.metrics_port, 8080);
+        assert_eq!(config.peer_health.latency_threshold_ms, 500);
+    }
+
+    #[test]
+    fn test_peer_health_thresholds() {
+        let yaml = r#"
+service_name: dcr
+log_level: info
+metrics_port: 9090
+peer_health:
+  latency_threshold_ms: 200
+  error_rate_threshold: 0.05
+"#;
+        let config: AppConfig = load_from_yaml(yaml).unwrap();
+        assert_eq!(config.peer_health.latency_threshold_ms, 200);
+        assert_eq!(config.peer_health.error_rate_threshold, 0.05);
+        assert_eq!(config.peer_health.smoothing, 0.2);
+
+        let yaml = r#"
+service_name: dcr
+log_level: info
+metrics_port: 9090
+peer_health:
+  error_rate_threshold: 0.0
+"#;
+        let result: Result<AppConfig, _> = load_from_yaml(yaml);
+        assert!(matches!(result, Err(ConfigError::ValidationError(_))));
     }
 
     #[test]
@@ -104,4 +416,215 @@ metrics_port: 9090
             _ => panic!("Expected ValidationError"),
         }
     }
+
+    #[test]
+    fn test_validation_error_reports_field() {
+        let yaml = r#"
+service_name: test-service
+log_level: info
+metrics_port: 0
+"#;
+        let result: Result<AppConfig, _> = load_from_yaml(yaml);
+        let Err(ConfigError::ValidationError(errors)) = result else {
+            panic!("Expected ValidationError");
+        };
+
+        let fields = errors.field_errors();
+        assert!(fields.contains_key("metrics_port"));
+        assert_eq!(fields["metrics_port"][0].code, "range");
+        assert!(!fields.contains_key("service_name"));
+    }
+
+    #[test]
+    fn test_load_from_env() {
+        let _env = EnvGuard::set(&[
+            ("CDDE_SERVICE_NAME", "dfl"),
+            ("CDDE_LOG_LEVEL", "debug"),
+            ("CDDE_METRICS_PORT", "9091"),
+            ("CDDE_DFL__SESSION_TIMEOUT_MS", "45000"),
+            ("CDDE_DFL__ANSWER_TIMEOUT_MS", "2500"),
+        ]);
+
+        let config: AppConfig = load_from_env().unwrap();
+        assert_eq!(config.service_name, "dfl");
+        assert_eq!(config.metrics_port, 9091);
+        assert_eq!(config.dfl.session_timeout_ms, 45_000);
+        assert_eq!(config.dfl.answer_timeout_ms, 2_500);
+        assert_eq!(config.dfl.slow_threshold_ms, 1_000);
+        assert_eq!(config.peer_health.latency_threshold_ms, 500);
+    }
+
+    #[test]
+    fn test_partial_yaml_inherits_defaults() {
+        let config: AppConfig = load_from_yaml("log_level: debug").unwrap();
+
+        assert_eq!(config.log_level, "debug");
+        assert_eq!(config.service_name, "cdde");
+        assert_eq!(config.metrics_port, 9090);
+        assert_eq!(config.peer_health.smoothing, 0.2);
+        assert_eq!(config.dfl.answer_timeout_ms, 5_000);
+    }
+
+    #[test]
+    fn test_partial_file_inherits_defaults() {
+        let path = std::env::temp_dir().join(format!("cdde-config-{}.yaml", std::process::id()));
+        let _env = EnvGuard::set(&[]);
+        std::fs::write(&path, "dfl:\n  slow_threshold_ms: 250\n").unwrap();
+
+        let result: Result<AppConfig, _> = load_config(path.to_str().unwrap());
+        std::fs::remove_file(&path).unwrap();
+        let config = result.unwrap();
+
+        assert_eq!(config.dfl.slow_threshold_ms, 250);
+        assert_eq!(config.peer_health.latency_threshold_ms, 500);
+        assert_eq!(config.peer_health.error_rate_threshold, 0.1);
+    }
+
+    #[test]
+    fn test_avp_allowlists() {
+        let yaml = r#"
+dcr:
+  avp_allowlists:
+    - vr_id: vr-partner
+      avps:
+        - code: 263
+        - code: 1407
+          vendor_id: 10415
+"#;
+        let config: AppConfig = load_from_yaml(yaml).unwrap();
+        let allowlist = &config.dcr.avp_allowlists[0];
+        assert_eq!(allowlist.vr_id, "vr-partner");
+        assert_eq!(
+            allowlist.avps,
+            vec![
+                AllowedAvp {
+                    code: 263,
+                    vendor_id: None
+                },
+                AllowedAvp {
+                    code: 1407,
+                    vendor_id: Some(10415)
+                },
+            ]
+        );
+        assert!(AppConfig::default().dcr.avp_allowlists.is_empty());
+    }
+
+    #[test]
+    fn test_peer_selection() {
+        let yaml = r#"
+dcr:
+  peer_selection:
+    - vr_id: vr001
+      strategy: weighted
+      weights:
+        hss01: 3
+    - vr_id: vr002
+      strategy: consistent_hash
+"#;
+        let config: AppConfig = load_from_yaml(yaml).unwrap();
+        let selection = &config.dcr.peer_selection;
+        assert_eq!(selection[0].strategy, PeerSelectionStrategy::Weighted);
+        assert_eq!(selection[0].weights.get("hss01"), Some(&3));
+        assert_eq!(selection[1].strategy, PeerSelectionStrategy::ConsistentHash);
+        assert!(selection[1].weights.is_empty());
+    }
+
+    #[test]
+    fn test_route_precedence() {
+        let yaml = r#"
+dcr:
+  route_precedence: host_realm_default
+"#;
+        let config: AppConfig = load_from_yaml(yaml).unwrap();
+        assert_eq!(
+            config.dcr.route_precedence,
+            RoutePrecedence::HostRealmDefault
+        );
+        assert_eq!(
+            AppConfig::default().dcr.route_precedence,
+            RoutePrecedence::Priority
+        );
+    }
+
+    #[test]
+    fn test_grpc_limits() {
+        let yaml = r#"
+dcr:
+  concurrency_limit_per_connection: 64
+  max_concurrent_streams: 128
+  max_in_flight: 512
+"#;
+        let config: AppConfig = load_from_yaml(yaml).unwrap();
+        assert_eq!(config.dcr.concurrency_limit_per_connection, Some(64));
+        assert_eq!(config.dcr.max_concurrent_streams, Some(128));
+        assert_eq!(config.dcr.max_in_flight, Some(512));
+        assert_eq!(AppConfig::default().dcr.max_in_flight, None);
+    }
+
+    #[test]
+    fn test_dead_letter() {
+        let yaml = r#"
+dcr:
+  dead_letter:
+    sink:
+      type: file
+      path: /var/log/cdde/dead-letters.jsonl
+"#;
+        let config: AppConfig = load_from_yaml(yaml).unwrap();
+        let dead_letter = config.dcr.dead_letter.unwrap();
+        assert_eq!(
+            dead_letter.sink,
+            DeadLetterSinkConfig::File {
+                path: "/var/log/cdde/dead-letters.jsonl".to_string()
+            }
+        );
+        assert_eq!(dead_letter.max_per_second, 10);
+        assert!(AppConfig::default().dcr.dead_letter.is_none());
+    }
+
+    #[test]
+    fn test_relay_endpoint() {
+        let yaml = r#"
+dcr:
+  relay_endpoint: http://dpa:50054
+"#;
+        let config: AppConfig = load_from_yaml(yaml).unwrap();
+        assert_eq!(
+            config.dcr.relay_endpoint.as_deref(),
+            Some("http://dpa:50054")
+        );
+        assert!(AppConfig::default().dcr.relay_endpoint.is_none());
+    }
+
+    #[test]
+    fn test_dcr_identity() {
+        let yaml = r#"
+dcr:
+  origin_host: dcr01.epc.example.com
+  origin_realm: epc.example.com
+"#;
+        let config: AppConfig = load_from_yaml(yaml).unwrap();
+        assert_eq!(
+            config.dcr.origin_host.as_deref(),
+            Some("dcr01.epc.example.com")
+        );
+        assert_eq!(config.dcr.origin_realm.as_deref(), Some("epc.example.com"));
+        assert!(AppConfig::default().dcr.origin_host.is_none());
+    }
+
+    #[test]
+    fn test_manipulation() {
+        let yaml = r#"
+dcr:
+  manipulation:
+    rules_path: /etc/cdde/rules.json
+    applications: [4, 16777238]
+"#;
+        let config: AppConfig = load_from_yaml(yaml).unwrap();
+        let manipulation = config.dcr.manipulation.unwrap();
+        assert_eq!(manipulation.rules_path, "/etc/cdde/rules.json");
+        assert_eq!(manipulation.applications, vec![4, 16777238]);
+        assert!(AppConfig::default().dcr.manipulation.is_none());
+    }
 }
@@ -0,0 +1,75 @@
+use cdde_core::codes::{AVP_DESTINATION_REALM, AVP_ORIGIN_HOST, AVP_SESSION_ID};
+use cdde_core::{DiameterAvp, DiameterHeader, DiameterPacket};
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+/// Typical routed request with the realm near the end
+fn request() -> Vec<u8> {
+    let mut avps = vec![
+        DiameterAvp {
+            code: AVP_SESSION_ID,
+            flags: 0x40,
+            vendor_id: None,
+            data: b"mme.example.com;1234567890;1".to_vec(),
+        },
+        DiameterAvp {
+            code: AVP_ORIGIN_HOST,
+            flags: 0x40,
+            vendor_id: None,
+            data: b"mme.example.com".to_vec(),
+        },
+    ];
+    avps.extend((0..20).map(|i| DiameterAvp {
+        code: 1400 + i,
+        flags: 0xC0,
+        vendor_id: Some(10415),
+        data: vec![0xAB; 16],
+    }));
+    avps.push(DiameterAvp {
+        code: AVP_DESTINATION_REALM,
+        flags: 0x40,
+        vendor_id: None,
+        data: b"hss.example.com".to_vec(),
+    });
+
+    DiameterPacket {
+        header: DiameterHeader {
+            version: 1,
+            length: 0,
+            flags: 0xC0,
+            command_code: 316,
+            application_id: 16777251,
+            hop_by_hop_id: 1,
+            end_to_end_id: 2,
+        },
+        avps,
+    }
+    .serialize()
+}
+
+fn realm_lookup(c: &mut Criterion) {
+    let bytes = request();
+
+    c.bench_function("full parse + find realm", |b| {
+        b.iter(|| {
+            let packet = DiameterPacket::parse(black_box(&bytes)).unwrap();
+            black_box(
+                packet
+                    .find_avp(AVP_DESTINATION_REALM)
+                    .map(|avp| avp.data.len()),
+            )
+        })
+    });
+
+    c.bench_function("find_top_level_avp realm", |b| {
+        b.iter(|| {
+            let header = DiameterPacket::peek_header(black_box(&bytes)).unwrap();
+            let realm =
+                DiameterPacket::find_top_level_avp(black_box(&bytes), AVP_DESTINATION_REALM)
+                    .unwrap();
+            black_box((header.application_id, realm.map(<[u8]>::len)))
+        })
+    });
+}
+
+criterion_group!(benches, realm_lookup);
+criterion_main!(benches);
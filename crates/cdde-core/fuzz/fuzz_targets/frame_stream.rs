@@ -0,0 +1,30 @@
+//! Split an untrusted byte stream into messages, as read from a TCP socket
+//!
+//! The first byte picks the read size so the accumulator sees partial reads.
+//! Run with `cargo +nightly fuzz run frame_stream` from `crates/cdde-core`.
+
+#![no_main]
+
+use cdde_core::{DiameterPacket, FrameAccumulator};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let Some((&chunk, stream)) = data.split_first() else {
+        return;
+    };
+    let chunk = usize::from(chunk).max(1);
+
+    let mut accumulator = FrameAccumulator::new();
+    for read in stream.chunks(chunk) {
+        accumulator.extend(read);
+        loop {
+            match accumulator.next_frame() {
+                Ok(Some(frame)) => {
+                    let _ = DiameterPacket::parse(&frame);
+                }
+                Ok(None) => break,
+                Err(_) => return,
+            }
+        }
+    }
+});
@@ -0,0 +1,19 @@
+//! Parse a single untrusted message
+//!
+//! Run with `cargo +nightly fuzz run parse` from `crates/cdde-core`.
+
+#![no_main]
+
+use cdde_core::DiameterPacket;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(packet) = DiameterPacket::parse(data) {
+        // Anything accepted must serialize and parse again
+        let bytes = packet.serialize();
+        let reparsed = DiameterPacket::parse(&bytes).expect("serialized packet must parse");
+        assert_eq!(reparsed.avps, packet.avps);
+    }
+    let _ = DiameterPacket::peek_header(data);
+    let _ = DiameterPacket::find_top_level_avp(data, 264);
+});
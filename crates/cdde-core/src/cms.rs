@@ -0,0 +1,47 @@
+//! JSON client for the CMS REST API
+
+use crate::error::{CddeError, Result};
+use hyper::{body, Client, StatusCode, Uri};
+use serde::de::DeserializeOwned;
+
+/// Fetch a JSON resource from the CMS
+///
+/// `path` is appended to the CMS base URL and must already be encoded.
+/// `what` names the resource in error messages.
+pub async fn fetch_json<T: DeserializeOwned>(cms_url: &str, path: &str, what: &str) -> Result<T> {
+    fetch_optional_json(cms_url, path, what)
+        .await?
+        .ok_or_else(|| CddeError::NetworkError(format!("CMS has no {what}")))
+}
+
+/// Fetch a JSON resource from the CMS, `None` if it answers 404
+pub async fn fetch_optional_json<T: DeserializeOwned>(
+    cms_url: &str,
+    path: &str,
+    what: &str,
+) -> Result<Option<T>> {
+    let uri: Uri = format!("{}{}", cms_url.trim_end_matches('/'), path)
+        .parse()
+        .map_err(|e| CddeError::ConfigError(format!("Invalid CMS URL {cms_url}: {e}")))?;
+
+    let response = Client::new()
+        .get(uri)
+        .await
+        .map_err(|e| CddeError::NetworkError(e.to_string()))?;
+    let status = response.status();
+    if status == StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+    if !status.is_success() {
+        return Err(CddeError::NetworkError(format!(
+            "CMS answered {status} for the {what}"
+        )));
+    }
+
+    let bytes = body::to_bytes(response.into_body())
+        .await
+        .map_err(|e| CddeError::NetworkError(e.to_string()))?;
+    serde_json::from_slice(&bytes)
+        .map(Some)
+        .map_err(|e| CddeError::InvalidPacket(format!("Invalid {what}: {e}")))
+}
@@ -0,0 +1,164 @@
+use crate::diameter::{DiameterPacket, DEFAULT_MAX_AVPS};
+use crate::error::{CddeError, Result};
+use crate::framing::{check_length, declared_length};
+use bytes::{Buf, BytesMut};
+use tokio_util::codec::{Decoder, Encoder};
+
+/// Largest message accepted by default, in bytes
+pub const DEFAULT_MAX_MESSAGE_LENGTH: usize = 1024 * 1024;
+
+/// Diameter message framing for `tokio_util::codec::Framed`
+///
+/// Splits the stream on the length field of the Diameter header and parses
+/// each message. A message that is framed but does not parse is yielded as
+/// an `Err` item, and the stream stays usable. A framing error, such as an
+/// oversized length, leaves the stream out of sync and ends it.
+#[derive(Debug, Clone)]
+pub struct DiameterCodec {
+    max_length: usize,
+    max_avps: usize,
+}
+
+impl DiameterCodec {
+    /// Create a codec with the default limits
+    pub fn new() -> Self {
+        Self {
+            max_length: DEFAULT_MAX_MESSAGE_LENGTH,
+            max_avps: DEFAULT_MAX_AVPS,
+        }
+    }
+
+    /// Set the largest message accepted, in bytes
+    pub fn with_max_length(mut self, max_length: usize) -> Self {
+        self.max_length = max_length;
+        self
+    }
+
+    /// Set the maximum number of AVPs accepted in a message
+    pub fn with_max_avps(mut self, max_avps: usize) -> Self {
+        self.max_avps = max_avps;
+        self
+    }
+}
+
+impl Default for DiameterCodec {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Decoder for DiameterCodec {
+    type Item = Result<DiameterPacket>;
+    type Error = CddeError;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Result<DiameterPacket>>> {
+        let Some(length) = declared_length(src)? else {
+            return Ok(None);
+        };
+        check_length(length, self.max_length)?;
+
+        if src.len() < length {
+            src.reserve(length - src.len());
+            return Ok(None);
+        }
+
+        let frame = src.split_to(length);
+        Ok(Some(DiameterPacket::parse_with_max_avps(
+            frame.chunk(),
+            self.max_avps,
+        )))
+    }
+}
+
+impl Encoder<DiameterPacket> for DiameterCodec {
+    type Error = CddeError;
+
+    fn encode(&mut self, packet: DiameterPacket, dst: &mut BytesMut) -> Result<()> {
+        packet.serialize_into(dst);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diameter::{DiameterAvp, DiameterHeader};
+
+    fn packet(hop_by_hop_id: u32) -> DiameterPacket {
+        DiameterPacket {
+            header: DiameterHeader {
+                version: 1,
+                length: 0,
+                flags: 0x80,
+                command_code: 280,
+                application_id: 0,
+                hop_by_hop_id,
+                end_to_end_id: 1,
+            },
+            avps: vec![DiameterAvp {
+                code: 264,
+                flags: 0x40,
+                vendor_id: None,
+                data: b"peer.example.com".to_vec(),
+            }],
+        }
+    }
+
+    #[test]
+    fn test_decode_split_frames() {
+        let data = [packet(1).serialize(), packet(2).serialize()].concat();
+        let mut codec = DiameterCodec::new();
+        let mut buffer = BytesMut::new();
+
+        buffer.extend_from_slice(&data[..3]);
+        assert!(codec.decode(&mut buffer).unwrap().is_none());
+
+        buffer.extend_from_slice(&data[3..30]);
+        assert!(codec.decode(&mut buffer).unwrap().is_none());
+
+        // The rest of the first message arrives together with the second
+        buffer.extend_from_slice(&data[30..]);
+        let first = codec.decode(&mut buffer).unwrap().unwrap().unwrap();
+        let second = codec.decode(&mut buffer).unwrap().unwrap().unwrap();
+        assert_eq!(first.header.hop_by_hop_id, 1);
+        assert_eq!(second.header.hop_by_hop_id, 2);
+        assert!(codec.decode(&mut buffer).unwrap().is_none());
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn test_encode_round_trip() {
+        let mut codec = DiameterCodec::new();
+        let mut buffer = BytesMut::new();
+
+        codec.encode(packet(7), &mut buffer).unwrap();
+        let decoded = codec.decode(&mut buffer).unwrap().unwrap().unwrap();
+
+        assert_eq!(decoded.header.hop_by_hop_id, 7);
+        assert_eq!(decoded.avps, packet(7).avps);
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn test_decode_rejects_oversized_message() {
+        let data = packet(1).serialize();
+        let mut codec = DiameterCodec::new().with_max_length(data.len() - 1);
+        let mut buffer = BytesMut::from(&data[..]);
+
+        assert!(codec.decode(&mut buffer).is_err());
+    }
+
+    #[test]
+    fn test_unparsable_message_is_skipped() {
+        let mut malformed = packet(1).serialize();
+        // The AVP claims more data than the message holds
+        malformed[27] = 0xFF;
+        let data = [malformed, packet(2).serialize()].concat();
+        let mut codec = DiameterCodec::new();
+        let mut buffer = BytesMut::from(&data[..]);
+
+        assert!(codec.decode(&mut buffer).unwrap().unwrap().is_err());
+        let next = codec.decode(&mut buffer).unwrap().unwrap().unwrap();
+        assert_eq!(next.header.hop_by_hop_id, 2);
+    }
+}
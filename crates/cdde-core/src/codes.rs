@@ -0,0 +1,136 @@
+//! Well-known Diameter command codes, AVP codes and values (RFC 6733)
+
+// ========================================
+// Command Codes
+// ========================================
+pub const CMD_CAPABILITIES_EXCHANGE: u32 = 257;
+pub const CMD_RE_AUTH: u32 = 258;
+pub const CMD_AA: u32 = 265;
+pub const CMD_ACCOUNTING: u32 = 271;
+pub const CMD_CREDIT_CONTROL: u32 = 272;
+pub const CMD_ABORT_SESSION: u32 = 274;
+pub const CMD_SESSION_TERMINATION: u32 = 275;
+pub const CMD_DEVICE_WATCHDOG: u32 = 280;
+pub const CMD_DISCONNECT_PEER: u32 = 282;
+pub const CMD_UPDATE_LOCATION: u32 = 316;
+pub const CMD_CANCEL_LOCATION: u32 = 317;
+pub const CMD_AUTHENTICATION_INFORMATION: u32 = 318;
+pub const CMD_INSERT_SUBSCRIBER_DATA: u32 = 319;
+pub const CMD_DELETE_SUBSCRIBER_DATA: u32 = 320;
+pub const CMD_PURGE_UE: u32 = 321;
+pub const CMD_RESET: u32 = 322;
+pub const CMD_NOTIFY: u32 = 323;
+
+// ========================================
+// Application Ids
+// ========================================
+pub const APP_DIAMETER_COMMON: u32 = 0;
+pub const APP_BASE_ACCOUNTING: u32 = 3;
+pub const APP_CREDIT_CONTROL: u32 = 4;
+pub const APP_3GPP_CX: u32 = 16777216;
+pub const APP_3GPP_SH: u32 = 16777217;
+pub const APP_3GPP_RX: u32 = 16777236;
+pub const APP_3GPP_GX: u32 = 16777238;
+pub const APP_3GPP_S6A: u32 = 16777251;
+pub const APP_RELAY: u32 = 0xFFFF_FFFF;
+
+// ========================================
+// AVP Codes
+// ========================================
+pub const AVP_PROXY_STATE: u32 = 33;
+pub const AVP_HOST_IP_ADDRESS: u32 = 257;
+pub const AVP_AUTH_APPLICATION_ID: u32 = 258;
+pub const AVP_VENDOR_SPECIFIC_APPLICATION_ID: u32 = 260;
+pub const AVP_SESSION_ID: u32 = 263;
+pub const AVP_ORIGIN_HOST: u32 = 264;
+pub const AVP_VENDOR_ID: u32 = 266;
+pub const AVP_RESULT_CODE: u32 = 268;
+pub const AVP_PRODUCT_NAME: u32 = 269;
+pub const AVP_DISCONNECT_CAUSE: u32 = 273;
+pub const AVP_AUTH_SESSION_STATE: u32 = 277;
+pub const AVP_PROXY_HOST: u32 = 280;
+pub const AVP_DESTINATION_REALM: u32 = 283;
+pub const AVP_PROXY_INFO: u32 = 284;
+pub const AVP_TERMINATION_CAUSE: u32 = 295;
+pub const AVP_DESTINATION_HOST: u32 = 293;
+pub const AVP_ORIGIN_REALM: u32 = 296;
+
+// ========================================
+// Result-Code values
+// ========================================
+pub const RESULT_SUCCESS: u32 = 2001;
+pub const RESULT_COMMAND_UNSUPPORTED: u32 = 3001;
+pub const RESULT_UNABLE_TO_DELIVER: u32 = 3002;
+pub const RESULT_TOO_BUSY: u32 = 3004;
+pub const RESULT_UNKNOWN_PEER: u32 = 5018;
+
+// ========================================
+// Auth-Session-State values
+// ========================================
+pub const AUTH_SESSION_STATE_MAINTAINED: u32 = 0;
+pub const AUTH_SESSION_NO_STATE_MAINTAINED: u32 = 1;
+
+/// Name of a well-known command without its Request/Answer suffix, for logs
+///
+/// Covers the base protocol and the common S6a, Gx and Rx commands.
+pub fn command_name(command_code: u32) -> Option<&'static str> {
+    let name = match command_code {
+        CMD_CAPABILITIES_EXCHANGE => "Capabilities-Exchange",
+        CMD_RE_AUTH => "Re-Auth",
+        CMD_AA => "AA",
+        CMD_ACCOUNTING => "Accounting",
+        CMD_CREDIT_CONTROL => "Credit-Control",
+        CMD_ABORT_SESSION => "Abort-Session",
+        CMD_SESSION_TERMINATION => "Session-Termination",
+        CMD_DEVICE_WATCHDOG => "Device-Watchdog",
+        CMD_DISCONNECT_PEER => "Disconnect-Peer",
+        CMD_UPDATE_LOCATION => "Update-Location",
+        CMD_CANCEL_LOCATION => "Cancel-Location",
+        CMD_AUTHENTICATION_INFORMATION => "Authentication-Information",
+        CMD_INSERT_SUBSCRIBER_DATA => "Insert-Subscriber-Data",
+        CMD_DELETE_SUBSCRIBER_DATA => "Delete-Subscriber-Data",
+        CMD_PURGE_UE => "Purge-UE",
+        CMD_RESET => "Reset",
+        CMD_NOTIFY => "Notify",
+        _ => return None,
+    };
+    Some(name)
+}
+
+/// Human-readable name of a well-known application id, for logs
+pub fn application_name(app_id: u32) -> Option<&'static str> {
+    let name = match app_id {
+        APP_DIAMETER_COMMON => "Diameter Common Messages",
+        APP_BASE_ACCOUNTING => "Diameter Base Accounting",
+        APP_CREDIT_CONTROL => "Diameter Credit Control (Gy)",
+        APP_3GPP_CX => "3GPP Cx",
+        APP_3GPP_SH => "3GPP Sh",
+        APP_3GPP_RX => "3GPP Rx",
+        APP_3GPP_GX => "3GPP Gx",
+        APP_3GPP_S6A => "3GPP S6a/S6d",
+        APP_RELAY => "Relay",
+        _ => return None,
+    };
+    Some(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_application_name() {
+        assert_eq!(application_name(16777251), Some("3GPP S6a/S6d"));
+        assert_eq!(application_name(APP_3GPP_GX), Some("3GPP Gx"));
+        assert_eq!(application_name(3), Some("Diameter Base Accounting"));
+        assert_eq!(application_name(16777999), None);
+    }
+
+    #[test]
+    fn test_command_name() {
+        assert_eq!(command_name(257), Some("Capabilities-Exchange"));
+        assert_eq!(command_name(CMD_CREDIT_CONTROL), Some("Credit-Control"));
+        assert_eq!(command_name(318), Some("Authentication-Information"));
+        assert_eq!(command_name(8388620), None);
+    }
+}
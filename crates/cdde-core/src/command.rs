@@ -0,0 +1,180 @@
+//! Required AVPs of the RFC 6733 base protocol commands
+//!
+//! A lightweight subset of the command ABNF: only the fixed and required
+//! AVPs are checked, not their order, multiplicity or optional AVPs.
+
+use crate::codes::*;
+use crate::diameter::DiameterPacket;
+
+/// Required AVPs of a command in one direction
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CommandSpec {
+    /// Command code
+    pub command_code: u32,
+
+    /// Whether the spec applies to the request or the answer
+    pub request: bool,
+
+    /// AVP codes that must be present
+    pub required: &'static [u32],
+}
+
+/// Base protocol command dictionary
+pub const BASE_COMMANDS: &[CommandSpec] = &[
+    CommandSpec {
+        command_code: CMD_CAPABILITIES_EXCHANGE,
+        request: true,
+        required: &[
+            AVP_ORIGIN_HOST,
+            AVP_ORIGIN_REALM,
+            AVP_HOST_IP_ADDRESS,
+            AVP_VENDOR_ID,
+            AVP_PRODUCT_NAME,
+        ],
+    },
+    CommandSpec {
+        command_code: CMD_CAPABILITIES_EXCHANGE,
+        request: false,
+        required: &[
+            AVP_RESULT_CODE,
+            AVP_ORIGIN_HOST,
+            AVP_ORIGIN_REALM,
+            AVP_HOST_IP_ADDRESS,
+            AVP_VENDOR_ID,
+            AVP_PRODUCT_NAME,
+        ],
+    },
+    CommandSpec {
+        command_code: CMD_DEVICE_WATCHDOG,
+        request: true,
+        required: &[AVP_ORIGIN_HOST, AVP_ORIGIN_REALM],
+    },
+    CommandSpec {
+        command_code: CMD_DEVICE_WATCHDOG,
+        request: false,
+        required: &[AVP_RESULT_CODE, AVP_ORIGIN_HOST, AVP_ORIGIN_REALM],
+    },
+    CommandSpec {
+        command_code: CMD_DISCONNECT_PEER,
+        request: true,
+        required: &[AVP_ORIGIN_HOST, AVP_ORIGIN_REALM, AVP_DISCONNECT_CAUSE],
+    },
+    CommandSpec {
+        command_code: CMD_DISCONNECT_PEER,
+        request: false,
+        required: &[AVP_RESULT_CODE, AVP_ORIGIN_HOST, AVP_ORIGIN_REALM],
+    },
+    CommandSpec {
+        command_code: CMD_SESSION_TERMINATION,
+        request: true,
+        required: &[
+            AVP_SESSION_ID,
+            AVP_ORIGIN_HOST,
+            AVP_ORIGIN_REALM,
+            AVP_DESTINATION_REALM,
+            AVP_AUTH_APPLICATION_ID,
+            AVP_TERMINATION_CAUSE,
+        ],
+    },
+    CommandSpec {
+        command_code: CMD_SESSION_TERMINATION,
+        request: false,
+        required: &[
+            AVP_SESSION_ID,
+            AVP_RESULT_CODE,
+            AVP_ORIGIN_HOST,
+            AVP_ORIGIN_REALM,
+        ],
+    },
+];
+
+/// Look up the spec for a command in the given direction
+pub fn command_spec(command_code: u32, request: bool) -> Option<&'static CommandSpec> {
+    BASE_COMMANDS
+        .iter()
+        .find(|spec| spec.command_code == command_code && spec.request == request)
+}
+
+/// Check a message against the command dictionary
+///
+/// Returns the codes of missing required AVPs. Commands not in the
+/// dictionary always pass.
+pub fn validate_command(packet: &DiameterPacket) -> Result<(), Vec<u32>> {
+    let Some(spec) = command_spec(packet.header.command_code, packet.header.is_request()) else {
+        return Ok(());
+    };
+
+    let missing: Vec<u32> = spec
+        .required
+        .iter()
+        .copied()
+        .filter(|code| packet.find_avp(*code).is_none())
+        .collect();
+
+    if missing.is_empty() {
+        Ok(())
+    } else {
+        Err(missing)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diameter::{DiameterAvp, DiameterHeader, FLAG_REQUEST};
+
+    fn packet(command_code: u32, flags: u8, codes: &[u32]) -> DiameterPacket {
+        DiameterPacket {
+            header: DiameterHeader {
+                version: 1,
+                length: 0,
+                flags,
+                command_code,
+                application_id: 0,
+                hop_by_hop_id: 1,
+                end_to_end_id: 1,
+            },
+            avps: codes
+                .iter()
+                .map(|code| DiameterAvp {
+                    code: *code,
+                    flags: 0x40,
+                    vendor_id: None,
+                    data: vec![0; 4],
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_cer_missing_product_name() {
+        let cer = packet(
+            CMD_CAPABILITIES_EXCHANGE,
+            FLAG_REQUEST,
+            &[
+                AVP_ORIGIN_HOST,
+                AVP_ORIGIN_REALM,
+                AVP_HOST_IP_ADDRESS,
+                AVP_VENDOR_ID,
+            ],
+        );
+        assert_eq!(validate_command(&cer), Err(vec![AVP_PRODUCT_NAME]));
+    }
+
+    #[test]
+    fn test_complete_and_unknown_commands_pass() {
+        let dwa = packet(
+            CMD_DEVICE_WATCHDOG,
+            0,
+            &[AVP_RESULT_CODE, AVP_ORIGIN_HOST, AVP_ORIGIN_REALM],
+        );
+        assert_eq!(validate_command(&dwa), Ok(()));
+
+        // Applications are not described by the base dictionary
+        assert_eq!(validate_command(&packet(316, FLAG_REQUEST, &[])), Ok(()));
+
+        // Request and answer specs are distinct
+        let dwr = packet(CMD_DEVICE_WATCHDOG, FLAG_REQUEST, &[AVP_ORIGIN_HOST]);
+        assert_eq!(validate_command(&dwr), Err(vec![AVP_ORIGIN_REALM]));
+    }
+}
@@ -0,0 +1,127 @@
+use crate::codes::{application_name, command_name};
+use crate::diameter::{DiameterAvp, DiameterPacket};
+use crate::json::{hex, value_to_json};
+use cdde_diameter_dict::{AvpDataType, DictionaryManager};
+use std::fmt;
+
+impl DiameterPacket {
+    /// Render the packet on one line for logs
+    ///
+    /// Commands, applications and AVPs are named where known and AVP values
+    /// decoded through the dictionary, e.g.
+    /// `Command=Device-Watchdog-Answer app=Diameter Common Messages avps=[Result-Code=2001]`.
+    pub fn describe(&self, dict: &DictionaryManager) -> String {
+        let direction = if self.header.is_request() {
+            "Request"
+        } else {
+            "Answer"
+        };
+        let command = match command_name(self.header.command_code) {
+            Some(name) => format!("{name}-{direction}"),
+            None => format!("{}-{}", self.header.command_code, direction),
+        };
+        let app = match application_name(self.header.application_id) {
+            Some(name) => name.to_string(),
+            None => self.header.application_id.to_string(),
+        };
+
+        format!(
+            "Command={} app={} avps=[{}]",
+            command,
+            app,
+            describe_avps(&self.avps, dict)
+        )
+    }
+}
+
+/// Rendered with the standard dictionary only
+impl fmt::Display for DiameterPacket {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.describe(&DictionaryManager::new()))
+    }
+}
+
+fn describe_avps(avps: &[DiameterAvp], dict: &DictionaryManager) -> String {
+    avps.iter()
+        .map(|avp| describe_avp(avp, dict))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn describe_avp(avp: &DiameterAvp, dict: &DictionaryManager) -> String {
+    let Some(info) = dict.lookup(avp.code) else {
+        return format!("{}=0x{}", avp.code, hex(&avp.data));
+    };
+
+    let value = if info.data_type == AvpDataType::Grouped {
+        match parse_members(&avp.data) {
+            Some(members) => format!("{{{}}}", describe_avps(&members, dict)),
+            None => format!("0x{}", hex(&avp.data)),
+        }
+    } else {
+        match info.data_type.parse(&avp.data) {
+            Ok(value) => value_to_json(value).to_string(),
+            Err(_) => format!("0x{}", hex(&avp.data)),
+        }
+    };
+
+    format!("{}={}", info.name, value)
+}
+
+fn parse_members(data: &[u8]) -> Option<Vec<DiameterAvp>> {
+    let mut members = Vec::new();
+    let mut offset = 0;
+
+    while offset < data.len() {
+        let (avp, length) = DiameterAvp::parse(&data[offset..]).ok()?;
+        members.push(avp);
+        offset += length;
+    }
+
+    Some(members)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diameter::{DiameterHeader, AVP_FLAG_MANDATORY};
+
+    fn avp(code: u32, data: Vec<u8>) -> DiameterAvp {
+        DiameterAvp {
+            code,
+            flags: AVP_FLAG_MANDATORY,
+            vendor_id: None,
+            data,
+        }
+    }
+
+    #[test]
+    fn test_describe_names_and_decodes() {
+        let packet = DiameterPacket {
+            header: DiameterHeader {
+                version: 1,
+                length: 20,
+                flags: 0x40,
+                command_code: 272,
+                application_id: 4,
+                hop_by_hop_id: 1,
+                end_to_end_id: 2,
+            },
+            avps: vec![
+                avp(263, b"gx;1".to_vec()),
+                avp(268, 2001u32.to_be_bytes().to_vec()),
+                avp(99999, vec![0xde, 0xad]),
+            ],
+        };
+
+        assert_eq!(
+            packet.describe(&DictionaryManager::new()),
+            "Command=Credit-Control-Answer app=Diameter Credit Control (Gy) \
+             avps=[Session-Id=\"gx;1\", Result-Code=2001, 99999=0xdead]"
+        );
+        assert_eq!(
+            packet.to_string(),
+            packet.describe(&DictionaryManager::new())
+        );
+    }
+}
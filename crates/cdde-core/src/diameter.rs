@@ -1,4 +1,7 @@
+use crate::codes;
 use crate::error::{CddeError, Result};
+use crate::framing::check_length;
+use bytes::{BufMut, Bytes, BytesMut};
 
 /// Diameter packet header (20 bytes)
 #[derive(Debug, Clone, PartialEq)]
@@ -22,7 +25,7 @@ pub struct DiameterAvp {
 }
 
 /// Complete Diameter packet
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct DiameterPacket {
     pub header: DiameterHeader,
     pub avps: Vec<DiameterAvp>,
@@ -34,11 +37,90 @@ pub const FLAG_PROXIABLE: u8 = 0x40;
 pub const FLAG_ERROR: u8 = 0x20;
 pub const FLAG_RETRANSMIT: u8 = 0x10;
 
+/// Set the T flag on a serialized request that is being sent again
+///
+/// RFC 6733 requires it on requests retransmitted after a failover so the
+/// receiver can detect duplicates. Anything shorter than a header is left
+/// untouched.
+pub fn mark_retransmitted(message: &mut [u8]) {
+    if message.len() >= 20 {
+        message[4] |= FLAG_RETRANSMIT;
+    }
+}
+
+/// Build a Vendor-Specific-Application-Id advertising `auth_app_id` of `vendor_id`
+///
+/// 3GPP applications such as S6a and Gx are advertised this way in the
+/// capabilities exchange rather than with a bare Auth-Application-Id.
+pub fn vendor_specific_app_id(vendor_id: u32, auth_app_id: u32) -> DiameterAvp {
+    let members = [
+        DiameterAvp {
+            code: codes::AVP_VENDOR_ID,
+            flags: AVP_FLAG_MANDATORY,
+            vendor_id: None,
+            data: vendor_id.to_be_bytes().to_vec(),
+        },
+        DiameterAvp {
+            code: codes::AVP_AUTH_APPLICATION_ID,
+            flags: AVP_FLAG_MANDATORY,
+            vendor_id: None,
+            data: auth_app_id.to_be_bytes().to_vec(),
+        },
+    ];
+    DiameterAvp::from_grouped(
+        codes::AVP_VENDOR_SPECIFIC_APPLICATION_ID,
+        AVP_FLAG_MANDATORY,
+        None,
+        &members,
+    )
+}
+
+/// Default limit on the number of top-level AVPs in a parsed message
+pub const DEFAULT_MAX_AVPS: usize = 1024;
+
 // AVP flags
 pub const AVP_FLAG_VENDOR: u8 = 0x80;
 pub const AVP_FLAG_MANDATORY: u8 = 0x40;
 pub const AVP_FLAG_PROTECTED: u8 = 0x20;
 
+/// AVP placement used by [`DiameterPacket::serialize_ordered`]
+///
+/// AVPs listed here are emitted first, in the listed order; all other AVPs
+/// follow in their original order.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AvpOrder {
+    leading: Vec<u32>,
+}
+
+impl AvpOrder {
+    /// Create an ordering from the AVP codes to place first
+    pub fn new(leading: Vec<u32>) -> Self {
+        Self { leading }
+    }
+
+    /// Position of an AVP code in the ordering
+    fn rank(&self, code: u32) -> usize {
+        self.leading
+            .iter()
+            .position(|c| *c == code)
+            .unwrap_or(self.leading.len())
+    }
+}
+
+impl Default for AvpOrder {
+    /// Session-Id first, then the identity and result AVPs
+    fn default() -> Self {
+        Self::new(vec![
+            codes::AVP_SESSION_ID,
+            codes::AVP_ORIGIN_HOST,
+            codes::AVP_ORIGIN_REALM,
+            codes::AVP_DESTINATION_HOST,
+            codes::AVP_DESTINATION_REALM,
+            codes::AVP_RESULT_CODE,
+        ])
+    }
+}
+
 impl DiameterHeader {
     /// Parse header from bytes
     pub fn parse(data: &[u8]) -> Result<Self> {
@@ -92,6 +174,17 @@ impl DiameterHeader {
         bytes
     }
 
+    /// Write header into `buf` without allocating
+    pub fn serialize_into(&self, buf: &mut BytesMut) {
+        buf.put_u8(self.version);
+        buf.put_slice(&self.length.to_be_bytes()[1..4]);
+        buf.put_u8(self.flags);
+        buf.put_slice(&self.command_code.to_be_bytes()[1..4]);
+        buf.put_u32(self.application_id);
+        buf.put_u32(self.hop_by_hop_id);
+        buf.put_u32(self.end_to_end_id);
+    }
+
     /// Check if this is a request
     pub fn is_request(&self) -> bool {
         (self.flags & FLAG_REQUEST) != 0
@@ -103,9 +196,38 @@ impl DiameterHeader {
     }
 }
 
-impl DiameterAvp {
-    /// Parse AVP from bytes
-    pub fn parse(data: &[u8]) -> Result<(Self, usize)> {
+/// How strictly AVP padding is checked when parsing
+///
+/// RFC 6733 requires every AVP to be padded to a 4-byte boundary with
+/// zero bytes, but some peers omit the padding of the last AVP or fill it
+/// with garbage.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PaddingMode {
+    /// Accept missing padding and any padding bytes
+    #[default]
+    Lenient,
+    /// Require the padding bytes to be present
+    Present,
+    /// Require the padding bytes to be present and zero
+    Zero,
+}
+
+/// Borrowed view of an encoded AVP
+struct RawAvp<'a> {
+    code: u32,
+    flags: u8,
+    vendor_id: Option<u32>,
+    data: &'a [u8],
+}
+
+impl<'a> RawAvp<'a> {
+    /// Split the AVP at the start of `data`, returning it with its padded length
+    fn split(data: &'a [u8]) -> Result<(Self, usize)> {
+        Self::split_with_padding(data, PaddingMode::Lenient)
+    }
+
+    /// Split the AVP at the start of `data`, checking its padding per `padding`
+    fn split_with_padding(data: &'a [u8], padding: PaddingMode) -> Result<(Self, usize)> {
         if data.len() < 8 {
             return Err(CddeError::InvalidPacket("AVP too short".to_string()));
         }
@@ -130,33 +252,127 @@ impl DiameterAvp {
             None
         };
 
-        let data_length = length - offset;
+        // A vendor AVP declaring a length of 8..=11 is shorter than its own header
+        let data_length = length.checked_sub(offset).ok_or_else(|| {
+            CddeError::InvalidPacket(format!(
+                "AVP length {length} shorter than its {offset}-byte header"
+            ))
+        })?;
         if data.len() < offset + data_length {
             return Err(CddeError::InvalidPacket("AVP data truncated".to_string()));
         }
 
-        let avp_data = data[offset..offset + data_length].to_vec();
-
         // Calculate padding (align to 4 bytes)
         let padded_length = length.div_ceil(4) * 4;
+        if padding != PaddingMode::Lenient {
+            let Some(pad) = data.get(length..padded_length) else {
+                return Err(CddeError::InvalidPacket(format!(
+                    "AVP {code} is missing {} bytes of padding",
+                    padded_length - data.len()
+                )));
+            };
+            if padding == PaddingMode::Zero && pad.iter().any(|&b| b != 0) {
+                return Err(CddeError::InvalidPacket(format!(
+                    "AVP {code} has non-zero padding"
+                )));
+            }
+        }
 
         Ok((
             Self {
                 code,
                 flags,
                 vendor_id,
-                data: avp_data,
+                data: &data[offset..offset + data_length],
             },
             padded_length,
         ))
     }
+}
+
+impl DiameterAvp {
+    /// Parse AVP from bytes, accepting missing or non-zero padding
+    pub fn parse(data: &[u8]) -> Result<(Self, usize)> {
+        Self::parse_with_padding(data, PaddingMode::Lenient)
+    }
+
+    /// Parse AVP from bytes, checking its padding per `padding`
+    pub fn parse_with_padding(data: &[u8], padding: PaddingMode) -> Result<(Self, usize)> {
+        let (raw, padded_length) = RawAvp::split_with_padding(data, padding)?;
+
+        Ok((
+            Self {
+                code: raw.code,
+                flags: raw.flags,
+                vendor_id: raw.vendor_id,
+                data: raw.data.to_vec(),
+            },
+            padded_length,
+        ))
+    }
+
+    /// Build a Grouped AVP from its members
+    ///
+    /// Every member is padded to a 4-byte boundary, the last one included,
+    /// so the group's length covers the padded member sizes (RFC 6733 4.4).
+    pub fn from_grouped(
+        code: u32,
+        flags: u8,
+        vendor_id: Option<u32>,
+        members: &[DiameterAvp],
+    ) -> Self {
+        let mut data = BytesMut::with_capacity(members.iter().map(Self::padded_len).sum());
+        for member in members {
+            member.serialize_into(&mut data);
+        }
+        Self {
+            code,
+            flags,
+            vendor_id,
+            data: data.to_vec(),
+        }
+    }
+
+    /// Parse the members of a Grouped AVP
+    ///
+    /// Each member is skipped by its padded length; padding missing after
+    /// the last member is tolerated. Members that are Grouped themselves,
+    /// e.g. inside Subscription-Data, are parsed by calling this on them.
+    pub fn parse_grouped(&self) -> Result<Vec<DiameterAvp>> {
+        let mut members = Vec::new();
+        let mut offset = 0;
+        while offset < self.data.len() {
+            let (member, length) = Self::parse(&self.data[offset..])?;
+            members.push(member);
+            offset += length;
+        }
+        Ok(members)
+    }
+
+    /// Compare two AVPs by meaning
+    ///
+    /// Code, vendor and data identify the value, and the Mandatory bit
+    /// decides whether a receiver may ignore it. The Protected bit does not
+    /// change the value and the Vendor-Specific bit follows from the vendor
+    /// id, so they are ignored.
+    pub fn semantic_eq(&self, other: &DiameterAvp) -> bool {
+        self.code == other.code
+            && self.vendor_id == other.vendor_id
+            && self.is_mandatory() == other.is_mandatory()
+            && self.data == other.data
+    }
+
+    /// Whether the Mandatory bit is set
+    pub fn is_mandatory(&self) -> bool {
+        self.flags & AVP_FLAG_MANDATORY != 0
+    }
 
     /// Serialize AVP to bytes
     pub fn serialize(&self) -> Vec<u8> {
         let mut bytes = Vec::new();
 
         bytes.extend_from_slice(&self.code.to_be_bytes());
-        bytes.push(self.flags);
+        bytes.push(self.wire_flags());
 
         let data_offset = if self.vendor_id.is_some() { 12 } else { 8 };
         let length = data_offset + self.data.len();
@@ -176,22 +392,68 @@ impl DiameterAvp {
 
         bytes
     }
+
+    /// Write AVP into `buf` without allocating
+    pub fn serialize_into(&self, buf: &mut BytesMut) {
+        let data_offset = if self.vendor_id.is_some() { 12 } else { 8 };
+        let length = data_offset + self.data.len();
+
+        buf.put_u32(self.code);
+        buf.put_u8(self.wire_flags());
+        buf.put_slice(&(length as u32).to_be_bytes()[1..4]);
+        if let Some(vid) = self.vendor_id {
+            buf.put_u32(vid);
+        }
+        buf.put_slice(&self.data);
+        buf.put_bytes(0, self.padded_len() - length);
+    }
+
+    /// Flags as serialized, with the Vendor-Specific bit set exactly when
+    /// a vendor id is written, so the AVP parses back the same way
+    fn wire_flags(&self) -> u8 {
+        match self.vendor_id {
+            Some(_) => self.flags | AVP_FLAG_VENDOR,
+            None => self.flags & !AVP_FLAG_VENDOR,
+        }
+    }
+
+    /// Length of the serialized AVP, padding included
+    fn padded_len(&self) -> usize {
+        let data_offset = if self.vendor_id.is_some() { 12 } else { 8 };
+        (data_offset + self.data.len()).div_ceil(4) * 4
+    }
 }
 
 impl DiameterPacket {
     /// Parse complete packet from bytes
     pub fn parse(data: &[u8]) -> Result<Self> {
-        let header = DiameterHeader::parse(data)?;
+        Self::parse_with_max_avps(data, DEFAULT_MAX_AVPS)
+    }
 
-        if data.len() < header.length as usize {
-            return Err(CddeError::InvalidPacket("Packet truncated".to_string()));
-        }
+    /// Parse complete packet, rejecting messages with more than `max_avps` AVPs
+    pub fn parse_with_max_avps(data: &[u8], max_avps: usize) -> Result<Self> {
+        Self::parse_avps(data, max_avps, PaddingMode::Lenient)
+    }
 
+    /// Parse complete packet, checking the padding of every AVP per `padding`
+    pub fn parse_with_padding(data: &[u8], padding: PaddingMode) -> Result<Self> {
+        Self::parse_avps(data, DEFAULT_MAX_AVPS, padding)
+    }
+
+    fn parse_avps(data: &[u8], max_avps: usize, padding: PaddingMode) -> Result<Self> {
+        let header = DiameterHeader::parse(data)?;
+        let data = Self::message_bytes(data, header.length)?;
+        let length = data.len();
         let mut avps = Vec::new();
         let mut offset = 20;
 
-        while offset < header.length as usize {
-            let (avp, avp_length) = DiameterAvp::parse(&data[offset..])?;
+        while offset < length {
+            if avps.len() == max_avps {
+                return Err(CddeError::InvalidPacket(format!(
+                    "Too many AVPs (limit {max_avps})"
+                )));
+            }
+            let (avp, avp_length) = DiameterAvp::parse_with_padding(&data[offset..], padding)?;
             avps.push(avp);
             offset += avp_length;
         }
@@ -199,13 +461,118 @@ impl DiameterPacket {
         Ok(Self { header, avps })
     }
 
+    /// Parse complete packet, rejecting messages declaring more than `max_len` bytes
+    ///
+    /// The declared length is checked before anything else, so a crafted
+    /// header cannot make the caller wait for or buffer a huge message.
+    pub fn parse_with_limit(data: &[u8], max_len: usize) -> Result<Self> {
+        let header = DiameterHeader::parse(data)?;
+        check_length(header.length as usize, max_len)?;
+        Self::parse(data)
+    }
+
+    /// Parse only the header, validating the message length against `data`
+    pub fn peek_header(data: &[u8]) -> Result<DiameterHeader> {
+        let header = DiameterHeader::parse(data)?;
+        Self::message_bytes(data, header.length)?;
+        Ok(header)
+    }
+
+    /// Find the data of the first top-level AVP with `code` without a full parse
+    ///
+    /// AVPs before the match are only skipped over, so nothing is allocated.
+    /// Returns `None` if the message has no such AVP.
+    pub fn find_top_level_avp(data: &[u8], code: u32) -> Result<Option<&[u8]>> {
+        let header = DiameterHeader::parse(data)?;
+        let data = Self::message_bytes(data, header.length)?;
+
+        let mut offset = 20;
+        while offset < data.len() {
+            let (avp, avp_length) = RawAvp::split(&data[offset..])?;
+            if avp.code == code {
+                return Ok(Some(avp.data));
+            }
+            offset += avp_length;
+        }
+
+        Ok(None)
+    }
+
+    /// Bytes of the message described by the header length field
+    ///
+    /// AVPs must not extend past the message length.
+    fn message_bytes(data: &[u8], length: u32) -> Result<&[u8]> {
+        let length = length as usize;
+        if length < 20 {
+            return Err(CddeError::InvalidPacket(format!(
+                "Invalid message length: {length}"
+            )));
+        }
+        if data.len() < length {
+            return Err(CddeError::InvalidPacket("Packet truncated".to_string()));
+        }
+        Ok(&data[..length])
+    }
+
     /// Serialize packet to bytes
     pub fn serialize(&self) -> Vec<u8> {
+        self.serialize_avps(self.avps.iter())
+    }
+
+    /// Length of the serialized packet, header included
+    pub fn encoded_len(&self) -> usize {
+        20 + self.avps.iter().map(DiameterAvp::padded_len).sum::<usize>()
+    }
+
+    /// Serialize packet into shared bytes
+    pub fn to_bytes(&self) -> Bytes {
+        let mut buf = BytesMut::new();
+        self.serialize_into(&mut buf);
+        buf.freeze()
+    }
+
+    /// Serialize packet followed by `extra` AVPs
+    ///
+    /// Lets a relay append AVPs such as Proxy-Info on the way out without
+    /// cloning the packet first.
+    pub fn serialize_with(&self, extra: &[DiameterAvp]) -> Vec<u8> {
+        self.serialize_avps(self.avps.iter().chain(extra))
+    }
+
+    /// Serialize packet with AVPs placed according to `order`
+    ///
+    /// Intended for peers with strict AVP ordering expectations; AVPs with
+    /// the same rank (e.g. Route-Record) keep their relative order.
+    pub fn serialize_ordered(&self, order: &AvpOrder) -> Vec<u8> {
+        let mut avps: Vec<&DiameterAvp> = self.avps.iter().collect();
+        avps.sort_by_key(|avp| order.rank(avp.code));
+        self.serialize_avps(avps.into_iter())
+    }
+
+    /// Write packet into `buf` without intermediate allocations
+    ///
+    /// The header length is computed from the AVPs, as with `serialize`, so
+    /// a reused buffer only grows when a message is larger than any before.
+    pub fn serialize_into(&self, buf: &mut BytesMut) {
+        let total_length = self.encoded_len();
+        buf.reserve(total_length);
+
+        let header = DiameterHeader {
+            length: total_length as u32,
+            ..self.header.clone()
+        };
+        header.serialize_into(buf);
+        for avp in &self.avps {
+            avp.serialize_into(buf);
+        }
+    }
+
+    fn serialize_avps<'a>(&self, avps: impl Iterator<Item = &'a DiameterAvp>) -> Vec<u8> {
         let mut bytes = Vec::new();
 
         // Serialize AVPs first to calculate total length
         let mut avp_bytes = Vec::new();
-        for avp in &self.avps {
+        for avp in avps {
             avp_bytes.extend_from_slice(&avp.serialize());
         }
 
@@ -220,6 +587,55 @@ impl DiameterPacket {
         bytes
     }
 
+    /// Turn a request into its answer
+    ///
+    /// The answer keeps the command, application and ids of the request and
+    /// only its Session-Id, followed by Result-Code, Origin-Host and
+    /// Origin-Realm, and its Proxy-Info AVPs in order (RFC 6733 §6.2).
+    /// Protocol errors (3xxx) set the E bit.
+    pub fn into_answer(
+        self,
+        result_code: u32,
+        origin_host: &str,
+        origin_realm: &str,
+    ) -> DiameterPacket {
+        let avp = |code, data: &[u8]| DiameterAvp {
+            code,
+            flags: AVP_FLAG_MANDATORY,
+            vendor_id: None,
+            data: data.to_vec(),
+        };
+
+        // Session-Id and Proxy-Info must be echoed back when present
+        let (proxy_info, rest): (Vec<DiameterAvp>, Vec<DiameterAvp>) = self
+            .avps
+            .into_iter()
+            .partition(|avp| avp.code == codes::AVP_PROXY_INFO);
+        let mut avps: Vec<DiameterAvp> = rest
+            .into_iter()
+            .filter(|avp| avp.code == codes::AVP_SESSION_ID)
+            .take(1)
+            .collect();
+        avps.push(avp(codes::AVP_RESULT_CODE, &result_code.to_be_bytes()));
+        avps.push(avp(codes::AVP_ORIGIN_HOST, origin_host.as_bytes()));
+        avps.push(avp(codes::AVP_ORIGIN_REALM, origin_realm.as_bytes()));
+        avps.extend(proxy_info);
+
+        let mut flags = self.header.flags & FLAG_PROXIABLE;
+        if (3000..4000).contains(&result_code) {
+            flags |= FLAG_ERROR;
+        }
+
+        DiameterPacket {
+            header: DiameterHeader {
+                length: 0,
+                flags,
+                ..self.header
+            },
+            avps,
+        }
+    }
+
     /// Find AVP by code
     pub fn find_avp(&self, code: u32) -> Option<&DiameterAvp> {
         self.avps.iter().find(|avp| avp.code == code)
@@ -229,6 +645,45 @@ impl DiameterPacket {
     pub fn find_all_avps(&self, code: u32) -> Vec<&DiameterAvp> {
         self.avps.iter().filter(|avp| avp.code == code).collect()
     }
+
+    /// IP addresses of all Host-IP-Address AVPs, skipping undecodable ones
+    ///
+    /// Multihomed peers advertise one Host-IP-Address per address.
+    pub fn host_ip_addresses(&self) -> Vec<std::net::IpAddr> {
+        self.find_all_avps(codes::AVP_HOST_IP_ADDRESS)
+            .into_iter()
+            .filter_map(|avp| crate::address::decode_address(&avp.data))
+            .collect()
+    }
+
+    /// Compare two messages by content
+    ///
+    /// Hop-by-Hop and End-to-End ids change from hop to hop and the header
+    /// length is derived, so they are ignored; AVPs compare as a multiset
+    /// using [`DiameterAvp::semantic_eq`].
+    pub fn content_eq(&self, other: &DiameterPacket) -> bool {
+        let (a, b) = (&self.header, &other.header);
+        if a.version != b.version
+            || a.flags != b.flags
+            || a.command_code != b.command_code
+            || a.application_id != b.application_id
+            || self.avps.len() != other.avps.len()
+        {
+            return false;
+        }
+
+        sorted_avps(&self.avps)
+            .into_iter()
+            .zip(sorted_avps(&other.avps))
+            .all(|(a, b)| a.semantic_eq(b))
+    }
+}
+
+/// AVPs in a canonical order, for order-insensitive comparison
+fn sorted_avps(avps: &[DiameterAvp]) -> Vec<&DiameterAvp> {
+    let mut avps: Vec<&DiameterAvp> = avps.iter().collect();
+    avps.sort_by_key(|avp| (avp.code, avp.vendor_id, avp.is_mandatory(), &avp.data));
+    avps
 }
 
 #[cfg(test)]
@@ -292,4 +747,531 @@ mod tests {
         assert_eq!(packet.avps.len(), 1);
         assert_eq!(packet.avps[0].code, 264);
     }
+
+    #[test]
+    fn test_serialize_ordered() {
+        let avp = |code: u32, data: &[u8]| DiameterAvp {
+            code,
+            flags: AVP_FLAG_MANDATORY,
+            vendor_id: None,
+            data: data.to_vec(),
+        };
+        let packet = DiameterPacket {
+            header: DiameterHeader {
+                version: 1,
+                length: 0,
+                flags: 0x80,
+                command_code: 316,
+                application_id: 16777251,
+                hop_by_hop_id: 1,
+                end_to_end_id: 2,
+            },
+            avps: vec![
+                avp(282, b"relay1"),
+                avp(296, b"example.com"),
+                avp(263, b"session;1"),
+                avp(282, b"relay2"),
+                avp(264, b"mme.example.com"),
+            ],
+        };
+
+        let ordered =
+            DiameterPacket::parse(&packet.serialize_ordered(&AvpOrder::default())).unwrap();
+        let codes: Vec<u32> = ordered.avps.iter().map(|a| a.code).collect();
+        assert_eq!(codes, vec![263, 264, 296, 282, 282]);
+        assert_eq!(ordered.avps[3].data, b"relay1");
+        assert_eq!(ordered.avps[4].data, b"relay2");
+
+        // Default serialization keeps insertion order
+        let plain = DiameterPacket::parse(&packet.serialize()).unwrap();
+        assert_eq!(plain.avps, packet.avps);
+    }
+
+    #[test]
+    fn test_max_avps_limit() {
+        let packet = DiameterPacket {
+            header: DiameterHeader {
+                version: 1,
+                length: 0,
+                flags: FLAG_REQUEST,
+                command_code: 316,
+                application_id: 16777251,
+                hop_by_hop_id: 1,
+                end_to_end_id: 2,
+            },
+            avps: (0..5)
+                .map(|i| DiameterAvp {
+                    code: 1000 + i,
+                    flags: 0,
+                    vendor_id: None,
+                    data: vec![],
+                })
+                .collect(),
+        };
+        let bytes = packet.serialize();
+
+        assert_eq!(
+            DiameterPacket::parse_with_max_avps(&bytes, 5)
+                .unwrap()
+                .avps
+                .len(),
+            5
+        );
+        assert!(matches!(
+            DiameterPacket::parse_with_max_avps(&bytes, 4),
+            Err(CddeError::InvalidPacket(_))
+        ));
+        assert!(DiameterPacket::parse(&bytes).is_ok());
+    }
+
+    #[test]
+    fn test_peek_matches_full_parse() {
+        let packet = DiameterPacket {
+            header: DiameterHeader {
+                version: 1,
+                length: 0,
+                flags: FLAG_REQUEST | FLAG_PROXIABLE,
+                command_code: 316,
+                application_id: 16777251,
+                hop_by_hop_id: 7,
+                end_to_end_id: 8,
+            },
+            avps: vec![
+                DiameterAvp {
+                    code: codes::AVP_SESSION_ID,
+                    flags: AVP_FLAG_MANDATORY,
+                    vendor_id: None,
+                    data: b"mme;1;2".to_vec(),
+                },
+                DiameterAvp {
+                    code: 628,
+                    flags: AVP_FLAG_VENDOR,
+                    vendor_id: Some(10415),
+                    data: vec![1, 2, 3],
+                },
+                DiameterAvp {
+                    code: codes::AVP_DESTINATION_REALM,
+                    flags: AVP_FLAG_MANDATORY,
+                    vendor_id: None,
+                    data: b"hss.example.com".to_vec(),
+                },
+            ],
+        };
+        let bytes = packet.serialize();
+        let parsed = DiameterPacket::parse(&bytes).unwrap();
+
+        assert_eq!(DiameterPacket::peek_header(&bytes).unwrap(), parsed.header);
+        for code in [codes::AVP_SESSION_ID, 628, codes::AVP_DESTINATION_REALM] {
+            assert_eq!(
+                DiameterPacket::find_top_level_avp(&bytes, code).unwrap(),
+                parsed.find_avp(code).map(|avp| avp.data.as_slice())
+            );
+        }
+        assert_eq!(
+            DiameterPacket::find_top_level_avp(&bytes, codes::AVP_ORIGIN_HOST).unwrap(),
+            None
+        );
+        assert!(DiameterPacket::peek_header(&bytes[..bytes.len() - 4]).is_err());
+    }
+
+    #[test]
+    fn test_vendor_avp_length_below_vendor_header() {
+        // V flag set, length 9: shorter than the 12-byte vendor AVP header
+        let mut data = vec![0x00, 0x00, 0x02, 0x74, 0xC0, 0x00, 0x00, 0x09];
+        data.extend_from_slice(&10415u32.to_be_bytes());
+        data.extend_from_slice(&[0xAA, 0x00, 0x00, 0x00]);
+
+        assert!(DiameterAvp::parse(&data).is_err());
+
+        let mut message = vec![0x01, 0x00, 0x00, 20 + data.len() as u8];
+        message.extend_from_slice(&[0x80, 0x00, 0x01, 0x01]);
+        message.extend_from_slice(&[0; 12]);
+        message.extend_from_slice(&data);
+        assert!(DiameterPacket::parse(&message).is_err());
+        assert!(DiameterPacket::find_top_level_avp(&message, 628).is_err());
+    }
+
+    #[test]
+    fn test_vendor_avp_length_10_errors() {
+        let mut data = vec![0x00, 0x00, 0x05, 0x7D, 0x80, 0x00, 0x00, 0x0A];
+        data.extend_from_slice(&10415u32.to_be_bytes());
+        data.extend_from_slice(&[0xFF; 8]);
+
+        match DiameterAvp::parse(&data) {
+            Err(CddeError::InvalidPacket(message)) => {
+                assert!(message.contains("length 10"), "{message}")
+            }
+            other => panic!("Expected InvalidPacket, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_content_eq_ignores_transient_ids() {
+        let avp = |code: u32, data: &[u8]| DiameterAvp {
+            code,
+            flags: AVP_FLAG_MANDATORY,
+            vendor_id: None,
+            data: data.to_vec(),
+        };
+        let packet = DiameterPacket {
+            header: DiameterHeader {
+                version: 1,
+                length: 0,
+                flags: FLAG_REQUEST | FLAG_PROXIABLE,
+                command_code: 316,
+                application_id: 16777251,
+                hop_by_hop_id: 1,
+                end_to_end_id: 2,
+            },
+            avps: vec![
+                avp(263, b"session;1"),
+                avp(264, b"mme.example.com"),
+                avp(282, b"route1"),
+                avp(282, b"route2"),
+            ],
+        };
+
+        let mut relayed = packet.clone();
+        relayed.header.hop_by_hop_id = 99;
+        relayed.header.length = 120;
+        assert!(packet.content_eq(&relayed));
+
+        // AVP order does not matter, repeated AVPs still count
+        relayed.header.end_to_end_id = 77;
+        relayed.avps.reverse();
+        assert!(packet.content_eq(&relayed));
+
+        let mut extra = packet.clone();
+        extra.avps.push(avp(282, b"route2"));
+        assert!(!packet.content_eq(&extra));
+
+        let mut answer = packet.clone();
+        answer.header.flags = FLAG_PROXIABLE;
+        assert!(!packet.content_eq(&answer));
+
+        let mut changed = packet.clone();
+        changed.avps[1].data = b"other.example.com".to_vec();
+        assert!(!packet.content_eq(&changed));
+    }
+
+    #[test]
+    fn test_semantic_eq_ignores_non_significant_flags() {
+        let origin_host = DiameterAvp {
+            code: 264,
+            flags: AVP_FLAG_MANDATORY,
+            vendor_id: None,
+            data: b"mme.example.com".to_vec(),
+        };
+        let protected = DiameterAvp {
+            flags: AVP_FLAG_MANDATORY | AVP_FLAG_PROTECTED,
+            ..origin_host.clone()
+        };
+
+        assert_ne!(origin_host, protected);
+        assert!(origin_host.semantic_eq(&protected));
+
+        let other_vendor = DiameterAvp {
+            flags: AVP_FLAG_VENDOR,
+            vendor_id: Some(10415),
+            ..origin_host.clone()
+        };
+        assert!(!origin_host.semantic_eq(&other_vendor));
+
+        let other_data = DiameterAvp {
+            data: b"hss.example.com".to_vec(),
+            ..origin_host.clone()
+        };
+        assert!(!origin_host.semantic_eq(&other_data));
+
+        let optional = DiameterAvp {
+            flags: 0,
+            ..origin_host.clone()
+        };
+        assert!(!origin_host.semantic_eq(&optional));
+    }
+
+    #[test]
+    fn test_serialize_into_matches_serialize() {
+        let packet = DiameterPacket {
+            header: DiameterHeader {
+                version: 1,
+                length: 0,
+                flags: 0xC0,
+                command_code: 316,
+                application_id: 16777251,
+                hop_by_hop_id: 0x01020304,
+                end_to_end_id: 0x05060708,
+            },
+            avps: vec![
+                DiameterAvp {
+                    code: 263,
+                    flags: 0x40,
+                    vendor_id: None,
+                    data: b"session;1".to_vec(),
+                },
+                DiameterAvp {
+                    code: 1407,
+                    flags: 0xC0,
+                    vendor_id: Some(10415),
+                    data: vec![0x21, 0xF3, 0x54],
+                },
+                DiameterAvp {
+                    code: 268,
+                    flags: 0x40,
+                    vendor_id: None,
+                    data: 2001u32.to_be_bytes().to_vec(),
+                },
+            ],
+        };
+
+        // A reused buffer keeps earlier content and appends after it
+        let mut buf = BytesMut::new();
+        packet.serialize_into(&mut buf);
+        assert_eq!(&buf[..], &packet.serialize()[..]);
+        packet.serialize_into(&mut buf);
+        assert_eq!(
+            &buf[..],
+            &[packet.serialize(), packet.serialize()].concat()[..]
+        );
+        assert_eq!(&packet.to_bytes()[..], &packet.serialize()[..]);
+        assert_eq!(packet.encoded_len(), packet.serialize().len());
+
+        let extra = DiameterAvp {
+            code: 33,
+            flags: 0x40,
+            vendor_id: None,
+            data: b"state".to_vec(),
+        };
+        let mut extended = packet.clone();
+        extended.avps.push(extra.clone());
+        assert_eq!(
+            packet.serialize_with(std::slice::from_ref(&extra)),
+            extended.serialize()
+        );
+    }
+
+    #[test]
+    fn test_into_answer() {
+        let request = DiameterPacket {
+            header: DiameterHeader {
+                version: 1,
+                length: 0,
+                flags: FLAG_REQUEST | FLAG_PROXIABLE,
+                command_code: 316,
+                application_id: 16777251,
+                hop_by_hop_id: 7,
+                end_to_end_id: 8,
+            },
+            avps: vec![
+                DiameterAvp {
+                    code: codes::AVP_SESSION_ID,
+                    flags: AVP_FLAG_MANDATORY,
+                    vendor_id: None,
+                    data: b"mme1;1".to_vec(),
+                },
+                DiameterAvp {
+                    code: codes::AVP_PROXY_INFO,
+                    flags: AVP_FLAG_MANDATORY,
+                    vendor_id: None,
+                    data: b"proxy-a".to_vec(),
+                },
+                DiameterAvp {
+                    code: codes::AVP_DESTINATION_REALM,
+                    flags: AVP_FLAG_MANDATORY,
+                    vendor_id: None,
+                    data: b"epc.example.com".to_vec(),
+                },
+                DiameterAvp {
+                    code: codes::AVP_PROXY_INFO,
+                    flags: AVP_FLAG_MANDATORY,
+                    vendor_id: None,
+                    data: b"proxy-b".to_vec(),
+                },
+            ],
+        };
+
+        let answer = request
+            .into_answer(3002, "dcr.example.com", "example.com")
+            .serialize();
+        let answer = DiameterPacket::parse(&answer).unwrap();
+
+        assert!(answer.header.is_answer());
+        assert_eq!(answer.header.flags, FLAG_PROXIABLE | FLAG_ERROR);
+        assert_eq!(answer.header.command_code, 316);
+        assert_eq!(answer.header.application_id, 16777251);
+        assert_eq!(
+            (answer.header.hop_by_hop_id, answer.header.end_to_end_id),
+            (7, 8)
+        );
+        let codes: Vec<u32> = answer.avps.iter().map(|avp| avp.code).collect();
+        assert_eq!(
+            codes,
+            [
+                codes::AVP_SESSION_ID,
+                codes::AVP_RESULT_CODE,
+                codes::AVP_ORIGIN_HOST,
+                codes::AVP_ORIGIN_REALM,
+                codes::AVP_PROXY_INFO,
+                codes::AVP_PROXY_INFO
+            ]
+        );
+        let proxy_info: Vec<&[u8]> = answer
+            .find_all_avps(codes::AVP_PROXY_INFO)
+            .iter()
+            .map(|avp| avp.data.as_slice())
+            .collect();
+        assert_eq!(proxy_info, [&b"proxy-a"[..], &b"proxy-b"[..]]);
+        assert_eq!(
+            answer.find_avp(codes::AVP_RESULT_CODE).unwrap().data,
+            3002u32.to_be_bytes()
+        );
+        assert_eq!(
+            answer.find_avp(codes::AVP_ORIGIN_HOST).unwrap().data,
+            b"dcr.example.com"
+        );
+        assert_eq!(
+            answer.find_avp(codes::AVP_ORIGIN_REALM).unwrap().data,
+            b"example.com"
+        );
+    }
+
+    #[test]
+    fn test_nested_grouped_round_trip() {
+        // Subscription-Data holding an odd-length vendor AVP and a group
+        let msisdn = DiameterAvp {
+            code: 701,
+            flags: 0xC0,
+            vendor_id: Some(10415),
+            data: vec![0x21, 0x43, 0x65],
+        };
+        let context_id = DiameterAvp {
+            code: 1423,
+            flags: 0xC0,
+            vendor_id: Some(10415),
+            data: 1u32.to_be_bytes().to_vec(),
+        };
+        let apn_profile =
+            DiameterAvp::from_grouped(1619, 0xC0, Some(10415), std::slice::from_ref(&context_id));
+        let subscription_data = DiameterAvp::from_grouped(
+            1400,
+            0xC0,
+            Some(10415),
+            &[msisdn.clone(), apn_profile.clone()],
+        );
+
+        let (parsed, _) = DiameterAvp::parse(&subscription_data.serialize()).unwrap();
+        let members = parsed.parse_grouped().unwrap();
+        assert_eq!(members, vec![msisdn, apn_profile]);
+        assert_eq!(members[1].parse_grouped().unwrap(), vec![context_id]);
+
+        // A member overrunning the group is an error, not a short read
+        let mut truncated = parsed.clone();
+        truncated.data.truncate(10);
+        assert!(truncated.parse_grouped().is_err());
+    }
+
+    #[test]
+    fn test_parse_with_limit_rejects_declared_length() {
+        // Headers of every kind claiming the largest 24-bit length
+        for seed in 0u32..64 {
+            let mut header = DiameterHeader {
+                version: 1,
+                length: 0x00FF_FFFF,
+                flags: (seed as u8) << 4,
+                command_code: seed.wrapping_mul(2_654_435_761) & 0x00FF_FFFF,
+                application_id: seed.wrapping_mul(40_503),
+                hop_by_hop_id: seed,
+                end_to_end_id: !seed,
+            }
+            .serialize();
+            header.extend(vec![0u8; (seed as usize) * 4]);
+
+            assert!(matches!(
+                DiameterPacket::parse_with_limit(&header, 65_535),
+                Err(CddeError::MessageTooLarge {
+                    declared: 0x00FF_FFFF,
+                    limit: 65_535
+                })
+            ));
+        }
+
+        let packet = DiameterPacket {
+            header: DiameterHeader {
+                version: 1,
+                length: 0,
+                flags: 0x80,
+                command_code: 280,
+                application_id: 0,
+                hop_by_hop_id: 1,
+                end_to_end_id: 1,
+            },
+            avps: vec![],
+        }
+        .serialize();
+        assert!(DiameterPacket::parse_with_limit(&packet, 20).is_ok());
+        assert!(DiameterPacket::parse_with_limit(&packet, 19).is_err());
+    }
+
+    #[test]
+    fn test_padding_modes() {
+        let avp = DiameterAvp {
+            code: 264,
+            flags: 0x40,
+            vendor_id: None,
+            data: b"hss".to_vec(),
+        };
+        let padded = avp.serialize();
+        let unpadded = &padded[..11];
+        let mut garbage = padded.clone();
+        garbage[11] = 0xAA;
+
+        for mode in [
+            PaddingMode::Lenient,
+            PaddingMode::Present,
+            PaddingMode::Zero,
+        ] {
+            assert_eq!(
+                DiameterAvp::parse_with_padding(&padded, mode).unwrap(),
+                (avp.clone(), 12)
+            );
+        }
+
+        // Missing padding is only accepted leniently
+        assert!(DiameterAvp::parse_with_padding(unpadded, PaddingMode::Lenient).is_ok());
+        assert!(DiameterAvp::parse_with_padding(unpadded, PaddingMode::Present).is_err());
+
+        // Non-zero padding is only rejected when checking its value
+        assert!(DiameterAvp::parse_with_padding(&garbage, PaddingMode::Present).is_ok());
+        assert!(DiameterAvp::parse_with_padding(&garbage, PaddingMode::Zero).is_err());
+
+        // Messages check every AVP, the last one included
+        let mut message = DiameterHeader {
+            version: 1,
+            length: 31,
+            flags: 0x80,
+            command_code: 280,
+            application_id: 0,
+            hop_by_hop_id: 1,
+            end_to_end_id: 1,
+        }
+        .serialize();
+        message.extend_from_slice(unpadded);
+        assert!(DiameterPacket::parse(&message).is_ok());
+        assert!(DiameterPacket::parse_with_padding(&message, PaddingMode::Present).is_err());
+    }
+
+    #[test]
+    fn test_vendor_specific_app_id() {
+        let avp = vendor_specific_app_id(10415, codes::APP_3GPP_S6A);
+        let (parsed, _) = DiameterAvp::parse(&avp.serialize()).unwrap();
+        assert_eq!(parsed.code, 260);
+        assert_eq!(parsed.flags, AVP_FLAG_MANDATORY);
+
+        let members = parsed.parse_grouped().unwrap();
+        assert_eq!(members.len(), 2);
+        assert_eq!(members[0].code, codes::AVP_VENDOR_ID);
+        assert_eq!(members[0].data, 10415u32.to_be_bytes());
+        assert_eq!(members[1].code, codes::AVP_AUTH_APPLICATION_ID);
+        assert_eq!(members[1].data, 16777251u32.to_be_bytes());
+    }
 }
@@ -1,3 +1,4 @@
+use cdde_diameter_dict::ParseError;
 use thiserror::Error;
 
 /// Main error type for CDDE system
@@ -15,6 +16,15 @@ pub enum CddeError {
     #[error("Invalid AVP value for code {code}: {reason}")]
     InvalidAvpValue { code: u32, reason: String },
 
+    #[error("Message length {declared} exceeds the limit of {limit} bytes")]
+    MessageTooLarge { declared: usize, limit: usize },
+
+    #[error("AVP data length does not match its type")]
+    InvalidAvpLength,
+
+    #[error("Unsupported AVP: {0}")]
+    UnsupportedAvp(u32),
+
     // ========================================
     // Routing Errors
     // ========================================
@@ -27,6 +37,9 @@ pub enum CddeError {
     #[error("Routing loop detected")]
     RoutingLoop,
 
+    #[error("Peer is not accepting new requests: {0}")]
+    PeerBusy(String),
+
     // ========================================
     // Timeout Errors
     // ========================================
@@ -36,6 +49,15 @@ pub enum CddeError {
     #[error("gRPC call timeout")]
     GrpcTimeout,
 
+    #[error("Capabilities exchange timeout after {0}ms")]
+    HandshakeTimeout(u64),
+
+    #[error("No data from peer for {0}ms")]
+    ReadTimeout(u64),
+
+    #[error("Write to peer blocked for {0}ms")]
+    WriteTimeout(u64),
+
     // ========================================
     // System Errors
     // ========================================
@@ -62,14 +84,21 @@ impl CddeError {
     /// Convert error to Diameter Result-Code
     pub fn to_result_code(&self) -> u32 {
         match self {
-            Self::InvalidPacket(_) => 3008, // DIAMETER_INVALID_AVP_VALUE
-            Self::MissingAvp(_) => 5005,    // DIAMETER_MISSING_AVP
-            Self::InvalidAvpValue { .. } => 3008,
-            Self::NoRoute(_) => 3003,      // DIAMETER_REALM_NOT_SERVED
-            Self::AllPeersDown(_) => 3002, // DIAMETER_UNABLE_TO_DELIVER
-            Self::RoutingLoop => 3005,     // DIAMETER_LOOP_DETECTED
+            Self::InvalidPacket(_) => 3008,       // DIAMETER_INVALID_HDR_BITS
+            Self::MissingAvp(_) => 5005,          // DIAMETER_MISSING_AVP
+            Self::InvalidAvpValue { .. } => 5004, // DIAMETER_INVALID_AVP_VALUE
+            Self::MessageTooLarge { .. } => 5015, // DIAMETER_INVALID_MESSAGE_LENGTH
+            Self::InvalidAvpLength => 5014,       // DIAMETER_INVALID_AVP_LENGTH
+            Self::UnsupportedAvp(_) => 5001,      // DIAMETER_AVP_UNSUPPORTED
+            Self::NoRoute(_) => 3003,             // DIAMETER_REALM_NOT_SERVED
+            Self::AllPeersDown(_) => 3002,        // DIAMETER_UNABLE_TO_DELIVER
+            Self::RoutingLoop => 3005,            // DIAMETER_LOOP_DETECTED
+            Self::PeerBusy(_) => 3004,            // DIAMETER_TOO_BUSY
             Self::SessionTimeout(_) => 3002,
             Self::GrpcTimeout => 3002,
+            Self::HandshakeTimeout(_) => 3002,
+            Self::ReadTimeout(_) | Self::WriteTimeout(_) => 3002,
+            Self::ConnectionClosed => 3002,
             _ => 3010, // DIAMETER_UNABLE_TO_COMPLY
         }
     }
@@ -89,11 +118,32 @@ impl CddeError {
     pub fn is_retryable(&self) -> bool {
         matches!(
             self,
-            Self::GrpcTimeout | Self::SctpError(_) | Self::NetworkError(_)
+            Self::GrpcTimeout
+                | Self::HandshakeTimeout(_)
+                | Self::ReadTimeout(_)
+                | Self::WriteTimeout(_)
+                | Self::SctpError(_)
+                | Self::NetworkError(_)
+                | Self::ConnectionClosed
         )
     }
 }
 
+impl From<ParseError> for CddeError {
+    fn from(e: ParseError) -> Self {
+        match e {
+            ParseError::InvalidLength => Self::InvalidAvpLength,
+            ParseError::UnknownAvpCode(code) => Self::UnsupportedAvp(code),
+            // The dictionary does not report which AVP held the value
+            ParseError::InvalidUtf8 => Self::InvalidAvpValue {
+                code: 0,
+                reason: e.to_string(),
+            },
+            ParseError::ParseError(_) => Self::InvalidPacket(e.to_string()),
+        }
+    }
+}
+
 /// Error severity levels
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ErrorSeverity {
@@ -133,6 +183,28 @@ mod tests {
             3003
         );
         assert_eq!(CddeError::RoutingLoop.to_result_code(), 3005);
+        assert_eq!(
+            CddeError::PeerBusy("draining".to_string()).to_result_code(),
+            3004
+        );
+    }
+
+    #[test]
+    fn test_dictionary_parse_errors() {
+        let err = CddeError::from(ParseError::UnknownAvpCode(99999));
+        assert!(matches!(err, CddeError::UnsupportedAvp(99999)));
+        assert_eq!(err.to_result_code(), 5001);
+
+        assert_eq!(
+            CddeError::from(ParseError::InvalidLength).to_result_code(),
+            5014
+        );
+        let err = CddeError::from(ParseError::InvalidUtf8);
+        assert_eq!(
+            err.to_string(),
+            "Invalid AVP value for code 0: Invalid UTF-8 string"
+        );
+        assert_eq!(err.to_result_code(), 5004);
     }
 
     #[test]
@@ -147,6 +219,9 @@ mod tests {
     #[test]
     fn test_error_retryable() {
         assert!(CddeError::GrpcTimeout.is_retryable());
+        assert!(CddeError::HandshakeTimeout(5000).is_retryable());
+        assert!(CddeError::ReadTimeout(60000).is_retryable());
+        assert!(CddeError::WriteTimeout(10000).is_retryable());
         assert!(!CddeError::RoutingLoop.is_retryable());
     }
 
@@ -160,9 +235,12 @@ mod tests {
     fn test_connection_closed_variant() {
         let err = CddeError::ConnectionClosed;
         assert_eq!(err.to_string(), "Connection closed by peer");
-        // ConnectionClosed is not explicitly mapped in to_result_code, so it should be UNABLE_TO_COMPLY (3010)
-        assert_eq!(err.to_result_code(), 3010);
+        // A peer that went away cannot be delivered to: DIAMETER_UNABLE_TO_DELIVER
+        assert_eq!(err.to_result_code(), 3002);
         // It should be a warning severity by default
         assert_eq!(err.severity(), ErrorSeverity::Warning);
+        // Reconnecting may succeed, unlike protocol errors
+        assert!(err.is_retryable());
+        assert!(!CddeError::InvalidPacket("test".to_string()).is_retryable());
     }
 }
@@ -0,0 +1,196 @@
+use crate::codec::DEFAULT_MAX_MESSAGE_LENGTH;
+use crate::error::{CddeError, Result};
+
+/// Size of the fixed Diameter header
+pub(crate) const HEADER_LENGTH: usize = 20;
+
+/// Accumulates bytes read from a stream until complete Diameter messages are available
+///
+/// TCP does not preserve message boundaries: a single read may contain a
+/// partial message or several messages. The accumulator uses the length
+/// field of the Diameter header to split the stream into messages.
+#[derive(Debug)]
+pub struct FrameAccumulator {
+    buffer: Vec<u8>,
+    max_length: usize,
+}
+
+impl FrameAccumulator {
+    /// Create an empty accumulator accepting messages up to the default length
+    pub fn new() -> Self {
+        Self {
+            buffer: Vec::new(),
+            max_length: DEFAULT_MAX_MESSAGE_LENGTH,
+        }
+    }
+
+    /// Set the largest message accepted, in bytes
+    ///
+    /// A longer declared length fails as soon as the header arrives instead
+    /// of buffering the message.
+    pub fn with_max_length(mut self, max_length: usize) -> Self {
+        self.max_length = max_length;
+        self
+    }
+
+    /// Append bytes read from the stream
+    pub fn extend(&mut self, data: &[u8]) {
+        self.buffer.extend_from_slice(data);
+    }
+
+    /// Take the next complete message, if one has been fully received
+    ///
+    /// Returns an error when the buffered data cannot be the start of a
+    /// Diameter message; the stream is then out of sync and should be closed.
+    pub fn next_frame(&mut self) -> Result<Option<Vec<u8>>> {
+        let Some(length) = self.declared_length()? else {
+            return Ok(None);
+        };
+
+        if self.buffer.len() < length {
+            return Ok(None);
+        }
+
+        let rest = self.buffer.split_off(length);
+        Ok(Some(std::mem::replace(&mut self.buffer, rest)))
+    }
+
+    /// Number of buffered bytes not yet returned as a message
+    pub fn len(&self) -> usize {
+        self.buffer.len()
+    }
+
+    /// Check if no bytes are buffered
+    pub fn is_empty(&self) -> bool {
+        self.buffer.is_empty()
+    }
+
+    /// Length of the message at the front of the buffer, once its length field is available
+    fn declared_length(&self) -> Result<Option<usize>> {
+        let length = declared_length(&self.buffer)?;
+        if let Some(length) = length {
+            check_length(length, self.max_length)?;
+        }
+        Ok(length)
+    }
+}
+
+impl Default for FrameAccumulator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Length of the message starting at the front of `buffer`
+///
+/// `None` until the length field has been received.
+pub(crate) fn declared_length(buffer: &[u8]) -> Result<Option<usize>> {
+    if buffer.len() < 4 {
+        return Ok(None);
+    }
+
+    let version = buffer[0];
+    if version != 1 {
+        return Err(CddeError::InvalidPacket(format!(
+            "Invalid version: {version}"
+        )));
+    }
+
+    let length = u32::from_be_bytes([0, buffer[1], buffer[2], buffer[3]]) as usize;
+    if length < HEADER_LENGTH {
+        return Err(CddeError::InvalidPacket(format!(
+            "Invalid message length: {length}"
+        )));
+    }
+
+    Ok(Some(length))
+}
+
+/// Reject a declared message length above `limit`
+pub(crate) fn check_length(declared: usize, limit: usize) -> Result<()> {
+    if declared > limit {
+        return Err(CddeError::MessageTooLarge { declared, limit });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diameter::{DiameterAvp, DiameterHeader, DiameterPacket};
+
+    fn packet(hop_by_hop_id: u32) -> Vec<u8> {
+        DiameterPacket {
+            header: DiameterHeader {
+                version: 1,
+                length: 0,
+                flags: 0x80,
+                command_code: 280,
+                application_id: 0,
+                hop_by_hop_id,
+                end_to_end_id: 1,
+            },
+            avps: vec![DiameterAvp {
+                code: 264,
+                flags: 0x40,
+                vendor_id: None,
+                data: b"peer.example.com".to_vec(),
+            }],
+        }
+        .serialize()
+    }
+
+    #[test]
+    fn test_split_message() {
+        let data = packet(1);
+        let mut acc = FrameAccumulator::new();
+
+        acc.extend(&data[..3]);
+        assert!(acc.next_frame().unwrap().is_none());
+
+        acc.extend(&data[3..25]);
+        assert!(acc.next_frame().unwrap().is_none());
+
+        acc.extend(&data[25..]);
+        assert_eq!(acc.next_frame().unwrap().unwrap(), data);
+        assert!(acc.is_empty());
+    }
+
+    #[test]
+    fn test_multiple_messages_in_one_read() {
+        let first = packet(1);
+        let second = packet(2);
+        let mut acc = FrameAccumulator::new();
+
+        acc.extend(&[first.clone(), second.clone(), second[..5].to_vec()].concat());
+
+        assert_eq!(acc.next_frame().unwrap().unwrap(), first);
+        assert_eq!(acc.next_frame().unwrap().unwrap(), second);
+        assert!(acc.next_frame().unwrap().is_none());
+        assert_eq!(acc.len(), 5);
+    }
+
+    #[test]
+    fn test_oversized_message_fails_on_header() {
+        let mut acc = FrameAccumulator::new().with_max_length(64);
+        acc.extend(&[1, 0xFF, 0xFF, 0xFF]);
+        assert!(matches!(
+            acc.next_frame(),
+            Err(CddeError::MessageTooLarge {
+                declared: 0xFF_FFFF,
+                limit: 64
+            })
+        ));
+    }
+
+    #[test]
+    fn test_invalid_header() {
+        let mut acc = FrameAccumulator::new();
+        acc.extend(&[2, 0, 0, 20]);
+        assert!(acc.next_frame().is_err());
+
+        let mut acc = FrameAccumulator::new();
+        acc.extend(&[1, 0, 0, 8]);
+        assert!(acc.next_frame().is_err());
+    }
+}
@@ -0,0 +1,250 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::RwLock;
+use std::time::Duration;
+
+/// Thresholds used to normalize peer health observations into a score
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HealthThresholds {
+    /// Answer latency considered "slow" (a latency of this value adds 1.0 to the score)
+    pub latency_threshold: Duration,
+
+    /// Error ratio considered "unhealthy" (an error rate of this value adds 1.0 to the score)
+    pub error_rate_threshold: f64,
+
+    /// Weight given to the newest observation in the rolling averages (0.0 - 1.0)
+    pub smoothing: f64,
+}
+
+impl Default for HealthThresholds {
+    fn default() -> Self {
+        Self {
+            latency_threshold: Duration::from_millis(500),
+            error_rate_threshold: 0.1,
+            smoothing: 0.2,
+        }
+    }
+}
+
+/// Rolling health observations for a single peer
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PeerHealth {
+    /// Exponentially weighted answer latency in milliseconds
+    pub latency_ms: f64,
+
+    /// Exponentially weighted error ratio (0.0 - 1.0)
+    pub error_rate: f64,
+
+    /// Number of observations recorded
+    pub samples: u64,
+
+    /// Peer reported down by its peer agent
+    pub down: bool,
+
+    /// Peer is draining and takes no new requests
+    pub draining: bool,
+}
+
+/// Shared registry of peer health scores
+///
+/// Fed with answer observations and consulted by routing to prefer
+/// healthier peers. Lower scores are better; unknown peers score 0 and
+/// peers marked down score infinity.
+#[derive(Debug, Default)]
+pub struct PeerHealthRegistry {
+    thresholds: HealthThresholds,
+    peers: RwLock<HashMap<String, PeerHealth>>,
+}
+
+impl PeerHealthRegistry {
+    /// Create a new registry with the given thresholds
+    pub fn new(thresholds: HealthThresholds) -> Self {
+        Self {
+            thresholds,
+            peers: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Get the thresholds used for scoring
+    pub fn thresholds(&self) -> &HealthThresholds {
+        &self.thresholds
+    }
+
+    /// Record an answer observed from a peer
+    pub fn record_answer(&self, peer: &str, latency: Duration, success: bool) {
+        let Ok(mut peers) = self.peers.write() else {
+            return;
+        };
+
+        let alpha = self.thresholds.smoothing.clamp(0.0, 1.0);
+        let latency_ms = latency.as_secs_f64() * 1000.0;
+        let error = if success { 0.0 } else { 1.0 };
+
+        let health = peers.entry(peer.to_string()).or_default();
+        if health.samples == 0 {
+            health.latency_ms = latency_ms;
+            health.error_rate = error;
+        } else {
+            health.latency_ms += alpha * (latency_ms - health.latency_ms);
+            health.error_rate += alpha * (error - health.error_rate);
+        }
+        health.samples += 1;
+    }
+
+    /// Get the recorded health of a peer
+    pub fn get(&self, peer: &str) -> Option<PeerHealth> {
+        self.peers.read().ok()?.get(peer).cloned()
+    }
+
+    /// Compute the health score of a peer (lower is better)
+    pub fn score(&self, peer: &str) -> f64 {
+        let Some(health) = self.get(peer) else {
+            return 0.0;
+        };
+        if health.down {
+            return f64::INFINITY;
+        }
+
+        let latency_limit = self.thresholds.latency_threshold.as_secs_f64() * 1000.0;
+        let latency_score = if latency_limit > 0.0 {
+            health.latency_ms / latency_limit
+        } else {
+            0.0
+        };
+        let error_score = if self.thresholds.error_rate_threshold > 0.0 {
+            health.error_rate / self.thresholds.error_rate_threshold
+        } else {
+            0.0
+        };
+
+        latency_score + error_score
+    }
+
+    /// Mark a peer down or back up, keeping its observations
+    pub fn set_down(&self, peer: &str, down: bool) {
+        if let Ok(mut peers) = self.peers.write() {
+            peers.entry(peer.to_string()).or_default().down = down;
+        }
+    }
+
+    /// Check if a peer is marked down
+    pub fn is_down(&self, peer: &str) -> bool {
+        self.get(peer).is_some_and(|health| health.down)
+    }
+
+    /// Mark a peer draining or back in service, keeping its observations
+    pub fn set_draining(&self, peer: &str, draining: bool) {
+        if let Ok(mut peers) = self.peers.write() {
+            peers.entry(peer.to_string()).or_default().draining = draining;
+        }
+    }
+
+    /// Check if a peer is draining
+    pub fn is_draining(&self, peer: &str) -> bool {
+        self.get(peer).is_some_and(|health| health.draining)
+    }
+
+    /// Get every known peer with its health, ordered by name
+    pub fn snapshot(&self) -> Vec<(String, PeerHealth)> {
+        let Ok(peers) = self.peers.read() else {
+            return Vec::new();
+        };
+        let mut snapshot: Vec<_> = peers
+            .iter()
+            .map(|(peer, health)| (peer.clone(), health.clone()))
+            .collect();
+        snapshot.sort_by(|a, b| a.0.cmp(&b.0));
+        snapshot
+    }
+
+    /// Forget all observations for a peer
+    pub fn reset(&self, peer: &str) {
+        if let Ok(mut peers) = self.peers.write() {
+            peers.remove(peer);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unknown_peer_scores_zero() {
+        let registry = PeerHealthRegistry::new(HealthThresholds::default());
+        assert_eq!(registry.score("peer1"), 0.0);
+        assert!(registry.get("peer1").is_none());
+    }
+
+    #[test]
+    fn test_errors_raise_score() {
+        let registry = PeerHealthRegistry::new(HealthThresholds::default());
+
+        for _ in 0..10 {
+            registry.record_answer("healthy", Duration::from_millis(10), true);
+            registry.record_answer("failing", Duration::from_millis(10), false);
+        }
+
+        assert!(registry.score("failing") > registry.score("healthy"));
+        assert_eq!(registry.get("failing").unwrap().samples, 10);
+    }
+
+    #[test]
+    fn test_latency_is_smoothed() {
+        let registry = PeerHealthRegistry::new(HealthThresholds {
+            smoothing: 0.5,
+            ..Default::default()
+        });
+
+        registry.record_answer("peer1", Duration::from_millis(100), true);
+        registry.record_answer("peer1", Duration::from_millis(300), true);
+
+        let health = registry.get("peer1").unwrap();
+        assert!((health.latency_ms - 200.0).abs() < 1e-9);
+
+        registry.reset("peer1");
+        assert!(registry.get("peer1").is_none());
+    }
+
+    #[test]
+    fn test_down_peer_scores_infinity() {
+        let registry = PeerHealthRegistry::new(HealthThresholds::default());
+        registry.record_answer("peer1", Duration::from_millis(10), true);
+
+        registry.set_down("peer1", true);
+        assert!(registry.is_down("peer1"));
+        assert_eq!(registry.score("peer1"), f64::INFINITY);
+
+        registry.set_down("peer1", false);
+        assert!(!registry.is_down("peer1"));
+        assert_eq!(registry.get("peer1").unwrap().samples, 1);
+    }
+
+    #[test]
+    fn test_snapshot_is_sorted() {
+        let registry = PeerHealthRegistry::new(HealthThresholds::default());
+        registry.record_answer("peer2", Duration::from_millis(10), true);
+        registry.set_down("peer1", true);
+
+        let snapshot = registry.snapshot();
+        assert_eq!(snapshot.len(), 2);
+        assert_eq!(snapshot[0].0, "peer1");
+        assert!(snapshot[0].1.down);
+        assert_eq!(snapshot[1].1.samples, 1);
+    }
+
+    #[test]
+    fn test_draining_keeps_observations() {
+        let registry = PeerHealthRegistry::new(HealthThresholds::default());
+        registry.record_answer("peer1", Duration::from_millis(10), true);
+
+        registry.set_draining("peer1", true);
+        assert!(registry.is_draining("peer1"));
+        assert!(!registry.is_down("peer1"));
+        assert_eq!(registry.get("peer1").unwrap().samples, 1);
+
+        registry.set_draining("peer1", false);
+        assert!(!registry.is_draining("peer1"));
+        assert!(!registry.is_draining("unknown"));
+    }
+}
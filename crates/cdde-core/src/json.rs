@@ -0,0 +1,133 @@
+use crate::diameter::{DiameterAvp, DiameterPacket};
+use cdde_diameter_dict::{AvpDataType, AvpValue, DictionaryManager};
+use serde_json::{json, Value};
+
+impl DiameterPacket {
+    /// Render the packet as JSON for logs and debugging
+    ///
+    /// AVPs are named and decoded through the dictionary; unknown AVPs and
+    /// values that fail to decode are rendered as hex.
+    pub fn to_json(&self, dict: &DictionaryManager) -> Value {
+        json!({
+            "header": {
+                "version": self.header.version,
+                "length": self.header.length,
+                "flags": self.header.flags,
+                "request": self.header.is_request(),
+                "command_code": self.header.command_code,
+                "application_id": self.header.application_id,
+                "hop_by_hop_id": self.header.hop_by_hop_id,
+                "end_to_end_id": self.header.end_to_end_id,
+            },
+            "avps": self.avps.iter().map(|avp| avp_to_json(avp, dict)).collect::<Vec<_>>(),
+        })
+    }
+}
+
+fn avp_to_json(avp: &DiameterAvp, dict: &DictionaryManager) -> Value {
+    let info = dict.lookup(avp.code);
+
+    let value = match &info {
+        Some(info) if info.data_type == AvpDataType::Grouped => grouped_to_json(avp, dict),
+        Some(info) => info
+            .data_type
+            .parse(&avp.data)
+            .map(value_to_json)
+            .unwrap_or_else(|_| Value::String(hex(&avp.data))),
+        None => Value::String(hex(&avp.data)),
+    };
+
+    let mut rendered = json!({
+        "name": info.map(|i| i.name).unwrap_or_else(|| format!("Unknown-{}", avp.code)),
+        "code": avp.code,
+        "flags": avp.flags,
+        "value": value,
+    });
+    if let Some(vendor_id) = avp.vendor_id {
+        rendered["vendor_id"] = json!(vendor_id);
+    }
+    rendered
+}
+
+fn grouped_to_json(avp: &DiameterAvp, dict: &DictionaryManager) -> Value {
+    match avp.parse_grouped() {
+        Ok(members) => Value::Array(
+            members
+                .iter()
+                .map(|member| avp_to_json(member, dict))
+                .collect(),
+        ),
+        Err(_) => Value::String(hex(&avp.data)),
+    }
+}
+
+pub(crate) fn value_to_json(value: AvpValue) -> Value {
+    match value {
+        AvpValue::Utf8String(s) | AvpValue::DiameterIdentity(s) | AvpValue::DiameterUri(s) => {
+            Value::String(s)
+        }
+        AvpValue::Unsigned32(v) | AvpValue::Time(v) => json!(v),
+        AvpValue::Unsigned64(v) => json!(v),
+        AvpValue::Integer32(v) | AvpValue::Enumerated(v) => json!(v),
+        AvpValue::Integer64(v) => json!(v),
+        AvpValue::Float32(v) => json!(v),
+        AvpValue::Float64(v) => json!(v),
+        AvpValue::OctetString(bytes)
+        | AvpValue::Grouped(bytes)
+        | AvpValue::Address(bytes)
+        | AvpValue::IpFilterRule(bytes) => Value::String(hex(&bytes)),
+    }
+}
+
+pub(crate) fn hex(data: &[u8]) -> String {
+    data.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diameter::{DiameterHeader, AVP_FLAG_MANDATORY};
+
+    fn avp(code: u32, data: Vec<u8>) -> DiameterAvp {
+        DiameterAvp {
+            code,
+            flags: AVP_FLAG_MANDATORY,
+            vendor_id: None,
+            data,
+        }
+    }
+
+    #[test]
+    fn test_to_json_names_avps() {
+        let packet = DiameterPacket {
+            header: DiameterHeader {
+                version: 1,
+                length: 20,
+                flags: 0x80,
+                command_code: 257,
+                application_id: 0,
+                hop_by_hop_id: 1,
+                end_to_end_id: 2,
+            },
+            avps: vec![
+                avp(264, b"dra.example.com".to_vec()),
+                avp(268, 2001u32.to_be_bytes().to_vec()),
+                avp(99999, vec![0xde, 0xad]),
+            ],
+        };
+
+        let value = packet.to_json(&DictionaryManager::new());
+
+        assert_eq!(value["header"]["command_code"], 257);
+        assert_eq!(value["header"]["request"], true);
+        assert_eq!(value["avps"][0]["name"], "Origin-Host");
+        assert_eq!(value["avps"][0]["value"], "dra.example.com");
+        assert_eq!(value["avps"][1]["name"], "Result-Code");
+        assert_eq!(value["avps"][1]["value"], 2001);
+        assert_eq!(value["avps"][2]["name"], "Unknown-99999");
+        assert_eq!(value["avps"][2]["value"], "dead");
+
+        let rendered = value.to_string();
+        assert!(rendered.contains(r#""name":"Origin-Host""#));
+    }
+}
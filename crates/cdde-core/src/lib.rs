@@ -4,10 +4,43 @@ pub mod error;
 // Diameter protocol module
 pub mod diameter;
 
+// Well-known protocol constants
+pub mod codes;
+
+// Address AVP encoding
+pub use cdde_diameter_dict::address;
+
+// Command dictionary of required AVPs
+pub mod command;
+
 // Transport abstraction module
 pub mod transport;
 
+// Stream framing module
+pub mod framing;
+
+// tokio_util codec for framed Diameter streams
+pub mod codec;
+
+// JSON rendering of packets for debugging
+mod json;
+
+// One-line rendering of packets for logs
+mod describe;
+
+// Peer health scoring module
+pub mod health;
+
+// CMS REST client
+pub mod cms;
+
 // Re-export commonly used types
-pub use diameter::{DiameterAvp, DiameterHeader, DiameterPacket};
+pub use codec::DiameterCodec;
+pub use command::validate_command;
+pub use diameter::{
+    AvpOrder, DiameterAvp, DiameterHeader, DiameterPacket, PaddingMode, DEFAULT_MAX_AVPS,
+};
 pub use error::{CddeError, ErrorSeverity, Result};
-pub use transport::Transport;
+pub use framing::FrameAccumulator;
+pub use health::{HealthThresholds, PeerHealth, PeerHealthRegistry};
+pub use transport::{Transport, TransportKind};
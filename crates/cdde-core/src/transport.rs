@@ -1,8 +1,28 @@
 use crate::error::Result;
 use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
 use std::net::SocketAddr;
 use tokio::io::{AsyncRead, AsyncWrite};
 
+/// Transport protocol a peer connection runs over
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TransportKind {
+    #[default]
+    Tcp,
+    Sctp,
+}
+
+impl TransportKind {
+    /// Protocol name as used in configuration
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Tcp => "tcp",
+            Self::Sctp => "sctp",
+        }
+    }
+}
+
 /// Abstract transport layer trait
 /// Allows switching between TCP and SCTP (or mocks) transparently
 #[async_trait]
@@ -0,0 +1,115 @@
+//! RFC 6733 header and AVP conformance tests
+
+mod fixtures;
+
+use cdde_core::{DiameterAvp, DiameterHeader, DiameterPacket};
+use fixtures::{avp, cases, grouped_payload, header, message};
+
+#[test]
+fn test_conformance_cases() {
+    for case in cases() {
+        let result = DiameterPacket::parse(&case.bytes);
+
+        match case.expected {
+            Some(expected) => {
+                let packet = result.unwrap_or_else(|e| panic!("{}: {e}", case.name));
+                assert_eq!(packet.avps, expected, "{}", case.name);
+                assert_eq!(packet.serialize(), case.bytes, "{}: round trip", case.name);
+            }
+            None => assert!(result.is_err(), "{}: expected an error", case.name),
+        }
+    }
+}
+
+#[test]
+fn test_maximum_message_length() {
+    let header = DiameterHeader::parse(&header(0x00FF_FFFF)).unwrap();
+    assert_eq!(header.length, 0x00FF_FFFF);
+    assert_eq!(&header.serialize()[1..4], &[0xFF, 0xFF, 0xFF]);
+}
+
+#[test]
+fn test_grouped_avp_members() {
+    let payload = grouped_payload();
+
+    let (first, first_length) = DiameterAvp::parse(&payload).unwrap();
+    let (second, second_length) = DiameterAvp::parse(&payload[first_length..]).unwrap();
+
+    assert_eq!(first.code, 266);
+    assert_eq!(second.code, 258);
+    assert_eq!(second.data, 16777251u32.to_be_bytes());
+    assert_eq!(first_length + second_length, payload.len());
+}
+
+#[test]
+fn test_grouped_avp_with_padded_member_round_trips() {
+    // Origin-Host of 5 bytes needs 3 bytes of padding inside the group
+    let member = DiameterAvp {
+        code: 264,
+        flags: 0x40,
+        vendor_id: None,
+        data: b"abcde".to_vec(),
+    };
+    let state = DiameterAvp {
+        code: 33,
+        flags: 0x40,
+        vendor_id: None,
+        data: b"xy".to_vec(),
+    };
+    let group = DiameterAvp::from_grouped(284, 0x40, None, &[member.clone(), state.clone()]);
+
+    let expected = message(&avp(
+        284,
+        0x40,
+        None,
+        &[avp(264, 0x40, None, b"abcde"), avp(33, 0x40, None, b"xy")].concat(),
+    ));
+    let mut packet = DiameterPacket::parse(&header(20)).unwrap();
+    packet.avps.push(group);
+    let bytes = packet.serialize();
+    assert_eq!(bytes, expected);
+
+    let reparsed = DiameterPacket::parse(&bytes).unwrap();
+    assert_eq!(reparsed.serialize(), bytes);
+    assert_eq!(
+        reparsed.avps[0].parse_grouped().unwrap(),
+        vec![member, state]
+    );
+}
+
+#[test]
+fn test_padding_matches_serializer() {
+    for len in 0..8 {
+        let data = vec![0xAB; len];
+        let encoded = avp(264, 0x40, None, &data);
+        let (parsed, consumed) = DiameterAvp::parse(&encoded).unwrap();
+
+        assert_eq!(consumed, encoded.len());
+        assert_eq!(parsed.serialize(), encoded);
+    }
+}
+
+/// Truncations and single-byte corruptions of every case must not panic
+///
+/// Mirrors the `parse` fuzz target over a fixed corpus so it runs on stable.
+#[test]
+fn test_corrupted_cases_do_not_panic() {
+    for case in cases() {
+        for len in 0..case.bytes.len() {
+            let _ = DiameterPacket::parse(&case.bytes[..len]);
+        }
+
+        for position in 0..case.bytes.len() {
+            for value in [0x00, 0x01, 0x08, 0x0B, 0x80, 0xFF] {
+                let mut bytes = case.bytes.clone();
+                bytes[position] = value;
+                if let Ok(packet) = DiameterPacket::parse(&bytes) {
+                    let reparsed = DiameterPacket::parse(&packet.serialize())
+                        .unwrap_or_else(|e| panic!("{}: {e}", case.name));
+                    assert_eq!(reparsed.avps, packet.avps, "{}", case.name);
+                }
+                let _ = DiameterPacket::find_top_level_avp(&bytes, 264);
+            }
+        }
+    }
+}
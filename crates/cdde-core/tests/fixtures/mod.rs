@@ -0,0 +1,163 @@
+//! RFC 6733 message fixtures shared by the conformance tests
+//!
+//! Each case is a raw message together with the AVPs it must decode to, or
+//! `None` when the parser must reject it.
+
+use cdde_core::DiameterAvp;
+
+/// A single conformance case
+pub struct Case {
+    pub name: &'static str,
+    pub bytes: Vec<u8>,
+    pub expected: Option<Vec<DiameterAvp>>,
+}
+
+/// Build a message header with an explicit length field
+pub fn header(length: u32) -> Vec<u8> {
+    let mut bytes = vec![1];
+    bytes.extend_from_slice(&length.to_be_bytes()[1..4]);
+    bytes.push(0x80); // Request
+    bytes.extend_from_slice(&257u32.to_be_bytes()[1..4]); // CER
+    bytes.extend_from_slice(&0u32.to_be_bytes()); // Application-Id
+    bytes.extend_from_slice(&0x1111_1111u32.to_be_bytes()); // Hop-by-Hop
+    bytes.extend_from_slice(&0x2222_2222u32.to_be_bytes()); // End-to-End
+    bytes
+}
+
+/// Build a message around already encoded AVPs
+pub fn message(avps: &[u8]) -> Vec<u8> {
+    let mut bytes = header(20 + avps.len() as u32);
+    bytes.extend_from_slice(avps);
+    bytes
+}
+
+/// Encode an AVP by hand, padded to 4 bytes
+pub fn avp(code: u32, flags: u8, vendor_id: Option<u32>, data: &[u8]) -> Vec<u8> {
+    let header_length = if vendor_id.is_some() { 12 } else { 8 };
+    let mut bytes = code.to_be_bytes().to_vec();
+    bytes.push(flags);
+    bytes.extend_from_slice(&((header_length + data.len()) as u32).to_be_bytes()[1..4]);
+    if let Some(vendor_id) = vendor_id {
+        bytes.extend_from_slice(&vendor_id.to_be_bytes());
+    }
+    bytes.extend_from_slice(data);
+    bytes.resize(bytes.len().div_ceil(4) * 4, 0);
+    bytes
+}
+
+fn decoded(code: u32, flags: u8, vendor_id: Option<u32>, data: &[u8]) -> DiameterAvp {
+    DiameterAvp {
+        code,
+        flags,
+        vendor_id,
+        data: data.to_vec(),
+    }
+}
+
+/// Grouped AVP payload: Vendor-Id and Auth-Application-Id
+pub fn grouped_payload() -> Vec<u8> {
+    let mut payload = avp(266, 0x40, None, &10415u32.to_be_bytes());
+    payload.extend(avp(258, 0x40, None, &16777251u32.to_be_bytes()));
+    payload
+}
+
+/// All conformance cases
+pub fn cases() -> Vec<Case> {
+    let host = b"peer.example.com";
+    let grouped = grouped_payload();
+
+    vec![
+        Case {
+            name: "minimum length message",
+            bytes: header(20),
+            expected: Some(vec![]),
+        },
+        Case {
+            name: "vendor AVP with data",
+            bytes: message(&avp(628, 0xC0, Some(10415), b"data")),
+            expected: Some(vec![decoded(628, 0xC0, Some(10415), b"data")]),
+        },
+        Case {
+            name: "vendor AVP without data",
+            bytes: message(&avp(628, 0xC0, Some(10415), b"")),
+            expected: Some(vec![decoded(628, 0xC0, Some(10415), b"")]),
+        },
+        Case {
+            name: "AVP with 3 bytes of padding",
+            bytes: message(&avp(264, 0x40, None, b"a")),
+            expected: Some(vec![decoded(264, 0x40, None, b"a")]),
+        },
+        Case {
+            name: "AVP with 2 bytes of padding",
+            bytes: message(&avp(264, 0x40, None, b"ab")),
+            expected: Some(vec![decoded(264, 0x40, None, b"ab")]),
+        },
+        Case {
+            name: "AVP with 1 byte of padding",
+            bytes: message(&avp(264, 0x40, None, b"abc")),
+            expected: Some(vec![decoded(264, 0x40, None, b"abc")]),
+        },
+        Case {
+            name: "padded AVP followed by another AVP",
+            bytes: message(&[avp(264, 0x40, None, b"abcde"), avp(296, 0x40, None, host)].concat()),
+            expected: Some(vec![
+                decoded(264, 0x40, None, b"abcde"),
+                decoded(296, 0x40, None, host),
+            ]),
+        },
+        Case {
+            name: "grouped AVP",
+            bytes: message(&avp(260, 0x40, None, &grouped)),
+            expected: Some(vec![decoded(260, 0x40, None, &grouped)]),
+        },
+        Case {
+            name: "header shorter than 20 bytes",
+            bytes: header(20)[..19].to_vec(),
+            expected: None,
+        },
+        Case {
+            name: "length field below header size",
+            bytes: header(12),
+            expected: None,
+        },
+        Case {
+            name: "length field beyond received bytes",
+            bytes: header(0x00FF_FFFF),
+            expected: None,
+        },
+        Case {
+            name: "AVP header truncated",
+            bytes: message(&avp(264, 0x40, None, host)[..6]),
+            expected: None,
+        },
+        Case {
+            name: "AVP data truncated",
+            bytes: message(&avp(264, 0x40, None, host)[..12]),
+            expected: None,
+        },
+        Case {
+            name: "AVP length below AVP header size",
+            bytes: message(&[0, 0, 1, 8, 0x40, 0, 0, 4]),
+            expected: None,
+        },
+        Case {
+            name: "vendor AVP length below vendor header size",
+            bytes: message(&[0, 0, 2, 116, 0xC0, 0, 0, 8, 0, 0, 0x28, 0xAF]),
+            expected: None,
+        },
+        Case {
+            name: "vendor AVP missing Vendor-Id",
+            bytes: message(&[0, 0, 2, 116, 0xC0, 0, 0, 12, 0, 0]),
+            expected: None,
+        },
+        Case {
+            name: "AVP extending past message length",
+            bytes: {
+                let mut bytes = message(&avp(264, 0x40, None, host));
+                bytes[3] -= 4; // Message length ends inside the AVP
+                bytes
+            },
+            expected: None,
+        },
+    ]
+}
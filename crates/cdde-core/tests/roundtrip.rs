@@ -0,0 +1,81 @@
+//! Property tests for the symmetry of message serialization and parsing
+
+use cdde_core::diameter::AVP_FLAG_VENDOR;
+use cdde_core::{DiameterAvp, DiameterHeader, DiameterPacket};
+use proptest::collection::vec;
+use proptest::prelude::*;
+
+/// AVP whose Vendor-Specific bit matches its vendor id
+fn avp() -> impl Strategy<Value = DiameterAvp> {
+    (
+        any::<u32>(),
+        any::<u8>(),
+        any::<Option<u32>>(),
+        vec(any::<u8>(), 0..64),
+    )
+        .prop_map(|(code, flags, vendor_id, data)| DiameterAvp {
+            code,
+            flags: match vendor_id {
+                Some(_) => flags | AVP_FLAG_VENDOR,
+                None => flags & !AVP_FLAG_VENDOR,
+            },
+            vendor_id,
+            data,
+        })
+}
+
+/// Message with a consistent header length
+fn message() -> impl Strategy<Value = DiameterPacket> {
+    (
+        any::<u8>(),
+        0..=0x00FF_FFFFu32,
+        any::<u32>(),
+        any::<u32>(),
+        any::<u32>(),
+        vec(avp(), 0..16),
+    )
+        .prop_map(
+            |(flags, command_code, application_id, hop_by_hop_id, end_to_end_id, avps)| {
+                let length = 20
+                    + avps
+                        .iter()
+                        .map(|avp| avp.serialize().len() as u32)
+                        .sum::<u32>();
+                DiameterPacket {
+                    header: DiameterHeader {
+                        version: 1,
+                        length,
+                        flags,
+                        command_code,
+                        application_id,
+                        hop_by_hop_id,
+                        end_to_end_id,
+                    },
+                    avps,
+                }
+            },
+        )
+}
+
+proptest! {
+    #[test]
+    fn test_message_round_trip(message in message()) {
+        let bytes = message.serialize();
+        prop_assert_eq!(bytes.len() % 4, 0);
+        prop_assert_eq!(bytes.len() as u32, message.header.length);
+        prop_assert_eq!(DiameterPacket::parse(&bytes).unwrap(), message);
+    }
+
+    #[test]
+    fn test_avp_round_trip_ignores_inconsistent_vendor_bit(
+        code in any::<u32>(),
+        flags in any::<u8>(),
+        vendor_id in any::<Option<u32>>(),
+        data in vec(any::<u8>(), 0..64),
+    ) {
+        let avp = DiameterAvp { code, flags, vendor_id, data };
+        let (parsed, length) = DiameterAvp::parse(&avp.serialize()).unwrap();
+        prop_assert_eq!(length, avp.serialize().len());
+        prop_assert!(parsed.semantic_eq(&avp));
+    }
+}
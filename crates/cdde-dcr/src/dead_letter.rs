@@ -0,0 +1,277 @@
+use cdde_core::codes::{AVP_DESTINATION_REALM, AVP_SESSION_ID};
+use cdde_core::DiameterPacket;
+use cdde_proto::DiameterPacketRequest;
+use serde::Serialize;
+use std::fs::OpenOptions;
+use std::io;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tracing::{error, warn};
+
+/// Records a file sink holds before dropping new ones
+const FILE_SINK_QUEUE_CAPACITY: usize = 1024;
+
+/// Why the DCR gave up on a request
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeadLetterReason {
+    /// No route matched the request
+    NoRoute,
+    /// Every peer of the routed pool is draining
+    PoolDraining,
+    /// Every attempted peer failed to deliver the request
+    RetriesExhausted,
+}
+
+impl DeadLetterReason {
+    /// Reason as used in records and metric labels
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::NoRoute => "no_route",
+            Self::PoolDraining => "pool_draining",
+            Self::RetriesExhausted => "retries_exhausted",
+        }
+    }
+}
+
+/// Summary of an undeliverable request, kept for post-incident analysis
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DeadLetter {
+    pub reason: DeadLetterReason,
+    pub vr_id: String,
+    pub connection_id: u64,
+    pub command_code: u32,
+    pub application_id: u32,
+    pub hop_by_hop_id: u32,
+    pub end_to_end_id: u32,
+    pub session_id: Option<String>,
+    pub destination_realm: Option<String>,
+    /// Pool the request was routed to, if any
+    pub pool_id: Option<String>,
+}
+
+impl DeadLetter {
+    /// Summarize a request, `None` if its payload does not parse
+    pub fn new(
+        reason: DeadLetterReason,
+        request: &DiameterPacketRequest,
+        pool_id: Option<&str>,
+    ) -> Option<Self> {
+        let packet = DiameterPacket::parse(&request.raw_payload).ok()?;
+        let text = |code| {
+            packet
+                .find_avp(code)
+                .map(|avp| String::from_utf8_lossy(&avp.data).into_owned())
+        };
+
+        Some(Self {
+            reason,
+            vr_id: request.vr_id.clone(),
+            connection_id: request.connection_id,
+            command_code: packet.header.command_code,
+            application_id: packet.header.application_id,
+            hop_by_hop_id: packet.header.hop_by_hop_id,
+            end_to_end_id: packet.header.end_to_end_id,
+            session_id: text(AVP_SESSION_ID),
+            destination_realm: text(AVP_DESTINATION_REALM),
+            pool_id: pool_id.map(str::to_string),
+        })
+    }
+}
+
+/// Destination of dead-letter records
+pub trait DeadLetterSink: Send + Sync {
+    fn write(&self, letter: &DeadLetter);
+}
+
+/// Records as structured warnings in the service log
+pub struct LogSink;
+
+impl DeadLetterSink for LogSink {
+    fn write(&self, letter: &DeadLetter) {
+        warn!(
+            reason = letter.reason.as_str(),
+            vr_id = %letter.vr_id,
+            command_code = letter.command_code,
+            application_id = letter.application_id,
+            hop_by_hop_id = letter.hop_by_hop_id,
+            session_id = ?letter.session_id,
+            destination_realm = ?letter.destination_realm,
+            pool_id = ?letter.pool_id,
+            "Dead letter"
+        );
+    }
+}
+
+/// Records as JSON lines appended to a file
+///
+/// Lines are written by a background task, so request processing never
+/// waits on the disk. Records are dropped while its queue is full.
+pub struct FileSink {
+    tx: mpsc::Sender<Vec<u8>>,
+    writer: JoinHandle<()>,
+}
+
+impl FileSink {
+    /// Open `path` for appending, creating it if needed
+    ///
+    /// The writer runs on the current Tokio runtime.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let (tx, mut rx) = mpsc::channel::<Vec<u8>>(FILE_SINK_QUEUE_CAPACITY);
+        let writer = tokio::spawn(async move {
+            let mut file = tokio::fs::File::from_std(file);
+            while let Some(line) = rx.recv().await {
+                if let Err(e) = file.write_all(&line).await {
+                    error!("Failed to write dead letter: {}", e);
+                }
+            }
+            if let Err(e) = file.flush().await {
+                error!("Failed to flush dead letters: {}", e);
+            }
+        });
+        Ok(Self { tx, writer })
+    }
+
+    /// Write out the queued records and stop the writer
+    pub async fn close(self) {
+        drop(self.tx);
+        let _ = self.writer.await;
+    }
+}
+
+impl DeadLetterSink for FileSink {
+    fn write(&self, letter: &DeadLetter) {
+        let mut line = serde_json::to_vec(letter).expect("dead letters serialize");
+        line.push(b'\n');
+        if self.tx.try_send(line).is_err() {
+            warn!("Dead-letter file writer is behind, dropping a record");
+        }
+    }
+}
+
+/// Records sent to an in-process consumer
+///
+/// Records are dropped while the channel is full rather than blocking
+/// request processing.
+pub struct ChannelSink {
+    tx: mpsc::Sender<DeadLetter>,
+}
+
+impl ChannelSink {
+    pub fn new(tx: mpsc::Sender<DeadLetter>) -> Self {
+        Self { tx }
+    }
+}
+
+impl DeadLetterSink for ChannelSink {
+    fn write(&self, letter: &DeadLetter) {
+        let _ = self.tx.try_send(letter.clone());
+    }
+}
+
+/// Rate-limited writer of dead letters
+///
+/// At most `max_per_second` records reach the sink each second; the rest
+/// are only counted, so a routing outage cannot flood the sink.
+pub struct DeadLetters {
+    sink: Box<dyn DeadLetterSink>,
+    max_per_second: u32,
+    // Start of the current one-second window and records written in it
+    window: Mutex<(Instant, u32)>,
+}
+
+impl DeadLetters {
+    pub fn new(sink: Box<dyn DeadLetterSink>, max_per_second: u32) -> Self {
+        Self {
+            sink,
+            max_per_second,
+            window: Mutex::new((Instant::now(), 0)),
+        }
+    }
+
+    /// Record a dead letter, unless the rate limit is reached
+    pub fn record(&self, letter: DeadLetter) {
+        self.record_at(letter, Instant::now());
+    }
+
+    /// Record a dead letter as of `now`, returning whether it was written
+    pub fn record_at(&self, letter: DeadLetter, now: Instant) -> bool {
+        cdde_metrics::DEAD_LETTERS_TOTAL
+            .with_label_values(&[letter.reason.as_str()])
+            .inc();
+
+        {
+            let mut window = self.window.lock().unwrap();
+            if now.duration_since(window.0) >= Duration::from_secs(1) {
+                *window = (now, 0);
+            }
+            if window.1 >= self.max_per_second {
+                cdde_metrics::DEAD_LETTERS_SUPPRESSED_TOTAL.inc();
+                return false;
+            }
+            window.1 += 1;
+        }
+
+        self.sink.write(&letter);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn letter() -> DeadLetter {
+        DeadLetter {
+            reason: DeadLetterReason::NoRoute,
+            vr_id: "vr001".to_string(),
+            connection_id: 1,
+            command_code: 318,
+            application_id: 16777251,
+            hop_by_hop_id: 1,
+            end_to_end_id: 1,
+            session_id: None,
+            destination_realm: None,
+            pool_id: None,
+        }
+    }
+
+    #[test]
+    fn test_records_beyond_rate_are_suppressed() {
+        let (tx, mut rx) = mpsc::channel(16);
+        let dead_letters = DeadLetters::new(Box::new(ChannelSink::new(tx)), 2);
+        let start = Instant::now();
+
+        assert!(dead_letters.record_at(letter(), start));
+        assert!(dead_letters.record_at(letter(), start + Duration::from_millis(100)));
+        assert!(!dead_letters.record_at(letter(), start + Duration::from_millis(900)));
+        // A new window admits records again
+        assert!(dead_letters.record_at(letter(), start + Duration::from_secs(1)));
+
+        let mut written = 0;
+        while rx.try_recv().is_ok() {
+            written += 1;
+        }
+        assert_eq!(written, 3);
+    }
+
+    #[tokio::test]
+    async fn test_file_sink_appends_json_lines() {
+        let path = std::env::temp_dir().join(format!("cdde-dead-letters-{}", std::process::id()));
+        let sink = FileSink::open(&path).unwrap();
+        sink.write(&letter());
+        sink.write(&letter());
+        sink.close().await;
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let lines: Vec<&str> = contents.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].contains(r#""reason":"no_route""#));
+    }
+}
@@ -0,0 +1,94 @@
+use cdde_core::cms::fetch_optional_json;
+use cdde_core::{CddeError, Result};
+use cdde_diameter_dict::DictionaryManager;
+use serde::Deserialize;
+use tracing::info;
+
+/// Fields of the CMS dictionary resource used by the DCR
+#[derive(Debug, Deserialize)]
+struct ActiveDictionary {
+    name: String,
+    version: String,
+    xml_content: String,
+}
+
+/// Fetch the dictionary version currently marked active in the CMS
+///
+/// Returns `None` when no version is active.
+async fn fetch_active_dictionary(cms_url: &str) -> Result<Option<ActiveDictionary>> {
+    fetch_optional_json(cms_url, "/api/v1/dictionaries/active", "active dictionary").await
+}
+
+/// Replace the dynamic AVPs with the CMS's active dictionary
+///
+/// With no active version the dynamic AVPs are cleared, so only the
+/// standard dictionary resolves. Returns whether a dictionary was loaded.
+pub async fn load_active_dictionary(manager: &DictionaryManager, cms_url: &str) -> Result<bool> {
+    let Some(dict) = fetch_active_dictionary(cms_url).await? else {
+        manager
+            .replace_dynamic_dictionary("<dictionary/>")
+            .map_err(CddeError::InternalError)?;
+        info!("No active dictionary in the CMS");
+        return Ok(false);
+    };
+
+    manager
+        .replace_dynamic_dictionary(&dict.xml_content)
+        .map_err(CddeError::ConfigError)?;
+    info!("Loaded active dictionary {} ({})", dict.name, dict.version);
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cdde_test_support::MockCms;
+
+    fn dictionary(name: &str) -> String {
+        serde_json::json!({
+            "id": 1,
+            "name": "dict",
+            "version": "1.0",
+            "xml_content": format!(r#"<dictionary><avp name="{name}" code="10101" type="Unsigned32"/></dictionary>"#),
+            "active": true
+        })
+        .to_string()
+    }
+
+    #[tokio::test]
+    async fn test_active_dictionary_replaces_dynamic_avps() {
+        let (url, _) = MockCms::new()
+            .with_json(dictionary("Version-One"))
+            .with_json(dictionary("Version-Two"))
+            .with_response(404, r#"{"error":"Resource not found"}"#)
+            .spawn()
+            .await;
+        let manager = DictionaryManager::new();
+
+        assert!(load_active_dictionary(&manager, &url).await.unwrap());
+        assert_eq!(manager.lookup(10101).unwrap().name, "Version-One");
+
+        // Reload after another version was activated
+        assert!(load_active_dictionary(&manager, &url).await.unwrap());
+        assert_eq!(manager.lookup(10101).unwrap().name, "Version-Two");
+
+        // No active version leaves only the standard AVPs
+        assert!(!load_active_dictionary(&manager, &url).await.unwrap());
+        assert!(manager.lookup(10101).is_none());
+        assert!(manager.lookup(264).is_some());
+    }
+
+    #[tokio::test]
+    async fn test_cms_error_keeps_current_dictionary() {
+        let (url, _) = MockCms::new()
+            .with_json(dictionary("Version-One"))
+            .with_response(500, r#"{"error":"Internal server error"}"#)
+            .spawn()
+            .await;
+        let manager = DictionaryManager::new();
+
+        load_active_dictionary(&manager, &url).await.unwrap();
+        assert!(load_active_dictionary(&manager, &url).await.is_err());
+        assert_eq!(manager.lookup(10101).unwrap().name, "Version-One");
+    }
+}
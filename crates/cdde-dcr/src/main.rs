@@ -1,42 +1,40 @@
+mod dead_letter;
+mod dictionary;
+mod peer_status;
 mod processor;
+mod realm_metrics;
+mod relay;
+mod retry;
 mod routing;
+mod selector;
+mod service;
+mod transform;
 
+pub use dead_letter::{
+    ChannelSink, DeadLetter, DeadLetterReason, DeadLetterSink, DeadLetters, FileSink, LogSink,
+};
+pub use dictionary::load_active_dictionary;
+pub use peer_status::PeerStatusService;
 pub use processor::PacketProcessor;
+pub use realm_metrics::{RealmMetrics, DEFAULT_REALM_LABEL_LIMIT, OTHER_REALM};
+pub use relay::PeerRelayClient;
+pub use retry::{RetryPolicy, RetryRule};
 pub use routing::{RouteCondition, RouteEntry, RoutingDecision, RoutingEngine};
+pub use selector::{
+    selector_for, ConsistentHash, FirstMatch, PeerSelector, RoundRobin, SelectionContext, Weighted,
+};
+pub use service::CoreRouterServiceImpl;
+pub use transform::{
+    load_rules, AvpAllowlist, DslTransform, Transform, TransformContext, TransformPipeline,
+};
 
-use cdde_proto::core_router_service_server::CoreRouterService;
-use cdde_proto::{DiameterPacketAction, DiameterPacketRequest};
+use cdde_config::{AppConfig, DeadLetterSinkConfig};
+use cdde_core::{HealthThresholds, PeerHealthRegistry};
+use cdde_diameter_dict::DictionaryManager;
+use cdde_dsl_engine::RuleEngine;
 use std::sync::Arc;
-use tonic::{Request, Response, Status};
-use tracing::info;
-
-/// Simple in-memory gRPC service implementation
-pub struct CoreRouterServiceImpl {
-    processor: Arc<PacketProcessor>,
-}
-
-impl CoreRouterServiceImpl {
-    pub fn new(processor: PacketProcessor) -> Self {
-        Self {
-            processor: Arc::new(processor),
-        }
-    }
-}
-
-#[tonic::async_trait]
-impl CoreRouterService for CoreRouterServiceImpl {
-    async fn process_packet(
-        &self,
-        request: Request<DiameterPacketRequest>,
-    ) -> Result<Response<DiameterPacketAction>, Status> {
-        let req = request.into_inner();
-        let action = self
-            .processor
-            .process(req)
-            .map_err(|e| Status::internal(format!("Processing error: {e}")))?;
-        Ok(Response::new(action))
-    }
-}
+use std::time::Duration;
+use tracing::{error, info, warn};
 
 #[tokio::main]
 async fn main() {
@@ -52,6 +50,31 @@ async fn main() {
         "Starting Diameter Core Router service"
     );
 
+    // Load configuration, falling back to defaults
+    let config: AppConfig = match std::env::var("CDDE_CONFIG") {
+        Ok(path) => cdde_config::load_config(&path).unwrap_or_else(|e| {
+            warn!("Failed to load config from {}: {}, using defaults", path, e);
+            AppConfig::default()
+        }),
+        Err(_) => AppConfig::default(),
+    };
+
+    // Peer health scores fed by answer observations
+    let health = Arc::new(PeerHealthRegistry::new(HealthThresholds {
+        latency_threshold: Duration::from_millis(config.peer_health.latency_threshold_ms),
+        error_rate_threshold: config.peer_health.error_rate_threshold,
+        smoothing: config.peer_health.smoothing,
+    }));
+
+    // Dictionary: the CMS's active version, reloaded on SIGHUP
+    let dictionary = Arc::new(DictionaryManager::new());
+    if let Ok(cms_url) = std::env::var("CMS_URL") {
+        if let Err(e) = load_active_dictionary(&dictionary, &cms_url).await {
+            error!("Failed to load active dictionary from {}: {}", cms_url, e);
+        }
+        tokio::spawn(reload_dictionary_on_hangup(dictionary.clone(), cms_url));
+    }
+
     // Create default routing configuration
     let routes = vec![RouteEntry {
         priority: 100,
@@ -59,20 +82,156 @@ async fn main() {
         target_pool_id: "default-pool".to_string(),
     }];
 
-    let routing_engine = RoutingEngine::new(routes);
-    let processor = PacketProcessor::new(routing_engine, None);
+    let routing_engine = config.dcr.peer_selection.iter().fold(
+        RoutingEngine::new(routes)
+            .with_precedence(config.dcr.route_precedence)
+            .with_health(health.clone()),
+        |engine, selection| {
+            let selector = selector_for(selection);
+            info!(
+                "Virtual router {} uses {} peer selection",
+                selection.vr_id,
+                selector.name()
+            );
+            engine.with_vr_selector(selection.vr_id.clone(), selector)
+        },
+    );
+    // Manipulation rules type AVP values with the dictionary reloaded above
+    let manipulation = config.dcr.manipulation.as_ref().map(|manipulation| {
+        let rules = load_rules(&manipulation.rules_path).unwrap_or_else(|e| {
+            error!("Failed to load manipulation rules: {}", e);
+            std::process::exit(1);
+        });
+        info!(
+            "Loaded {} manipulation rules from {}",
+            rules.len(),
+            manipulation.rules_path
+        );
+        let engine = RuleEngine::new(rules).with_dictionary(dictionary.clone());
+        let stage = DslTransform::new("dsl", engine);
+        if manipulation.applications.is_empty() {
+            stage
+        } else {
+            stage.with_applications(manipulation.applications.iter().copied())
+        }
+    });
+    let mut processor =
+        PacketProcessor::new(routing_engine, None).with_strict_commands(config.dcr.strict_commands);
+    if let Some(stage) = manipulation {
+        processor = processor.with_transform(Box::new(stage));
+    }
+    match (&config.dcr.origin_host, &config.dcr.origin_realm) {
+        (Some(origin_host), Some(origin_realm)) => {
+            info!("DCR identity is {} of realm {}", origin_host, origin_realm);
+            processor = processor.with_identity(origin_host.clone(), origin_realm.clone());
+        }
+        _ => warn!(
+            "dcr.origin_host and dcr.origin_realm are not both set, \
+             instances share the default identity"
+        ),
+    }
+    if let Some(result_code) = config.dcr.draining_result_code {
+        processor = processor.with_draining_result_code(result_code);
+    }
+    if let Some(dead_letter) = &config.dcr.dead_letter {
+        let sink: Box<dyn DeadLetterSink> = match &dead_letter.sink {
+            DeadLetterSinkConfig::Log => Box::new(LogSink),
+            DeadLetterSinkConfig::File { path } => match FileSink::open(path) {
+                Ok(sink) => Box::new(sink),
+                Err(e) => {
+                    error!(
+                        "Failed to open dead-letter file {}: {}, logging instead",
+                        path, e
+                    );
+                    Box::new(LogSink)
+                }
+            },
+        };
+        processor = processor.with_dead_letters(DeadLetters::new(sink, dead_letter.max_per_second));
+    }
+
+    // Whitelist mode runs last so AVPs added by earlier stages are filtered too
+    if !config.dcr.avp_allowlists.is_empty() {
+        let allowlist = config
+            .dcr
+            .avp_allowlists
+            .iter()
+            .fold(AvpAllowlist::new(), |allowlist, vr| {
+                allowlist.with_vr(vr.vr_id.clone(), vr.avps.clone())
+            });
+        processor = processor.with_transform(Box::new(allowlist));
+    }
 
     info!("DCR service initialized with packet processor");
 
     // Start gRPC server
     let addr = "[::1]:50051".parse().unwrap();
-    let service = CoreRouterServiceImpl::new(processor);
+    let mut service = CoreRouterServiceImpl::new(processor);
+    if let Some(limit) = config.dcr.max_in_flight {
+        service = service.with_max_in_flight(limit);
+    }
+    if let Some(endpoint) = &config.dcr.relay_endpoint {
+        match PeerRelayClient::new(endpoint) {
+            Ok(relay) => {
+                info!(
+                    "Delivering routed requests through the DPA relay at {}",
+                    endpoint
+                );
+                service = service.with_relay(relay);
+            }
+            Err(e) => {
+                error!("{}", e);
+                std::process::exit(1);
+            }
+        }
+    }
 
     info!("Starting gRPC server on {}", addr);
 
-    tonic::transport::Server::builder()
-        .add_service(cdde_proto::core_router_service_server::CoreRouterServiceServer::new(service))
-        .serve(addr)
-        .await
-        .unwrap();
+    let mut server = tonic::transport::Server::builder()
+        .max_concurrent_streams(config.dcr.max_concurrent_streams);
+    if let Some(limit) = config.dcr.concurrency_limit_per_connection {
+        server = server.concurrency_limit_per_connection(limit);
+    }
+    let service = Arc::new(service);
+    let router = server
+        .add_service(
+            cdde_proto::core_router_service_server::CoreRouterServiceServer::from_arc(
+                service.clone(),
+            ),
+        )
+        // Peer status from the DPA, so draining and down peers are avoided
+        .add_service(
+            cdde_proto::routing_update_service_server::RoutingUpdateServiceServer::new(
+                PeerStatusService::new(health),
+            ),
+        );
+    #[cfg(feature = "compact-codec")]
+    let router = router.add_service(
+        cdde_proto::compact::core_router_service_server::CoreRouterServiceServer::from_arc(service),
+    );
+    router.serve(addr).await.unwrap();
+}
+
+/// Reload the active dictionary from the CMS each time SIGHUP is received
+async fn reload_dictionary_on_hangup(dictionary: Arc<DictionaryManager>, cms_url: String) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut hangup = match signal(SignalKind::hangup()) {
+        Ok(hangup) => hangup,
+        Err(e) => {
+            error!(
+                "Cannot listen for SIGHUP, dictionary reload disabled: {}",
+                e
+            );
+            return;
+        }
+    };
+
+    while hangup.recv().await.is_some() {
+        info!("SIGHUP received, reloading active dictionary");
+        if let Err(e) = load_active_dictionary(&dictionary, &cms_url).await {
+            error!("Failed to reload active dictionary: {}", e);
+        }
+    }
 }
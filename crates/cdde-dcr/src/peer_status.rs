@@ -0,0 +1,84 @@
+use cdde_core::PeerHealthRegistry;
+use cdde_proto::routing_update_service_server::RoutingUpdateService;
+use cdde_proto::{PeerStatus, PeerStatusRequest, UpdateResponse};
+use std::sync::Arc;
+use tonic::{Request, Response, Status};
+use tracing::info;
+
+/// Keeps peer selection current from DPA peer status notifications
+///
+/// Down peers are scored out of selection and draining peers are skipped,
+/// so requests go to the rest of their pool.
+pub struct PeerStatusService {
+    health: Arc<PeerHealthRegistry>,
+}
+
+impl PeerStatusService {
+    /// Create a handler updating the registry used for routing
+    pub fn new(health: Arc<PeerHealthRegistry>) -> Self {
+        Self { health }
+    }
+}
+
+#[tonic::async_trait]
+impl RoutingUpdateService for PeerStatusService {
+    async fn update_peer_status(
+        &self,
+        request: Request<PeerStatusRequest>,
+    ) -> Result<Response<UpdateResponse>, Status> {
+        let request = request.into_inner();
+        let status = PeerStatus::try_from(request.current_status)
+            .map_err(|_| Status::invalid_argument("unknown peer status"))?;
+
+        info!(peer = %request.peer_node_id, status = ?status, "Peer status update");
+        self.health
+            .set_down(&request.peer_node_id, status == PeerStatus::Down);
+        self.health
+            .set_draining(&request.peer_node_id, status == PeerStatus::Draining);
+
+        Ok(Response::new(UpdateResponse {
+            success: true,
+            message: String::new(),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::routing::{RouteCondition, RouteEntry, RoutingEngine};
+    use cdde_core::HealthThresholds;
+
+    fn request(status: PeerStatus) -> Request<PeerStatusRequest> {
+        Request::new(PeerStatusRequest {
+            peer_node_id: "hss01".to_string(),
+            current_status: status as i32,
+            virtual_router_ids: vec![],
+        })
+    }
+
+    #[tokio::test]
+    async fn test_draining_pool_follows_notifications() {
+        let health = Arc::new(PeerHealthRegistry::new(HealthThresholds::default()));
+        let engine = RoutingEngine::new(vec![RouteEntry {
+            priority: 10,
+            condition: RouteCondition::Default,
+            target_pool_id: "pool-hss".to_string(),
+        }])
+        .with_pool("pool-hss", vec!["hss01".to_string()])
+        .with_health(health.clone());
+        let service = PeerStatusService::new(health);
+
+        service
+            .update_peer_status(request(PeerStatus::Draining))
+            .await
+            .unwrap();
+        assert!(engine.pool_draining("pool-hss"));
+
+        service
+            .update_peer_status(request(PeerStatus::Up))
+            .await
+            .unwrap();
+        assert!(!engine.pool_draining("pool-hss"));
+    }
+}
@@ -1,91 +1,557 @@
-use crate::routing::RoutingEngine;
-use cdde_core::{DiameterPacket, Result};
-use cdde_dsl_engine::{Avp, RuleEngine};
+use crate::dead_letter::{DeadLetter, DeadLetterReason, DeadLetters};
+use crate::realm_metrics::RealmMetrics;
+use crate::retry::RetryPolicy;
+use crate::routing::{RoutingDecision, RoutingEngine};
+use crate::selector::SelectionContext;
+use crate::transform::{DslTransform, Transform, TransformContext, TransformPipeline};
+use bytes::Bytes;
+use cdde_core::codes::{
+    application_name, command_name, AVP_DESTINATION_HOST, AVP_DESTINATION_REALM, AVP_PROXY_HOST,
+    AVP_PROXY_INFO, AVP_PROXY_STATE, AVP_RESULT_CODE, AVP_SESSION_ID, CMD_DEVICE_WATCHDOG,
+    CMD_DISCONNECT_PEER, RESULT_COMMAND_UNSUPPORTED, RESULT_SUCCESS, RESULT_TOO_BUSY,
+};
+use cdde_core::command::BASE_COMMANDS;
+use cdde_core::diameter::{mark_retransmitted, AVP_FLAG_MANDATORY};
+use cdde_core::{DiameterAvp, DiameterHeader, DiameterPacket, Result};
+use cdde_dsl_engine::RuleEngine;
 use cdde_proto::{ActionType, DiameterPacketAction, DiameterPacketRequest};
+use std::future::Future;
+use std::time::Instant;
+use tracing::{debug, warn};
+
+/// DIAMETER_UNABLE_TO_DELIVER
+const RESULT_UNABLE_TO_DELIVER: u32 = 3002;
+
+/// DIAMETER_REALM_NOT_SERVED
+const RESULT_REALM_NOT_SERVED: u32 = 3003;
+
+/// Metric label of applications without a well-known name
+const OTHER_APPLICATION: &str = "other";
 
 /// Packet processor for DCR
 pub struct PacketProcessor {
     routing_engine: RoutingEngine,
-    rule_engine: Option<RuleEngine>,
+    pipeline: TransformPipeline,
+    retry_policy: RetryPolicy,
+    realm_metrics: RealmMetrics,
+    origin_host: String,
+    origin_realm: String,
+    strict_commands: bool,
+    draining_result_code: u32,
+    dead_letters: Option<DeadLetters>,
 }
 
 impl PacketProcessor {
     /// Create new packet processor
+    ///
+    /// The rule engine, when given, becomes the first transform stage.
     pub fn new(routing_engine: RoutingEngine, rule_engine: Option<RuleEngine>) -> Self {
+        let mut pipeline = TransformPipeline::new();
+        if let Some(engine) = rule_engine {
+            pipeline.push(Box::new(DslTransform::new("dsl", engine)));
+        }
+
         Self {
             routing_engine,
-            rule_engine,
+            pipeline,
+            retry_policy: RetryPolicy::default(),
+            realm_metrics: RealmMetrics::default(),
+            origin_host: "dcr.example.com".to_string(),
+            origin_realm: "example.com".to_string(),
+            strict_commands: false,
+            draining_result_code: RESULT_TOO_BUSY,
+            dead_letters: None,
         }
     }
 
+    /// Set the Origin-Host and Origin-Realm of answers generated by the DCR
+    pub fn with_identity(mut self, origin_host: String, origin_realm: String) -> Self {
+        self.origin_host = origin_host;
+        self.origin_realm = origin_realm;
+        self
+    }
+
+    /// Answer unroutable requests for unknown commands with COMMAND_UNSUPPORTED
+    ///
+    /// A command is known when it belongs to the base protocol or a route
+    /// names it; other unroutable requests keep REALM_NOT_SERVED.
+    pub fn with_strict_commands(mut self, strict: bool) -> Self {
+        self.strict_commands = strict;
+        self
+    }
+
+    /// Set the Result-Code answering requests whose pool is entirely draining
+    ///
+    /// Defaults to DIAMETER_TOO_BUSY.
+    pub fn with_draining_result_code(mut self, result_code: u32) -> Self {
+        self.draining_result_code = result_code;
+        self
+    }
+
+    /// Record requests given up as undeliverable
+    pub fn with_dead_letters(mut self, dead_letters: DeadLetters) -> Self {
+        self.dead_letters = Some(dead_letters);
+        self
+    }
+
+    /// Set which requests may be retried on another peer
+    pub fn with_retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.retry_policy = policy;
+        self
+    }
+
+    /// Limit the number of distinct realm labels in the per-realm metrics
+    pub fn with_realm_label_limit(mut self, limit: usize) -> Self {
+        self.realm_metrics = RealmMetrics::new(limit);
+        self
+    }
+
+    /// Append a transform stage after the existing ones
+    pub fn with_transform(mut self, stage: Box<dyn Transform>) -> Self {
+        self.pipeline.push(stage);
+        self
+    }
+
     /// Process incoming packet request
+    ///
+    /// Routed requests are forwarded with their failover candidates;
+    /// unroutable ones are answered with DIAMETER_REALM_NOT_SERVED.
     pub fn process(&self, request: DiameterPacketRequest) -> Result<DiameterPacketAction> {
-        // Parse Diameter packet
-        let packet = DiameterPacket::parse(&request.raw_payload)?;
+        let header = DiameterHeader::parse(&request.raw_payload)?;
+        if let Some(action) = self.local_action(&request, &header) {
+            return Ok(action);
+        }
 
-        // Extract routing parameters
-        let dest_host = packet
-            .find_avp(293)
-            .and_then(|avp| String::from_utf8(avp.data.clone()).ok());
+        let Some((route, packet)) = self.route(&request)? else {
+            return Ok(self.unroutable_reply(&request, &header));
+        };
+        if let Some(action) = self.draining_reply(&request, &route) {
+            return Ok(action);
+        }
 
-        let dest_realm = packet
-            .find_avp(283)
-            .and_then(|avp| String::from_utf8(avp.data.clone()).ok());
+        // Only requests that are safe to retry get more than one candidate
+        let limit = if self
+            .retry_policy
+            .allows(packet.header.application_id, packet.header.command_code)
+        {
+            self.retry_policy.max_attempts
+        } else {
+            1
+        };
+        let candidate_peers = self.routing_engine.failover_candidates(
+            &route.pool_id,
+            &route.target_peer,
+            limit,
+            &selection_context(&request),
+        );
 
-        // Find route
-        let route = self.routing_engine.find_route(
-            dest_host.as_deref(),
-            dest_realm.as_deref(),
-            packet.header.application_id,
-            packet.header.command_code,
+        // Requests carry our Proxy-Info outwards, answers lose it on the way back
+        let payload = if packet.header.is_request() {
+            self.with_proxy_info(&packet, &request)
+        } else {
+            self.strip_proxy_info(packet.serialize())
+        };
+
+        Ok(DiameterPacketAction {
+            action_type: ActionType::Forward as i32,
+            target_host_name: route.target_peer,
+            response_payload: payload.into(),
+            original_connection_id: request.connection_id,
+            candidate_peers,
+            result_code: 0,
+        })
+    }
+
+    /// Route a request and deliver it, retrying on other pool peers when allowed
+    ///
+    /// `deliver` sends the payload to a peer and returns its answer. A
+    /// delivery error or a DIAMETER_UNABLE_TO_DELIVER answer moves on to the
+    /// next peer of the pool if the retry policy allows the request. The
+    /// returned action replies with the first usable answer, or with
+    /// DIAMETER_UNABLE_TO_DELIVER when no peer could be reached. Every
+    /// attempt is recorded in the peer health scores.
+    ///
+    /// Messages that need no delivery get the same action as from `process`.
+    pub async fn forward_with_retry<F, Fut>(
+        &self,
+        request: DiameterPacketRequest,
+        mut deliver: F,
+    ) -> Result<DiameterPacketAction>
+    where
+        F: FnMut(String, Vec<u8>) -> Fut,
+        Fut: Future<Output = Result<Vec<u8>>>,
+    {
+        let header = DiameterHeader::parse(&request.raw_payload)?;
+        if let Some(action) = self.local_action(&request, &header) {
+            return Ok(action);
+        }
+        if !header.is_request() {
+            return self.process(request);
+        }
+        let Some((route, packet)) = self.route(&request)? else {
+            return Ok(self.unroutable_reply(&request, &header));
+        };
+        if let Some(action) = self.draining_reply(&request, &route) {
+            return Ok(action);
+        }
+
+        let retryable = self
+            .retry_policy
+            .allows(packet.header.application_id, packet.header.command_code);
+        let mut payload = self.with_proxy_info(&packet, &request);
+        let mut tried = vec![route.target_peer.clone()];
+        let mut peer = route.target_peer;
+
+        loop {
+            cdde_metrics::FORWARD_ATTEMPTS_TOTAL
+                .with_label_values(&[&route.pool_id])
+                .inc();
+            let started = Instant::now();
+            let result = deliver(peer.clone(), payload.clone()).await;
+            let delivered = matches!(&result, Ok(answer) if !is_unable_to_deliver(answer));
+            self.routing_engine
+                .record_answer(&peer, started.elapsed(), delivered);
+            let last = match result {
+                Ok(answer) if delivered => {
+                    let answer = self.strip_proxy_info(answer);
+                    return Ok(DiameterPacketAction {
+                        action_type: ActionType::Reply as i32,
+                        target_host_name: peer,
+                        response_payload: answer.into(),
+                        original_connection_id: request.connection_id,
+                        candidate_peers: vec![],
+                        result_code: 0,
+                    });
+                }
+                other => other,
+            };
+
+            let next = if retryable && tried.len() < self.retry_policy.max_attempts {
+                self.routing_engine.select_peer_excluding(
+                    &route.pool_id,
+                    &tried,
+                    &selection_context(&request),
+                )
+            } else {
+                None
+            };
+
+            let Some(next) = next else {
+                // Give up with whatever the last peer produced
+                cdde_metrics::FORWARD_RETRIES_EXHAUSTED_TOTAL
+                    .with_label_values(&[&route.pool_id])
+                    .inc();
+                self.dead_letter(
+                    DeadLetterReason::RetriesExhausted,
+                    &request,
+                    Some(&route.pool_id),
+                );
+                return Ok(match last {
+                    Ok(answer) => DiameterPacketAction {
+                        action_type: ActionType::Reply as i32,
+                        target_host_name: peer,
+                        response_payload: self.strip_proxy_info(answer).into(),
+                        original_connection_id: request.connection_id,
+                        candidate_peers: vec![],
+                        result_code: 0,
+                    },
+                    Err(e) => {
+                        warn!("Delivery to {} failed: {}", peer, e);
+                        self.error_reply(&request, RESULT_UNABLE_TO_DELIVER)
+                    }
+                });
+            };
+
+            warn!(
+                "Delivery of {} request to {} failed, retrying on {}",
+                application_name(packet.header.application_id).unwrap_or("unknown application"),
+                peer,
+                next
+            );
+            cdde_metrics::FORWARD_RETRIES_TOTAL
+                .with_label_values(&[&route.pool_id])
+                .inc();
+            tried.push(next.clone());
+            peer = next;
+            mark_retransmitted(&mut payload);
+        }
+    }
+
+    /// Reply for a request no route matches
+    fn unroutable_reply(
+        &self,
+        request: &DiameterPacketRequest,
+        header: &DiameterHeader,
+    ) -> DiameterPacketAction {
+        let result_code = if self.strict_commands && !self.knows_command(header.command_code) {
+            debug!("Command {} is not supported", header.command_code);
+            RESULT_COMMAND_UNSUPPORTED
+        } else {
+            RESULT_REALM_NOT_SERVED
+        };
+        self.dead_letter(DeadLetterReason::NoRoute, request, None);
+        self.error_reply(request, result_code)
+    }
+
+    /// Reply for a request whose pool has no peer left that is not draining
+    fn draining_reply(
+        &self,
+        request: &DiameterPacketRequest,
+        route: &RoutingDecision,
+    ) -> Option<DiameterPacketAction> {
+        if !self.routing_engine.pool_draining(&route.pool_id) {
+            return None;
+        }
+        debug!("Every peer of pool {} is draining", route.pool_id);
+        self.dead_letter(
+            DeadLetterReason::PoolDraining,
+            request,
+            Some(&route.pool_id),
         );
+        Some(self.error_reply(request, self.draining_result_code))
+    }
+
+    /// Record a request that will not be delivered, if dead letters are kept
+    fn dead_letter(
+        &self,
+        reason: DeadLetterReason,
+        request: &DiameterPacketRequest,
+        pool_id: Option<&str>,
+    ) {
+        let Some(dead_letters) = &self.dead_letters else {
+            return;
+        };
+        if let Some(letter) = DeadLetter::new(reason, request, pool_id) {
+            dead_letters.record(letter);
+        }
+    }
 
-        if route.is_none() {
-            // No route found - return error action
-            return Ok(DiameterPacketAction {
+    /// Wire bytes of a relayed request with our Proxy-Info appended
+    ///
+    /// The Proxy-State records the client connection the request came from.
+    fn with_proxy_info(&self, packet: &DiameterPacket, request: &DiameterPacketRequest) -> Vec<u8> {
+        let member = |code, data: &[u8]| DiameterAvp {
+            code,
+            flags: AVP_FLAG_MANDATORY,
+            vendor_id: None,
+            data: data.to_vec(),
+        };
+        let proxy_info = DiameterAvp::from_grouped(
+            AVP_PROXY_INFO,
+            AVP_FLAG_MANDATORY,
+            None,
+            &[
+                member(AVP_PROXY_HOST, self.origin_host.as_bytes()),
+                member(AVP_PROXY_STATE, &request.connection_id.to_be_bytes()),
+            ],
+        );
+        packet.serialize_with(&[proxy_info])
+    }
+
+    /// Remove the Proxy-Info we added from an answer
+    ///
+    /// Proxy-Info of other relays is kept, and answers that do not parse are
+    /// passed through untouched.
+    fn strip_proxy_info(&self, answer: Vec<u8>) -> Vec<u8> {
+        let Ok(mut packet) = DiameterPacket::parse(&answer) else {
+            return answer;
+        };
+        let before = packet.avps.len();
+        packet
+            .avps
+            .retain(|avp| avp.code != AVP_PROXY_INFO || !self.is_own_proxy_info(avp));
+        if packet.avps.len() == before {
+            return answer;
+        }
+        packet.serialize()
+    }
+
+    fn is_own_proxy_info(&self, avp: &DiameterAvp) -> bool {
+        avp.parse_grouped().is_ok_and(|members| {
+            members.iter().any(|member| {
+                member.code == AVP_PROXY_HOST && member.data == self.origin_host.as_bytes()
+            })
+        })
+    }
+
+    /// Whether the command is part of the base protocol or named by a route
+    fn knows_command(&self, command_code: u32) -> bool {
+        BASE_COMMANDS
+            .iter()
+            .any(|spec| spec.command_code == command_code)
+            || self.routing_engine.routes_command(command_code)
+    }
+
+    /// Handle base protocol messages that are never routed
+    ///
+    /// Watchdogs and disconnects concern the hop they arrive on, so they are
+    /// answered here; stray answers to them are discarded.
+    fn local_action(
+        &self,
+        request: &DiameterPacketRequest,
+        header: &DiameterHeader,
+    ) -> Option<DiameterPacketAction> {
+        if ![CMD_DEVICE_WATCHDOG, CMD_DISCONNECT_PEER].contains(&header.command_code) {
+            return None;
+        }
+
+        if !header.is_request() {
+            debug!(
+                "Discarding base protocol message, command {}",
+                header.command_code
+            );
+            return Some(DiameterPacketAction {
                 action_type: ActionType::Discard as i32,
-                target_host_name: "".to_string(),
-                response_payload: vec![],
+                target_host_name: String::new(),
+                response_payload: Bytes::new(),
                 original_connection_id: request.connection_id,
+                candidate_peers: vec![],
+                result_code: 0,
             });
         }
 
-        let route = route.unwrap();
+        // DWR and DPR carry nothing the answer needs to echo
+        let answer = DiameterPacket {
+            header: header.clone(),
+            avps: vec![],
+        }
+        .into_answer(RESULT_SUCCESS, &self.origin_host, &self.origin_realm);
 
-        // Apply manipulation rules if configured
-        if let Some(ref engine) = self.rule_engine {
-            let mut avps: Vec<Avp> = packet
-                .avps
-                .iter()
-                .map(|diameter_avp| Avp {
-                    code: diameter_avp.code,
-                    value: String::from_utf8_lossy(&diameter_avp.data).to_string(),
-                })
-                .collect();
+        Some(DiameterPacketAction {
+            action_type: ActionType::Reply as i32,
+            target_host_name: String::new(),
+            response_payload: answer.to_bytes(),
+            original_connection_id: request.connection_id,
+            candidate_peers: vec![],
+            result_code: 0,
+        })
+    }
 
-            // Process with DSL engine
-            engine.process(&mut avps).ok();
+    /// Answer a request with an error Result-Code from this node
+    ///
+    /// `result_code` is kept on the action so the DFL can still build the
+    /// answer itself if the request does not parse.
+    fn error_reply(
+        &self,
+        request: &DiameterPacketRequest,
+        result_code: u32,
+    ) -> DiameterPacketAction {
+        let answer = DiameterPacket::parse(&request.raw_payload)
+            .map(|packet| {
+                packet
+                    .into_answer(result_code, &self.origin_host, &self.origin_realm)
+                    .to_bytes()
+            })
+            .unwrap_or_default();
 
-            // Update packet AVPs (simplified - in production would need proper conversion)
-            // For now, we'll skip the conversion back
+        DiameterPacketAction {
+            action_type: ActionType::Reply as i32,
+            target_host_name: String::new(),
+            response_payload: answer,
+            original_connection_id: request.connection_id,
+            candidate_peers: vec![],
+            result_code,
         }
+    }
 
-        // Serialize modified packet
-        let response_payload = packet.serialize();
+    /// Route a request, then parse and transform it
+    ///
+    /// Routing only needs the header and a few top-level AVPs, which are read
+    /// straight from the wire bytes, so unroutable requests are never fully
+    /// parsed.
+    fn route(
+        &self,
+        request: &DiameterPacketRequest,
+    ) -> Result<Option<(RoutingDecision, DiameterPacket)>> {
+        let data = &request.raw_payload;
+        let header = DiameterPacket::peek_header(data)?;
 
-        Ok(DiameterPacketAction {
-            action_type: ActionType::Forward as i32,
-            target_host_name: route.target_peer,
-            response_payload,
-            original_connection_id: request.connection_id,
-        })
+        // Extract routing parameters
+        let dest_host = top_level_str(data, AVP_DESTINATION_HOST)?;
+        let dest_realm = top_level_str(data, AVP_DESTINATION_REALM)?;
+        self.realm_metrics.record(dest_realm);
+
+        // Find route
+        let route = self.routing_engine.find_route_in(
+            &selection_context(request),
+            dest_host,
+            dest_realm,
+            header.application_id,
+            header.command_code,
+        );
+
+        let Some(route) = route else {
+            debug!(
+                "No route for realm {:?}, command {} ({}), application {} ({})",
+                dest_realm,
+                header.command_code,
+                command_name(header.command_code).unwrap_or("unknown"),
+                header.application_id,
+                application_name(header.application_id).unwrap_or("unknown")
+            );
+            return Ok(None);
+        };
+
+        let mut packet = DiameterPacket::parse(data)?;
+
+        // Apply manipulation stages in order, skipping relay-only applications
+        let ctx = TransformContext {
+            vr_id: request.vr_id.clone(),
+        };
+        if self.pipeline.applies_to(&ctx, packet.header.application_id) {
+            self.pipeline.apply(&mut packet, &ctx);
+        } else {
+            cdde_metrics::MANIPULATION_BYPASSED_TOTAL
+                .with_label_values(&[&application_label(packet.header.application_id)])
+                .inc();
+        }
+
+        Ok(Some((route, packet)))
     }
 }
 
+/// Label of an application id in metrics
+///
+/// Only well-known applications get their own label, keeping the number of
+/// label values bounded whatever ids clients send.
+fn application_label(application_id: u32) -> String {
+    match application_name(application_id) {
+        Some(_) => application_id.to_string(),
+        None => OTHER_APPLICATION.to_string(),
+    }
+}
+
+/// Attributes of a request used to pick a peer
+///
+/// The Session-Id is the one the client sent, before any manipulation.
+fn selection_context(request: &DiameterPacketRequest) -> SelectionContext<'_> {
+    SelectionContext {
+        vr_id: Some(&request.vr_id),
+        session_id: top_level_str(&request.raw_payload, AVP_SESSION_ID)
+            .ok()
+            .flatten(),
+    }
+}
+
+/// UTF-8 value of a top-level AVP, read without parsing the whole message
+fn top_level_str(data: &[u8], code: u32) -> Result<Option<&str>> {
+    Ok(DiameterPacket::find_top_level_avp(data, code)?
+        .and_then(|data| std::str::from_utf8(data).ok()))
+}
+
+/// Check if an answer carries DIAMETER_UNABLE_TO_DELIVER
+fn is_unable_to_deliver(answer: &[u8]) -> bool {
+    DiameterPacket::parse(answer).ok().and_then(|packet| {
+        let avp = packet.find_avp(AVP_RESULT_CODE)?;
+        Some(u32::from_be_bytes(avp.data.as_slice().try_into().ok()?))
+    }) == Some(RESULT_UNABLE_TO_DELIVER)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::routing::{RouteCondition, RouteEntry};
+    use cdde_core::CddeError;
 
     #[test]
     fn test_packet_processor() {
@@ -108,12 +574,547 @@ mod tests {
             connection_id: 123,
             vr_id: "vr001".to_string(),
             reception_timestamp: 1234567890,
-            raw_payload: test_packet,
+            raw_payload: test_packet.into(),
             session_tx_id: 456,
+            trace_id: String::new(),
         };
 
         let action = processor.process(request).unwrap();
         assert_eq!(action.action_type, ActionType::Forward as i32);
         assert_eq!(action.target_host_name, "default-pool".to_string());
     }
+
+    fn retry_processor(policy: RetryPolicy) -> PacketProcessor {
+        let routes = vec![RouteEntry {
+            priority: 10,
+            condition: RouteCondition::Default,
+            target_pool_id: "pool-hss".to_string(),
+        }];
+        let routing_engine = RoutingEngine::new(routes)
+            .with_pool("pool-hss", vec!["hss01".to_string(), "hss02".to_string()]);
+
+        PacketProcessor::new(routing_engine, None).with_retry_policy(policy)
+    }
+
+    fn air_request() -> DiameterPacketRequest {
+        let packet = DiameterPacket {
+            header: cdde_core::DiameterHeader {
+                version: 1,
+                length: 0,
+                flags: 0xC0,
+                command_code: 318,
+                application_id: 16777251,
+                hop_by_hop_id: 1,
+                end_to_end_id: 2,
+            },
+            avps: vec![],
+        };
+
+        DiameterPacketRequest {
+            connection_id: 7,
+            vr_id: "vr001".to_string(),
+            reception_timestamp: 0,
+            raw_payload: packet.to_bytes(),
+            session_tx_id: 0,
+            trace_id: String::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_retry_on_second_peer() {
+        let processor = retry_processor(RetryPolicy {
+            max_attempts: 2,
+            allowed: vec![crate::retry::RetryRule {
+                app_id: 16777251,
+                command_code: Some(318),
+            }],
+        });
+
+        let mut first_peer = None;
+        let action = processor
+            .forward_with_retry(air_request(), |peer, payload| {
+                let failed = first_peer.get_or_insert_with(|| peer.clone()) == &peer;
+                async move {
+                    if failed {
+                        Err(CddeError::NetworkError("connection reset".to_string()))
+                    } else {
+                        Ok(payload)
+                    }
+                }
+            })
+            .await
+            .unwrap();
+
+        let first_peer = first_peer.unwrap();
+        assert_eq!(action.action_type, ActionType::Reply as i32);
+        assert_ne!(action.target_host_name, first_peer);
+        assert!(["hss01", "hss02"].contains(&action.target_host_name.as_str()));
+    }
+
+    #[tokio::test]
+    async fn test_no_retry_without_policy() {
+        let processor = retry_processor(RetryPolicy::default());
+
+        let mut attempts = 0;
+        let action = processor
+            .forward_with_retry(air_request(), |_peer, _payload| {
+                attempts += 1;
+                async { Err(CddeError::NetworkError("connection reset".to_string())) }
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(action.action_type, ActionType::Reply as i32);
+        assert_eq!(action.result_code, RESULT_UNABLE_TO_DELIVER);
+        assert_eq!(attempts, 1);
+    }
+
+    #[test]
+    fn test_forward_carries_failover_candidates() {
+        let processor = retry_processor(RetryPolicy {
+            max_attempts: 2,
+            allowed: vec![crate::retry::RetryRule {
+                app_id: 16777251,
+                command_code: None,
+            }],
+        });
+
+        let action = processor.process(air_request()).unwrap();
+        assert_eq!(action.action_type, ActionType::Forward as i32);
+        assert_eq!(action.candidate_peers.len(), 2);
+        assert_eq!(action.candidate_peers[0], action.target_host_name);
+        assert_ne!(action.candidate_peers[0], action.candidate_peers[1]);
+
+        // Without a retry rule only the selected peer is offered
+        let processor = retry_processor(RetryPolicy::default());
+        let action = processor.process(air_request()).unwrap();
+        assert_eq!(
+            action.candidate_peers,
+            vec![action.target_host_name.clone()]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_retry_metrics_by_pool() {
+        // A pool of its own keeps other tests out of the counters
+        let routes = vec![RouteEntry {
+            priority: 10,
+            condition: RouteCondition::Default,
+            target_pool_id: "pool-metrics".to_string(),
+        }];
+        let routing_engine = RoutingEngine::new(routes).with_pool(
+            "pool-metrics",
+            vec!["hss01".to_string(), "hss02".to_string()],
+        );
+        let processor = PacketProcessor::new(routing_engine, None).with_retry_policy(RetryPolicy {
+            max_attempts: 2,
+            allowed: vec![crate::retry::RetryRule {
+                app_id: 16777251,
+                command_code: None,
+            }],
+        });
+
+        let mut failed_once = false;
+        processor
+            .forward_with_retry(air_request(), |_peer, payload| {
+                let fail = !std::mem::replace(&mut failed_once, true);
+                async move {
+                    if fail {
+                        Err(CddeError::NetworkError("connection reset".to_string()))
+                    } else {
+                        Ok(payload)
+                    }
+                }
+            })
+            .await
+            .unwrap();
+
+        let pool = ["pool-metrics"];
+        assert_eq!(
+            cdde_metrics::FORWARD_ATTEMPTS_TOTAL
+                .with_label_values(&pool)
+                .get(),
+            2.0
+        );
+        assert_eq!(
+            cdde_metrics::FORWARD_RETRIES_TOTAL
+                .with_label_values(&pool)
+                .get(),
+            1.0
+        );
+        assert_eq!(
+            cdde_metrics::FORWARD_RETRIES_EXHAUSTED_TOTAL
+                .with_label_values(&pool)
+                .get(),
+            0.0
+        );
+    }
+
+    #[test]
+    fn test_relay_only_application_bypasses_manipulation() {
+        use cdde_core::DiameterAvp;
+        use cdde_dsl_engine::{Action, Condition, Rule};
+
+        let routes = vec![RouteEntry {
+            priority: 10,
+            condition: RouteCondition::Default,
+            target_pool_id: "default-pool".to_string(),
+        }];
+        // Rules only for Gy, so Sh traffic is relayed as is
+        let engine = RuleEngine::new(vec![Rule::new(
+            10,
+            vec![Condition::Always],
+            vec![Action::RemoveAvp { code: 264 }],
+        )]);
+        let processor = PacketProcessor::new(RoutingEngine::new(routes), None).with_transform(
+            Box::new(DslTransform::new("gy", engine).with_applications([4])),
+        );
+
+        let mut packet = DiameterPacket {
+            header: cdde_core::DiameterHeader {
+                version: 1,
+                length: 0,
+                flags: 0xC0,
+                command_code: 306,
+                application_id: 16777217,
+                hop_by_hop_id: 1,
+                end_to_end_id: 2,
+            },
+            avps: vec![DiameterAvp {
+                code: 264,
+                flags: 0x40,
+                vendor_id: None,
+                data: b"as.example.com".to_vec(),
+            }],
+        };
+        let payload = packet.serialize();
+        let request = DiameterPacketRequest {
+            connection_id: 1,
+            vr_id: "vr001".to_string(),
+            reception_timestamp: 0,
+            raw_payload: payload.clone().into(),
+            session_tx_id: 0,
+            trace_id: String::new(),
+        };
+
+        // Untouched apart from our Proxy-Info
+        let action = processor.process(request.clone()).unwrap();
+        let relayed = DiameterPacket::parse(&action.response_payload).unwrap();
+        assert_eq!(relayed.avps[..1], packet.avps[..]);
+        assert_eq!(relayed.avps[1].code, AVP_PROXY_INFO);
+        assert_eq!(
+            cdde_metrics::MANIPULATION_BYPASSED_TOTAL
+                .with_label_values(&["16777217"])
+                .get(),
+            1.0
+        );
+
+        // The same message under Gy goes through the rules
+        packet.header.application_id = 4;
+        let action = processor
+            .process(DiameterPacketRequest {
+                raw_payload: packet.to_bytes(),
+                ..request
+            })
+            .unwrap();
+        assert!(DiameterPacket::parse(&action.response_payload)
+            .unwrap()
+            .find_avp(264)
+            .is_none());
+    }
+
+    #[test]
+    fn test_dwr_answered_with_dwa() {
+        let processor = PacketProcessor::new(RoutingEngine::new(vec![]), None)
+            .with_identity("dcr01.example.com".to_string(), "example.com".to_string());
+
+        // A DWR with no AVPs at all must not be treated as unroutable traffic
+        let dwr = DiameterPacket {
+            header: DiameterHeader {
+                version: 1,
+                length: 0,
+                flags: 0x80,
+                command_code: CMD_DEVICE_WATCHDOG,
+                application_id: 0,
+                hop_by_hop_id: 7,
+                end_to_end_id: 8,
+            },
+            avps: vec![],
+        };
+        let action = processor
+            .process(DiameterPacketRequest {
+                connection_id: 42,
+                vr_id: "vr001".to_string(),
+                reception_timestamp: 0,
+                raw_payload: dwr.to_bytes(),
+                session_tx_id: 0,
+                trace_id: String::new(),
+            })
+            .unwrap();
+
+        assert_eq!(action.action_type, ActionType::Reply as i32);
+        assert_eq!(action.original_connection_id, 42);
+        let dwa = DiameterPacket::parse(&action.response_payload).unwrap();
+        assert!(!dwa.header.is_request());
+        assert_eq!(dwa.header.command_code, CMD_DEVICE_WATCHDOG);
+        assert_eq!(dwa.header.hop_by_hop_id, 7);
+        assert_eq!(dwa.header.end_to_end_id, 8);
+        let result_code = dwa.avps.iter().find(|a| a.code == AVP_RESULT_CODE).unwrap();
+        assert_eq!(result_code.data, RESULT_SUCCESS.to_be_bytes());
+        let origin_host = dwa
+            .avps
+            .iter()
+            .find(|a| a.code == cdde_core::codes::AVP_ORIGIN_HOST)
+            .unwrap();
+        assert_eq!(origin_host.data, b"dcr01.example.com");
+    }
+
+    #[tokio::test]
+    async fn test_retried_forward_sets_retransmit_flag() {
+        use cdde_core::diameter::FLAG_RETRANSMIT;
+
+        let processor = retry_processor(RetryPolicy {
+            max_attempts: 2,
+            allowed: vec![crate::retry::RetryRule {
+                app_id: 16777251,
+                command_code: None,
+            }],
+        });
+
+        let mut flags = vec![];
+        processor
+            .forward_with_retry(air_request(), |_peer, payload| {
+                flags.push(payload[4]);
+                let first = flags.len() == 1;
+                async move {
+                    if first {
+                        Err(CddeError::NetworkError("connection reset".to_string()))
+                    } else {
+                        Ok(payload)
+                    }
+                }
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(flags.len(), 2);
+        assert_eq!(flags[0] & FLAG_RETRANSMIT, 0);
+        assert_eq!(flags[1] & FLAG_RETRANSMIT, FLAG_RETRANSMIT);
+    }
+
+    #[test]
+    fn test_unknown_command_is_unsupported_in_strict_mode() {
+        let routes = vec![RouteEntry {
+            priority: 10,
+            condition: RouteCondition::ApplicationCommand {
+                app_id: 16777251,
+                command_code: 316,
+            },
+            target_pool_id: "hss-pool".to_string(),
+        }];
+        let request = |application_id, command_code| {
+            let packet = DiameterPacket {
+                header: DiameterHeader {
+                    version: 1,
+                    length: 0,
+                    flags: 0xC0,
+                    command_code,
+                    application_id,
+                    hop_by_hop_id: 1,
+                    end_to_end_id: 2,
+                },
+                avps: vec![],
+            };
+            DiameterPacketRequest {
+                connection_id: 7,
+                vr_id: "vr001".to_string(),
+                reception_timestamp: 0,
+                raw_payload: packet.to_bytes(),
+                session_tx_id: 0,
+                trace_id: String::new(),
+            }
+        };
+
+        let strict = PacketProcessor::new(RoutingEngine::new(routes.clone()), None)
+            .with_strict_commands(true);
+        let action = strict.process(request(16777251, 999)).unwrap();
+        assert_eq!(action.action_type, ActionType::Reply as i32);
+        assert_eq!(action.result_code, RESULT_COMMAND_UNSUPPORTED);
+
+        // The answer is built here, as a protocol error from this node
+        let answer = DiameterPacket::parse(&action.response_payload).unwrap();
+        assert!(!answer.header.is_request());
+        assert_eq!(answer.header.flags & 0x20, 0x20);
+        assert_eq!(answer.header.hop_by_hop_id, 1);
+        assert_eq!(
+            answer.find_avp(AVP_RESULT_CODE).unwrap().data,
+            RESULT_COMMAND_UNSUPPORTED.to_be_bytes()
+        );
+        assert_eq!(
+            answer
+                .find_avp(cdde_core::codes::AVP_ORIGIN_HOST)
+                .unwrap()
+                .data,
+            b"dcr.example.com"
+        );
+
+        // Known but unroutable commands and lenient mode keep REALM_NOT_SERVED
+        let action = strict.process(request(16777238, 316)).unwrap();
+        assert_eq!(action.result_code, RESULT_REALM_NOT_SERVED);
+        let lenient = PacketProcessor::new(RoutingEngine::new(routes), None);
+        let action = lenient.process(request(16777251, 999)).unwrap();
+        assert_eq!(action.result_code, RESULT_REALM_NOT_SERVED);
+    }
+
+    #[tokio::test]
+    async fn test_proxy_info_added_on_forward_and_removed_from_answer() {
+        let processor = retry_processor(RetryPolicy::default())
+            .with_identity("dcr01.example.com".to_string(), "example.com".to_string());
+
+        let mut forwarded = None;
+        let action = processor
+            .forward_with_retry(air_request(), |_peer, payload| {
+                forwarded = Some(payload.clone());
+                // The peer echoes Proxy-Info back in its answer
+                let mut answer = DiameterPacket::parse(&payload).unwrap();
+                answer.header.flags &= !0x80;
+                async move { Ok(answer.serialize()) }
+            })
+            .await
+            .unwrap();
+
+        let forwarded = DiameterPacket::parse(&forwarded.unwrap()).unwrap();
+        let proxy_info = forwarded.find_avp(AVP_PROXY_INFO).unwrap();
+        let members = proxy_info.parse_grouped().unwrap();
+        assert_eq!(members[0].code, AVP_PROXY_HOST);
+        assert_eq!(members[0].data, b"dcr01.example.com");
+        assert_eq!(members[1].code, AVP_PROXY_STATE);
+        assert_eq!(members[1].data, 7u64.to_be_bytes());
+
+        let answer = DiameterPacket::parse(&action.response_payload).unwrap();
+        assert!(answer.header.is_answer());
+        assert!(answer.find_avp(AVP_PROXY_INFO).is_none());
+    }
+
+    #[test]
+    fn test_busy_reply_when_every_peer_is_draining() {
+        use cdde_core::{HealthThresholds, PeerHealthRegistry};
+        use std::sync::Arc;
+
+        let health = Arc::new(PeerHealthRegistry::new(HealthThresholds::default()));
+        health.set_draining("hss01", true);
+        let routing_engine = RoutingEngine::new(vec![RouteEntry {
+            priority: 10,
+            condition: RouteCondition::Default,
+            target_pool_id: "hss-pool".to_string(),
+        }])
+        .with_pool("hss-pool", vec!["hss01".to_string()])
+        .with_health(health.clone());
+        let processor = PacketProcessor::new(routing_engine, None);
+
+        let action = processor.process(air_request()).unwrap();
+        assert_eq!(action.action_type, ActionType::Reply as i32);
+        assert_eq!(action.result_code, RESULT_TOO_BUSY);
+
+        // Back in service, the peer gets the request again
+        health.set_draining("hss01", false);
+        let action = processor.process(air_request()).unwrap();
+        assert_eq!(action.action_type, ActionType::Forward as i32);
+        assert_eq!(action.target_host_name, "hss01");
+    }
+
+    #[tokio::test]
+    async fn test_undeliverable_request_is_dead_lettered_once() {
+        use crate::dead_letter::ChannelSink;
+
+        let (tx, mut rx) = tokio::sync::mpsc::channel(8);
+        let processor = PacketProcessor::new(RoutingEngine::new(vec![]), None)
+            .with_dead_letters(DeadLetters::new(Box::new(ChannelSink::new(tx)), 10));
+
+        let action = processor.process(air_request()).unwrap();
+        assert_eq!(action.result_code, RESULT_REALM_NOT_SERVED);
+
+        let letter = rx.try_recv().unwrap();
+        assert_eq!(letter.reason, DeadLetterReason::NoRoute);
+        assert_eq!(letter.connection_id, 7);
+        assert_eq!(letter.command_code, 318);
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_failed_retries_are_dead_lettered_with_pool() {
+        use crate::dead_letter::ChannelSink;
+
+        let (tx, mut rx) = tokio::sync::mpsc::channel(8);
+        let processor = retry_processor(RetryPolicy {
+            max_attempts: 2,
+            allowed: vec![crate::retry::RetryRule {
+                app_id: 16777251,
+                command_code: None,
+            }],
+        })
+        .with_dead_letters(DeadLetters::new(Box::new(ChannelSink::new(tx)), 10));
+
+        let action = processor
+            .forward_with_retry(air_request(), |_peer, _payload| async {
+                Err(CddeError::NetworkError("connection refused".to_string()))
+            })
+            .await
+            .unwrap();
+        assert_eq!(action.result_code, RESULT_UNABLE_TO_DELIVER);
+
+        let letter = rx.try_recv().unwrap();
+        assert_eq!(letter.reason, DeadLetterReason::RetriesExhausted);
+        assert_eq!(letter.pool_id.as_deref(), Some("pool-hss"));
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_deliveries_feed_peer_health() {
+        use cdde_core::{HealthThresholds, PeerHealthRegistry};
+        use std::sync::Arc;
+
+        let health = Arc::new(PeerHealthRegistry::new(HealthThresholds::default()));
+        let routes = vec![RouteEntry {
+            priority: 10,
+            condition: RouteCondition::Default,
+            target_pool_id: "pool-hss".to_string(),
+        }];
+        let routing_engine = RoutingEngine::new(routes)
+            .with_pool("pool-hss", vec!["hss01".to_string(), "hss02".to_string()])
+            .with_health(health.clone());
+        let processor = PacketProcessor::new(routing_engine, None).with_retry_policy(RetryPolicy {
+            max_attempts: 2,
+            allowed: vec![crate::retry::RetryRule {
+                app_id: 16777251,
+                command_code: None,
+            }],
+        });
+
+        let mut first_peer = None;
+        processor
+            .forward_with_retry(air_request(), |peer, payload| {
+                let failed = first_peer.get_or_insert_with(|| peer.clone()) == &peer;
+                async move {
+                    if failed {
+                        Err(CddeError::NetworkError("connection reset".to_string()))
+                    } else {
+                        Ok(payload)
+                    }
+                }
+            })
+            .await
+            .unwrap();
+
+        let failed = first_peer.unwrap();
+        let answered = if failed == "hss01" { "hss02" } else { "hss01" };
+        assert!(health.score(&failed) > health.score(answered));
+    }
+
+    #[test]
+    fn test_unknown_applications_share_a_label() {
+        assert_eq!(application_label(16777217), "16777217");
+        assert_eq!(application_label(16777999), OTHER_APPLICATION);
+        assert_eq!(application_label(u32::MAX - 7), OTHER_APPLICATION);
+    }
 }
@@ -0,0 +1,103 @@
+use std::collections::HashSet;
+use std::sync::RwLock;
+
+/// Label used for missing realms and realms beyond the label limit
+pub const OTHER_REALM: &str = "other";
+
+/// Default number of distinct realm labels
+pub const DEFAULT_REALM_LABEL_LIMIT: usize = 100;
+
+/// Counts routed requests per Destination-Realm with bounded label cardinality
+///
+/// The first `limit` realms seen get their own label; later realms and
+/// requests without a Destination-Realm are counted as "other".
+#[derive(Debug)]
+pub struct RealmMetrics {
+    limit: usize,
+    known: RwLock<HashSet<String>>,
+}
+
+impl RealmMetrics {
+    /// Create with a limit on distinct realm labels
+    pub fn new(limit: usize) -> Self {
+        Self {
+            limit,
+            known: RwLock::new(HashSet::new()),
+        }
+    }
+
+    /// Label a realm is counted under
+    ///
+    /// Realms are case-insensitive, so labels are lowercase.
+    pub fn label(&self, realm: Option<&str>) -> String {
+        let Some(realm) = realm.filter(|realm| !realm.is_empty()) else {
+            return OTHER_REALM.to_string();
+        };
+        let realm = realm.to_ascii_lowercase();
+        let realm = realm.as_str();
+
+        if let Ok(known) = self.known.read() {
+            if known.contains(realm) {
+                return realm.to_string();
+            }
+        }
+
+        let Ok(mut known) = self.known.write() else {
+            return OTHER_REALM.to_string();
+        };
+        if known.contains(realm) || known.len() < self.limit {
+            known.insert(realm.to_string());
+            realm.to_string()
+        } else {
+            OTHER_REALM.to_string()
+        }
+    }
+
+    /// Count a request for a realm
+    pub fn record(&self, realm: Option<&str>) {
+        cdde_metrics::REALM_REQUESTS_TOTAL
+            .with_label_values(&[&self.label(realm)])
+            .inc();
+    }
+}
+
+impl Default for RealmMetrics {
+    fn default() -> Self {
+        Self::new(DEFAULT_REALM_LABEL_LIMIT)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cdde_metrics::REALM_REQUESTS_TOTAL;
+
+    fn count(label: &str) -> f64 {
+        REALM_REQUESTS_TOTAL.with_label_values(&[label]).get()
+    }
+
+    #[test]
+    fn test_realms_beyond_limit_fall_into_other() {
+        let metrics = RealmMetrics::new(2);
+        let other_before = count(OTHER_REALM);
+
+        metrics.record(Some("hss.realm-a.test"));
+        metrics.record(Some("hss.realm-b.test"));
+        metrics.record(Some("hss.realm-a.test"));
+        metrics.record(Some("hss.realm-c.test"));
+
+        assert_eq!(count("hss.realm-a.test"), 2.0);
+        assert_eq!(count("hss.realm-b.test"), 1.0);
+        assert_eq!(count("hss.realm-c.test"), 0.0);
+        assert!(count(OTHER_REALM) - other_before >= 1.0);
+    }
+
+    #[test]
+    fn test_missing_realm_is_other() {
+        let metrics = RealmMetrics::default();
+        assert_eq!(metrics.label(None), OTHER_REALM);
+        assert_eq!(metrics.label(Some("")), OTHER_REALM);
+        assert_eq!(metrics.label(Some("epc.example.com")), "epc.example.com");
+        assert_eq!(metrics.label(Some("EPC.Example.com")), "epc.example.com");
+    }
+}
@@ -0,0 +1,43 @@
+use cdde_core::{CddeError, Result};
+use cdde_proto::peer_relay_service_client::PeerRelayServiceClient;
+use cdde_proto::PeerForwardRequest;
+use tonic::transport::Channel;
+use tonic::Code;
+
+/// Client of the DPA peer relay, delivering routed requests to their peer
+#[derive(Clone)]
+pub struct PeerRelayClient {
+    client: PeerRelayServiceClient<Channel>,
+}
+
+impl PeerRelayClient {
+    /// Create a client for the relay at `endpoint`
+    ///
+    /// The connection is opened on the first delivery.
+    pub fn new(endpoint: &str) -> Result<Self> {
+        let channel = Channel::from_shared(endpoint.to_string())
+            .map_err(|e| CddeError::ConfigError(format!("Invalid relay endpoint {endpoint}: {e}")))?
+            .connect_lazy();
+        Ok(Self {
+            client: PeerRelayServiceClient::new(channel),
+        })
+    }
+
+    /// Send a serialized request to `peer` and return the serialized answer
+    pub async fn deliver(&self, peer: String, payload: Vec<u8>) -> Result<Vec<u8>> {
+        let request = PeerForwardRequest {
+            peer_node_id: peer,
+            raw_payload: payload,
+        };
+        let response = self
+            .client
+            .clone()
+            .forward(request)
+            .await
+            .map_err(|status| match status.code() {
+                Code::ResourceExhausted => CddeError::PeerBusy(status.message().to_string()),
+                _ => CddeError::NetworkError(status.to_string()),
+            })?;
+        Ok(response.into_inner().raw_payload)
+    }
+}
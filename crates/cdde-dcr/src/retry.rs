@@ -0,0 +1,67 @@
+use serde::{Deserialize, Serialize};
+
+/// Requests that may be retried on another peer of the same pool
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RetryRule {
+    /// Application the rule applies to
+    pub app_id: u32,
+
+    /// Command code, or every command of the application when absent
+    pub command_code: Option<u32>,
+}
+
+/// Retry policy for idempotent requests
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RetryPolicy {
+    /// Maximum number of peers tried, including the first one
+    pub max_attempts: usize,
+
+    /// Requests that are safe to retry
+    pub allowed: Vec<RetryRule>,
+}
+
+impl RetryPolicy {
+    /// Check if a request may be retried
+    pub fn allows(&self, app_id: u32, command_code: u32) -> bool {
+        self.allowed.iter().any(|rule| {
+            rule.app_id == app_id && rule.command_code.is_none_or(|c| c == command_code)
+        })
+    }
+}
+
+impl Default for RetryPolicy {
+    /// No request is retried
+    fn default() -> Self {
+        Self {
+            max_attempts: 2,
+            allowed: Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_allows() {
+        let policy = RetryPolicy {
+            max_attempts: 2,
+            allowed: vec![
+                RetryRule {
+                    app_id: 16777251,
+                    command_code: Some(318), // AIR
+                },
+                RetryRule {
+                    app_id: 4,
+                    command_code: None,
+                },
+            ],
+        };
+
+        assert!(policy.allows(16777251, 318));
+        assert!(!policy.allows(16777251, 316));
+        assert!(policy.allows(4, 272));
+        assert!(!RetryPolicy::default().allows(4, 272));
+    }
+}
@@ -1,4 +1,15 @@
+use crate::selector::{PeerSelector, RoundRobin, SelectionContext};
+use cdde_config::RoutePrecedence;
+use cdde_core::PeerHealthRegistry;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Health score from which a peer is only picked when no peer scores lower
+///
+/// A score of 1.0 is reached at the latency or error rate threshold.
+const UNHEALTHY_SCORE: f64 = 1.0;
 
 /// Routing decision result
 #[derive(Debug, Clone)]
@@ -6,6 +17,9 @@ pub struct RoutingDecision {
     /// Target peer hostname
     pub target_peer: String,
 
+    /// Pool the target peer was selected from
+    pub pool_id: String,
+
     /// Routing priority
     pub priority: u8,
 }
@@ -22,15 +36,67 @@ pub struct RouteEntry {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type")]
 pub enum RouteCondition {
-    DestinationHost { value: String },
-    ApplicationCommand { app_id: u32, command_code: u32 },
-    DestinationRealm { value: String },
+    DestinationHost {
+        value: String,
+    },
+    ApplicationCommand {
+        app_id: u32,
+        command_code: u32,
+    },
+    DestinationRealm {
+        value: String,
+    },
+    Application {
+        app_id: u32,
+    },
+    /// Every condition matches
+    And {
+        conditions: Vec<RouteCondition>,
+    },
+    /// At least one condition matches
+    Or {
+        conditions: Vec<RouteCondition>,
+    },
     Default,
 }
 
+impl RouteCondition {
+    /// Whether the condition names the command code explicitly
+    fn names_command(&self, command_code: u32) -> bool {
+        match self {
+            RouteCondition::ApplicationCommand {
+                command_code: code, ..
+            } => *code == command_code,
+            RouteCondition::And { conditions } | RouteCondition::Or { conditions } => conditions
+                .iter()
+                .any(|condition| condition.names_command(command_code)),
+            _ => false,
+        }
+    }
+
+    /// Tier of the condition under host, realm, default precedence
+    ///
+    /// Conditions other than Destination-Host and Default share the realm
+    /// tier.
+    fn tier(&self) -> u8 {
+        match self {
+            RouteCondition::DestinationHost { .. } => 0,
+            RouteCondition::Default => 2,
+            _ => 1,
+        }
+    }
+}
+
 /// Simple routing engine
+///
+/// Peers are picked by a `PeerSelector`, round robin unless configured
+/// otherwise, globally or per Virtual Router.
 pub struct RoutingEngine {
     routes: Vec<RouteEntry>,
+    pools: HashMap<String, Vec<String>>,
+    health: Option<Arc<PeerHealthRegistry>>,
+    selector: Box<dyn PeerSelector>,
+    vr_selectors: HashMap<String, Box<dyn PeerSelector>>,
 }
 
 impl RoutingEngine {
@@ -40,7 +106,156 @@ impl RoutingEngine {
         sorted_routes.sort_by_key(|r| r.priority);
         Self {
             routes: sorted_routes,
+            pools: HashMap::new(),
+            health: None,
+            selector: Box::new(RoundRobin::default()),
+            vr_selectors: HashMap::new(),
+        }
+    }
+
+    /// Register the peers that make up a pool
+    pub fn with_pool(mut self, pool_id: impl Into<String>, peers: Vec<String>) -> Self {
+        self.pools.insert(pool_id.into(), peers);
+        self
+    }
+
+    /// Use peer health scores to prefer healthier peers within a pool
+    pub fn with_health(mut self, health: Arc<PeerHealthRegistry>) -> Self {
+        self.health = Some(health);
+        self
+    }
+
+    /// Feed the outcome of a delivery into the peer health scores, if kept
+    pub fn record_answer(&self, peer: &str, latency: Duration, success: bool) {
+        if let Some(health) = &self.health {
+            health.record_answer(peer, latency, success);
+        }
+    }
+
+    /// Set the order in which routes are tried
+    pub fn with_precedence(mut self, precedence: RoutePrecedence) -> Self {
+        match precedence {
+            RoutePrecedence::Priority => self.routes.sort_by_key(|r| r.priority),
+            RoutePrecedence::HostRealmDefault => self
+                .routes
+                .sort_by_key(|r| (r.condition.tier(), r.priority)),
+        }
+        self
+    }
+
+    /// Set the peer selection strategy of Virtual Routers without their own
+    pub fn with_selector(mut self, selector: Box<dyn PeerSelector>) -> Self {
+        self.selector = selector;
+        self
+    }
+
+    /// Set the peer selection strategy of one Virtual Router
+    pub fn with_vr_selector(
+        mut self,
+        vr_id: impl Into<String>,
+        selector: Box<dyn PeerSelector>,
+    ) -> Self {
+        self.vr_selectors.insert(vr_id.into(), selector);
+        self
+    }
+
+    fn selector(&self, ctx: &SelectionContext<'_>) -> &dyn PeerSelector {
+        ctx.vr_id
+            .and_then(|vr_id| self.vr_selectors.get(vr_id))
+            .unwrap_or(&self.selector)
+            .as_ref()
+    }
+
+    /// Pick a peer from a pool, preferring the lowest health score
+    ///
+    /// The selector chooses among peers with equal scores. Pools without
+    /// registered peers resolve to the pool id itself.
+    fn select_peer(&self, pool_id: &str, ctx: &SelectionContext<'_>) -> String {
+        match self.pools.get(pool_id) {
+            Some(peers) if !peers.is_empty() => self
+                .select_peer_excluding(pool_id, &[], ctx)
+                .unwrap_or_else(|| pool_id.to_string()),
+            _ => pool_id.to_string(),
+        }
+    }
+
+    fn is_draining(&self, peer: &str) -> bool {
+        self.health
+            .as_ref()
+            .is_some_and(|health| health.is_draining(peer))
+    }
+
+    /// Whether every peer of a pool is draining
+    ///
+    /// Pools without registered peers are never considered draining.
+    pub fn pool_draining(&self, pool_id: &str) -> bool {
+        self.pools
+            .get(pool_id)
+            .is_some_and(|peers| !peers.is_empty() && peers.iter().all(|p| self.is_draining(p)))
+    }
+
+    /// Pick another peer from a pool, skipping peers that were already tried
+    ///
+    /// Draining peers are never picked. The selector chooses among all peers
+    /// below the unhealthy score, or among the best scored ones if none is.
+    pub fn select_peer_excluding(
+        &self,
+        pool_id: &str,
+        excluded: &[String],
+        ctx: &SelectionContext<'_>,
+    ) -> Option<String> {
+        let peers: Vec<&String> = self
+            .pools
+            .get(pool_id)?
+            .iter()
+            .filter(|peer| !excluded.contains(peer) && !self.is_draining(peer))
+            .collect();
+        if peers.is_empty() {
+            return None;
+        }
+
+        let scores: Vec<f64> = peers
+            .iter()
+            .map(|peer| self.health.as_ref().map_or(0.0, |h| h.score(peer)))
+            .collect();
+        let best = scores.iter().copied().fold(f64::INFINITY, f64::min);
+        let candidates: Vec<&str> = peers
+            .iter()
+            .zip(&scores)
+            .filter(|(_, score)| **score < UNHEALTHY_SCORE || **score <= best)
+            .map(|(peer, _)| peer.as_str())
+            .collect();
+
+        let index = self.selector(ctx).select(&candidates, ctx);
+        candidates.get(index).map(|peer| peer.to_string())
+    }
+
+    /// Peers of a pool to try in order, starting with `first`
+    ///
+    /// Each following peer is picked like a retry would pick it, up to
+    /// `limit` peers in total.
+    pub fn failover_candidates(
+        &self,
+        pool_id: &str,
+        first: &str,
+        limit: usize,
+        ctx: &SelectionContext<'_>,
+    ) -> Vec<String> {
+        let mut candidates = vec![first.to_string()];
+        while candidates.len() < limit {
+            match self.select_peer_excluding(pool_id, &candidates, ctx) {
+                Some(peer) => candidates.push(peer),
+                None => break,
+            }
         }
+        candidates
+    }
+
+    /// Whether a route names the command code explicitly
+    pub fn routes_command(&self, command_code: u32) -> bool {
+        self.routes
+            .iter()
+            .any(|route| route.condition.names_command(command_code))
     }
 
     /// Find route for given parameters
@@ -50,6 +265,24 @@ impl RoutingEngine {
         dest_realm: Option<&str>,
         app_id: u32,
         command_code: u32,
+    ) -> Option<RoutingDecision> {
+        self.find_route_in(
+            &SelectionContext::default(),
+            dest_host,
+            dest_realm,
+            app_id,
+            command_code,
+        )
+    }
+
+    /// Find route for a request, selecting the peer with its context
+    pub fn find_route_in(
+        &self,
+        ctx: &SelectionContext<'_>,
+        dest_host: Option<&str>,
+        dest_realm: Option<&str>,
+        app_id: u32,
+        command_code: u32,
     ) -> Option<RoutingDecision> {
         for route in &self.routes {
             if self.matches(
@@ -59,9 +292,9 @@ impl RoutingEngine {
                 app_id,
                 command_code,
             ) {
-                // For now, use pool_id as target peer
                 return Some(RoutingDecision {
-                    target_peer: route.target_pool_id.clone(),
+                    target_peer: self.select_peer(&route.target_pool_id, ctx),
+                    pool_id: route.target_pool_id.clone(),
                     priority: route.priority,
                 });
             }
@@ -78,12 +311,24 @@ impl RoutingEngine {
         command_code: u32,
     ) -> bool {
         match condition {
-            RouteCondition::DestinationHost { value } => dest_host.is_some_and(|h| h == value),
+            // Diameter identities are case-insensitive
+            RouteCondition::DestinationHost { value } => {
+                dest_host.is_some_and(|h| h.eq_ignore_ascii_case(value))
+            }
             RouteCondition::ApplicationCommand {
                 app_id: a,
                 command_code: c,
             } => *a == app_id && *c == command_code,
-            RouteCondition::DestinationRealm { value } => dest_realm.is_some_and(|r| r == value),
+            RouteCondition::DestinationRealm { value } => {
+                dest_realm.is_some_and(|r| r.eq_ignore_ascii_case(value))
+            }
+            RouteCondition::Application { app_id: a } => *a == app_id,
+            RouteCondition::And { conditions } => conditions.iter().all(|condition| {
+                self.matches(condition, dest_host, dest_realm, app_id, command_code)
+            }),
+            RouteCondition::Or { conditions } => conditions.iter().any(|condition| {
+                self.matches(condition, dest_host, dest_realm, app_id, command_code)
+            }),
             RouteCondition::Default => true,
         }
     }
@@ -160,4 +405,233 @@ mod tests {
 
         assert!(decision.is_none());
     }
+
+    #[test]
+    fn test_unhealthy_peer_chosen_less_often() {
+        use cdde_core::HealthThresholds;
+        use std::time::Duration;
+
+        let health = Arc::new(PeerHealthRegistry::new(HealthThresholds::default()));
+        for _ in 0..20 {
+            health.record_answer("hss01", Duration::from_millis(20), true);
+            health.record_answer("hss02", Duration::from_millis(20), false);
+        }
+
+        let routes = vec![RouteEntry {
+            priority: 10,
+            condition: RouteCondition::Default,
+            target_pool_id: "pool-hss".to_string(),
+        }];
+        let engine = RoutingEngine::new(routes)
+            .with_pool("pool-hss", vec!["hss01".to_string(), "hss02".to_string()])
+            .with_health(health);
+
+        let mut counts: HashMap<String, usize> = HashMap::new();
+        for _ in 0..100 {
+            let decision = engine.find_route(None, None, 0, 0).unwrap();
+            *counts.entry(decision.target_peer).or_default() += 1;
+        }
+
+        let healthy = counts.get("hss01").copied().unwrap_or(0);
+        let unhealthy = counts.get("hss02").copied().unwrap_or(0);
+        assert!(unhealthy < healthy);
+    }
+
+    #[test]
+    fn test_equal_scores_share_load() {
+        let routes = vec![RouteEntry {
+            priority: 10,
+            condition: RouteCondition::Default,
+            target_pool_id: "pool-hss".to_string(),
+        }];
+        let engine = RoutingEngine::new(routes)
+            .with_pool("pool-hss", vec!["hss01".to_string(), "hss02".to_string()]);
+
+        let first = engine.find_route(None, None, 0, 0).unwrap().target_peer;
+        let second = engine.find_route(None, None, 0, 0).unwrap().target_peer;
+        assert_ne!(first, second);
+    }
+
+    #[test]
+    fn test_healthy_peers_with_unequal_latency_share_load() {
+        use cdde_core::HealthThresholds;
+
+        let health = Arc::new(PeerHealthRegistry::new(HealthThresholds::default()));
+        health.record_answer("hss01", Duration::from_millis(40), true);
+        health.record_answer("hss02", Duration::from_millis(120), true);
+        assert!(health.score("hss01") < health.score("hss02"));
+
+        let engine = RoutingEngine::new(vec![RouteEntry {
+            priority: 10,
+            condition: RouteCondition::Default,
+            target_pool_id: "pool-hss".to_string(),
+        }])
+        .with_pool("pool-hss", vec!["hss01".to_string(), "hss02".to_string()])
+        .with_health(health);
+
+        // Round robin still alternates between both peers
+        let picks: Vec<String> = (0..4)
+            .map(|_| engine.find_route(None, None, 0, 0).unwrap().target_peer)
+            .collect();
+        assert_ne!(picks[0], picks[1]);
+        assert_eq!(picks[0], picks[2]);
+        assert_eq!(picks[1], picks[3]);
+    }
+
+    #[test]
+    fn test_draining_peers_are_skipped() {
+        let health = Arc::new(PeerHealthRegistry::new(
+            cdde_core::HealthThresholds::default(),
+        ));
+        health.set_draining("hss01", true);
+        let engine = RoutingEngine::new(vec![RouteEntry {
+            priority: 10,
+            condition: RouteCondition::Default,
+            target_pool_id: "hss-pool".to_string(),
+        }])
+        .with_pool("hss-pool", vec!["hss01".to_string(), "hss02".to_string()])
+        .with_health(health.clone());
+
+        for _ in 0..4 {
+            let route = engine.find_route(None, None, 16777251, 316).unwrap();
+            assert_eq!(route.target_peer, "hss02");
+        }
+        assert!(!engine.pool_draining("hss-pool"));
+
+        health.set_draining("hss02", true);
+        assert!(engine.pool_draining("hss-pool"));
+        assert!(!engine.pool_draining("unknown-pool"));
+    }
+
+    #[test]
+    fn test_and_condition_requires_realm_and_application() {
+        let condition: RouteCondition = serde_json::from_str(
+            r#"{
+                "type": "And",
+                "conditions": [
+                    {"type": "DestinationRealm", "value": "epc.example.com"},
+                    {"type": "Application", "app_id": 16777251}
+                ]
+            }"#,
+        )
+        .unwrap();
+        let engine = RoutingEngine::new(vec![RouteEntry {
+            priority: 10,
+            condition,
+            target_pool_id: "hss-pool".to_string(),
+        }]);
+
+        let route = |realm, app_id| engine.find_route(None, Some(realm), app_id, 316);
+        assert_eq!(
+            route("epc.example.com", 16777251).unwrap().pool_id,
+            "hss-pool"
+        );
+        assert!(route("epc.example.com", 16777238).is_none());
+        assert!(route("ims.example.com", 16777251).is_none());
+    }
+
+    #[test]
+    fn test_or_condition_matches_either() {
+        let engine = RoutingEngine::new(vec![RouteEntry {
+            priority: 10,
+            condition: RouteCondition::Or {
+                conditions: vec![
+                    RouteCondition::Application { app_id: 16777238 },
+                    RouteCondition::ApplicationCommand {
+                        app_id: 16777251,
+                        command_code: 316,
+                    },
+                ],
+            },
+            target_pool_id: "pcrf-pool".to_string(),
+        }]);
+
+        assert!(engine.find_route(None, None, 16777238, 272).is_some());
+        assert!(engine.find_route(None, None, 16777251, 316).is_some());
+        assert!(engine.find_route(None, None, 16777251, 318).is_none());
+        assert!(engine.routes_command(316));
+    }
+
+    #[test]
+    fn test_mixed_case_identities_match_lowercase_routes() {
+        let engine = RoutingEngine::new(vec![
+            RouteEntry {
+                priority: 10,
+                condition: RouteCondition::DestinationHost {
+                    value: "hss01.epc.example.com".to_string(),
+                },
+                target_pool_id: "hss01".to_string(),
+            },
+            RouteEntry {
+                priority: 20,
+                condition: RouteCondition::DestinationRealm {
+                    value: "example.com".to_string(),
+                },
+                target_pool_id: "realm-pool".to_string(),
+            },
+        ]);
+
+        let route = engine
+            .find_route(Some("HSS01.epc.Example.com"), None, 16777251, 316)
+            .unwrap();
+        assert_eq!(route.pool_id, "hss01");
+        let route = engine
+            .find_route(None, Some("Example.COM"), 16777251, 316)
+            .unwrap();
+        assert_eq!(route.pool_id, "realm-pool");
+    }
+
+    #[test]
+    fn test_host_realm_default_precedence() {
+        let routes = vec![
+            RouteEntry {
+                priority: 1,
+                condition: RouteCondition::Default,
+                target_pool_id: "default-pool".to_string(),
+            },
+            RouteEntry {
+                priority: 5,
+                condition: RouteCondition::DestinationRealm {
+                    value: "epc.example.com".to_string(),
+                },
+                target_pool_id: "realm-pool".to_string(),
+            },
+            RouteEntry {
+                priority: 50,
+                condition: RouteCondition::DestinationHost {
+                    value: "hss01.epc.example.com".to_string(),
+                },
+                target_pool_id: "hss01-pool".to_string(),
+            },
+        ];
+
+        // By priority alone the default route shadows the others
+        let engine = RoutingEngine::new(routes.clone());
+        let route = engine
+            .find_route(
+                Some("hss01.epc.example.com"),
+                Some("epc.example.com"),
+                16777251,
+                316,
+            )
+            .unwrap();
+        assert_eq!(route.pool_id, "default-pool");
+
+        let engine = RoutingEngine::new(routes).with_precedence(RoutePrecedence::HostRealmDefault);
+        let route = |host, realm| {
+            engine
+                .find_route(host, Some(realm), 16777251, 316)
+                .unwrap()
+                .pool_id
+        };
+        assert_eq!(
+            route(Some("hss01.epc.example.com"), "epc.example.com"),
+            "hss01-pool"
+        );
+        assert_eq!(
+            route(Some("hss02.epc.example.com"), "epc.example.com"),
+            "realm-pool"
+        );
+        assert_eq!(route(None, "ims.example.com"), "default-pool");
+    }
 }
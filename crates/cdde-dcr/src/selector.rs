@@ -0,0 +1,236 @@
+use cdde_config::{PeerSelectionConfig, PeerSelectionStrategy};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Request attributes a peer selector may base its choice on
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SelectionContext<'a> {
+    /// Virtual Router the request belongs to
+    pub vr_id: Option<&'a str>,
+
+    /// Session-Id of the request
+    pub session_id: Option<&'a str>,
+}
+
+/// Strategy picking one peer out of the candidates of a pool
+///
+/// Candidates are the pool's peers with the best health score, in pool
+/// order, minus peers already tried for the request.
+pub trait PeerSelector: Send + Sync {
+    /// Strategy name, for logs
+    fn name(&self) -> &'static str;
+
+    /// Index of the chosen peer in `candidates`, which is never empty
+    fn select(&self, candidates: &[&str], ctx: &SelectionContext<'_>) -> usize;
+}
+
+/// Always the first candidate; later peers only take over on failure
+pub struct FirstMatch;
+
+impl PeerSelector for FirstMatch {
+    fn name(&self) -> &'static str {
+        "first-match"
+    }
+
+    fn select(&self, _candidates: &[&str], _ctx: &SelectionContext<'_>) -> usize {
+        0
+    }
+}
+
+/// Each candidate in turn
+#[derive(Default)]
+pub struct RoundRobin {
+    next: AtomicUsize,
+}
+
+impl PeerSelector for RoundRobin {
+    fn name(&self) -> &'static str {
+        "round-robin"
+    }
+
+    fn select(&self, candidates: &[&str], _ctx: &SelectionContext<'_>) -> usize {
+        self.next.fetch_add(1, Ordering::Relaxed) % candidates.len()
+    }
+}
+
+/// Candidates in turn, each as often as its weight
+///
+/// Peers without a configured weight count as 1; a weight of 0 keeps a
+/// peer out of rotation unless every candidate has weight 0.
+#[derive(Default)]
+pub struct Weighted {
+    weights: HashMap<String, u32>,
+    next: AtomicUsize,
+}
+
+impl Weighted {
+    /// Create a weighted selector from per-peer weights
+    pub fn new(weights: HashMap<String, u32>) -> Self {
+        Self {
+            weights,
+            next: AtomicUsize::new(0),
+        }
+    }
+
+    fn weight(&self, peer: &str) -> usize {
+        self.weights.get(peer).copied().unwrap_or(1) as usize
+    }
+}
+
+impl PeerSelector for Weighted {
+    fn name(&self) -> &'static str {
+        "weighted"
+    }
+
+    fn select(&self, candidates: &[&str], _ctx: &SelectionContext<'_>) -> usize {
+        let total: usize = candidates.iter().map(|peer| self.weight(peer)).sum();
+        if total == 0 {
+            return 0;
+        }
+
+        let mut turn = self.next.fetch_add(1, Ordering::Relaxed) % total;
+        for (index, peer) in candidates.iter().enumerate() {
+            let weight = self.weight(peer);
+            if turn < weight {
+                return index;
+            }
+            turn -= weight;
+        }
+        0
+    }
+}
+
+/// The same candidate for every request of a session
+///
+/// Uses rendezvous hashing on the Session-Id, so a peer leaving the pool
+/// only moves the sessions it was serving. Requests without a Session-Id
+/// go to the first candidate.
+pub struct ConsistentHash;
+
+impl PeerSelector for ConsistentHash {
+    fn name(&self) -> &'static str {
+        "consistent-hash"
+    }
+
+    fn select(&self, candidates: &[&str], ctx: &SelectionContext<'_>) -> usize {
+        let Some(session_id) = ctx.session_id else {
+            return 0;
+        };
+
+        (0..candidates.len())
+            .max_by_key(|&index| rendezvous_score(session_id, candidates[index]))
+            .unwrap_or(0)
+    }
+}
+
+/// Score of a peer for a session in rendezvous hashing
+///
+/// FNV-1a over the Session-Id and the peer name, finished with the murmur3
+/// mixer so peers whose names differ in one character still spread evenly.
+/// Unlike the std hasher, the result is fixed across builds and processes,
+/// so every DCR instance pins a session to the same peer.
+fn rendezvous_score(session_id: &str, peer: &str) -> u64 {
+    let bytes = session_id.bytes().chain([0]).chain(peer.bytes());
+    let mut hash = bytes.fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0000_0100_0000_01b3)
+    });
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xff51_afd7_ed55_8ccd);
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xc4ce_b9fe_1a85_ec53);
+    hash ^ (hash >> 33)
+}
+
+/// Build the selector a virtual router is configured with
+pub fn selector_for(config: &PeerSelectionConfig) -> Box<dyn PeerSelector> {
+    match config.strategy {
+        PeerSelectionStrategy::FirstMatch => Box::new(FirstMatch),
+        PeerSelectionStrategy::RoundRobin => Box::new(RoundRobin::default()),
+        PeerSelectionStrategy::Weighted => Box::new(Weighted::new(config.weights.clone())),
+        PeerSelectionStrategy::ConsistentHash => Box::new(ConsistentHash),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POOL: [&str; 3] = ["hss01", "hss02", "hss03"];
+
+    fn picks(selector: &dyn PeerSelector, session_ids: &[Option<&str>]) -> Vec<&'static str> {
+        session_ids
+            .iter()
+            .map(|session_id| {
+                let ctx = SelectionContext {
+                    vr_id: None,
+                    session_id: *session_id,
+                };
+                POOL[selector.select(&POOL, &ctx)]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_first_match_sticks_to_first_peer() {
+        assert_eq!(
+            picks(&FirstMatch, &[None; 4]),
+            ["hss01", "hss01", "hss01", "hss01"]
+        );
+    }
+
+    #[test]
+    fn test_round_robin_cycles_through_pool() {
+        assert_eq!(
+            picks(&RoundRobin::default(), &[None; 4]),
+            ["hss01", "hss02", "hss03", "hss01"]
+        );
+    }
+
+    #[test]
+    fn test_weighted_follows_weights() {
+        let selector = Weighted::new(HashMap::from([
+            ("hss01".to_string(), 3),
+            ("hss03".to_string(), 0),
+        ]));
+        assert_eq!(
+            picks(&selector, &[None; 8]),
+            ["hss01", "hss01", "hss01", "hss02", "hss01", "hss01", "hss01", "hss02"]
+        );
+    }
+
+    #[test]
+    fn test_consistent_hash_pins_sessions() {
+        let sessions: Vec<String> = (0..32).map(|i| format!("mme1;{i}")).collect();
+        let session_ids: Vec<Option<&str>> = sessions.iter().map(|s| Some(s.as_str())).collect();
+
+        // Repeated requests of a session land on the same peer
+        let first = picks(&ConsistentHash, &session_ids);
+        assert_eq!(first, picks(&ConsistentHash, &session_ids));
+
+        // The assignment is fixed, not seeded per process
+        assert_eq!(
+            first[..6],
+            ["hss03", "hss02", "hss03", "hss03", "hss02", "hss01"]
+        );
+
+        // Sessions are spread over the pool rather than pinned to one peer
+        for peer in POOL {
+            assert!(first.contains(&peer));
+        }
+
+        // Removing a peer only moves the sessions it was serving
+        let ctx = |session_id| SelectionContext {
+            vr_id: None,
+            session_id: Some(session_id),
+        };
+        let reduced = ["hss01", "hss02"];
+        for (session_id, peer) in sessions.iter().zip(&first) {
+            if *peer != "hss03" {
+                assert_eq!(
+                    reduced[ConsistentHash.select(&reduced, &ctx(session_id))],
+                    *peer
+                );
+            }
+        }
+    }
+}
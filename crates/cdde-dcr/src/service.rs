@@ -0,0 +1,176 @@
+use crate::processor::PacketProcessor;
+use crate::relay::PeerRelayClient;
+use cdde_proto::core_router_service_server::CoreRouterService;
+use cdde_proto::{DiameterPacketAction, DiameterPacketRequest};
+use std::sync::Arc;
+use tokio::sync::Semaphore;
+use tonic::{Request, Response, Status};
+use tracing::info;
+
+/// Simple in-memory gRPC service implementation
+pub struct CoreRouterServiceImpl {
+    processor: Arc<PacketProcessor>,
+    in_flight: Option<Arc<Semaphore>>,
+    relay: Option<PeerRelayClient>,
+}
+
+impl CoreRouterServiceImpl {
+    pub fn new(processor: PacketProcessor) -> Self {
+        Self {
+            processor: Arc::new(processor),
+            in_flight: None,
+            relay: None,
+        }
+    }
+
+    /// Deliver routed requests through the DPA relay instead of leaving it
+    /// to the DFL, retrying on other peers as the retry policy allows
+    pub fn with_relay(mut self, relay: PeerRelayClient) -> Self {
+        self.relay = Some(relay);
+        self
+    }
+
+    /// Refuse requests with RESOURCE_EXHAUSTED while `limit` are in progress
+    pub fn with_max_in_flight(mut self, limit: usize) -> Self {
+        self.in_flight = Some(Arc::new(Semaphore::new(limit)));
+        self
+    }
+}
+
+#[tonic::async_trait]
+impl CoreRouterService for CoreRouterServiceImpl {
+    async fn process_packet(
+        &self,
+        request: Request<DiameterPacketRequest>,
+    ) -> Result<Response<DiameterPacketAction>, Status> {
+        // Shed rather than queue, so the DFL can fail over straight away
+        let _permit = match &self.in_flight {
+            Some(in_flight) => Some(in_flight.try_acquire().map_err(|_| {
+                cdde_metrics::REQUESTS_SHED_TOTAL.inc();
+                Status::resource_exhausted("DCR is at its in-flight request limit")
+            })?),
+            None => None,
+        };
+
+        let req = request.into_inner();
+        // Transactions sampled by the DFL are logged in full
+        let trace_id = (!req.trace_id.is_empty()).then(|| req.trace_id.clone());
+        if let Some(trace_id) = &trace_id {
+            info!(
+                trace_id = %trace_id,
+                connection_id = req.connection_id,
+                vr_id = %req.vr_id,
+                payload_bytes = req.raw_payload.len(),
+                "Routing sampled transaction"
+            );
+        }
+
+        let action = match &self.relay {
+            Some(relay) => {
+                self.processor
+                    .forward_with_retry(req, |peer, payload| relay.deliver(peer, payload))
+                    .await
+            }
+            None => self.processor.process(req),
+        };
+        let action = action.map_err(|e| {
+            if let Some(trace_id) = &trace_id {
+                info!(trace_id = %trace_id, error = %e, "Sampled transaction failed");
+            }
+            Status::internal(format!("Processing error: {e}"))
+        })?;
+        if let Some(trace_id) = &trace_id {
+            info!(
+                trace_id = %trace_id,
+                action_type = ?action.action_type(),
+                target = %action.target_host_name,
+                "Sampled transaction routed"
+            );
+        }
+        Ok(Response::new(action))
+    }
+}
+
+/// The same routing over the compact codec, for DFLs built with it
+#[cfg(feature = "compact-codec")]
+#[tonic::async_trait]
+impl cdde_proto::compact::core_router_service_server::CoreRouterService for CoreRouterServiceImpl {
+    async fn process_packet(
+        &self,
+        request: Request<DiameterPacketRequest>,
+    ) -> Result<Response<DiameterPacketAction>, Status> {
+        CoreRouterService::process_packet(self, request).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::routing::{RouteCondition, RouteEntry, RoutingEngine};
+    use tonic::Code;
+
+    fn service() -> CoreRouterServiceImpl {
+        let routes = vec![RouteEntry {
+            priority: 100,
+            condition: RouteCondition::Default,
+            target_pool_id: "default-pool".to_string(),
+        }];
+        CoreRouterServiceImpl::new(PacketProcessor::new(RoutingEngine::new(routes), None))
+    }
+
+    fn request() -> Request<DiameterPacketRequest> {
+        let packet = cdde_core::DiameterPacket {
+            header: cdde_core::DiameterHeader {
+                version: 1,
+                length: 0,
+                flags: 0x80,
+                command_code: 316,
+                application_id: 16777251,
+                hop_by_hop_id: 1,
+                end_to_end_id: 2,
+            },
+            avps: vec![],
+        };
+        Request::new(DiameterPacketRequest {
+            connection_id: 1,
+            vr_id: "vr001".to_string(),
+            reception_timestamp: 0,
+            raw_payload: packet.to_bytes(),
+            session_tx_id: 0,
+            trace_id: String::new(),
+        })
+    }
+
+    #[tokio::test]
+    async fn test_requests_beyond_in_flight_limit_are_shed() {
+        let service = service().with_max_in_flight(1);
+        let shed_before = cdde_metrics::REQUESTS_SHED_TOTAL.get();
+
+        // A request still in progress holds the only slot
+        let in_progress = service.in_flight.as_ref().unwrap().try_acquire().unwrap();
+        let status = service.process_packet(request()).await.unwrap_err();
+        assert_eq!(status.code(), Code::ResourceExhausted);
+        assert!(cdde_metrics::REQUESTS_SHED_TOTAL.get() > shed_before);
+
+        // Once it completes, requests are served again
+        drop(in_progress);
+        assert!(service.process_packet(request()).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_unreachable_relay_is_unable_to_deliver() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        drop(listener);
+        let relay = PeerRelayClient::new(&format!("http://{addr}")).unwrap();
+        let service = service().with_relay(relay);
+
+        let action = service
+            .process_packet(request())
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(action.action_type(), cdde_proto::ActionType::Reply);
+        assert_eq!(action.result_code, 3002);
+    }
+}
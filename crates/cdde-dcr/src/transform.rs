@@ -0,0 +1,553 @@
+use cdde_config::AllowedAvp;
+use cdde_core::diameter::AVP_FLAG_MANDATORY;
+use cdde_core::{CddeError, DiameterAvp, DiameterPacket, Result};
+use cdde_dsl_engine::{Avp, Rule, RuleEngine};
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use tracing::{debug, warn};
+
+/// Context shared with every transform stage
+#[derive(Debug, Clone, Default)]
+pub struct TransformContext {
+    /// Virtual router the packet belongs to
+    pub vr_id: String,
+}
+
+/// A single message manipulation stage
+pub trait Transform: Send + Sync {
+    /// Stage name used in logs and metrics
+    fn name(&self) -> &str;
+
+    /// Apply the stage, returning whether the packet was changed
+    fn apply(&self, packet: &mut DiameterPacket, ctx: &TransformContext) -> Result<bool>;
+
+    /// Whether the stage can change messages of this application
+    fn applies_to(&self, _ctx: &TransformContext, _application_id: u32) -> bool {
+        true
+    }
+}
+
+/// Ordered list of transform stages
+#[derive(Default)]
+pub struct TransformPipeline {
+    stages: Vec<Box<dyn Transform>>,
+}
+
+impl TransformPipeline {
+    /// Create an empty pipeline
+    pub fn new() -> Self {
+        Self { stages: Vec::new() }
+    }
+
+    /// Append a stage to the end of the pipeline
+    pub fn with_stage(mut self, stage: Box<dyn Transform>) -> Self {
+        self.stages.push(stage);
+        self
+    }
+
+    /// Append a stage to the end of the pipeline
+    pub fn push(&mut self, stage: Box<dyn Transform>) {
+        self.stages.push(stage);
+    }
+
+    /// Number of stages
+    pub fn len(&self) -> usize {
+        self.stages.len()
+    }
+
+    /// Check if the pipeline has no stages
+    pub fn is_empty(&self) -> bool {
+        self.stages.is_empty()
+    }
+
+    /// Check if any stage can change messages of this application
+    pub fn applies_to(&self, ctx: &TransformContext, application_id: u32) -> bool {
+        self.stages
+            .iter()
+            .any(|stage| stage.applies_to(ctx, application_id))
+    }
+
+    /// Run every stage in order, returning whether any stage changed the packet
+    ///
+    /// A failing stage is logged and skipped so the remaining stages still
+    /// run. Stages that do not apply to the packet's application are skipped.
+    pub fn apply(&self, packet: &mut DiameterPacket, ctx: &TransformContext) -> bool {
+        let mut changed = false;
+
+        for stage in &self.stages {
+            if !stage.applies_to(ctx, packet.header.application_id) {
+                continue;
+            }
+
+            let outcome = match stage.apply(packet, ctx) {
+                Ok(true) => {
+                    changed = true;
+                    "changed"
+                }
+                Ok(false) => "unchanged",
+                Err(e) => {
+                    warn!("Transform stage {} failed: {}", stage.name(), e);
+                    "error"
+                }
+            };
+
+            debug!("Transform stage {}: {}", stage.name(), outcome);
+            cdde_metrics::TRANSFORM_STAGE_TOTAL
+                .with_label_values(&[stage.name(), outcome])
+                .inc();
+        }
+
+        changed
+    }
+}
+
+/// Read a JSON list of manipulation rules
+pub fn load_rules(path: impl AsRef<Path>) -> Result<Vec<Rule>> {
+    let path = path.as_ref();
+    let json = std::fs::read_to_string(path)
+        .map_err(|e| CddeError::ConfigError(format!("Cannot read {}: {e}", path.display())))?;
+    serde_json::from_str(&json)
+        .map_err(|e| CddeError::ConfigError(format!("Invalid rules in {}: {e}", path.display())))
+}
+
+/// Transform stage running the manipulation DSL
+pub struct DslTransform {
+    name: String,
+    engine: RuleEngine,
+    applications: Option<HashSet<u32>>,
+}
+
+impl DslTransform {
+    /// Create a DSL stage applying to every application
+    pub fn new(name: impl Into<String>, engine: RuleEngine) -> Self {
+        Self {
+            name: name.into(),
+            engine,
+            applications: None,
+        }
+    }
+
+    /// Only run the rules on messages of these applications
+    pub fn with_applications(mut self, applications: impl IntoIterator<Item = u32>) -> Self {
+        self.applications = Some(applications.into_iter().collect());
+        self
+    }
+}
+
+impl Transform for DslTransform {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn applies_to(&self, _ctx: &TransformContext, application_id: u32) -> bool {
+        self.applications
+            .as_ref()
+            .is_none_or(|applications| applications.contains(&application_id))
+    }
+
+    fn apply(&self, packet: &mut DiameterPacket, _ctx: &TransformContext) -> Result<bool> {
+        let dict = self.engine.dictionary();
+        let original: Vec<Avp> = packet
+            .avps
+            .iter()
+            .map(|diameter_avp| Avp::from_raw(diameter_avp.code, &diameter_avp.data, dict))
+            .collect();
+
+        let mut avps = original.clone();
+        let outcome = self
+            .engine
+            .process(&mut avps)
+            .map_err(|e| cdde_core::CddeError::InternalError(format!("DSL error: {e}")))?;
+        if outcome.budget_exceeded {
+            // Keep what the evaluated rules did rather than dropping the packet
+            warn!(
+                "Stage {} hit its execution budget after {} rules",
+                self.name, outcome.rules_evaluated
+            );
+            cdde_metrics::RULE_BUDGET_EXCEEDED_TOTAL.inc();
+        }
+
+        let unchanged = avps.len() == original.len()
+            && avps
+                .iter()
+                .zip(&original)
+                .all(|(a, b)| a.code == b.code && a.value == b.value);
+        if unchanged {
+            return Ok(false);
+        }
+
+        // Keep the original encoding of AVPs the rules left untouched
+        let mut remaining: Vec<Option<(DiameterAvp, Avp)>> =
+            packet.avps.drain(..).zip(original).map(Some).collect();
+        let kept: Vec<Option<DiameterAvp>> = avps
+            .iter()
+            .map(|avp| {
+                remaining
+                    .iter_mut()
+                    .find(|slot| {
+                        slot.as_ref().is_some_and(|(_, orig)| {
+                            orig.code == avp.code && orig.value == avp.value
+                        })
+                    })
+                    .and_then(Option::take)
+                    .map(|(orig, _)| orig)
+            })
+            .collect();
+
+        // Modified AVPs keep the header of the AVP they replace, added ones
+        // take the vendor from the dictionary
+        packet.avps = avps
+            .into_iter()
+            .zip(kept)
+            .map(|(avp, kept)| {
+                if let Some(orig) = kept {
+                    return orig;
+                }
+                let replaced = remaining
+                    .iter_mut()
+                    .find(|slot| slot.as_ref().is_some_and(|(orig, _)| orig.code == avp.code))
+                    .and_then(Option::take);
+                let (flags, vendor_id) = match replaced {
+                    Some((orig, _)) => (orig.flags, orig.vendor_id),
+                    None => (
+                        AVP_FLAG_MANDATORY,
+                        dict.lookup(avp.code).and_then(|info| info.vendor_id),
+                    ),
+                };
+                DiameterAvp {
+                    code: avp.code,
+                    flags,
+                    vendor_id,
+                    data: avp.to_raw(dict),
+                }
+            })
+            .collect();
+
+        Ok(true)
+    }
+}
+
+/// Transform stage forwarding only allowlisted AVPs of selected virtual routers
+///
+/// Complements DSL removal rules for interconnects that require anything
+/// not explicitly allowed to be stripped. Virtual routers without an
+/// allowlist are left untouched.
+#[derive(Default)]
+pub struct AvpAllowlist {
+    allowed: HashMap<String, HashSet<AllowedAvp>>,
+}
+
+impl AvpAllowlist {
+    /// Create a stage without any virtual router in whitelist mode
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Put a virtual router in whitelist mode with the given AVPs
+    pub fn with_vr(mut self, vr_id: impl Into<String>, avps: Vec<AllowedAvp>) -> Self {
+        self.allowed
+            .insert(vr_id.into(), avps.into_iter().collect());
+        self
+    }
+}
+
+impl Transform for AvpAllowlist {
+    fn name(&self) -> &str {
+        "avp-allowlist"
+    }
+
+    fn applies_to(&self, ctx: &TransformContext, _application_id: u32) -> bool {
+        self.allowed.contains_key(&ctx.vr_id)
+    }
+
+    fn apply(&self, packet: &mut DiameterPacket, ctx: &TransformContext) -> Result<bool> {
+        let Some(allowed) = self.allowed.get(&ctx.vr_id) else {
+            return Ok(false);
+        };
+
+        let before = packet.avps.len();
+        packet.avps.retain(|avp| {
+            allowed.contains(&AllowedAvp {
+                code: avp.code,
+                vendor_id: avp.vendor_id,
+            })
+        });
+
+        let removed = before - packet.avps.len();
+        if removed > 0 {
+            debug!("Stripped {} AVPs not allowed for {}", removed, ctx.vr_id);
+        }
+        Ok(removed > 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cdde_core::diameter::AVP_FLAG_PROTECTED;
+    use cdde_core::DiameterHeader;
+    use cdde_dsl_engine::{Action, Condition, Rule};
+    use std::sync::{Arc, Mutex};
+
+    fn packet() -> DiameterPacket {
+        DiameterPacket {
+            header: DiameterHeader {
+                version: 1,
+                length: 20,
+                flags: 0x80,
+                command_code: 316,
+                application_id: 16777251,
+                hop_by_hop_id: 1,
+                end_to_end_id: 2,
+            },
+            avps: vec![DiameterAvp {
+                code: 264,
+                flags: AVP_FLAG_MANDATORY,
+                vendor_id: None,
+                data: b"mme.internal.net".to_vec(),
+            }],
+        }
+    }
+
+    /// Stage recording its execution order
+    struct Recorder {
+        name: &'static str,
+        log: Arc<Mutex<Vec<&'static str>>>,
+        changes: bool,
+    }
+
+    impl Transform for Recorder {
+        fn name(&self) -> &str {
+            self.name
+        }
+
+        fn apply(&self, _packet: &mut DiameterPacket, _ctx: &TransformContext) -> Result<bool> {
+            self.log.lock().unwrap().push(self.name);
+            Ok(self.changes)
+        }
+    }
+
+    #[test]
+    fn test_stages_run_in_order() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let pipeline = TransformPipeline::new()
+            .with_stage(Box::new(Recorder {
+                name: "topology-hide",
+                log: log.clone(),
+                changes: false,
+            }))
+            .with_stage(Box::new(Recorder {
+                name: "rewrite",
+                log: log.clone(),
+                changes: true,
+            }));
+
+        let mut packet = packet();
+        let changed = pipeline.apply(&mut packet, &TransformContext::default());
+
+        assert!(changed);
+        assert_eq!(*log.lock().unwrap(), vec!["topology-hide", "rewrite"]);
+    }
+
+    #[test]
+    fn test_dsl_stage_rewrites_packet() {
+        let hide = RuleEngine::new(vec![Rule::new(
+            10,
+            vec![Condition::AvpExists { code: 264 }],
+            vec![Action::ModifyAvp {
+                code: 264,
+                value: "dra.example.com".to_string(),
+            }],
+        )]);
+        let strip = RuleEngine::new(vec![Rule::new(
+            10,
+            vec![Condition::AvpEquals {
+                code: 264,
+                value: "dra.example.com".to_string(),
+            }],
+            vec![Action::AddAvp {
+                code: 1,
+                value: "user@example.com".to_string(),
+            }],
+        )]);
+
+        // The second stage only matches if the first already ran
+        let pipeline = TransformPipeline::new()
+            .with_stage(Box::new(DslTransform::new("topology-hide", hide)))
+            .with_stage(Box::new(DslTransform::new("tag", strip)));
+
+        let mut packet = packet();
+        assert!(pipeline.apply(&mut packet, &TransformContext::default()));
+
+        assert_eq!(packet.find_avp(264).unwrap().data, b"dra.example.com");
+        assert_eq!(packet.find_avp(1).unwrap().data, b"user@example.com");
+    }
+
+    #[test]
+    fn test_unchanged_dsl_stage() {
+        let engine = RuleEngine::new(vec![Rule::new(
+            10,
+            vec![Condition::AvpExists { code: 999 }],
+            vec![Action::RemoveAvp { code: 264 }],
+        )]);
+        let stage = DslTransform::new("noop", engine);
+
+        let mut packet = packet();
+        assert!(!stage
+            .apply(&mut packet, &TransformContext::default())
+            .unwrap());
+        assert_eq!(packet.avps, self::packet().avps);
+    }
+
+    #[test]
+    fn test_dsl_stage_matches_binary_result_code() {
+        let engine = RuleEngine::new(vec![Rule::new(
+            10,
+            vec![Condition::AvpEquals {
+                code: 268,
+                value: "2001".to_string(),
+            }],
+            vec![Action::SetAvp {
+                code: 268,
+                value: "5012".to_string(),
+            }],
+        )]);
+        let stage = DslTransform::new("rewrite-result", engine);
+
+        let mut packet = packet();
+        packet.avps.push(DiameterAvp {
+            code: 268,
+            flags: AVP_FLAG_MANDATORY,
+            vendor_id: None,
+            data: 2001u32.to_be_bytes().to_vec(),
+        });
+
+        assert!(stage
+            .apply(&mut packet, &TransformContext::default())
+            .unwrap());
+        assert_eq!(packet.find_avp(268).unwrap().data, 5012u32.to_be_bytes());
+        assert_eq!(packet.find_avp(264).unwrap().data, b"mme.internal.net");
+    }
+
+    #[test]
+    fn test_dsl_stage_keeps_vendor_of_rewritten_avps() {
+        let engine = RuleEngine::new(vec![Rule::new(
+            10,
+            vec![Condition::AvpExists { code: 1407 }],
+            vec![
+                Action::ModifyAvp {
+                    code: 1407,
+                    value: "21f365".to_string(),
+                },
+                Action::AddAvp {
+                    code: 1405,
+                    value: "1".to_string(),
+                },
+            ],
+        )]);
+        let stage = DslTransform::new("rewrite-plmn", engine);
+
+        let mut packet = packet();
+        packet.avps.push(DiameterAvp {
+            code: 1407,
+            flags: AVP_FLAG_MANDATORY | AVP_FLAG_PROTECTED,
+            vendor_id: Some(10415),
+            data: vec![0x12, 0xF4, 0x56],
+        });
+
+        assert!(stage
+            .apply(&mut packet, &TransformContext::default())
+            .unwrap());
+
+        // The modified AVP keeps its flags and vendor
+        let visited = packet.find_avp(1407).unwrap();
+        assert_eq!(visited.data, [0x21, 0xF3, 0x65]);
+        assert_eq!(visited.flags, AVP_FLAG_MANDATORY | AVP_FLAG_PROTECTED);
+        assert_eq!(visited.vendor_id, Some(10415));
+
+        // The added AVP takes its vendor from the dictionary
+        let ulr_flags = packet.find_avp(1405).unwrap();
+        assert_eq!(ulr_flags.data, 1u32.to_be_bytes());
+        assert_eq!(ulr_flags.vendor_id, Some(10415));
+
+        // Both survive a round trip through the wire format
+        let parsed = DiameterPacket::parse(&packet.serialize()).unwrap();
+        assert_eq!(parsed.find_avp(1407).unwrap().vendor_id, Some(10415));
+        assert_eq!(parsed.find_avp(1405).unwrap().vendor_id, Some(10415));
+    }
+
+    #[test]
+    fn test_allowlist_strips_other_avps() {
+        let stage = AvpAllowlist::new().with_vr(
+            "vr-partner",
+            vec![
+                AllowedAvp {
+                    code: 264,
+                    vendor_id: None,
+                },
+                AllowedAvp {
+                    code: 1407,
+                    vendor_id: Some(10415),
+                },
+            ],
+        );
+
+        let mut packet = packet();
+        packet.avps.push(DiameterAvp {
+            code: 1407,
+            flags: AVP_FLAG_MANDATORY | 0x80,
+            vendor_id: Some(10415),
+            data: b"visited-plmn".to_vec(),
+        });
+        // Same code under another vendor is not allowed
+        packet.avps.push(DiameterAvp {
+            code: 1407,
+            flags: 0x80,
+            vendor_id: Some(9999),
+            data: b"secret".to_vec(),
+        });
+        packet.avps.push(DiameterAvp {
+            code: 1,
+            flags: AVP_FLAG_MANDATORY,
+            vendor_id: None,
+            data: b"user@example.com".to_vec(),
+        });
+
+        // Other virtual routers are not filtered
+        let mut other = packet.clone();
+        let ctx = TransformContext {
+            vr_id: "vr-internal".to_string(),
+        };
+        assert!(!stage.apply(&mut other, &ctx).unwrap());
+        assert_eq!(other.avps.len(), 4);
+
+        let ctx = TransformContext {
+            vr_id: "vr-partner".to_string(),
+        };
+        assert!(stage.apply(&mut packet, &ctx).unwrap());
+        let kept: Vec<_> = packet
+            .avps
+            .iter()
+            .map(|avp| (avp.code, avp.vendor_id))
+            .collect();
+        assert_eq!(kept, vec![(264, None), (1407, Some(10415))]);
+    }
+
+    #[test]
+    fn test_load_rules() {
+        let path = std::env::temp_dir().join(format!("cdde-rules-{}.json", std::process::id()));
+        std::fs::write(
+            &path,
+            r#"[{"priority": 10, "conditions": [{"type": "Always"}],
+                 "actions": [{"type": "RemoveAvp", "code": 264}]}]"#,
+        )
+        .unwrap();
+        let rules = load_rules(&path).unwrap();
+        assert_eq!(rules.len(), 1);
+        assert_eq!(rules[0].priority, 10);
+
+        std::fs::write(&path, "{}").unwrap();
+        assert!(matches!(load_rules(&path), Err(CddeError::ConfigError(_))));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
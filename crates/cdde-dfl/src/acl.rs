@@ -0,0 +1,160 @@
+use cdde_core::{CddeError, Result, Transport};
+use std::net::IpAddr;
+use std::str::FromStr;
+
+/// IPv4 or IPv6 address range in CIDR notation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cidr {
+    network: IpAddr,
+    prefix_len: u8,
+}
+
+impl Cidr {
+    /// Check if the address falls within the range
+    ///
+    /// IPv4-mapped IPv6 addresses are matched as IPv4.
+    pub fn contains(&self, addr: IpAddr) -> bool {
+        match (self.network, addr.to_canonical()) {
+            (IpAddr::V4(network), IpAddr::V4(addr)) => {
+                let mask = u32::MAX
+                    .checked_shl(32 - u32::from(self.prefix_len))
+                    .unwrap_or(0);
+                u32::from(network) & mask == u32::from(addr) & mask
+            }
+            (IpAddr::V6(network), IpAddr::V6(addr)) => {
+                let mask = u128::MAX
+                    .checked_shl(128 - u32::from(self.prefix_len))
+                    .unwrap_or(0);
+                u128::from(network) & mask == u128::from(addr) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for Cidr {
+    type Err = CddeError;
+
+    /// Parse `10.0.0.0/8` or `2001:db8::/32`; a bare address is a single host
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || CddeError::ConfigError(format!("Invalid CIDR: {s}"));
+        let (addr, prefix_len) = match s.trim().split_once('/') {
+            Some((addr, prefix_len)) => (addr, Some(prefix_len)),
+            None => (s.trim(), None),
+        };
+
+        let network: IpAddr = addr.parse().map_err(|_| invalid())?;
+        let max_len = if network.is_ipv4() { 32 } else { 128 };
+        let prefix_len = match prefix_len {
+            Some(len) => len.parse().map_err(|_| invalid())?,
+            None => max_len,
+        };
+        if prefix_len > max_len {
+            return Err(invalid());
+        }
+
+        Ok(Self {
+            network,
+            prefix_len,
+        })
+    }
+}
+
+/// Client address allowlist and denylist checked when a connection is accepted
+///
+/// The denylist wins over the allowlist; an empty allowlist admits every
+/// address that is not denied.
+#[derive(Debug, Clone, Default)]
+pub struct AccessList {
+    allow: Vec<Cidr>,
+    deny: Vec<Cidr>,
+}
+
+impl AccessList {
+    /// Create an access list from parsed ranges
+    pub fn new(allow: Vec<Cidr>, deny: Vec<Cidr>) -> Self {
+        Self { allow, deny }
+    }
+
+    /// Build an access list from comma-separated CIDR lists
+    pub fn parse(allow: &str, deny: &str) -> Result<Self> {
+        Ok(Self::new(parse_list(allow)?, parse_list(deny)?))
+    }
+
+    /// Check if a client address may connect
+    pub fn permits(&self, addr: IpAddr) -> bool {
+        if self.deny.iter().any(|cidr| cidr.contains(addr)) {
+            return false;
+        }
+        self.allow.is_empty() || self.allow.iter().any(|cidr| cidr.contains(addr))
+    }
+
+    /// Check a freshly accepted connection by its peer address
+    ///
+    /// Connections whose peer address cannot be read are refused.
+    pub fn admits<T: Transport>(&self, socket: &T) -> bool {
+        socket
+            .peer_addr()
+            .map(|addr| self.permits(addr.ip()))
+            .unwrap_or(false)
+    }
+}
+
+fn parse_list(list: &str) -> Result<Vec<Cidr>> {
+    list.split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(str::parse)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_cidr_contains() {
+        let cidr: Cidr = "10.1.0.0/16".parse().unwrap();
+        assert!(cidr.contains(ip("10.1.200.3")));
+        assert!(!cidr.contains(ip("10.2.0.1")));
+        assert!(cidr.contains(ip("::ffff:10.1.0.9")));
+
+        let cidr: Cidr = "2001:db8::/32".parse().unwrap();
+        assert!(cidr.contains(ip("2001:db8:1::1")));
+        assert!(!cidr.contains(ip("2001:db9::1")));
+        assert!(!cidr.contains(ip("10.1.0.1")));
+
+        let any: Cidr = "0.0.0.0/0".parse().unwrap();
+        assert!(any.contains(ip("192.0.2.1")));
+
+        let host: Cidr = "192.0.2.7".parse().unwrap();
+        assert!(host.contains(ip("192.0.2.7")));
+        assert!(!host.contains(ip("192.0.2.8")));
+    }
+
+    #[test]
+    fn test_invalid_cidr() {
+        assert!("10.0.0.0/33".parse::<Cidr>().is_err());
+        assert!("10.0.0/8".parse::<Cidr>().is_err());
+        assert!("10.0.0.0/x".parse::<Cidr>().is_err());
+        assert!(AccessList::parse("10.0.0.0/8, bogus", "").is_err());
+    }
+
+    #[test]
+    fn test_deny_wins_over_allow() {
+        let acl = AccessList::parse("10.0.0.0/8, 192.168.1.0/24", "10.9.0.0/16").unwrap();
+
+        assert!(acl.permits(ip("10.1.2.3")));
+        assert!(acl.permits(ip("192.168.1.20")));
+        assert!(!acl.permits(ip("10.9.0.1")));
+        assert!(!acl.permits(ip("172.16.0.1")));
+
+        let open = AccessList::parse("", "203.0.113.0/24").unwrap();
+        assert!(open.permits(ip("172.16.0.1")));
+        assert!(!open.permits(ip("203.0.113.5")));
+    }
+}
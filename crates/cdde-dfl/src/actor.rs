@@ -0,0 +1,760 @@
+use crate::answer::{error_answer, LocalIdentity, RESULT_TOO_BUSY, RESULT_UNABLE_TO_DELIVER};
+use crate::session::SessionConfig;
+use crate::store::TransactionStore;
+use cdde_core::codes::{command_name, AVP_SESSION_ID};
+use cdde_core::DiameterPacket;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, oneshot, watch};
+use tokio_stream::StreamExt;
+use tokio_util::time::delay_queue::Key;
+use tokio_util::time::DelayQueue;
+use tracing::{debug, info, warn};
+
+/// Default time allowed for pending sessions to finish on shutdown
+pub const DEFAULT_DRAIN_DEADLINE: Duration = Duration::from_secs(10);
+
+/// Default number of requests a single connection may have pending
+pub const DEFAULT_MAX_PENDING_PER_CONNECTION: usize = 1000;
+
+/// Message handled by the session actor
+#[derive(Debug)]
+pub enum ActorMessage {
+    /// Request received on a client connection
+    IngressRequest {
+        conn_id: u64,
+        /// Virtual Router the request belongs to, selecting its answer timeout
+        vr_id: Option<String>,
+        packet: DiameterPacket,
+        /// Told whether the request was taken; the actor answers the ones it
+        /// turns away itself
+        admitted: oneshot::Sender<bool>,
+    },
+
+    /// Answer received for a pending request
+    Answer {
+        conn_id: u64,
+        packet: DiameterPacket,
+    },
+
+    /// Give up on every pending request, replying with how many there were
+    Flush { reply: oneshot::Sender<usize> },
+}
+
+/// Action produced by the session actor
+#[derive(Debug)]
+pub enum SessionAction {
+    /// Send an answer back on a client connection
+    Reply {
+        conn_id: u64,
+        packet: DiameterPacket,
+    },
+}
+
+/// Request given up on because its answer timeout fired
+#[derive(Debug)]
+pub struct TimeoutInfo {
+    /// Connection id and Hop-by-Hop id of the request
+    pub key: (u64, u32),
+
+    /// Command code of the request
+    pub command_code: u32,
+
+    /// Session-Id of the request, empty when it has none
+    pub session_id: String,
+
+    /// Time between receiving the request and timing out
+    pub elapsed_ms: u64,
+
+    /// UNABLE_TO_DELIVER answer sent back to the client
+    pub error_response: DiameterPacket,
+}
+
+/// Request waiting for its answer
+struct PendingRequest {
+    request: DiameterPacket,
+    delay_key: Key,
+    received_at: Instant,
+}
+
+/// Actor tracking pending requests and answering them with 3002 on timeout
+///
+/// Pending requests are keyed by (connection id, Hop-by-Hop id). When the
+/// shutdown signal fires the actor answers new requests with
+/// DIAMETER_TOO_BUSY but keeps matching answers and firing timeouts until
+/// nothing is pending or the drain deadline passes.
+///
+/// Each connection may only have a limited number of requests pending; beyond
+/// that new requests are answered with DIAMETER_TOO_BUSY straight away.
+pub struct SessionActor {
+    config: SessionConfig,
+    inbox: mpsc::Receiver<ActorMessage>,
+    outbound: mpsc::Sender<SessionAction>,
+    pending: HashMap<(u64, u32), PendingRequest>,
+    pending_per_connection: HashMap<u64, usize>,
+    max_pending_per_connection: usize,
+    timeout_queue: DelayQueue<(u64, u32)>,
+    shutdown: Option<watch::Receiver<bool>>,
+    drain_deadline: Duration,
+    identity: LocalIdentity,
+    store: Option<Arc<TransactionStore>>,
+}
+
+impl SessionActor {
+    /// Create a new session actor
+    pub fn new(
+        config: SessionConfig,
+        inbox: mpsc::Receiver<ActorMessage>,
+        outbound: mpsc::Sender<SessionAction>,
+    ) -> Self {
+        Self {
+            config,
+            inbox,
+            outbound,
+            pending: HashMap::new(),
+            pending_per_connection: HashMap::new(),
+            max_pending_per_connection: DEFAULT_MAX_PENDING_PER_CONNECTION,
+            timeout_queue: DelayQueue::new(),
+            shutdown: None,
+            drain_deadline: DEFAULT_DRAIN_DEADLINE,
+            identity: LocalIdentity::default(),
+            store: None,
+        }
+    }
+
+    /// Drop the transactions of flushed requests from `store`
+    pub fn with_transaction_store(mut self, store: Arc<TransactionStore>) -> Self {
+        self.store = Some(store);
+        self
+    }
+
+    /// Set the Origin-Host and Origin-Realm of timeout answers
+    pub fn with_local_identity(mut self, identity: LocalIdentity) -> Self {
+        self.identity = identity;
+        self
+    }
+
+    /// Set how many requests a single connection may have pending
+    pub fn with_max_pending_per_connection(mut self, limit: usize) -> Self {
+        self.max_pending_per_connection = limit;
+        self
+    }
+
+    /// Drain pending sessions once `shutdown` becomes true
+    pub fn with_shutdown(
+        mut self,
+        shutdown: watch::Receiver<bool>,
+        drain_deadline: Duration,
+    ) -> Self {
+        self.shutdown = Some(shutdown);
+        self.drain_deadline = drain_deadline;
+        self
+    }
+
+    /// Number of requests waiting for an answer
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Run until the inbox closes or a shutdown drain completes
+    pub async fn run(mut self) {
+        loop {
+            tokio::select! {
+                // Stop taking requests as soon as shutdown is signalled
+                biased;
+
+                _ = wait_for_shutdown(&mut self.shutdown) => {
+                    self.drain().await;
+                    break;
+                }
+                message = self.inbox.recv() => match message {
+                    Some(message) => self.handle(message).await,
+                    None => break,
+                },
+                Some(expired) = self.timeout_queue.next(), if !self.timeout_queue.is_empty() => {
+                    self.on_timeout(expired.into_inner()).await;
+                }
+            }
+        }
+
+        debug!("Session actor stopped");
+    }
+
+    /// Service answers and timeouts until nothing is pending or the deadline passes
+    async fn drain(&mut self) {
+        info!(
+            "Draining {} pending sessions (deadline {:?})",
+            self.pending.len(),
+            self.drain_deadline
+        );
+
+        let deadline = tokio::time::sleep(self.drain_deadline);
+        tokio::pin!(deadline);
+        let mut inbox_open = true;
+
+        while !self.pending.is_empty() {
+            tokio::select! {
+                _ = &mut deadline => {
+                    warn!("Drain deadline reached with {} pending sessions", self.pending.len());
+                    break;
+                }
+                Some(expired) = self.timeout_queue.next() => {
+                    self.on_timeout(expired.into_inner()).await;
+                }
+                message = self.inbox.recv(), if inbox_open => match message {
+                    Some(ActorMessage::IngressRequest { conn_id, packet, admitted, .. }) => {
+                        warn!(
+                            "Rejecting request {} on connection {} while draining",
+                            packet.header.hop_by_hop_id, conn_id
+                        );
+                        self.reject(conn_id, &packet, admitted).await;
+                    }
+                    Some(message) => self.handle(message).await,
+                    None => inbox_open = false,
+                },
+            }
+        }
+    }
+
+    async fn handle(&mut self, message: ActorMessage) {
+        match message {
+            ActorMessage::IngressRequest {
+                conn_id,
+                vr_id,
+                packet,
+                admitted,
+            } => {
+                let key = (conn_id, packet.header.hop_by_hop_id);
+                let in_flight = self
+                    .pending_per_connection
+                    .get(&conn_id)
+                    .copied()
+                    .unwrap_or(0);
+                if !self.pending.contains_key(&key) && in_flight >= self.max_pending_per_connection
+                {
+                    warn!(
+                        "Connection {} has {} pending requests, answering {} with TOO_BUSY",
+                        conn_id, in_flight, key.1
+                    );
+                    self.reject(conn_id, &packet, admitted).await;
+                    return;
+                }
+
+                let delay_key = self
+                    .timeout_queue
+                    .insert(key, self.config.answer_timeout_for(vr_id.as_deref()));
+                if let Some(previous) = self.pending.insert(
+                    key,
+                    PendingRequest {
+                        request: packet,
+                        delay_key,
+                        received_at: Instant::now(),
+                    },
+                ) {
+                    // Retransmission with the same Hop-by-Hop id restarts the timer
+                    self.timeout_queue.remove(&previous.delay_key);
+                } else {
+                    *self.pending_per_connection.entry(conn_id).or_default() += 1;
+                }
+
+                let _ = admitted.send(true);
+            }
+            ActorMessage::Answer { conn_id, packet } => {
+                let key = (conn_id, packet.header.hop_by_hop_id);
+                let Some(pending) = self.pending.get(&key) else {
+                    debug!("Dropping answer {} with no pending request", key.1);
+                    return;
+                };
+                // Answers share their request's command code; anything else
+                // is a peer bug and must not complete the transaction
+                let expected = pending.request.header.command_code;
+                if packet.header.command_code != expected {
+                    warn!(
+                        "Dropping answer {} with command {}, pending request has command {}",
+                        key.1, packet.header.command_code, expected
+                    );
+                    cdde_metrics::ANSWER_COMMAND_MISMATCH_TOTAL.inc();
+                    return;
+                }
+                let Some(pending) = self.complete(key) else {
+                    return;
+                };
+                self.timeout_queue.remove(&pending.delay_key);
+
+                self.send(SessionAction::Reply { conn_id, packet }).await;
+            }
+            ActorMessage::Flush { reply } => {
+                let flushed = self.flush().await;
+                let _ = reply.send(flushed);
+            }
+        }
+    }
+
+    /// Turn a request away, answering it with DIAMETER_TOO_BUSY
+    async fn reject(&self, conn_id: u64, packet: &DiameterPacket, admitted: oneshot::Sender<bool>) {
+        let _ = admitted.send(false);
+        let answer = error_answer(packet, RESULT_TOO_BUSY, &self.identity);
+        self.send(SessionAction::Reply {
+            conn_id,
+            packet: answer,
+        })
+        .await;
+    }
+
+    /// Answer every pending request with UNABLE_TO_DELIVER and forget it
+    async fn flush(&mut self) -> usize {
+        let keys: Vec<(u64, u32)> = self.pending.keys().copied().collect();
+        warn!("Flushing {} pending sessions", keys.len());
+
+        for key in &keys {
+            let Some(pending) = self.complete(*key) else {
+                continue;
+            };
+            self.timeout_queue.remove(&pending.delay_key);
+            if let Some(store) = &self.store {
+                store.remove(key.0, key.1).await;
+            }
+            let answer = error_answer(&pending.request, RESULT_UNABLE_TO_DELIVER, &self.identity);
+            self.send(SessionAction::Reply {
+                conn_id: key.0,
+                packet: answer,
+            })
+            .await;
+        }
+        keys.len()
+    }
+
+    /// Stop tracking a pending request
+    fn complete(&mut self, key: (u64, u32)) -> Option<PendingRequest> {
+        let pending = self.pending.remove(&key)?;
+        if let Some(count) = self.pending_per_connection.get_mut(&key.0) {
+            *count -= 1;
+            if *count == 0 {
+                self.pending_per_connection.remove(&key.0);
+            }
+        }
+        Some(pending)
+    }
+
+    async fn on_timeout(&mut self, key: (u64, u32)) {
+        let Some(pending) = self.complete(key) else {
+            return;
+        };
+
+        let info = TimeoutInfo {
+            key,
+            command_code: pending.request.header.command_code,
+            session_id: pending
+                .request
+                .find_avp(AVP_SESSION_ID)
+                .map(|avp| String::from_utf8_lossy(&avp.data).to_string())
+                .unwrap_or_default(),
+            elapsed_ms: pending.received_at.elapsed().as_millis() as u64,
+            error_response: error_answer(
+                &pending.request,
+                RESULT_UNABLE_TO_DELIVER,
+                &self.identity,
+            ),
+        };
+        report_timeout(&info);
+        self.send(SessionAction::Reply {
+            conn_id: key.0,
+            packet: info.error_response,
+        })
+        .await;
+    }
+
+    async fn send(&self, action: SessionAction) {
+        if self.outbound.send(action).await.is_err() {
+            warn!("Session action receiver dropped");
+        }
+    }
+}
+
+/// Log a timed-out request and record how long it waited
+fn report_timeout(info: &TimeoutInfo) {
+    cdde_metrics::LATENCY_SECONDS.observe(info.elapsed_ms as f64 / 1000.0);
+    warn!(
+        connection_id = info.key.0,
+        hop_by_hop_id = info.key.1,
+        command_code = info.command_code,
+        command = command_name(info.command_code).unwrap_or("unknown"),
+        session_id = %info.session_id,
+        elapsed_ms = info.elapsed_ms,
+        "Request timed out"
+    );
+}
+
+/// Resolve once the shutdown flag is set; never resolves without a signal
+async fn wait_for_shutdown(shutdown: &mut Option<watch::Receiver<bool>>) {
+    let Some(shutdown) = shutdown else {
+        return std::future::pending().await;
+    };
+
+    if shutdown.wait_for(|stop| *stop).await.is_err() {
+        std::future::pending::<()>().await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cdde_core::DiameterHeader;
+
+    fn request(hop_by_hop_id: u32) -> DiameterPacket {
+        DiameterPacket {
+            header: DiameterHeader {
+                version: 1,
+                length: 20,
+                flags: 0xC0,
+                command_code: 316,
+                application_id: 16777251,
+                hop_by_hop_id,
+                end_to_end_id: hop_by_hop_id,
+            },
+            avps: vec![],
+        }
+    }
+
+    fn result_code(packet: &DiameterPacket) -> Option<u32> {
+        packet
+            .find_avp(268)
+            .map(|avp| u32::from_be_bytes(avp.data[..4].try_into().unwrap()))
+    }
+
+    /// Ingress message for a request, with the receiver of the actor's verdict
+    fn ingress(
+        conn_id: u64,
+        vr_id: Option<&str>,
+        packet: DiameterPacket,
+    ) -> (ActorMessage, oneshot::Receiver<bool>) {
+        let (admitted, verdict) = oneshot::channel();
+        let message = ActorMessage::IngressRequest {
+            conn_id,
+            vr_id: vr_id.map(str::to_string),
+            packet,
+            admitted,
+        };
+        (message, verdict)
+    }
+
+    fn config(answer_timeout: Duration) -> SessionConfig {
+        SessionConfig {
+            answer_timeout,
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_answer_completes_pending_request() {
+        let (tx, inbox) = mpsc::channel(8);
+        let (outbound, mut actions) = mpsc::channel(8);
+        let actor =
+            tokio::spawn(SessionActor::new(config(Duration::from_secs(5)), inbox, outbound).run());
+
+        let (message, admitted) = ingress(1, None, request(10));
+        tx.send(message).await.unwrap();
+        assert!(admitted.await.unwrap());
+
+        let mut answer = request(10);
+        answer.header.flags = 0x40;
+        tx.send(ActorMessage::Answer {
+            conn_id: 1,
+            packet: answer,
+        })
+        .await
+        .unwrap();
+        let SessionAction::Reply { conn_id, packet } = actions.recv().await.unwrap();
+        assert_eq!(conn_id, 1);
+        assert!(packet.header.is_answer());
+        assert_eq!(result_code(&packet), None);
+
+        drop(tx);
+        actor.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_pending_session_times_out_during_drain() {
+        let (tx, inbox) = mpsc::channel(8);
+        let (outbound, mut actions) = mpsc::channel(8);
+        let (shutdown_tx, shutdown) = watch::channel(false);
+        let actor = tokio::spawn(
+            SessionActor::new(config(Duration::from_millis(200)), inbox, outbound)
+                .with_shutdown(shutdown, Duration::from_secs(5))
+                .run(),
+        );
+
+        let (message, admitted) = ingress(7, None, request(42));
+        tx.send(message).await.unwrap();
+        assert!(admitted.await.unwrap());
+
+        // Shut down before the answer timeout fires
+        shutdown_tx.send(true).unwrap();
+
+        // Requests arriving during the drain are turned away and answered
+        let (message, admitted) = ingress(7, None, request(43));
+        tx.send(message).await.unwrap();
+        assert!(!admitted.await.unwrap());
+        let SessionAction::Reply { conn_id, packet } = actions.recv().await.unwrap();
+        assert_eq!(conn_id, 7);
+        assert_eq!(packet.header.hop_by_hop_id, 43);
+        assert_eq!(result_code(&packet), Some(RESULT_TOO_BUSY));
+
+        let SessionAction::Reply { conn_id, packet } =
+            tokio::time::timeout(Duration::from_secs(2), actions.recv())
+                .await
+                .expect("No timeout action during drain")
+                .unwrap();
+        assert_eq!(conn_id, 7);
+        assert_eq!(packet.header.hop_by_hop_id, 42);
+        assert_eq!(result_code(&packet), Some(3002));
+
+        // The drained actor exits even though the inbox is still open
+        tokio::time::timeout(Duration::from_secs(2), actor)
+            .await
+            .expect("Actor did not exit after draining")
+            .unwrap();
+        assert!(actions.recv().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_drain_deadline() {
+        let (_tx, inbox) = mpsc::channel(8);
+        let (outbound, _actions) = mpsc::channel(8);
+        let (shutdown_tx, shutdown) = watch::channel(false);
+
+        let mut actor = SessionActor::new(config(Duration::from_secs(30)), inbox, outbound)
+            .with_shutdown(shutdown, Duration::from_millis(100));
+        actor.handle(ingress(1, None, request(1)).0).await;
+        assert_eq!(actor.pending(), 1);
+
+        shutdown_tx.send(true).unwrap();
+        tokio::time::timeout(Duration::from_secs(2), actor.run())
+            .await
+            .expect("Drain did not stop at the deadline");
+    }
+
+    #[tokio::test]
+    async fn test_virtual_router_answer_timeouts() {
+        let (tx, inbox) = mpsc::channel(8);
+        let (outbound, mut actions) = mpsc::channel(8);
+        let config = SessionConfig {
+            answer_timeout: Duration::from_secs(30),
+            vr_answer_timeouts: HashMap::from([
+                ("vr-fast".to_string(), Duration::from_millis(100)),
+                ("vr-slow".to_string(), Duration::from_millis(600)),
+            ]),
+            ..Default::default()
+        };
+        let actor = tokio::spawn(SessionActor::new(config, inbox, outbound).run());
+
+        let started = Instant::now();
+        for (hop_by_hop_id, vr_id) in [(1, "vr-slow"), (2, "vr-fast")] {
+            let (message, admitted) = ingress(1, Some(vr_id), request(hop_by_hop_id));
+            tx.send(message).await.unwrap();
+            assert!(admitted.await.unwrap());
+        }
+
+        let mut timed_out = Vec::new();
+        for _ in 0..2 {
+            let SessionAction::Reply { packet, .. } =
+                tokio::time::timeout(Duration::from_secs(5), actions.recv())
+                    .await
+                    .expect("Request never timed out")
+                    .unwrap();
+            assert_eq!(result_code(&packet), Some(3002));
+            timed_out.push((packet.header.hop_by_hop_id, started.elapsed()));
+        }
+
+        // vr-fast's request expires first even though it arrived second
+        assert_eq!(timed_out[0].0, 2);
+        assert!(timed_out[0].1 >= Duration::from_millis(100));
+        assert!(timed_out[0].1 < Duration::from_millis(600));
+        assert_eq!(timed_out[1].0, 1);
+        assert!(timed_out[1].1 >= Duration::from_millis(600));
+
+        drop(tx);
+        actor.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_timeout_answer_carries_local_identity() {
+        let (tx, inbox) = mpsc::channel(8);
+        let (outbound, mut actions) = mpsc::channel(8);
+        let actor = tokio::spawn(
+            SessionActor::new(config(Duration::from_millis(50)), inbox, outbound)
+                .with_local_identity(LocalIdentity::new("dfl01.operator.net", "operator.net"))
+                .run(),
+        );
+
+        let (message, admitted) = ingress(3, None, request(5));
+        tx.send(message).await.unwrap();
+        assert!(admitted.await.unwrap());
+
+        let SessionAction::Reply { conn_id, packet } = actions.recv().await.unwrap();
+        assert_eq!(conn_id, 3);
+        assert_eq!(packet.find_avp(268).unwrap().data, 3002u32.to_be_bytes());
+        assert_eq!(packet.find_avp(264).unwrap().data, b"dfl01.operator.net");
+        assert_eq!(packet.find_avp(296).unwrap().data, b"operator.net");
+
+        drop(tx);
+        actor.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_answer_with_other_command_is_not_correlated() {
+        let (tx, inbox) = mpsc::channel(8);
+        let (outbound, mut actions) = mpsc::channel(8);
+        let actor =
+            tokio::spawn(SessionActor::new(config(Duration::from_secs(5)), inbox, outbound).run());
+
+        let (message, admitted) = ingress(1, None, request(10));
+        tx.send(message).await.unwrap();
+        assert!(admitted.await.unwrap());
+
+        let before = cdde_metrics::ANSWER_COMMAND_MISMATCH_TOTAL.get();
+        let mut wrong = request(10);
+        wrong.header.flags = 0x40;
+        wrong.header.command_code = 318;
+        tx.send(ActorMessage::Answer {
+            conn_id: 1,
+            packet: wrong,
+        })
+        .await
+        .unwrap();
+
+        // The request is still pending, so the matching answer completes it
+        let mut answer = request(10);
+        answer.header.flags = 0x40;
+        tx.send(ActorMessage::Answer {
+            conn_id: 1,
+            packet: answer,
+        })
+        .await
+        .unwrap();
+        let SessionAction::Reply { packet, .. } = actions.recv().await.unwrap();
+        assert_eq!(packet.header.command_code, 316);
+        assert_eq!(result_code(&packet), None);
+        assert!(cdde_metrics::ANSWER_COMMAND_MISMATCH_TOTAL.get() > before);
+
+        drop(tx);
+        actor.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_requests_beyond_connection_limit_are_too_busy() {
+        let (tx, inbox) = mpsc::channel(8);
+        let (outbound, mut actions) = mpsc::channel(8);
+        let actor = tokio::spawn(
+            SessionActor::new(config(Duration::from_secs(5)), inbox, outbound)
+                .with_max_pending_per_connection(2)
+                .run(),
+        );
+
+        let mut verdicts = vec![];
+        for hop_by_hop_id in [10, 11, 12] {
+            let (message, admitted) = ingress(1, None, request(hop_by_hop_id));
+            tx.send(message).await.unwrap();
+            verdicts.push(admitted.await.unwrap());
+        }
+        assert_eq!(verdicts, vec![true, true, false]);
+        let SessionAction::Reply { conn_id, packet } = actions.recv().await.unwrap();
+        assert_eq!(conn_id, 1);
+        assert_eq!(packet.header.hop_by_hop_id, 12);
+        assert_eq!(result_code(&packet), Some(RESULT_TOO_BUSY));
+
+        // Other connections have their own allowance
+        let (message, admitted) = ingress(2, None, request(12));
+        tx.send(message).await.unwrap();
+        assert!(admitted.await.unwrap());
+
+        drop(tx);
+        actor.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_timeout_is_observed_and_logged() {
+        use cdde_test_support::LogBuffer;
+
+        // The actor runs on this thread, so it logs to this subscriber
+        let logs = LogBuffer::default();
+        let writer = logs.clone();
+        let _guard = tracing::subscriber::set_default(
+            tracing_subscriber::fmt()
+                .with_writer(move || writer.clone())
+                .with_ansi(false)
+                .finish(),
+        );
+
+        let (tx, inbox) = mpsc::channel(8);
+        let (outbound, mut actions) = mpsc::channel(8);
+        let actor = tokio::spawn(
+            SessionActor::new(config(Duration::from_millis(50)), inbox, outbound).run(),
+        );
+
+        let mut packet = request(10);
+        packet.avps.push(cdde_core::DiameterAvp {
+            code: AVP_SESSION_ID,
+            flags: 0x40,
+            vendor_id: None,
+            data: b"mme1;timeout;1".to_vec(),
+        });
+        let before = cdde_metrics::LATENCY_SECONDS.get_sample_count();
+        let (message, admitted) = ingress(1, None, packet);
+        tx.send(message).await.unwrap();
+        assert!(admitted.await.unwrap());
+        let SessionAction::Reply { packet, .. } = actions.recv().await.unwrap();
+        assert_eq!(result_code(&packet), Some(RESULT_UNABLE_TO_DELIVER));
+
+        assert!(cdde_metrics::LATENCY_SECONDS.get_sample_count() > before);
+        let output = logs.contents();
+        assert!(output.contains("Request timed out"));
+        assert!(output.contains("session_id=mme1;timeout;1"));
+        assert!(output.contains("command_code=316"));
+
+        drop(tx);
+        actor.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_flush_answers_every_pending_session() {
+        let (_tx, inbox) = mpsc::channel(8);
+        let (outbound, mut actions) = mpsc::channel(8);
+        let store = Arc::new(TransactionStore::new());
+        let mut actor = SessionActor::new(config(Duration::from_secs(30)), inbox, outbound)
+            .with_transaction_store(store.clone());
+
+        for (conn_id, hop_by_hop_id) in [(1, 10), (2, 20)] {
+            store
+                .insert(
+                    conn_id,
+                    hop_by_hop_id,
+                    316,
+                    hop_by_hop_id,
+                    String::new(),
+                    Duration::from_secs(30),
+                )
+                .await;
+            let (message, admitted) = ingress(conn_id, None, request(hop_by_hop_id));
+            actor.handle(message).await;
+            assert!(admitted.await.unwrap());
+        }
+        assert_eq!(actor.pending(), 2);
+
+        let (reply, flushed) = oneshot::channel();
+        actor.handle(ActorMessage::Flush { reply }).await;
+        assert_eq!(flushed.await.unwrap(), 2);
+        assert_eq!(actor.pending(), 0);
+        assert!(store.is_empty());
+
+        let mut answered = vec![];
+        for _ in 0..2 {
+            let SessionAction::Reply { conn_id, packet } = actions.recv().await.unwrap();
+            assert_eq!(result_code(&packet), Some(RESULT_UNABLE_TO_DELIVER));
+            answered.push((conn_id, packet.header.hop_by_hop_id));
+        }
+        answered.sort();
+        assert_eq!(answered, vec![(1, 10), (2, 20)]);
+    }
+}
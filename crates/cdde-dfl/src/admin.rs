@@ -0,0 +1,124 @@
+use crate::actor::ActorMessage;
+use axum::extract::State;
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::routing::post;
+use axum::{Json, Router};
+use serde::Serialize;
+use std::sync::Arc;
+use tokio::sync::{mpsc, oneshot};
+use tracing::warn;
+
+/// Result of flushing the session actor
+#[derive(Debug, Serialize)]
+pub struct FlushResponse {
+    /// Number of pending requests answered with UNABLE_TO_DELIVER
+    pub flushed: usize,
+}
+
+struct AdminState {
+    actor: mpsc::Sender<ActorMessage>,
+    token: String,
+}
+
+/// Operator endpoints of the DFL
+///
+/// Every request must carry `Authorization: Bearer <token>`.
+pub fn admin_router(actor: mpsc::Sender<ActorMessage>, token: String) -> Router {
+    Router::new()
+        .route("/admin/sessions/flush", post(flush_sessions))
+        .with_state(Arc::new(AdminState { actor, token }))
+}
+
+async fn flush_sessions(
+    State(state): State<Arc<AdminState>>,
+    headers: HeaderMap,
+) -> Result<Json<FlushResponse>, StatusCode> {
+    let authorized = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .is_some_and(|token| token_matches(token, &state.token));
+    if !authorized {
+        warn!("Rejected unauthorized session flush");
+        return Err(StatusCode::UNAUTHORIZED);
+    }
+
+    let (reply, flushed) = oneshot::channel();
+    state
+        .actor
+        .send(ActorMessage::Flush { reply })
+        .await
+        .map_err(|_| StatusCode::SERVICE_UNAVAILABLE)?;
+    let flushed = flushed.await.map_err(|_| StatusCode::SERVICE_UNAVAILABLE)?;
+    Ok(Json(FlushResponse { flushed }))
+}
+
+/// Compare a presented token with the configured one
+///
+/// Every byte is compared, so the time taken does not reveal how much of
+/// the token was guessed right. A blank configured token matches nothing.
+fn token_matches(presented: &str, expected: &str) -> bool {
+    if expected.trim().is_empty() {
+        return false;
+    }
+    let (presented, expected) = (presented.as_bytes(), expected.as_bytes());
+    presented.len() == expected.len()
+        && presented
+            .iter()
+            .zip(expected)
+            .fold(0u8, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use axum::http::Request;
+    use tower::ServiceExt;
+
+    fn flush_request(token: Option<&str>) -> Request<Body> {
+        let mut request = Request::post("/admin/sessions/flush");
+        if let Some(token) = token {
+            request = request.header(header::AUTHORIZATION, format!("Bearer {token}"));
+        }
+        request.body(Body::empty()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_flush_requires_token() {
+        let (actor, mut inbox) = mpsc::channel(1);
+        let router = admin_router(actor, "secret".to_string());
+
+        // Stand-in actor reporting two flushed sessions
+        tokio::spawn(async move {
+            while let Some(ActorMessage::Flush { reply }) = inbox.recv().await {
+                let _ = reply.send(2);
+            }
+        });
+
+        let response = router
+            .clone()
+            .oneshot(flush_request(Some("wrong")))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let response = router.oneshot(flush_request(Some("secret"))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), 1024)
+            .await
+            .unwrap();
+        assert_eq!(&body[..], br#"{"flushed":2}"#);
+    }
+
+    #[test]
+    fn test_token_matches() {
+        assert!(token_matches("secret", "secret"));
+        assert!(!token_matches("secreT", "secret"));
+        assert!(!token_matches("secret2", "secret"));
+        assert!(!token_matches("", "secret"));
+        assert!(!token_matches("", ""));
+        assert!(!token_matches(" ", " "));
+    }
+}
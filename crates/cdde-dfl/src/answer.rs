@@ -0,0 +1,145 @@
+use cdde_core::address::encode_address;
+use cdde_core::codes::{AVP_HOST_IP_ADDRESS, AVP_PRODUCT_NAME, AVP_VENDOR_ID};
+use cdde_core::diameter::AVP_FLAG_MANDATORY;
+use cdde_core::{DiameterAvp, DiameterPacket};
+use std::net::IpAddr;
+
+/// DIAMETER_UNABLE_TO_DELIVER
+pub const RESULT_UNABLE_TO_DELIVER: u32 = 3002;
+
+/// DIAMETER_TOO_BUSY
+pub const RESULT_TOO_BUSY: u32 = 3004;
+
+/// Default Result-Code answering requests whose AVPs do not parse
+///
+/// DIAMETER_INVALID_AVP_LENGTH, a permanent failure sent without the E bit.
+pub const DEFAULT_MALFORMED_RESULT_CODE: u32 = 5014;
+
+const PRODUCT_NAME: &[u8] = b"cdde-dfl";
+
+/// Identity used in answers generated locally by the DFL
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LocalIdentity {
+    pub origin_host: String,
+    pub origin_realm: String,
+}
+
+impl LocalIdentity {
+    /// Create an identity
+    pub fn new(origin_host: impl Into<String>, origin_realm: impl Into<String>) -> Self {
+        Self {
+            origin_host: origin_host.into(),
+            origin_realm: origin_realm.into(),
+        }
+    }
+
+    /// Read `ORIGIN_HOST` and `ORIGIN_REALM`, keeping the defaults for unset ones
+    pub fn from_env() -> Self {
+        let default = Self::default();
+        Self {
+            origin_host: std::env::var("ORIGIN_HOST").unwrap_or(default.origin_host),
+            origin_realm: std::env::var("ORIGIN_REALM").unwrap_or(default.origin_realm),
+        }
+    }
+}
+
+impl Default for LocalIdentity {
+    fn default() -> Self {
+        Self::new("dfl.example.com", "example.com")
+    }
+}
+
+/// Build an error answer for a request that could not be handled
+pub fn error_answer(
+    request: &DiameterPacket,
+    result_code: u32,
+    identity: &LocalIdentity,
+) -> DiameterPacket {
+    request
+        .clone()
+        .into_answer(result_code, &identity.origin_host, &identity.origin_realm)
+}
+
+/// Build the CEA to a client's CER
+///
+/// `host_ip_address` is the local address the CER was received on.
+pub fn capabilities_answer(
+    request: &DiameterPacket,
+    result_code: u32,
+    identity: &LocalIdentity,
+    host_ip_address: IpAddr,
+) -> DiameterPacket {
+    let mut answer = error_answer(request, result_code, identity);
+
+    // Host-IP-Address (257)
+    answer.avps.push(DiameterAvp {
+        code: AVP_HOST_IP_ADDRESS,
+        flags: AVP_FLAG_MANDATORY,
+        vendor_id: None,
+        data: encode_address(host_ip_address),
+    });
+
+    // Vendor-Id (266)
+    answer.avps.push(DiameterAvp {
+        code: AVP_VENDOR_ID,
+        flags: AVP_FLAG_MANDATORY,
+        vendor_id: None,
+        data: 0u32.to_be_bytes().to_vec(),
+    });
+
+    // Product-Name (269)
+    answer.avps.push(DiameterAvp {
+        code: AVP_PRODUCT_NAME,
+        flags: 0,
+        vendor_id: None,
+        data: PRODUCT_NAME.to_vec(),
+    });
+
+    answer
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cdde_core::diameter::{FLAG_ERROR, FLAG_PROXIABLE};
+    use cdde_core::DiameterHeader;
+
+    fn request() -> DiameterPacket {
+        DiameterPacket {
+            header: DiameterHeader {
+                version: 1,
+                length: 20,
+                flags: 0xC0, // Request + Proxiable
+                command_code: 316,
+                application_id: 16777251,
+                hop_by_hop_id: 10,
+                end_to_end_id: 20,
+            },
+            avps: vec![DiameterAvp {
+                code: 263,
+                flags: AVP_FLAG_MANDATORY,
+                vendor_id: None,
+                data: b"session;1".to_vec(),
+            }],
+        }
+    }
+
+    #[test]
+    fn test_error_answer() {
+        let answer = error_answer(
+            &request(),
+            RESULT_UNABLE_TO_DELIVER,
+            &LocalIdentity::default(),
+        );
+
+        assert!(answer.header.is_answer());
+        assert_eq!(answer.header.flags, FLAG_PROXIABLE | FLAG_ERROR);
+        assert_eq!(answer.header.command_code, 316);
+        assert_eq!(answer.header.hop_by_hop_id, 10);
+        assert_eq!(answer.header.end_to_end_id, 20);
+        assert_eq!(answer.avps[0].data, b"session;1");
+
+        let result_code = answer.find_avp(268).unwrap();
+        assert_eq!(result_code.data, 3002u32.to_be_bytes());
+    }
+}
@@ -0,0 +1,257 @@
+use cdde_core::codes::AVP_ORIGIN_HOST;
+use cdde_core::DiameterPacket;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Answer cache settings
+#[derive(Debug, Clone)]
+pub struct AnswerCacheConfig {
+    /// Maximum number of cached answers; 0 disables the cache
+    pub capacity: usize,
+
+    /// How long an answer may be replayed for a retransmitted request
+    pub ttl: Duration,
+}
+
+impl Default for AnswerCacheConfig {
+    fn default() -> Self {
+        Self {
+            capacity: 10_000,
+            ttl: Duration::from_secs(30),
+        }
+    }
+}
+
+/// Requests are identified by (Origin-Host, End-to-End id)
+type CacheKey = (String, u32);
+
+#[derive(Debug)]
+struct CachedAnswer {
+    payload: Vec<u8>,
+    stored_at: Instant,
+    last_used: u64,
+}
+
+#[derive(Debug, Default)]
+struct CacheInner {
+    entries: HashMap<CacheKey, CachedAnswer>,
+    /// Last use tick to key, oldest first
+    recency: BTreeMap<u64, CacheKey>,
+    tick: u64,
+}
+
+impl CacheInner {
+    fn touch(&mut self, key: &CacheKey) {
+        self.tick += 1;
+        let tick = self.tick;
+        if let Some(entry) = self.entries.get_mut(key) {
+            self.recency.remove(&entry.last_used);
+            entry.last_used = tick;
+            self.recency.insert(tick, key.clone());
+        }
+    }
+
+    fn remove(&mut self, key: &CacheKey) {
+        if let Some(entry) = self.entries.remove(key) {
+            self.recency.remove(&entry.last_used);
+        }
+    }
+}
+
+/// LRU cache of answers already sent, replayed for retransmitted requests
+///
+/// A retransmission keeps its End-to-End id (RFC 6733 section 6.3), so a
+/// duplicate is answered from the cache instead of being routed again.
+#[derive(Debug)]
+pub struct AnswerCache {
+    config: AnswerCacheConfig,
+    inner: Mutex<CacheInner>,
+}
+
+impl AnswerCache {
+    /// Create an empty cache
+    pub fn new(config: AnswerCacheConfig) -> Self {
+        Self {
+            config,
+            inner: Mutex::new(CacheInner::default()),
+        }
+    }
+
+    /// Cache key of a request, if it carries an Origin-Host
+    fn key(request: &DiameterPacket) -> Option<CacheKey> {
+        let origin_host = request.find_avp(AVP_ORIGIN_HOST)?;
+        Some((
+            String::from_utf8_lossy(&origin_host.data).to_string(),
+            request.header.end_to_end_id,
+        ))
+    }
+
+    /// Get the answer previously sent for this request
+    ///
+    /// The Hop-by-Hop id is rewritten to match the retransmitted request.
+    pub fn get(&self, request: &DiameterPacket) -> Option<Vec<u8>> {
+        if self.config.capacity == 0 {
+            return None;
+        }
+        let key = Self::key(request)?;
+        let mut inner = self.lock();
+
+        let expired = inner.entries.get(&key)?.stored_at.elapsed() > self.config.ttl;
+        if expired {
+            inner.remove(&key);
+            return None;
+        }
+        inner.touch(&key);
+
+        let mut payload = inner.entries.get(&key)?.payload.clone();
+        if payload.len() >= 16 {
+            payload[12..16].copy_from_slice(&request.header.hop_by_hop_id.to_be_bytes());
+        }
+        Some(payload)
+    }
+
+    /// Remember the answer sent for a request, evicting the least recently used entry when full
+    pub fn insert(&self, request: &DiameterPacket, answer: Vec<u8>) {
+        if self.config.capacity == 0 {
+            return;
+        }
+        let Some(key) = Self::key(request) else {
+            return;
+        };
+        let mut inner = self.lock();
+
+        inner.remove(&key);
+        while inner.entries.len() >= self.config.capacity {
+            let Some((_, oldest)) = inner.recency.pop_first() else {
+                break;
+            };
+            inner.entries.remove(&oldest);
+        }
+
+        inner.entries.insert(
+            key.clone(),
+            CachedAnswer {
+                payload: answer,
+                stored_at: Instant::now(),
+                last_used: 0,
+            },
+        );
+        inner.touch(&key);
+    }
+
+    /// Number of cached answers, including expired ones not yet evicted
+    pub fn len(&self) -> usize {
+        self.lock().entries.len()
+    }
+
+    /// Check if nothing is cached
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, CacheInner> {
+        // The maps stay consistent even if a holder panicked
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Default for AnswerCache {
+    fn default() -> Self {
+        Self::new(AnswerCacheConfig::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cdde_core::{DiameterAvp, DiameterHeader};
+
+    fn request(origin_host: &str, hop_by_hop_id: u32, end_to_end_id: u32) -> DiameterPacket {
+        DiameterPacket {
+            header: DiameterHeader {
+                version: 1,
+                length: 20,
+                flags: 0xC0,
+                command_code: 316,
+                application_id: 16777251,
+                hop_by_hop_id,
+                end_to_end_id,
+            },
+            avps: vec![DiameterAvp {
+                code: AVP_ORIGIN_HOST,
+                flags: 0x40,
+                vendor_id: None,
+                data: origin_host.as_bytes().to_vec(),
+            }],
+        }
+    }
+
+    fn answer(request: &DiameterPacket) -> Vec<u8> {
+        let mut answer = request.clone();
+        answer.header.flags = 0x40;
+        answer.serialize()
+    }
+
+    #[test]
+    fn test_retransmission_gets_cached_answer() {
+        let cache = AnswerCache::default();
+        let original = request("mme.example.com", 1, 100);
+        cache.insert(&original, answer(&original));
+
+        // Same End-to-End id from the same origin, new Hop-by-Hop id
+        let cached = cache.get(&request("mme.example.com", 7, 100)).unwrap();
+        let cached = DiameterPacket::parse(&cached).unwrap();
+        assert!(cached.header.is_answer());
+        assert_eq!(cached.header.hop_by_hop_id, 7);
+        assert_eq!(cached.header.end_to_end_id, 100);
+
+        assert!(cache.get(&request("other.example.com", 1, 100)).is_none());
+        assert!(cache.get(&request("mme.example.com", 1, 101)).is_none());
+    }
+
+    #[test]
+    fn test_expired_answer_is_dropped() {
+        let cache = AnswerCache::new(AnswerCacheConfig {
+            capacity: 10,
+            ttl: Duration::ZERO,
+        });
+        let original = request("mme.example.com", 1, 100);
+        cache.insert(&original, answer(&original));
+
+        std::thread::sleep(Duration::from_millis(5));
+        assert!(cache.get(&original).is_none());
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_least_recently_used_is_evicted() {
+        let cache = AnswerCache::new(AnswerCacheConfig {
+            capacity: 2,
+            ttl: Duration::from_secs(30),
+        });
+        let first = request("mme.example.com", 1, 1);
+        let second = request("mme.example.com", 2, 2);
+        let third = request("mme.example.com", 3, 3);
+
+        cache.insert(&first, answer(&first));
+        cache.insert(&second, answer(&second));
+        assert!(cache.get(&first).is_some()); // first is now the most recent
+        cache.insert(&third, answer(&third));
+
+        assert_eq!(cache.len(), 2);
+        assert!(cache.get(&first).is_some());
+        assert!(cache.get(&second).is_none());
+        assert!(cache.get(&third).is_some());
+    }
+
+    #[test]
+    fn test_requests_without_origin_host_are_not_cached() {
+        let cache = AnswerCache::default();
+        let mut anonymous = request("mme.example.com", 1, 1);
+        anonymous.avps.clear();
+
+        cache.insert(&anonymous, answer(&anonymous));
+        assert!(cache.is_empty());
+    }
+}
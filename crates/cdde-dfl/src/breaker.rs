@@ -0,0 +1,209 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Circuit breaker settings
+#[derive(Debug, Clone)]
+pub struct BreakerConfig {
+    /// Consecutive failures that open the breaker
+    pub failure_threshold: u32,
+
+    /// Time the breaker stays open before letting a probe through
+    pub cool_down: Duration,
+}
+
+impl Default for BreakerConfig {
+    fn default() -> Self {
+        Self {
+            failure_threshold: 5,
+            cool_down: Duration::from_secs(10),
+        }
+    }
+}
+
+/// Circuit breaker state
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BreakerState {
+    /// Calls go through normally
+    Closed,
+
+    /// Calls fail fast until the cool-down has passed
+    Open,
+
+    /// A single probe call is allowed to test recovery
+    HalfOpen,
+}
+
+#[derive(Debug)]
+struct BreakerInner {
+    state: BreakerState,
+    consecutive_failures: u32,
+    opened_at: Option<Instant>,
+    probe_in_flight: bool,
+}
+
+/// Circuit breaker guarding calls to the DCR
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    config: BreakerConfig,
+    inner: Mutex<BreakerInner>,
+}
+
+impl CircuitBreaker {
+    /// Create a closed breaker
+    pub fn new(config: BreakerConfig) -> Self {
+        Self {
+            config,
+            inner: Mutex::new(BreakerInner {
+                state: BreakerState::Closed,
+                consecutive_failures: 0,
+                opened_at: None,
+                probe_in_flight: false,
+            }),
+        }
+    }
+
+    /// Get current state
+    pub fn state(&self) -> BreakerState {
+        self.lock().state
+    }
+
+    /// Check if a call may be attempted
+    ///
+    /// Once the cool-down has passed an open breaker turns half-open and
+    /// lets exactly one probe through.
+    pub fn allow_request(&self) -> bool {
+        let mut inner = self.lock();
+
+        match inner.state {
+            BreakerState::Closed => true,
+            BreakerState::Open => {
+                let cooled_down = inner
+                    .opened_at
+                    .is_some_and(|at| at.elapsed() >= self.config.cool_down);
+                if cooled_down {
+                    inner.state = BreakerState::HalfOpen;
+                    inner.probe_in_flight = true;
+                }
+                cooled_down
+            }
+            BreakerState::HalfOpen => {
+                if inner.probe_in_flight {
+                    false
+                } else {
+                    inner.probe_in_flight = true;
+                    true
+                }
+            }
+        }
+    }
+
+    /// Record a successful call, closing the breaker
+    pub fn record_success(&self) {
+        let mut inner = self.lock();
+        inner.state = BreakerState::Closed;
+        inner.consecutive_failures = 0;
+        inner.opened_at = None;
+        inner.probe_in_flight = false;
+    }
+
+    /// Record a failed call, opening the breaker when the threshold is reached
+    pub fn record_failure(&self) {
+        let mut inner = self.lock();
+        inner.consecutive_failures = inner.consecutive_failures.saturating_add(1);
+        inner.probe_in_flight = false;
+
+        let trip = match inner.state {
+            BreakerState::HalfOpen => true,
+            BreakerState::Closed => inner.consecutive_failures >= self.config.failure_threshold,
+            BreakerState::Open => false,
+        };
+        if trip {
+            inner.state = BreakerState::Open;
+            inner.opened_at = Some(Instant::now());
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, BreakerInner> {
+        // The state stays consistent even if a holder panicked
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Default for CircuitBreaker {
+    fn default() -> Self {
+        Self::new(BreakerConfig::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn breaker() -> CircuitBreaker {
+        CircuitBreaker::new(BreakerConfig {
+            failure_threshold: 3,
+            cool_down: Duration::from_millis(50),
+        })
+    }
+
+    #[test]
+    fn test_opens_after_consecutive_failures() {
+        let breaker = breaker();
+
+        for _ in 0..2 {
+            assert!(breaker.allow_request());
+            breaker.record_failure();
+        }
+        assert_eq!(breaker.state(), BreakerState::Closed);
+
+        assert!(breaker.allow_request());
+        breaker.record_failure();
+        assert_eq!(breaker.state(), BreakerState::Open);
+
+        // Fast-fail while open
+        assert!(!breaker.allow_request());
+        assert!(!breaker.allow_request());
+    }
+
+    #[test]
+    fn test_success_resets_failure_count() {
+        let breaker = breaker();
+
+        breaker.record_failure();
+        breaker.record_failure();
+        breaker.record_success();
+        breaker.record_failure();
+        breaker.record_failure();
+
+        assert_eq!(breaker.state(), BreakerState::Closed);
+    }
+
+    #[test]
+    fn test_half_open_probe() {
+        let breaker = breaker();
+        for _ in 0..3 {
+            breaker.record_failure();
+        }
+        assert!(!breaker.allow_request());
+
+        std::thread::sleep(Duration::from_millis(60));
+
+        // Exactly one probe goes through
+        assert!(breaker.allow_request());
+        assert_eq!(breaker.state(), BreakerState::HalfOpen);
+        assert!(!breaker.allow_request());
+
+        // A failed probe re-opens the breaker
+        breaker.record_failure();
+        assert_eq!(breaker.state(), BreakerState::Open);
+        assert!(!breaker.allow_request());
+
+        std::thread::sleep(Duration::from_millis(60));
+
+        // A successful probe closes it
+        assert!(breaker.allow_request());
+        breaker.record_success();
+        assert_eq!(breaker.state(), BreakerState::Closed);
+        assert!(breaker.allow_request());
+    }
+}
@@ -40,6 +40,8 @@ impl DcrClient {
             target_host_name: "mock-target".to_string(),
             response_payload: request.raw_payload, // Echo back
             original_connection_id: request.connection_id,
+            candidate_peers: vec!["mock-target".to_string()],
+            result_code: 0,
         })
     }
 }
@@ -62,8 +64,9 @@ mod tests {
             connection_id: 123,
             vr_id: "vr1".to_string(),
             reception_timestamp: 1000,
-            raw_payload: vec![1, 2, 3],
+            raw_payload: vec![1, 2, 3].into(),
             session_tx_id: 456,
+            trace_id: String::new(),
         };
 
         let response = client.send_packet(request).await.unwrap();
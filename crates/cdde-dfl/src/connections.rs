@@ -0,0 +1,124 @@
+use cdde_core::DiameterPacket;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc;
+use tracing::{debug, warn};
+
+/// Answers a connection may have queued before it writes them
+const OUTBOUND_CAPACITY: usize = 64;
+
+/// Outbound queues of the open client connections, by connection id
+///
+/// Lets answers produced outside a connection's task, such as those of the
+/// session actor, be written on the connection they belong to.
+#[derive(Default)]
+pub struct ConnectionRegistry {
+    connections: Mutex<HashMap<u64, mpsc::Sender<DiameterPacket>>>,
+}
+
+impl ConnectionRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Open the outbound queue of a connection, closed again when dropped
+    pub fn register(self: &Arc<Self>, conn_id: u64) -> Outbound {
+        let (tx, rx) = mpsc::channel(OUTBOUND_CAPACITY);
+        self.connections.lock().unwrap().insert(conn_id, tx);
+        Outbound {
+            conn_id,
+            registry: self.clone(),
+            rx,
+        }
+    }
+
+    /// Queue an answer on a connection, returning whether it was queued
+    ///
+    /// Answers for closed connections, or connections too far behind, are
+    /// dropped.
+    pub fn deliver(&self, conn_id: u64, packet: DiameterPacket) -> bool {
+        let Some(tx) = self.connections.lock().unwrap().get(&conn_id).cloned() else {
+            debug!(
+                "Dropping answer {} for closed connection {}",
+                packet.header.hop_by_hop_id, conn_id
+            );
+            return false;
+        };
+        match tx.try_send(packet) {
+            Ok(()) => true,
+            Err(e) => {
+                warn!("Dropping answer for connection {}: {}", conn_id, e);
+                false
+            }
+        }
+    }
+
+    /// Number of open connections
+    pub fn len(&self) -> usize {
+        self.connections.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Answers queued for one connection
+pub struct Outbound {
+    conn_id: u64,
+    registry: Arc<ConnectionRegistry>,
+    rx: mpsc::Receiver<DiameterPacket>,
+}
+
+impl Outbound {
+    /// Next queued answer
+    pub async fn recv(&mut self) -> Option<DiameterPacket> {
+        self.rx.recv().await
+    }
+}
+
+impl Drop for Outbound {
+    fn drop(&mut self) {
+        self.registry
+            .connections
+            .lock()
+            .unwrap()
+            .remove(&self.conn_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cdde_core::DiameterHeader;
+
+    fn answer(hop_by_hop_id: u32) -> DiameterPacket {
+        DiameterPacket {
+            header: DiameterHeader {
+                version: 1,
+                length: 0,
+                flags: 0,
+                command_code: 316,
+                application_id: 16777251,
+                hop_by_hop_id,
+                end_to_end_id: hop_by_hop_id,
+            },
+            avps: vec![],
+        }
+    }
+
+    #[tokio::test]
+    async fn test_answers_reach_their_connection_until_it_closes() {
+        let registry = Arc::new(ConnectionRegistry::new());
+        let mut first = registry.register(1);
+        let second = registry.register(2);
+
+        assert!(registry.deliver(1, answer(10)));
+        assert!(!registry.deliver(3, answer(11)));
+        assert_eq!(first.recv().await.unwrap().header.hop_by_hop_id, 10);
+
+        drop(second);
+        assert_eq!(registry.len(), 1);
+        assert!(!registry.deliver(2, answer(12)));
+    }
+}
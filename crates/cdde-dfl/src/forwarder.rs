@@ -0,0 +1,120 @@
+//! Delivery of forwarded requests to upstream peers
+
+use async_trait::async_trait;
+use cdde_core::{CddeError, Result};
+use cdde_proto::peer_relay_service_client::PeerRelayServiceClient;
+use cdde_proto::PeerForwardRequest;
+use tonic::transport::Channel;
+use tonic::Code;
+
+/// Default peer relay endpoint of the DPA
+pub const DEFAULT_RELAY_ENDPOINT: &str = "http://[::1]:50054";
+
+/// Sends a request to a named peer and waits for its answer
+#[async_trait]
+pub trait PeerForwarder: Send + Sync {
+    /// Forward the serialized request to `peer`, returning the serialized answer
+    async fn forward(&self, peer: &str, payload: Vec<u8>) -> Result<Vec<u8>>;
+}
+
+/// Forwards requests through the peer relay of the DPA
+pub struct RelayForwarder {
+    client: PeerRelayServiceClient<Channel>,
+}
+
+impl RelayForwarder {
+    /// Create a forwarder for the DPA relay at `endpoint`
+    ///
+    /// The connection is opened on the first forward.
+    pub fn new(endpoint: String) -> Result<Self> {
+        let channel = Channel::from_shared(endpoint.clone())
+            .map_err(|e| CddeError::ConfigError(format!("Invalid relay endpoint {endpoint}: {e}")))?
+            .connect_lazy();
+        Ok(Self {
+            client: PeerRelayServiceClient::new(channel),
+        })
+    }
+}
+
+#[async_trait]
+impl PeerForwarder for RelayForwarder {
+    async fn forward(&self, peer: &str, payload: Vec<u8>) -> Result<Vec<u8>> {
+        let request = PeerForwardRequest {
+            peer_node_id: peer.to_string(),
+            raw_payload: payload,
+        };
+        let response = self
+            .client
+            .clone()
+            .forward(request)
+            .await
+            .map_err(|status| match status.code() {
+                Code::ResourceExhausted => CddeError::PeerBusy(status.message().to_string()),
+                _ => CddeError::NetworkError(status.to_string()),
+            })?;
+        Ok(response.into_inner().raw_payload)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cdde_proto::peer_relay_service_server::{PeerRelayService, PeerRelayServiceServer};
+    use cdde_proto::PeerForwardResponse;
+    use std::time::Duration;
+    use tonic::{Request, Response, Status};
+
+    /// DPA stand-in echoing payloads for one peer and busy for the others
+    struct EchoRelay;
+
+    #[tonic::async_trait]
+    impl PeerRelayService for EchoRelay {
+        async fn forward(
+            &self,
+            request: Request<PeerForwardRequest>,
+        ) -> std::result::Result<Response<PeerForwardResponse>, Status> {
+            let request = request.into_inner();
+            if request.peer_node_id != "hss01" {
+                return Err(Status::resource_exhausted("connection is draining"));
+            }
+            Ok(Response::new(PeerForwardResponse {
+                raw_payload: request.raw_payload,
+            }))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_forward_through_relay() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        drop(listener);
+        let server = tokio::spawn(async move {
+            tonic::transport::Server::builder()
+                .add_service(PeerRelayServiceServer::new(EchoRelay))
+                .serve(addr)
+                .await
+                .unwrap();
+        });
+
+        // Wait for the DPA stand-in to start
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let forwarder = RelayForwarder::new(format!("http://{addr}")).unwrap();
+        assert_eq!(
+            forwarder.forward("hss01", vec![1, 2, 3]).await.unwrap(),
+            vec![1, 2, 3]
+        );
+        let busy = forwarder.forward("hss02", vec![1]).await.unwrap_err();
+        assert!(matches!(busy, CddeError::PeerBusy(_)));
+
+        server.abort();
+    }
+
+    #[tokio::test]
+    async fn test_invalid_endpoint_is_rejected() {
+        assert!(matches!(
+            RelayForwarder::new("not a uri".to_string()),
+            Err(CddeError::ConfigError(_))
+        ));
+    }
+}
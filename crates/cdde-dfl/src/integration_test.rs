@@ -1,12 +1,95 @@
 #[cfg(test)]
 mod integration_tests {
+    use crate::breaker::{BreakerConfig, BreakerState};
     use crate::network::TcpServer;
+    use crate::session::SessionConfig;
     use crate::store::TransactionStore;
-    use cdde_core::{DiameterHeader, DiameterPacket};
+    use cdde_core::{DiameterAvp, DiameterHeader, DiameterPacket};
+    use cdde_proto::DiameterPacketAction;
+    use cdde_test_support::{reply, MockDcr};
+    use std::net::SocketAddr;
     use std::sync::Arc;
     use std::time::Duration;
-    use tokio::io::AsyncWriteExt;
-    use tokio::net::TcpStream;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+    use tokio::task::JoinHandle;
+    use tonic::Status;
+
+    /// Serve connections on an ephemeral localhost port
+    async fn serve(server: TcpServer) -> (SocketAddr, JoinHandle<()>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let handle = tokio::spawn(async move {
+            server.serve(listener).await.unwrap();
+        });
+        (addr, handle)
+    }
+
+    /// DFL calling the DCR at `dcr_addr`
+    fn dfl(dcr_addr: SocketAddr) -> TcpServer {
+        TcpServer::new("127.0.0.1:0".to_string(), Arc::new(TransactionStore::new()))
+            .with_dcr_endpoint(format!("http://{dcr_addr}"))
+    }
+
+    /// Proxiable application request
+    fn request(hop_by_hop_id: u32, end_to_end_id: u32, avps: Vec<DiameterAvp>) -> DiameterPacket {
+        DiameterPacket {
+            header: DiameterHeader {
+                version: 1,
+                length: 20,
+                flags: 0xC0, // Request + Proxiable
+                command_code: 316,
+                application_id: 16777251,
+                hop_by_hop_id,
+                end_to_end_id,
+            },
+            avps,
+        }
+    }
+
+    /// CER from `origin_host` in realm example.com
+    fn cer(origin_host: &str) -> DiameterPacket {
+        let identity = |code, data: &[u8]| DiameterAvp {
+            code,
+            flags: 0x40,
+            vendor_id: None,
+            data: data.to_vec(),
+        };
+        DiameterPacket {
+            header: DiameterHeader {
+                version: 1,
+                length: 20,
+                flags: 0x80,
+                command_code: 257,
+                application_id: 0,
+                hop_by_hop_id: 1,
+                end_to_end_id: 2,
+            },
+            avps: vec![
+                identity(264, origin_host.as_bytes()),
+                identity(296, b"example.com"),
+            ],
+        }
+    }
+
+    /// Send a packet and wait for the message coming back
+    async fn exchange(stream: &mut TcpStream, packet: &DiameterPacket) -> DiameterPacket {
+        stream.write_all(&packet.serialize()).await.unwrap();
+
+        let mut buffer = [0u8; 4096];
+        let n = tokio::time::timeout(Duration::from_secs(3), stream.read(&mut buffer))
+            .await
+            .expect("No answer received")
+            .unwrap();
+        DiameterPacket::parse(&buffer[..n]).unwrap()
+    }
+
+    /// Result-Code of an answer
+    fn result_code(answer: &DiameterPacket) -> Option<u32> {
+        answer
+            .find_avp(268)
+            .map(|avp| u32::from_be_bytes(avp.data[..4].try_into().unwrap()))
+    }
 
     #[tokio::test]
     async fn test_tcp_connection_and_packet_exchange() {
@@ -47,4 +130,403 @@ mod integration_tests {
         // Cleanup
         server_handle.abort();
     }
+
+    #[tokio::test]
+    async fn test_unreachable_dcr_answers_unable_to_deliver() {
+        // Reserve a port for the DCR and close it again so connections are refused
+        let dcr_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let dcr_addr = dcr_listener.local_addr().unwrap();
+        drop(dcr_listener);
+
+        let (addr, server_handle) = serve(dfl(dcr_addr)).await;
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+
+        let answer = exchange(&mut stream, &request(123, 456, vec![])).await;
+        assert!(answer.header.is_answer());
+        assert_eq!(answer.header.hop_by_hop_id, 123);
+        assert_eq!(answer.header.end_to_end_id, 456);
+        assert_eq!(result_code(&answer), Some(3002));
+
+        server_handle.abort();
+    }
+
+    #[tokio::test]
+    async fn test_slow_dcr_answers_unable_to_deliver_after_answer_timeout() {
+        // DCR that takes far longer than the answer timeout to respond
+        let (dcr_addr, dcr_handle) = MockDcr::new(|_| Ok(DiameterPacketAction::default()))
+            .with_delay(Duration::from_secs(10))
+            .spawn()
+            .await;
+
+        let server = dfl(dcr_addr).with_session_config(SessionConfig {
+            timeout_duration: Duration::from_secs(30),
+            answer_timeout: Duration::from_millis(200),
+            ..Default::default()
+        });
+        let store = server.store().clone();
+        let (addr, server_handle) = serve(server).await;
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+
+        // Well before the DCR's 10s delay
+        let answer = exchange(&mut stream, &request(321, 654, vec![])).await;
+        assert!(answer.header.is_answer());
+        assert_eq!(answer.header.hop_by_hop_id, 321);
+        assert_eq!(result_code(&answer), Some(3002));
+
+        // The timed out transaction no longer occupies the store
+        assert!(store.is_empty());
+
+        server_handle.abort();
+        dcr_handle.abort();
+    }
+
+    #[tokio::test]
+    async fn test_listener_vr_selects_answer_timeout() {
+        // DCR that takes far longer than the VR's answer timeout to respond
+        let (dcr_addr, dcr_handle) = MockDcr::new(|_| Ok(DiameterPacketAction::default()))
+            .with_delay(Duration::from_secs(10))
+            .spawn()
+            .await;
+
+        let server = dfl(dcr_addr)
+            .with_vr_id("vr-fast".to_string())
+            .with_session_config(SessionConfig {
+                timeout_duration: Duration::from_secs(30),
+                answer_timeout: Duration::from_secs(20),
+                vr_answer_timeouts: [("vr-fast".to_string(), Duration::from_millis(200))].into(),
+                ..Default::default()
+            });
+        let (addr, server_handle) = serve(server).await;
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+
+        // Well before the default answer timeout
+        let answer = exchange(&mut stream, &request(322, 655, vec![])).await;
+        assert_eq!(answer.header.hop_by_hop_id, 322);
+        assert_eq!(result_code(&answer), Some(3002));
+
+        server_handle.abort();
+        dcr_handle.abort();
+    }
+
+    #[tokio::test]
+    async fn test_circuit_breaker_fast_fails_until_probe_succeeds() {
+        use std::sync::atomic::{AtomicBool, Ordering};
+
+        // DCR that fails until told to recover, echoing once it has
+        let healthy = Arc::new(AtomicBool::new(false));
+        let dcr = MockDcr::new({
+            let healthy = healthy.clone();
+            move |request| {
+                if !healthy.load(Ordering::SeqCst) {
+                    return Err(Box::new(Status::unavailable("overloaded")));
+                }
+                Ok(reply(request.raw_payload))
+            }
+        });
+        let (dcr_addr, dcr_handle) = dcr.spawn().await;
+
+        let server = dfl(dcr_addr).with_breaker_config(BreakerConfig {
+            failure_threshold: 2,
+            cool_down: Duration::from_millis(300),
+        });
+        let breaker = server.breaker().clone();
+        let (addr, server_handle) = serve(server).await;
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+
+        // Two consecutive failures open the breaker
+        for id in 1..=2 {
+            let answer = exchange(&mut stream, &request(id, id, vec![])).await;
+            assert_eq!(result_code(&answer), Some(3002));
+        }
+        assert_eq!(dcr.calls(), 2);
+        assert_eq!(breaker.state(), BreakerState::Open);
+
+        // While open, answers come back without calling the DCR
+        healthy.store(true, Ordering::SeqCst);
+        let answer = exchange(&mut stream, &request(3, 3, vec![])).await;
+        assert_eq!(result_code(&answer), Some(3002));
+        assert_eq!(dcr.calls(), 2);
+
+        // After the cool-down a probe reaches the recovered DCR
+        tokio::time::sleep(Duration::from_millis(350)).await;
+        let answer = exchange(&mut stream, &request(4, 4, vec![])).await;
+        assert!(answer.header.is_request()); // Echoed by the DCR
+        assert_eq!(answer.header.hop_by_hop_id, 4);
+        assert_eq!(dcr.calls(), 3);
+        assert_eq!(breaker.state(), BreakerState::Closed);
+
+        server_handle.abort();
+        dcr_handle.abort();
+    }
+
+    #[tokio::test]
+    async fn test_retransmitted_request_is_answered_from_cache() {
+        // DCR that answers every request
+        let dcr = MockDcr::new(|request| {
+            let mut answer = DiameterPacket::parse(&request.raw_payload)
+                .map_err(|e| Box::new(Status::invalid_argument(e.to_string())))?;
+            answer.header.flags &= !0x80;
+            Ok(reply(answer.serialize()))
+        });
+        let (dcr_addr, dcr_handle) = dcr.spawn().await;
+
+        let (addr, server_handle) = serve(dfl(dcr_addr)).await;
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+
+        let origin_host = || {
+            vec![DiameterAvp {
+                code: 264,
+                flags: 0x40,
+                vendor_id: None,
+                data: b"mme.example.com".to_vec(),
+            }]
+        };
+
+        let first = exchange(&mut stream, &request(1, 0x5555, origin_host())).await;
+        assert!(first.header.is_answer());
+        assert_eq!(dcr.calls(), 1);
+
+        // The retransmission keeps its End-to-End id and is not routed again
+        let second = exchange(&mut stream, &request(2, 0x5555, origin_host())).await;
+        assert!(second.header.is_answer());
+        assert_eq!(second.header.hop_by_hop_id, 2);
+        assert_eq!(second.header.end_to_end_id, 0x5555);
+        assert_eq!(dcr.calls(), 1);
+
+        server_handle.abort();
+        dcr_handle.abort();
+    }
+
+    #[tokio::test]
+    async fn test_forward_tries_next_candidate_when_first_fails() {
+        use crate::forwarder::PeerForwarder;
+        use cdde_core::CddeError;
+        use cdde_proto::ActionType;
+        use std::sync::Mutex;
+
+        // Forwarder where hss1 is unreachable and hss2 answers
+        #[derive(Default)]
+        struct FailingFirstForwarder {
+            attempts: Mutex<Vec<String>>,
+            flags: Mutex<Vec<u8>>,
+        }
+
+        #[async_trait::async_trait]
+        impl PeerForwarder for FailingFirstForwarder {
+            async fn forward(&self, peer: &str, payload: Vec<u8>) -> cdde_core::Result<Vec<u8>> {
+                self.attempts.lock().unwrap().push(peer.to_string());
+                self.flags.lock().unwrap().push(payload[4]);
+                if peer == "hss1" {
+                    return Err(CddeError::NetworkError("connection refused".to_string()));
+                }
+                let mut answer = DiameterPacket::parse(&payload)?;
+                answer.header.flags &= !0x80;
+                answer.header.end_to_end_id = 0x2222;
+                Ok(answer.serialize())
+            }
+        }
+
+        // DCR that forwards every request with two failover candidates
+        let (dcr_addr, dcr_handle) = MockDcr::new(|request| {
+            Ok(DiameterPacketAction {
+                action_type: ActionType::Forward as i32,
+                target_host_name: "hss1".to_string(),
+                response_payload: request.raw_payload,
+                original_connection_id: 0,
+                candidate_peers: vec!["hss1".to_string(), "hss2".to_string()],
+                result_code: 0,
+            })
+        })
+        .spawn()
+        .await;
+
+        let forwarder = Arc::new(FailingFirstForwarder::default());
+        let (addr, server_handle) = serve(dfl(dcr_addr).with_forwarder(forwarder.clone())).await;
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+
+        let mut packet = request(9, 0x1111, vec![]);
+        packet.header.command_code = 318;
+        let answer = exchange(&mut stream, &packet).await;
+
+        // The answer comes from hss2, after hss1 was tried first
+        assert!(answer.header.is_answer());
+        assert_eq!(answer.header.end_to_end_id, 0x2222);
+        assert_eq!(*forwarder.attempts.lock().unwrap(), vec!["hss1", "hss2"]);
+        // Only the second attempt is marked as a retransmission
+        assert_eq!(*forwarder.flags.lock().unwrap(), vec![0xC0, 0xD0]);
+
+        server_handle.abort();
+        dcr_handle.abort();
+    }
+
+    #[tokio::test]
+    async fn test_forward_skips_unavailable_candidates() {
+        use crate::forwarder::PeerForwarder;
+        use cdde_core::{HealthThresholds, PeerHealthRegistry};
+        use cdde_proto::ActionType;
+        use std::sync::Mutex;
+
+        /// Forwarder answering every request, recording the peers tried
+        #[derive(Default)]
+        struct AnsweringForwarder {
+            attempts: Mutex<Vec<String>>,
+        }
+
+        #[async_trait::async_trait]
+        impl PeerForwarder for AnsweringForwarder {
+            async fn forward(&self, peer: &str, payload: Vec<u8>) -> cdde_core::Result<Vec<u8>> {
+                self.attempts.lock().unwrap().push(peer.to_string());
+                let mut answer = DiameterPacket::parse(&payload)?;
+                answer.header.flags &= !0x80;
+                Ok(answer.serialize())
+            }
+        }
+
+        let (dcr_addr, dcr_handle) = MockDcr::new(|request| {
+            Ok(DiameterPacketAction {
+                action_type: ActionType::Forward as i32,
+                target_host_name: "hss1".to_string(),
+                response_payload: request.raw_payload,
+                original_connection_id: 0,
+                candidate_peers: vec!["hss1".to_string(), "hss2".to_string(), "hss3".to_string()],
+                result_code: 0,
+            })
+        })
+        .spawn()
+        .await;
+
+        // The DPA reported hss1 down and hss2 draining
+        let health = Arc::new(PeerHealthRegistry::new(HealthThresholds::default()));
+        health.set_down("hss1", true);
+        health.set_draining("hss2", true);
+
+        let forwarder = Arc::new(AnsweringForwarder::default());
+        let server = dfl(dcr_addr)
+            .with_forwarder(forwarder.clone())
+            .with_peer_health(health);
+        let (addr, server_handle) = serve(server).await;
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+
+        let mut packet = request(9, 0x1111, vec![]);
+        packet.header.command_code = 318;
+        let answer = exchange(&mut stream, &packet).await;
+        assert!(answer.header.is_answer());
+        assert_eq!(*forwarder.attempts.lock().unwrap(), vec!["hss3"]);
+
+        server_handle.abort();
+        dcr_handle.abort();
+    }
+
+    #[tokio::test]
+    async fn test_cer_from_unknown_peer_is_rejected() {
+        use crate::peer_allowlist::PeerAllowlist;
+
+        let server = TcpServer::new("127.0.0.1:0".to_string(), Arc::new(TransactionStore::new()))
+            .with_peer_allowlist(PeerAllowlist::new([("mme1.example.com", "example.com")]));
+        let (addr, server_handle) = serve(server).await;
+
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        let cea = exchange(&mut stream, &cer("rogue.example.com")).await;
+        assert!(cea.header.is_answer());
+        assert_eq!(cea.header.command_code, 257);
+        assert_eq!(result_code(&cea), Some(5018));
+
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        let cea = exchange(&mut stream, &cer("mme1.example.com")).await;
+        assert_eq!(result_code(&cea), Some(2001));
+        // Host-IP-Address is the address the CER arrived on
+        assert_eq!(
+            cea.find_avp(257).unwrap().data,
+            cdde_core::address::encode_address(addr.ip())
+        );
+
+        server_handle.abort();
+    }
+
+    #[tokio::test]
+    async fn test_traffic_without_successful_cer_is_not_handled() {
+        use crate::peer_allowlist::PeerAllowlist;
+
+        let server = TcpServer::new("127.0.0.1:0".to_string(), Arc::new(TransactionStore::new()))
+            .with_peer_allowlist(PeerAllowlist::new([("mme1.example.com", "example.com")]));
+        let (addr, server_handle) = serve(server).await;
+
+        let request = request(3, 4, vec![]);
+        let mut buffer = [0u8; 4096];
+
+        // A request before any CER is dropped without an answer
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream.write_all(&request.serialize()).await.unwrap();
+        assert!(
+            tokio::time::timeout(Duration::from_millis(300), stream.read(&mut buffer))
+                .await
+                .is_err()
+        );
+
+        // After a rejected CER the connection is closed
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        let cea = exchange(&mut stream, &cer("rogue.example.com")).await;
+        assert_eq!(result_code(&cea), Some(5018));
+
+        let _ = stream.write_all(&request.serialize()).await;
+        let closed = tokio::time::timeout(Duration::from_secs(3), stream.read(&mut buffer))
+            .await
+            .expect("Connection left open after a rejected CER");
+        assert!(matches!(closed, Ok(0) | Err(_)));
+
+        server_handle.abort();
+    }
+
+    #[tokio::test]
+    async fn test_pipelined_requests_beyond_pending_cap_answer_too_busy() {
+        use crate::actor::{SessionAction, SessionActor};
+        use crate::answer::RESULT_TOO_BUSY;
+        use crate::connections::ConnectionRegistry;
+
+        // DCR slow enough that every request is still pending when the next arrives
+        let (dcr_addr, dcr_handle) = MockDcr::echo()
+            .with_delay(Duration::from_secs(2))
+            .spawn()
+            .await;
+
+        // Session actor holding at most two requests per connection
+        let (actor_tx, actor_rx) = tokio::sync::mpsc::channel(16);
+        let (action_tx, mut action_rx) = tokio::sync::mpsc::channel(16);
+        tokio::spawn(
+            SessionActor::new(SessionConfig::default(), actor_rx, action_tx)
+                .with_max_pending_per_connection(2)
+                .run(),
+        );
+        let connections = Arc::new(ConnectionRegistry::new());
+        let outbound = connections.clone();
+        tokio::spawn(async move {
+            while let Some(SessionAction::Reply { conn_id, packet }) = action_rx.recv().await {
+                outbound.deliver(conn_id, packet);
+            }
+        });
+
+        let (addr, server_handle) =
+            serve(dfl(dcr_addr).with_session_actor(actor_tx, connections)).await;
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+
+        // Three requests in a single write, ahead of any answer
+        let pipelined: Vec<u8> = (1..=3)
+            .flat_map(|id| request(id, id, vec![]).serialize())
+            .collect();
+        stream.write_all(&pipelined).await.unwrap();
+
+        // The one over the cap is turned away long before the DCR answers
+        let mut buffer = [0u8; 4096];
+        let n = tokio::time::timeout(Duration::from_secs(1), stream.read(&mut buffer))
+            .await
+            .expect("No TOO_BUSY answer received")
+            .unwrap();
+        let answer = DiameterPacket::parse(&buffer[..n]).unwrap();
+        assert!(answer.header.is_answer());
+        assert!((1..=3).contains(&answer.header.hop_by_hop_id));
+        assert_eq!(result_code(&answer), Some(RESULT_TOO_BUSY));
+
+        server_handle.abort();
+        dcr_handle.abort();
+    }
 }
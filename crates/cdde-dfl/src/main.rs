@@ -1,16 +1,50 @@
+mod acl;
+mod actor;
+mod admin;
+mod answer;
+mod answer_cache;
+mod breaker;
 mod client;
+mod connections;
+mod forwarder;
 mod integration_test;
 mod network;
+mod peer_allowlist;
+mod peer_status;
+mod persistence;
+mod sampling;
 mod session;
+mod sessions;
 mod store;
+mod vr_timeouts;
 
+pub use acl::{AccessList, Cidr};
+pub use actor::{
+    ActorMessage, SessionAction, SessionActor, TimeoutInfo, DEFAULT_DRAIN_DEADLINE,
+    DEFAULT_MAX_PENDING_PER_CONNECTION,
+};
+pub use admin::{admin_router, FlushResponse};
+pub use answer::{LocalIdentity, DEFAULT_MALFORMED_RESULT_CODE};
+pub use answer_cache::{AnswerCache, AnswerCacheConfig};
+pub use breaker::{BreakerConfig, BreakerState, CircuitBreaker};
 pub use client::DcrClient;
-pub use network::TcpServer;
-pub use session::TransactionContext;
+pub use connections::{ConnectionRegistry, Outbound};
+pub use forwarder::{PeerForwarder, RelayForwarder, DEFAULT_RELAY_ENDPOINT};
+pub use network::{TcpServer, DEFAULT_MAX_MESSAGE_SIZE, DEFAULT_VR_ID};
+pub use peer_allowlist::{fetch_peer_allowlist, PeerAllowlist};
+pub use peer_status::PeerStatusService;
+pub use persistence::{load_snapshot, save_snapshot, PersistedTransaction};
+pub use sampling::TraceSampler;
+pub use session::{SessionConfig, TransactionContext};
+pub use sessions::{run_session_sweep, sweep_sessions, SessionRegistry, SessionSweepConfig};
 pub use store::TransactionStore;
+pub use vr_timeouts::fetch_vr_timeouts;
 
+use cdde_config::AppConfig;
+use cdde_core::{HealthThresholds, PeerHealthRegistry};
+use cdde_proto::routing_update_service_server::RoutingUpdateServiceServer;
 use std::sync::Arc;
-use tracing::info;
+use tracing::{error, info, warn};
 
 #[tokio::main]
 async fn main() {
@@ -26,6 +60,15 @@ async fn main() {
         "Starting Diameter Frontline service"
     );
 
+    // Load configuration, falling back to defaults
+    let config: AppConfig = match std::env::var("CDDE_CONFIG") {
+        Ok(path) => cdde_config::load_config(&path).unwrap_or_else(|e| {
+            warn!("Failed to load config from {}: {}, using defaults", path, e);
+            AppConfig::default()
+        }),
+        Err(_) => AppConfig::default(),
+    };
+
     // Initialize DCR client
     let dcr_endpoint =
         std::env::var("DCR_ENDPOINT").unwrap_or_else(|_| "http://[::1]:50051".to_string());
@@ -36,13 +79,347 @@ async fn main() {
     // Initialize Session Store
     let store = Arc::new(TransactionStore::new());
 
+    // Session timeouts, the dedicated variables taking precedence
+    let mut session_config = SessionConfig {
+        timeout_duration: std::time::Duration::from_millis(config.dfl.session_timeout_ms),
+        answer_timeout: std::time::Duration::from_millis(config.dfl.answer_timeout_ms),
+        slow_threshold: std::time::Duration::from_millis(config.dfl.slow_threshold_ms),
+        ..Default::default()
+    };
+    if let Some(ms) = env_millis("SESSION_TIMEOUT_MS") {
+        session_config.timeout_duration = ms;
+    }
+    if let Some(ms) = env_millis("ANSWER_TIMEOUT_MS") {
+        session_config.answer_timeout = ms;
+    }
+    if let Some(ms) = env_millis("SLOW_TRANSACTION_MS") {
+        session_config.slow_threshold = ms;
+    }
+    if let Ok(cms_url) = std::env::var("CMS_URL") {
+        match fetch_vr_timeouts(&cms_url).await {
+            Ok(timeouts) => {
+                info!(
+                    "Loaded answer timeouts of {} Virtual Routers",
+                    timeouts.len()
+                );
+                session_config.vr_answer_timeouts = timeouts;
+            }
+            Err(e) => error!(
+                "Failed to load Virtual Router timeouts from {}: {}",
+                cms_url, e
+            ),
+        }
+    }
+
+    // DCR circuit breaker
+    let mut breaker_config = BreakerConfig::default();
+    if let Some(threshold) = std::env::var("BREAKER_FAILURE_THRESHOLD")
+        .ok()
+        .and_then(|v| v.parse().ok())
+    {
+        breaker_config.failure_threshold = threshold;
+    }
+    if let Some(ms) = env_millis("BREAKER_COOL_DOWN_MS") {
+        breaker_config.cool_down = ms;
+    }
+
+    // Peer status updates from the DPA
+    let health = Arc::new(PeerHealthRegistry::new(HealthThresholds {
+        latency_threshold: std::time::Duration::from_millis(
+            config.peer_health.latency_threshold_ms,
+        ),
+        error_rate_threshold: config.peer_health.error_rate_threshold,
+        smoothing: config.peer_health.smoothing,
+    }));
+    let status_addr =
+        std::env::var("STATUS_BIND_ADDR").unwrap_or_else(|_| "[::1]:50052".to_string());
+    let status_health = health.clone();
+    match status_addr.parse() {
+        Ok(addr) => {
+            info!("Starting peer status service on {}", status_addr);
+            tokio::spawn(async move {
+                if let Err(e) = tonic::transport::Server::builder()
+                    .add_service(RoutingUpdateServiceServer::new(PeerStatusService::new(
+                        status_health,
+                    )))
+                    .serve(addr)
+                    .await
+                {
+                    error!("Peer status service error: {}", e);
+                }
+            });
+        }
+        Err(e) => error!("Invalid STATUS_BIND_ADDR {}: {}", status_addr, e),
+    }
+
+    // Answers replayed for retransmitted requests
+    let mut answer_cache_config = AnswerCacheConfig::default();
+    if let Some(capacity) = std::env::var("ANSWER_CACHE_SIZE")
+        .ok()
+        .and_then(|v| v.parse().ok())
+    {
+        answer_cache_config.capacity = capacity;
+    }
+    if let Some(ms) = env_millis("ANSWER_CACHE_TTL_MS") {
+        answer_cache_config.ttl = ms;
+    }
+
+    // Client subnets allowed to connect
+    let access_list = AccessList::parse(
+        &std::env::var("ALLOWED_CIDRS").unwrap_or_default(),
+        &std::env::var("DENIED_CIDRS").unwrap_or_default(),
+    )
+    .unwrap_or_else(|e| {
+        error!("Invalid client access list: {}", e);
+        std::process::exit(1);
+    });
+
+    // Fraction of transactions traced in full, e.g. 0.001
+    let trace_sampler = TraceSampler::new(
+        std::env::var("TRACE_SAMPLE_RATE")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(0.0),
+    );
+
+    let max_avps = std::env::var("MAX_AVPS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(cdde_core::DEFAULT_MAX_AVPS);
+    let max_message_size = std::env::var("MAX_MESSAGE_SIZE")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_MAX_MESSAGE_SIZE);
+    let malformed_result_code = std::env::var("MALFORMED_RESULT_CODE")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_MALFORMED_RESULT_CODE);
+
+    // Origin-Host and Origin-Realm of answers generated by the DFL itself
+    let identity = LocalIdentity::from_env();
+
+    // Session actor, drained on shutdown
+    let drain_deadline = env_millis("DRAIN_DEADLINE_MS").unwrap_or(DEFAULT_DRAIN_DEADLINE);
+    let max_pending_per_connection = std::env::var("MAX_PENDING_PER_CONNECTION")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_MAX_PENDING_PER_CONNECTION);
+    let (actor_tx, actor_rx) = tokio::sync::mpsc::channel::<ActorMessage>(1024);
+    let (action_tx, mut action_rx) = tokio::sync::mpsc::channel::<SessionAction>(1024);
+    let (shutdown_tx, shutdown_rx) = tokio::sync::watch::channel(false);
+    let actor = tokio::spawn(
+        SessionActor::new(session_config.clone(), actor_rx, action_tx.clone())
+            .with_shutdown(shutdown_rx, drain_deadline)
+            .with_local_identity(identity.clone())
+            .with_max_pending_per_connection(max_pending_per_connection)
+            .with_transaction_store(store.clone())
+            .run(),
+    );
+
+    // Operator endpoints, only served when a token guards them
+    if let (Ok(admin_addr), Ok(token)) = (
+        std::env::var("ADMIN_BIND_ADDR"),
+        std::env::var("ADMIN_TOKEN"),
+    ) {
+        if token.trim().is_empty() {
+            error!("ADMIN_TOKEN is empty, not starting the admin endpoint");
+        } else {
+            let router = admin_router(actor_tx.clone(), token);
+            match tokio::net::TcpListener::bind(&admin_addr).await {
+                Ok(listener) => {
+                    info!("Starting admin endpoint on {}", admin_addr);
+                    tokio::spawn(async move {
+                        if let Err(e) = axum::serve(listener, router).await {
+                            error!("Admin endpoint error: {}", e);
+                        }
+                    });
+                }
+                Err(e) => error!("Failed to bind admin endpoint {}: {}", admin_addr, e),
+            }
+        }
+    }
+
+    // Answers from the actor are written by the connection they belong to
+    let connections = Arc::new(ConnectionRegistry::new());
+    let outbound = connections.clone();
+    tokio::spawn(async move {
+        while let Some(SessionAction::Reply { conn_id, packet }) = action_rx.recv().await {
+            outbound.deliver(conn_id, packet);
+        }
+    });
+
+    // Transactions left pending by the previous run, reported at their deadline
+    let snapshot_path = std::env::var("STATE_SNAPSHOT_PATH")
+        .ok()
+        .map(std::path::PathBuf::from);
+    let mut first_connection_id = 1;
+    if let Some(path) = &snapshot_path {
+        match load_snapshot(path) {
+            Ok(entries) if !entries.is_empty() => {
+                info!("Restored {} pending transactions", entries.len());
+                // New connections must not receive answers meant for old ones
+                first_connection_id = entries
+                    .iter()
+                    .map(|entry| entry.connection_id + 1)
+                    .max()
+                    .unwrap_or(1);
+                spawn_orphan_timeouts(entries).await;
+            }
+            Ok(_) => {}
+            Err(e) => error!("Failed to load transaction snapshot: {}", e),
+        }
+
+        let interval =
+            env_millis("STATE_SNAPSHOT_INTERVAL_MS").unwrap_or(std::time::Duration::from_secs(1));
+        let store = store.clone();
+        let path = path.clone();
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                if let Err(e) = save_snapshot(&path, &store.snapshot()) {
+                    error!("Failed to save transaction snapshot: {}", e);
+                }
+            }
+        });
+    }
+    drop(action_tx);
+
+    // Sessions never terminated by their client are dropped at a maximum age
+    let sessions = env_millis("SESSION_MAX_AGE_MS").map(|max_age| {
+        let sessions = Arc::new(SessionRegistry::new());
+        let mut sweep = SessionSweepConfig {
+            max_age,
+            ..Default::default()
+        };
+        if let Some(interval) = env_millis("SESSION_SWEEP_INTERVAL_MS") {
+            sweep.interval = interval;
+        }
+        info!("Dropping sessions older than {:?}", max_age);
+        tokio::spawn(run_session_sweep(sessions.clone(), store.clone(), sweep));
+        sessions
+    });
+
+    // Clients allowed to complete a capabilities exchange, if restricted
+    let restrict_peers = std::env::var("CER_ALLOWLIST_ENABLED").is_ok_and(|v| v == "true");
+    let peer_allowlist = match std::env::var("CMS_URL") {
+        Ok(cms_url) if restrict_peers => {
+            // Stay restrictive when the CMS is unreachable
+            let allowlist = fetch_peer_allowlist(&cms_url).await.unwrap_or_else(|e| {
+                error!("Failed to load peers from {}: {}", cms_url, e);
+                PeerAllowlist::default()
+            });
+            info!("Accepting CERs from {} known peers", allowlist.len());
+            Some(allowlist)
+        }
+        Err(_) if restrict_peers => {
+            error!("CER_ALLOWLIST_ENABLED requires CMS_URL");
+            std::process::exit(1);
+        }
+        _ => None,
+    };
+
+    // Forward actions reach the peers through the DPA relay
+    let relay_endpoint =
+        std::env::var("DPA_RELAY_ENDPOINT").unwrap_or_else(|_| DEFAULT_RELAY_ENDPOINT.to_string());
+    let forwarder = RelayForwarder::new(relay_endpoint.clone()).unwrap_or_else(|e| {
+        error!("Invalid DPA_RELAY_ENDPOINT: {}", e);
+        std::process::exit(1);
+    });
+    info!(
+        "Forwarding requests through the DPA relay at {}",
+        relay_endpoint
+    );
+
     // Start TCP Server
     let bind_addr = std::env::var("BIND_ADDR").unwrap_or_else(|_| "0.0.0.0:3868".to_string());
-    let server = TcpServer::new(bind_addr.clone(), store);
+    let vr_id = std::env::var("VR_ID").unwrap_or_else(|_| DEFAULT_VR_ID.to_string());
+    info!("Requests belong to Virtual Router {}", vr_id);
+    let mut server = TcpServer::new(bind_addr.clone(), store.clone())
+        .with_dcr_endpoint(dcr_endpoint)
+        .with_session_config(session_config)
+        .with_breaker_config(breaker_config)
+        .with_answer_cache_config(answer_cache_config)
+        .with_access_list(access_list)
+        .with_trace_sampler(trace_sampler)
+        .with_max_avps(max_avps)
+        .with_max_message_size(max_message_size)
+        .with_malformed_result_code(malformed_result_code)
+        .with_local_identity(identity)
+        .with_vr_id(vr_id)
+        .with_forwarder(Arc::new(forwarder))
+        .with_peer_health(health)
+        .with_session_actor(actor_tx.clone(), connections)
+        .with_first_connection_id(first_connection_id);
+    if let Some(allowlist) = peer_allowlist {
+        server = server.with_peer_allowlist(allowlist);
+    }
+    if let Some(sessions) = sessions {
+        server = server.with_session_registry(sessions);
+    }
 
     info!("Starting TCP listener on {}", bind_addr);
 
-    if let Err(e) = server.start().await {
-        info!("Server error: {}", e);
+    tokio::select! {
+        result = server.start() => {
+            if let Err(e) = result {
+                info!("Server error: {}", e);
+            }
+        }
+        _ = tokio::signal::ctrl_c() => {
+            info!("Shutdown requested");
+        }
+    }
+
+    // Let pending sessions finish before exiting
+    let _ = shutdown_tx.send(true);
+    drop(actor_tx);
+    if let Err(e) = actor.await {
+        error!("Session actor failed: {}", e);
+    }
+
+    if let Some(path) = &snapshot_path {
+        if let Err(e) = save_snapshot(path, &store.snapshot()) {
+            error!("Failed to save transaction snapshot: {}", e);
+        }
     }
 }
+
+/// Log and discard restored transactions as they time out
+///
+/// Their client connections closed with the previous run, so there is no
+/// one left to answer.
+async fn spawn_orphan_timeouts(entries: Vec<PersistedTransaction>) {
+    let sessions: std::collections::HashMap<(u64, u32), _> = entries
+        .iter()
+        .map(|entry| {
+            (
+                (entry.connection_id, entry.hop_by_hop_id),
+                (entry.command_code, entry.session_id.clone()),
+            )
+        })
+        .collect();
+    let orphans = TransactionStore::restore(entries).await;
+
+    tokio::spawn(async move {
+        while let Some((conn_id, hop_by_hop_id)) = orphans.next_timeout().await {
+            if let Some((command_code, session_id)) = sessions.get(&(conn_id, hop_by_hop_id)) {
+                warn!(
+                    "Discarding request {} (command {}, session {}) of connection {} from the previous run: its client is gone",
+                    hop_by_hop_id, command_code, session_id, conn_id
+                );
+            }
+            if orphans.is_empty() {
+                break;
+            }
+        }
+    });
+}
+
+/// Read a duration in milliseconds from the environment
+fn env_millis(name: &str) -> Option<std::time::Duration> {
+    std::env::var(name)
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .map(std::time::Duration::from_millis)
+}
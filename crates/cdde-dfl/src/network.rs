@@ -1,21 +1,267 @@
 // Force re-link
+use crate::acl::AccessList;
+use crate::actor::ActorMessage;
+use crate::answer::{
+    capabilities_answer, error_answer, LocalIdentity, DEFAULT_MALFORMED_RESULT_CODE,
+    RESULT_UNABLE_TO_DELIVER,
+};
+use crate::answer_cache::{AnswerCache, AnswerCacheConfig};
+use crate::breaker::{BreakerConfig, CircuitBreaker};
+use crate::connections::ConnectionRegistry;
+use crate::forwarder::PeerForwarder;
+use crate::peer_allowlist::PeerAllowlist;
+use crate::sampling::TraceSampler;
+use crate::session::{ends_session, SessionConfig, TransactionContext};
+use crate::sessions::SessionRegistry;
 use crate::store::TransactionStore;
-use cdde_core::{DiameterPacket, Result, Transport};
+use bytes::BytesMut;
+use cdde_core::codes::{
+    application_name, command_name, AVP_ORIGIN_HOST, AVP_ORIGIN_REALM, AVP_SESSION_ID,
+    CMD_CAPABILITIES_EXCHANGE, RESULT_SUCCESS, RESULT_UNKNOWN_PEER,
+};
+use cdde_core::diameter::mark_retransmitted;
+use cdde_core::{
+    CddeError, DiameterAvp, DiameterHeader, DiameterPacket, FrameAccumulator, PeerHealthRegistry,
+    Result, Transport, DEFAULT_MAX_AVPS,
+};
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
-use tokio::io::AsyncReadExt;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWrite, AsyncWriteExt};
 use tokio::net::TcpListener;
+use tokio::sync::mpsc::error::SendError;
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::{JoinError, JoinSet};
 use tracing::{debug, error, info, warn};
 
+/// Default DCR gRPC endpoint
+pub const DEFAULT_DCR_ENDPOINT: &str = "http://[::1]:50051";
+
+/// Virtual Router of requests when the listener is not assigned one
+pub const DEFAULT_VR_ID: &str = "default";
+
+/// Default largest message accepted from a peer, in bytes
+pub const DEFAULT_MAX_MESSAGE_SIZE: usize = 65_535;
+
+/// What the session actor decided about a request
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Tracking {
+    /// No actor tracks the request; its answer is written directly
+    Untracked,
+    /// The actor times the request out and relays its answer
+    Tracked,
+    /// The actor turned the request away and answered it itself
+    Rejected,
+}
+
+#[cfg(not(feature = "compact-codec"))]
+type DcrGrpcClient =
+    cdde_proto::core_router_service_client::CoreRouterServiceClient<tonic::transport::Channel>;
+
+/// With the compact codec the DCR is called on its compact service
+#[cfg(feature = "compact-codec")]
+type DcrGrpcClient = cdde_proto::compact::core_router_service_client::CoreRouterServiceClient<
+    tonic::transport::Channel,
+>;
+
+/// What a request task hands back to its connection to write
+enum Reply {
+    /// Answer built for the request
+    Packet(DiameterPacket),
+    /// Serialized answer replayed from the answer cache
+    Cached(Vec<u8>),
+}
+
+/// Outcome of a DCR call bounded by the answer timeout
+type DcrResult = std::result::Result<
+    std::result::Result<tonic::Response<cdde_proto::DiameterPacketAction>, tonic::Status>,
+    tokio::time::error::Elapsed,
+>;
+
 /// TCP Server for Diameter connections
+#[derive(Clone)]
 pub struct TcpServer {
     addr: String,
     store: Arc<TransactionStore>,
+    sessions: Option<Arc<SessionRegistry>>,
+    dcr_endpoint: String,
+    session_config: SessionConfig,
+    breaker: Arc<CircuitBreaker>,
+    answer_cache: Arc<AnswerCache>,
+    access_list: Arc<AccessList>,
+    trace_sampler: TraceSampler,
+    forwarder: Option<Arc<dyn PeerForwarder>>,
+    peer_health: Option<Arc<PeerHealthRegistry>>,
+    peer_allowlist: Option<Arc<PeerAllowlist>>,
+    identity: LocalIdentity,
+    vr_id: String,
+    max_avps: usize,
+    max_message_size: usize,
+    malformed_result_code: u32,
+    actor: Option<mpsc::Sender<ActorMessage>>,
+    connections: Arc<ConnectionRegistry>,
+    next_connection_id: Arc<AtomicU64>,
 }
 
 impl TcpServer {
     /// Create new TCP server
     pub fn new(addr: String, store: Arc<TransactionStore>) -> Self {
-        Self { addr, store }
+        Self {
+            addr,
+            store,
+            sessions: None,
+            dcr_endpoint: DEFAULT_DCR_ENDPOINT.to_string(),
+            session_config: SessionConfig::default(),
+            breaker: Arc::new(CircuitBreaker::default()),
+            answer_cache: Arc::new(AnswerCache::default()),
+            access_list: Arc::new(AccessList::default()),
+            trace_sampler: TraceSampler::default(),
+            forwarder: None,
+            peer_health: None,
+            peer_allowlist: None,
+            identity: LocalIdentity::default(),
+            vr_id: DEFAULT_VR_ID.to_string(),
+            max_avps: DEFAULT_MAX_AVPS,
+            max_message_size: DEFAULT_MAX_MESSAGE_SIZE,
+            malformed_result_code: DEFAULT_MALFORMED_RESULT_CODE,
+            actor: None,
+            connections: Arc::new(ConnectionRegistry::new()),
+            next_connection_id: Arc::new(AtomicU64::new(1)),
+        }
+    }
+
+    /// Track application sessions in a registry, so they can be swept
+    pub fn with_session_registry(mut self, sessions: Arc<SessionRegistry>) -> Self {
+        self.sessions = Some(sessions);
+        self
+    }
+
+    /// Set the DCR endpoint used by connection handlers
+    pub fn with_dcr_endpoint(mut self, endpoint: String) -> Self {
+        self.dcr_endpoint = endpoint;
+        self
+    }
+
+    /// Set the session and answer timeouts
+    pub fn with_session_config(mut self, config: SessionConfig) -> Self {
+        self.session_config = config;
+        self
+    }
+
+    /// Set the maximum number of AVPs accepted in a received message
+    pub fn with_max_avps(mut self, max_avps: usize) -> Self {
+        self.max_avps = max_avps;
+        self
+    }
+
+    /// Set the largest message accepted from a peer, in bytes
+    ///
+    /// A peer declaring a longer message is disconnected as soon as the
+    /// header arrives.
+    pub fn with_max_message_size(mut self, max_message_size: usize) -> Self {
+        self.max_message_size = max_message_size;
+        self
+    }
+
+    /// Set the Result-Code answering requests whose AVPs do not parse
+    pub fn with_malformed_result_code(mut self, result_code: u32) -> Self {
+        self.malformed_result_code = result_code;
+        self
+    }
+
+    /// Set the circuit breaker guarding DCR calls
+    pub fn with_breaker_config(mut self, config: BreakerConfig) -> Self {
+        self.breaker = Arc::new(CircuitBreaker::new(config));
+        self
+    }
+
+    /// Set the size and TTL of the cache answering retransmitted requests
+    pub fn with_answer_cache_config(mut self, config: AnswerCacheConfig) -> Self {
+        self.answer_cache = Arc::new(AnswerCache::new(config));
+        self
+    }
+
+    /// Restrict which client addresses may connect
+    pub fn with_access_list(mut self, access_list: AccessList) -> Self {
+        self.access_list = Arc::new(access_list);
+        self
+    }
+
+    /// Set which transactions are traced in full
+    pub fn with_trace_sampler(mut self, sampler: TraceSampler) -> Self {
+        self.trace_sampler = sampler;
+        self
+    }
+
+    /// Set how Forward actions are delivered to upstream peers
+    pub fn with_forwarder(mut self, forwarder: Arc<dyn PeerForwarder>) -> Self {
+        self.forwarder = Some(forwarder);
+        self
+    }
+
+    /// Skip forward candidates this registry reports down or draining
+    pub fn with_peer_health(mut self, health: Arc<PeerHealthRegistry>) -> Self {
+        self.peer_health = Some(health);
+        self
+    }
+
+    /// Only complete capabilities exchanges with these peers
+    ///
+    /// Clients must then complete a capabilities exchange before anything
+    /// else they send is handled, and are disconnected when their CER is
+    /// rejected. Without an allowlist every CER is accepted.
+    pub fn with_peer_allowlist(mut self, allowlist: PeerAllowlist) -> Self {
+        self.peer_allowlist = Some(Arc::new(allowlist));
+        self
+    }
+
+    /// Set the Origin-Host and Origin-Realm of locally generated answers
+    pub fn with_local_identity(mut self, identity: LocalIdentity) -> Self {
+        self.identity = identity;
+        self
+    }
+
+    /// Set the Virtual Router requests received on this listener belong to
+    ///
+    /// It selects the answer timeout and is passed to the DCR with each
+    /// request.
+    pub fn with_vr_id(mut self, vr_id: String) -> Self {
+        self.vr_id = vr_id;
+        self
+    }
+
+    /// Hand requests to the session actor, which answers them on timeout
+    ///
+    /// Answers to those requests then reach the client through the actor,
+    /// which queues them on the connection in `connections`; answers arriving
+    /// after the actor gave up on a request are dropped.
+    pub fn with_session_actor(
+        mut self,
+        actor: mpsc::Sender<ActorMessage>,
+        connections: Arc<ConnectionRegistry>,
+    ) -> Self {
+        self.actor = Some(actor);
+        self.connections = connections;
+        self
+    }
+
+    /// Number connections from `id` on
+    ///
+    /// Keeps ids of a previous run, still referenced by restored
+    /// transactions, from being reused.
+    pub fn with_first_connection_id(mut self, id: u64) -> Self {
+        self.next_connection_id = Arc::new(AtomicU64::new(id));
+        self
+    }
+
+    /// Get the circuit breaker shared by connection handlers
+    pub fn breaker(&self) -> &Arc<CircuitBreaker> {
+        &self.breaker
+    }
+
+    /// Get the transaction store shared by connection handlers
+    pub fn store(&self) -> &Arc<TransactionStore> {
+        &self.store
     }
 
     /// Start listening loop
@@ -23,15 +269,24 @@ impl TcpServer {
         let listener = TcpListener::bind(&self.addr).await?;
         info!("DFL listening on {}", self.addr);
 
+        self.serve(listener).await
+    }
+
+    /// Accept connections on an already bound listener
+    pub async fn serve(&self, listener: TcpListener) -> Result<()> {
         loop {
             match listener.accept().await {
                 Ok((socket, addr)) => {
-                    info!("New connection from {}", addr);
-                    let store = self.store.clone();
+                    if !self.admit(&socket) {
+                        continue;
+                    }
+                    let connection_id = self.next_connection_id.fetch_add(1, Ordering::Relaxed);
+                    info!("New connection {} from {}", connection_id, addr);
+                    let server = self.clone();
 
                     // Spawn connection handler
                     tokio::spawn(async move {
-                        if let Err(e) = Self::handle_connection(socket, store).await {
+                        if let Err(e) = server.handle_connection(socket, connection_id).await {
                             error!("Connection error from {}: {}", addr, e);
                         }
                     });
@@ -43,181 +298,637 @@ impl TcpServer {
         }
     }
 
-    /// Handle individual connection
+    /// Check a new connection against the access list before reading from it
+    ///
+    /// Refused connections are counted and closed when the socket is dropped.
+    fn admit<T: Transport>(&self, socket: &T) -> bool {
+        if self.access_list.admits(socket) {
+            return true;
+        }
+
+        match socket.peer_addr() {
+            Ok(addr) => warn!("Refusing connection from {}", addr),
+            Err(e) => warn!("Refusing connection with unknown peer address: {}", e),
+        }
+        cdde_metrics::REJECTED_CONNECTIONS_TOTAL.inc();
+        false
+    }
+
     /// Handle individual connection
     async fn handle_connection<T: Transport>(
+        &self,
         mut socket: T,
-        _store: Arc<TransactionStore>,
+        connection_id: u64,
     ) -> Result<()> {
         // Connect to DCR
-        // In real impl, this address should be configurable
-        let mut dcr_client: Option<
-            cdde_proto::core_router_service_client::CoreRouterServiceClient<
-                tonic::transport::Channel,
-            >,
-        > = match cdde_proto::core_router_service_client::CoreRouterServiceClient::connect(
-            "http://[::1]:50051",
-        )
-        .await
-        {
-            Ok(client) => Some(client),
-            Err(e) => {
-                error!("Failed to connect to DCR: {}", e);
-                None
-            }
-        };
+        let dcr_client: Option<DcrGrpcClient> =
+            match DcrGrpcClient::connect(self.dcr_endpoint.clone()).await {
+                Ok(client) => Some(client),
+                Err(e) => {
+                    error!("Failed to connect to DCR: {}", e);
+                    None
+                }
+            };
 
         let mut buffer = [0u8; 4096]; // 4KB buffer
 
+        // Bytes read but not yet split into messages
+        let mut frames = FrameAccumulator::new().with_max_length(self.max_message_size);
+        // Reused for every answer written on this connection
+        let mut scratch = BytesMut::new();
+        // Answers handed back by the session actor
+        let mut outbound = self.connections.register(connection_id);
+        // Without an allowlist, clients may skip the capabilities exchange
+        let mut capabilities_exchanged = self.peer_allowlist.is_none();
+        // Requests are handled on their own tasks, so a slow DCR or peer
+        // holds up neither reading nor the answers queued by the actor
+        let mut requests = JoinSet::new();
+
         loop {
-            // Read header first (simplified: reading chunks for now)
-            let n = socket.read(&mut buffer).await?;
+            let n = tokio::select! {
+                n = socket.read(&mut buffer) => n?,
+                Some(answer) = outbound.recv() => {
+                    write_packet(&mut socket, &mut scratch, &answer).await?;
+                    continue;
+                }
+                Some(reply) = requests.join_next() => {
+                    write_reply(&mut socket, &mut scratch, reply).await?;
+                    continue;
+                }
+            };
             if n == 0 {
+                if !frames.is_empty() {
+                    debug!("Discarding {} bytes of an incomplete message", frames.len());
+                }
+                // The client may only have closed its sending side
+                while let Some(reply) = requests.join_next().await {
+                    write_reply(&mut socket, &mut scratch, reply).await?;
+                }
                 info!("Connection closed by peer");
                 return Ok(());
             }
 
             debug!("Received {} bytes", n);
+            frames.extend(&buffer[..n]);
 
-            // Try to parse packet
-            match DiameterPacket::parse(&buffer[..n]) {
-                Ok(packet) => {
-                    debug!("Parsed packet: Command Code {}", packet.header.command_code);
-
-                    if let Some(client) = &mut dcr_client {
-                        let request = tonic::Request::new(cdde_proto::DiameterPacketRequest {
-                            connection_id: 0,             // Placeholder
-                            vr_id: "default".to_string(), // Placeholder
-                            reception_timestamp: std::time::SystemTime::now()
-                                .duration_since(std::time::UNIX_EPOCH)
-                                .unwrap_or_default()
-                                .as_nanos() as u64,
-                            raw_payload: packet.serialize(),
-                            session_tx_id: 0, // Placeholder
-                        });
+            // A read may end mid-message or hold several messages
+            while let Some(frame) = frames.next_frame().inspect_err(|e| match e {
+                CddeError::MessageTooLarge { .. } => warn!("Closing connection: {}", e),
+                _ => error!("Lost message framing, closing connection: {}", e),
+            })? {
+                match DiameterPacket::parse_with_max_avps(&frame, self.max_avps) {
+                    Ok(packet) => {
+                        debug!(
+                            "Parsed packet: Command {} ({}), Application {} ({})",
+                            packet.header.command_code,
+                            command_name(packet.header.command_code).unwrap_or("unknown"),
+                            packet.header.application_id,
+                            application_name(packet.header.application_id).unwrap_or("unknown")
+                        );
 
-                        match client.process_packet(request).await {
-                            Ok(response) => {
-                                let action = response.into_inner();
-                                let action_type =
-                                    cdde_proto::ActionType::try_from(action.action_type)
-                                        .unwrap_or(cdde_proto::ActionType::Discard);
-
-                                info!("Received action from DCR: {:?}", action_type);
-
-                                match action_type {
-                                    cdde_proto::ActionType::Reply => {
-                                        if !action.response_payload.is_empty() {
-                                            debug!(
-                                                "Sending Reply to client, {} bytes",
-                                                action.response_payload.len()
-                                            );
-                                            use tokio::io::AsyncWriteExt;
-                                            if let Err(e) =
-                                                socket.write_all(&action.response_payload).await
-                                            {
-                                                error!("Failed to write response to socket: {}", e);
-                                            }
-                                        }
-                                    }
-                                    cdde_proto::ActionType::Forward => {
-                                        if !action.target_host_name.is_empty() {
-                                            info!(
-                                                "Forwarding packet to target: {}",
-                                                action.target_host_name
-                                            );
-                                            // TODO: Implement actual forwarding via DPA or direct connection
-                                        } else {
-                                            warn!("Forward action received but no target host specified");
-                                        }
-                                    }
-                                    cdde_proto::ActionType::Discard => {
-                                        info!("Discarding packet as requested by DCR");
-                                    }
-                                }
+                        // The capabilities exchange is answered by the DFL itself
+                        if packet.header.is_request()
+                            && packet.header.command_code == CMD_CAPABILITIES_EXCHANGE
+                        {
+                            if !self
+                                .answer_capabilities_exchange(&mut socket, &mut scratch, &packet)
+                                .await?
+                            {
+                                info!(
+                                    "Closing connection {} after rejecting its CER",
+                                    connection_id
+                                );
+                                return Ok(());
                             }
-                            Err(e) => error!("Failed to process packet via DCR: {}", e),
+                            capabilities_exchanged = true;
+                            continue;
                         }
-                    } else {
-                        error!("DCR client not available, dropping packet");
+                        if !capabilities_exchanged {
+                            warn!(
+                                "Dropping command {} received on connection {} before the capabilities exchange",
+                                packet.header.command_code, connection_id
+                            );
+                            continue;
+                        }
+
+                        let server = self.clone();
+                        let dcr_client = dcr_client.clone();
+                        requests.spawn(async move {
+                            server
+                                .process_packet(dcr_client, connection_id, packet)
+                                .await
+                        });
+                    }
+                    Err(e) if !capabilities_exchanged => {
+                        warn!(
+                            "Dropping malformed message received on connection {} before the capabilities exchange: {}",
+                            connection_id, e
+                        );
+                    }
+                    Err(e) => {
+                        error!("Failed to parse packet: {}", e);
+                        self.answer_malformed(&mut socket, &mut scratch, &frame)
+                            .await?;
                     }
-                }
-                Err(e) => {
-                    error!("Failed to parse packet: {}", e);
-                    // In real impl: handle partial reads / buffering
                 }
             }
         }
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use async_trait::async_trait;
-    use std::net::{IpAddr, Ipv4Addr, SocketAddr};
-    use std::pin::Pin;
-    use std::task::{Context, Poll};
-    use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+    /// Send a parsed packet to the DCR and apply the returned action
+    ///
+    /// Returns what is to be written back on the connection.
+    async fn process_packet(
+        &self,
+        mut dcr_client: Option<DcrGrpcClient>,
+        connection_id: u64,
+        packet: DiameterPacket,
+    ) -> Option<Reply> {
+        // Tear down the session explicitly instead of waiting for its timeout
+        if ends_session(&packet) {
+            if let Some(session_id) = packet.find_avp(AVP_SESSION_ID) {
+                let session_id = String::from_utf8_lossy(&session_id.data);
+                if let Some(sessions) = &self.sessions {
+                    sessions.end(&session_id);
+                }
+                let removed = self.store.remove_session(&session_id).await;
+                debug!(
+                    "Session {} ended, removed {} pending transactions",
+                    session_id, removed
+                );
+            }
+        }
 
-    // Mock Transport for testing
-    struct MockTransport {
-        read_data: Vec<u8>,
+        // Replay the answer to a retransmitted request instead of routing it again
+        if packet.header.is_request() {
+            if let Some(answer) = self.answer_cache.get(&packet) {
+                debug!(
+                    "Answering retransmitted request {} from cache",
+                    packet.header.end_to_end_id
+                );
+                return Some(Reply::Cached(answer));
+            }
+        }
+
+        let is_request = packet.header.is_request();
+
+        // Let the session actor time the request out and answer it; requests
+        // it turns away are already answered. This comes before the breaker,
+        // so a half-open probe is never taken by a request that goes no further.
+        let tracking = if is_request {
+            self.track_request(connection_id, &self.vr_id, &packet)
+                .await
+        } else {
+            Tracking::Untracked
+        };
+        if tracking == Tracking::Rejected {
+            return None;
+        }
+
+        let answer = self
+            .route_through_dcr(&mut dcr_client, connection_id, &packet)
+            .await?;
+        // Answers of tracked requests reach the client through the actor
+        let answer = if tracking == Tracking::Tracked {
+            self.answer_through_actor(connection_id, answer).await?
+        } else {
+            answer
+        };
+        Some(Reply::Packet(answer))
     }
 
-    impl AsyncRead for MockTransport {
-        fn poll_read(
-            self: Pin<&mut Self>,
-            _cx: &mut Context<'_>,
-            buf: &mut ReadBuf<'_>,
-        ) -> Poll<std::io::Result<()>> {
-            let me = self.get_mut();
-            if me.read_data.is_empty() {
-                return Poll::Ready(Ok(()));
+    /// Have the DCR decide what to do with a packet and carry it out
+    ///
+    /// Returns the answer to send back, if any.
+    async fn route_through_dcr(
+        &self,
+        dcr_client: &mut Option<DcrGrpcClient>,
+        connection_id: u64,
+        packet: &DiameterPacket,
+    ) -> Option<DiameterPacket> {
+        // Fail fast while the DCR is considered down
+        if !self.breaker.allow_request() {
+            debug!("DCR circuit breaker open, answering with UNABLE_TO_DELIVER");
+            return self.unable_to_deliver(packet);
+        }
+
+        let Some(client) = dcr_client else {
+            warn!("DCR client not available, answering with UNABLE_TO_DELIVER");
+            self.breaker.record_failure();
+            return self.unable_to_deliver(packet);
+        };
+
+        let is_request = packet.header.is_request();
+        let vr_id = self.vr_id.clone();
+
+        // Track requests until the DCR has decided what to do with them
+        if is_request {
+            let session_id = packet
+                .find_avp(AVP_SESSION_ID)
+                .map(|avp| String::from_utf8_lossy(&avp.data).to_string())
+                .unwrap_or_default();
+            if let Some(sessions) = self.sessions.as_ref().filter(|_| !session_id.is_empty()) {
+                sessions.touch(&session_id, Instant::now());
             }
 
-            let len = std::cmp::min(buf.remaining(), me.read_data.len());
-            buf.put_slice(&me.read_data[..len]);
-            me.read_data.drain(..len);
+            self.store
+                .insert(
+                    connection_id,
+                    packet.header.hop_by_hop_id,
+                    packet.header.command_code,
+                    packet.header.end_to_end_id,
+                    session_id,
+                    self.session_config.timeout_duration,
+                )
+                .await;
+        }
+
+        let answer_timeout = self.session_config.answer_timeout_for(Some(&vr_id));
+        let trace_id = self.trace_sampler.sample();
+        if let Some(trace_id) = &trace_id {
+            info!(
+                trace_id = %trace_id,
+                connection_id,
+                vr_id = %vr_id,
+                packet = ?packet,
+                "Tracing sampled transaction"
+            );
+        }
+        let request = tonic::Request::new(cdde_proto::DiameterPacketRequest {
+            connection_id,
+            vr_id,
+            reception_timestamp: std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_nanos() as u64,
+            raw_payload: packet.to_bytes(),
+            session_tx_id: 0, // Placeholder
+            trace_id: trace_id.clone().unwrap_or_default(),
+        });
+
+        let result = tokio::time::timeout(answer_timeout, client.process_packet(request)).await;
+
+        if is_request {
+            let context = self
+                .store
+                .remove(connection_id, packet.header.hop_by_hop_id)
+                .await;
+            if let (Some(context), Ok(_)) = (context, &result) {
+                report_slow_transaction(&context, self.session_config.slow_threshold);
+            }
+        }
+
+        self.apply_result(packet, result, trace_id, answer_timeout)
+            .await
+    }
+
+    /// Register a request with the session actor and wait for its verdict
+    async fn track_request(
+        &self,
+        connection_id: u64,
+        vr_id: &str,
+        packet: &DiameterPacket,
+    ) -> Tracking {
+        let Some(actor) = &self.actor else {
+            return Tracking::Untracked;
+        };
+        let (admitted, verdict) = oneshot::channel();
+        let message = ActorMessage::IngressRequest {
+            conn_id: connection_id,
+            vr_id: Some(vr_id.to_string()),
+            packet: packet.clone(),
+            admitted,
+        };
+        if actor.send(message).await.is_err() {
+            return Tracking::Untracked;
+        }
+        match verdict.await {
+            Ok(true) => Tracking::Tracked,
+            Ok(false) => Tracking::Rejected,
+            // The actor stopped before deciding
+            Err(_) => Tracking::Untracked,
+        }
+    }
 
-            Poll::Ready(Ok(()))
+    /// Pass the answer to a tracked request to the session actor
+    ///
+    /// The answer is handed back, to be written directly, if the actor has
+    /// stopped.
+    async fn answer_through_actor(
+        &self,
+        connection_id: u64,
+        answer: DiameterPacket,
+    ) -> Option<DiameterPacket> {
+        let Some(actor) = &self.actor else {
+            return Some(answer);
+        };
+        let message = ActorMessage::Answer {
+            conn_id: connection_id,
+            packet: answer,
+        };
+        match actor.send(message).await {
+            Err(SendError(ActorMessage::Answer { packet, .. })) => Some(packet),
+            _ => None,
         }
     }
 
-    impl AsyncWrite for MockTransport {
-        fn poll_write(
-            self: Pin<&mut Self>,
-            _cx: &mut Context<'_>,
-            buf: &[u8],
-        ) -> Poll<std::io::Result<usize>> {
-            Poll::Ready(Ok(buf.len()))
+    /// Work out the answer to a packet from the outcome of its DCR call
+    ///
+    /// Returns `None` when nothing is to be sent back.
+    async fn apply_result(
+        &self,
+        packet: &DiameterPacket,
+        result: DcrResult,
+        trace_id: Option<String>,
+        answer_timeout: Duration,
+    ) -> Option<DiameterPacket> {
+        match result {
+            Ok(Ok(response)) => {
+                self.breaker.record_success();
+                let action = response.into_inner();
+                if let Some(trace_id) = &trace_id {
+                    info!(
+                        trace_id = %trace_id,
+                        action_type = action.action_type,
+                        target = %action.target_host_name,
+                        response_bytes = action.response_payload.len(),
+                        "Sampled transaction answered by DCR"
+                    );
+                }
+                if packet.header.is_request()
+                    && action.action_type == cdde_proto::ActionType::Reply as i32
+                    && !action.response_payload.is_empty()
+                {
+                    self.answer_cache
+                        .insert(packet, action.response_payload.to_vec());
+                }
+                self.apply_action(packet, action, answer_timeout).await
+            }
+            Ok(Err(e)) => {
+                error!("Failed to process packet via DCR: {}: {}", e, packet);
+                self.breaker.record_failure();
+                self.unable_to_deliver(packet)
+            }
+            Err(_) => {
+                self.breaker.record_failure();
+                warn!(
+                    "No answer from DCR within {:?}, answering with UNABLE_TO_DELIVER: {}",
+                    answer_timeout, packet
+                );
+                self.unable_to_deliver(packet)
+            }
         }
+    }
+
+    /// Act on the action returned by the DCR, returning the answer to send back
+    async fn apply_action(
+        &self,
+        packet: &DiameterPacket,
+        action: cdde_proto::DiameterPacketAction,
+        answer_timeout: Duration,
+    ) -> Option<DiameterPacket> {
+        let action_type = cdde_proto::ActionType::try_from(action.action_type)
+            .unwrap_or(cdde_proto::ActionType::Discard);
 
-        fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
-            Poll::Ready(Ok(()))
+        info!("Received action from DCR: {:?}", action_type);
+
+        match action_type {
+            cdde_proto::ActionType::Reply => {
+                if !action.response_payload.is_empty() {
+                    debug!(
+                        "Sending Reply to client, {} bytes",
+                        action.response_payload.len()
+                    );
+                    match DiameterPacket::parse(&action.response_payload) {
+                        Ok(answer) => Some(answer),
+                        Err(e) => {
+                            error!("Invalid reply from DCR: {}", e);
+                            self.unable_to_deliver(packet)
+                        }
+                    }
+                } else if action.result_code != 0 && packet.header.is_request() {
+                    debug!("Answering with Result-Code {}", action.result_code);
+                    Some(error_answer(packet, action.result_code, &self.identity))
+                } else {
+                    None
+                }
+            }
+            cdde_proto::ActionType::Forward => {
+                if action.target_host_name.is_empty() {
+                    warn!("Forward action received but no target host specified");
+                    return None;
+                }
+                let Some(forwarder) = &self.forwarder else {
+                    warn!(
+                        "No peer forwarder configured for {}, answering with UNABLE_TO_DELIVER",
+                        action.target_host_name
+                    );
+                    return self.unable_to_deliver(packet);
+                };
+                self.forward_to_candidates(packet, forwarder, action, answer_timeout)
+                    .await
+            }
+            cdde_proto::ActionType::Discard => {
+                info!("Discarding packet as requested by DCR");
+                None
+            }
         }
+    }
+
+    /// Try the candidate peers of a Forward action in order
+    ///
+    /// Candidates the DPA reported down or draining are skipped. The first
+    /// answer received is relayed to the client; when every candidate fails,
+    /// or none answers within `answer_timeout` all together, the request is
+    /// answered with UNABLE_TO_DELIVER.
+    async fn forward_to_candidates(
+        &self,
+        packet: &DiameterPacket,
+        forwarder: &Arc<dyn PeerForwarder>,
+        action: cdde_proto::DiameterPacketAction,
+        answer_timeout: Duration,
+    ) -> Option<DiameterPacket> {
+        let candidates = if action.candidate_peers.is_empty() {
+            vec![action.target_host_name]
+        } else {
+            action.candidate_peers
+        };
 
-        fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
-            Poll::Ready(Ok(()))
+        let candidates: Vec<String> = candidates
+            .into_iter()
+            .filter(|peer| match &self.peer_health {
+                Some(health) if health.is_down(peer) || health.is_draining(peer) => {
+                    debug!("Skipping unavailable candidate {}", peer);
+                    false
+                }
+                _ => true,
+            })
+            .collect();
+
+        let deadline = tokio::time::Instant::now() + answer_timeout;
+        let mut payload = Vec::from(action.response_payload);
+        for (attempt, peer) in candidates.iter().enumerate() {
+            if attempt > 0 {
+                mark_retransmitted(&mut payload);
+            }
+            info!("Forwarding packet to target: {}", peer);
+            let Ok(answer) =
+                tokio::time::timeout_at(deadline, forwarder.forward(peer, payload.clone())).await
+            else {
+                warn!(
+                    "No answer from {} within {:?}, answering with UNABLE_TO_DELIVER",
+                    peer, answer_timeout
+                );
+                return self.unable_to_deliver(packet);
+            };
+            match answer.and_then(|answer| DiameterPacket::parse(&answer)) {
+                Ok(answer) => return Some(answer),
+                Err(e) => warn!("Forwarding to {} failed: {}", peer, e),
+            }
         }
+
+        warn!(
+            "All {} candidate peers failed, answering with UNABLE_TO_DELIVER",
+            candidates.len()
+        );
+        self.unable_to_deliver(packet)
+    }
+
+    /// Answer a client's CER, rejecting peers missing from the allowlist
+    ///
+    /// Returns whether the exchange succeeded.
+    async fn answer_capabilities_exchange<T: Transport>(
+        &self,
+        socket: &mut T,
+        scratch: &mut BytesMut,
+        packet: &DiameterPacket,
+    ) -> Result<bool> {
+        let identity = |code| {
+            packet
+                .find_avp(code)
+                .map(|avp| String::from_utf8_lossy(&avp.data).to_string())
+                .unwrap_or_default()
+        };
+        let origin_host = identity(AVP_ORIGIN_HOST);
+        let origin_realm = identity(AVP_ORIGIN_REALM);
+
+        let result_code = match &self.peer_allowlist {
+            Some(allowlist) if !allowlist.allows(&origin_host, &origin_realm) => {
+                warn!(
+                    "Rejecting CER from unknown peer {} of realm {}",
+                    origin_host, origin_realm
+                );
+                RESULT_UNKNOWN_PEER
+            }
+            _ => {
+                info!(
+                    "Capabilities exchange with {} of realm {}",
+                    origin_host, origin_realm
+                );
+                RESULT_SUCCESS
+            }
+        };
+
+        let host_ip_address = socket.local_addr()?.ip();
+        let answer = capabilities_answer(packet, result_code, &self.identity, host_ip_address);
+        write_packet(socket, scratch, &answer).await?;
+        Ok(result_code == RESULT_SUCCESS)
     }
 
-    #[async_trait]
-    impl Transport for MockTransport {
-        fn peer_addr(&self) -> Result<SocketAddr> {
-            Ok(SocketAddr::new(
-                IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)),
-                12345,
-            ))
+    /// Answer a request whose header is valid but whose AVPs do not parse
+    ///
+    /// The answer keeps the Session-Id when it precedes the malformed AVP.
+    /// Malformed answers and capabilities exchanges are only logged.
+    async fn answer_malformed<T: Transport>(
+        &self,
+        socket: &mut T,
+        scratch: &mut BytesMut,
+        frame: &[u8],
+    ) -> Result<()> {
+        // Frames always hold a valid header
+        let header = DiameterHeader::parse(frame)?;
+        if !header.is_request() || header.command_code == CMD_CAPABILITIES_EXCHANGE {
+            return Ok(());
         }
 
-        fn local_addr(&self) -> Result<SocketAddr> {
-            Ok(SocketAddr::new(
-                IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)),
-                3868,
-            ))
+        let mut avps = Vec::new();
+        let mut offset = 20;
+        while let Ok((avp, length)) = DiameterAvp::parse(&frame[offset..]) {
+            avps.push(avp);
+            offset += length;
+        }
+        let request = DiameterPacket { header, avps };
+        let answer = error_answer(&request, self.malformed_result_code, &self.identity);
+        write_packet(socket, scratch, &answer).await
+    }
+
+    /// DIAMETER_UNABLE_TO_DELIVER answer to a request
+    ///
+    /// Answers cannot be answered, there is nobody left to notify.
+    fn unable_to_deliver(&self, packet: &DiameterPacket) -> Option<DiameterPacket> {
+        packet
+            .header
+            .is_request()
+            .then(|| error_answer(packet, RESULT_UNABLE_TO_DELIVER, &self.identity))
+    }
+}
+
+/// Write what a request task handed back, if anything
+async fn write_reply<T: AsyncWrite + Send + Unpin>(
+    socket: &mut T,
+    scratch: &mut BytesMut,
+    reply: std::result::Result<Option<Reply>, JoinError>,
+) -> Result<()> {
+    match reply {
+        Ok(Some(Reply::Packet(answer))) => write_packet(socket, scratch, &answer).await,
+        Ok(Some(Reply::Cached(answer))) => Ok(socket.write_all(&answer).await?),
+        Ok(None) => Ok(()),
+        Err(e) => {
+            error!("Request task failed: {}", e);
+            Ok(())
         }
     }
+}
+
+/// Serialize a packet into the connection's scratch buffer and write it out
+async fn write_packet<T: AsyncWrite + Send + Unpin>(
+    socket: &mut T,
+    scratch: &mut BytesMut,
+    packet: &DiameterPacket,
+) -> Result<()> {
+    scratch.clear();
+    packet.serialize_into(scratch);
+    socket.write_all(scratch).await?;
+    Ok(())
+}
+
+/// Warn about and count a transaction answered later than the threshold
+///
+/// Returns whether the transaction was slow.
+fn report_slow_transaction(context: &TransactionContext, threshold: Duration) -> bool {
+    let elapsed = context.elapsed();
+    if elapsed <= threshold {
+        return false;
+    }
+
+    warn!(
+        command_code = context.original_command_code,
+        command = command_name(context.original_command_code).unwrap_or("unknown"),
+        elapsed_ms = elapsed.as_millis() as u64,
+        session_id = %context.session_id,
+        "Slow transaction: answered after {}ms",
+        elapsed.as_millis()
+    );
+    cdde_metrics::SLOW_TRANSACTIONS_TOTAL.inc();
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cdde_core::codes::AVP_RESULT_CODE;
+    use cdde_core::diameter::FLAG_ERROR;
+    use cdde_test_support::MockTransport;
 
     #[tokio::test]
     async fn test_handle_connection_parse_v2() {
@@ -234,16 +945,569 @@ mod tests {
             avps: vec![],
         };
 
-        let data = packet.serialize();
-        let transport = MockTransport { read_data: data };
+        let transport = MockTransport::new().with_read(packet.serialize());
+        let written = transport.written();
         let store = Arc::new(TransactionStore::new());
+        let server = TcpServer::new("127.0.0.1:0".to_string(), store)
+            .with_dcr_endpoint("http://127.0.0.1:1".to_string());
+
+        // Processes the one packet, then the read hits EOF
+        server.handle_connection(transport, 1).await.unwrap();
+
+        // Without a DCR the request is answered with UNABLE_TO_DELIVER
+        let answer = DiameterPacket::parse(&written.lock().unwrap()).unwrap();
+        assert!(answer.header.is_answer());
+        assert_eq!(answer.header.hop_by_hop_id, 1);
+        assert_eq!(
+            answer.find_avp(AVP_RESULT_CODE).unwrap().data,
+            RESULT_UNABLE_TO_DELIVER.to_be_bytes()
+        );
+    }
+
+    /// Answers written by a connection, split into messages
+    fn written_answers(written: &[u8]) -> Vec<DiameterPacket> {
+        let mut frames = FrameAccumulator::new();
+        frames.extend(written);
+        let mut answers = Vec::new();
+        while let Some(frame) = frames.next_frame().unwrap() {
+            answers.push(DiameterPacket::parse(&frame).unwrap());
+        }
+        assert!(frames.is_empty());
+        answers
+    }
+
+    fn request(hop_by_hop_id: u32) -> DiameterPacket {
+        DiameterPacket {
+            header: cdde_core::DiameterHeader {
+                version: 1,
+                length: 0,
+                flags: 0xC0,
+                command_code: 316,
+                application_id: 16777251,
+                hop_by_hop_id,
+                end_to_end_id: hop_by_hop_id,
+            },
+            avps: vec![cdde_core::DiameterAvp {
+                code: AVP_SESSION_ID,
+                flags: 0x40,
+                vendor_id: None,
+                data: b"mme1;framing".to_vec(),
+            }],
+        }
+    }
+
+    #[tokio::test]
+    async fn test_packet_read_one_byte_at_a_time_is_parsed_once() {
+        let transport = request(1)
+            .serialize()
+            .into_iter()
+            .fold(MockTransport::new(), |transport, byte| {
+                transport.with_read(vec![byte])
+            });
+        let written = transport.written();
+        let server = TcpServer::new("127.0.0.1:0".to_string(), Arc::new(TransactionStore::new()))
+            .with_dcr_endpoint("http://127.0.0.1:1".to_string());
+        server.handle_connection(transport, 1).await.unwrap();
+
+        let answers = written_answers(&written.lock().unwrap());
+        assert_eq!(answers.len(), 1);
+        assert_eq!(answers[0].header.hop_by_hop_id, 1);
+    }
+
+    #[tokio::test]
+    async fn test_two_packets_in_one_read_are_both_handled() {
+        let mut segment = request(1).serialize();
+        segment.extend(request(2).serialize());
+        // The second read completes a third message split across reads
+        let third = request(3).serialize();
+        segment.extend_from_slice(&third[..10]);
+        let transport = MockTransport::new()
+            .with_read(segment)
+            .with_read(third[10..].to_vec());
+        let written = transport.written();
+        let server = TcpServer::new("127.0.0.1:0".to_string(), Arc::new(TransactionStore::new()))
+            .with_dcr_endpoint("http://127.0.0.1:1".to_string());
+        server.handle_connection(transport, 1).await.unwrap();
+
+        let ids: Vec<u32> = written_answers(&written.lock().unwrap())
+            .iter()
+            .map(|answer| answer.header.hop_by_hop_id)
+            .collect();
+        assert_eq!(ids, [1, 2, 3]);
+    }
+
+    #[tokio::test]
+    async fn test_dwr_is_answered_with_dwa() {
+        use cdde_core::codes::CMD_DEVICE_WATCHDOG;
+        use cdde_core::DiameterAvp;
+        use cdde_test_support::{reply, MockDcr};
+
+        // DCR answering watchdogs itself, as the real one does
+        let (dcr_addr, dcr_handle) = MockDcr::new(|request| {
+            let mut dwa = DiameterPacket::parse(&request.raw_payload).unwrap();
+            dwa.header.flags = 0;
+            dwa.avps.push(DiameterAvp {
+                code: AVP_RESULT_CODE,
+                flags: 0x40,
+                vendor_id: None,
+                data: RESULT_SUCCESS.to_be_bytes().to_vec(),
+            });
+            Ok(reply(dwa.serialize()))
+        })
+        .spawn()
+        .await;
+
+        let dwr = DiameterPacket {
+            header: cdde_core::DiameterHeader {
+                version: 1,
+                length: 0,
+                flags: 0x80,
+                command_code: CMD_DEVICE_WATCHDOG,
+                application_id: 0,
+                hop_by_hop_id: 5,
+                end_to_end_id: 6,
+            },
+            avps: vec![],
+        };
+        let transport = MockTransport::new().with_read(dwr.serialize());
+        let written = transport.written();
+        let server = TcpServer::new("127.0.0.1:0".to_string(), Arc::new(TransactionStore::new()))
+            .with_dcr_endpoint(format!("http://{dcr_addr}"));
+        server.handle_connection(transport, 1).await.unwrap();
+
+        let dwa = DiameterPacket::parse(&written.lock().unwrap()).unwrap();
+        assert!(dwa.header.is_answer());
+        assert_eq!(dwa.header.command_code, CMD_DEVICE_WATCHDOG);
+        assert_eq!((dwa.header.hop_by_hop_id, dwa.header.end_to_end_id), (5, 6));
+        assert_eq!(
+            dwa.find_avp(AVP_RESULT_CODE).unwrap().data,
+            RESULT_SUCCESS.to_be_bytes()
+        );
+
+        dcr_handle.abort();
+    }
+
+    #[tokio::test]
+    async fn test_requests_are_answered_through_the_session_actor() {
+        use crate::actor::{SessionAction, SessionActor};
+        use cdde_test_support::{reply, MockDcr};
+
+        // Request 1 is answered by the DCR, request 2 forwarded nowhere
+        let (dcr_addr, dcr_handle) = MockDcr::new(|request| {
+            let mut packet = DiameterPacket::parse(&request.raw_payload).unwrap();
+            if packet.header.hop_by_hop_id == 2 {
+                return Ok(cdde_proto::DiameterPacketAction {
+                    action_type: cdde_proto::ActionType::Forward as i32,
+                    target_host_name: "hss1.example.com".to_string(),
+                    ..reply(vec![])
+                });
+            }
+            packet.header.flags = 0x40;
+            Ok(reply(packet.serialize()))
+        })
+        .spawn()
+        .await;
+
+        let session_config = SessionConfig {
+            answer_timeout: Duration::from_millis(200),
+            ..Default::default()
+        };
+        let (actor_tx, actor_rx) = mpsc::channel(16);
+        let (action_tx, mut action_rx) = mpsc::channel(16);
+        tokio::spawn(SessionActor::new(session_config.clone(), actor_rx, action_tx).run());
+        let connections = Arc::new(ConnectionRegistry::new());
+        let outbound = connections.clone();
+        tokio::spawn(async move {
+            while let Some(SessionAction::Reply { conn_id, packet }) = action_rx.recv().await {
+                outbound.deliver(conn_id, packet);
+            }
+        });
+
+        let server = TcpServer::new("127.0.0.1:0".to_string(), Arc::new(TransactionStore::new()))
+            .with_dcr_endpoint(format!("http://{dcr_addr}"))
+            .with_session_config(session_config)
+            .with_session_actor(actor_tx, connections);
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut client = tokio::net::TcpStream::connect(listener.local_addr().unwrap())
+            .await
+            .unwrap();
+        let (socket, _) = listener.accept().await.unwrap();
+        let connection = tokio::spawn(async move { server.handle_connection(socket, 9).await });
+
+        let mut requests = request(1).serialize();
+        requests.extend(request(2).serialize());
+        client.write_all(&requests).await.unwrap();
+
+        let mut frames = FrameAccumulator::new();
+        let mut answers = Vec::new();
+        let mut buffer = [0u8; 1024];
+        while answers.len() < 2 {
+            let n = tokio::time::timeout(Duration::from_secs(5), client.read(&mut buffer))
+                .await
+                .unwrap()
+                .unwrap();
+            assert!(n > 0, "connection closed before both answers");
+            frames.extend(&buffer[..n]);
+            while let Some(frame) = frames.next_frame().unwrap() {
+                answers.push(DiameterPacket::parse(&frame).unwrap());
+            }
+        }
+
+        assert_eq!(answers[0].header.hop_by_hop_id, 1);
+        assert!(answers[0].find_avp(AVP_RESULT_CODE).is_none());
+        // The actor answers the request left without an answer
+        assert_eq!(answers[1].header.hop_by_hop_id, 2);
+        assert_eq!(
+            answers[1].find_avp(AVP_RESULT_CODE).unwrap().data,
+            RESULT_UNABLE_TO_DELIVER.to_be_bytes()
+        );
+
+        // The connection ends cleanly once the client hangs up
+        drop(client);
+        connection.await.unwrap().unwrap();
+        dcr_handle.abort();
+    }
+
+    #[tokio::test]
+    async fn test_session_termination_answer_removes_pending_session() {
+        let store = Arc::new(TransactionStore::new());
+        store
+            .insert(
+                7,
+                100,
+                272,
+                200,
+                "session;42".to_string(),
+                Duration::from_secs(30),
+            )
+            .await;
+
+        let sta = DiameterPacket {
+            header: cdde_core::DiameterHeader {
+                version: 1,
+                length: 0,
+                flags: 0x40, // Answer
+                command_code: 275,
+                application_id: 4,
+                hop_by_hop_id: 101,
+                end_to_end_id: 201,
+            },
+            avps: vec![cdde_core::DiameterAvp {
+                code: AVP_SESSION_ID,
+                flags: 0x40,
+                vendor_id: None,
+                data: b"session;42".to_vec(),
+            }],
+        };
+
+        let transport = MockTransport::new().with_read(sta.serialize());
+        let server = TcpServer::new("127.0.0.1:0".to_string(), store.clone())
+            .with_dcr_endpoint("http://127.0.0.1:1".to_string());
+        server.handle_connection(transport, 7).await.unwrap();
+
+        assert!(store.is_empty());
+    }
+
+    #[test]
+    fn test_slow_transaction_is_reported() {
+        use cdde_test_support::LogBuffer;
+        use tokio_util::time::DelayQueue;
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .unwrap();
+        let key = runtime.block_on(async {
+            let mut queue = DelayQueue::new();
+            queue.insert((1u64, 1u32), Duration::from_secs(30))
+        });
+
+        let mut context = TransactionContext::new(key, 1, 316, 2, "session;1".to_string());
+        context.ingress_timestamp = std::time::Instant::now() - Duration::from_millis(250);
+
+        let logs = LogBuffer::default();
+        let writer = logs.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_writer(move || writer.clone())
+            .with_ansi(false)
+            .finish();
+
+        let before = cdde_metrics::SLOW_TRANSACTIONS_TOTAL.get();
+        let (fast, slow) = tracing::subscriber::with_default(subscriber, || {
+            (
+                report_slow_transaction(&context, Duration::from_secs(1)),
+                report_slow_transaction(&context, Duration::from_millis(100)),
+            )
+        });
+
+        assert!(!fast);
+        assert!(slow);
+        assert!(cdde_metrics::SLOW_TRANSACTIONS_TOTAL.get() > before);
+
+        let output = logs.contents();
+        assert!(output.contains("Slow transaction"));
+        assert!(output.contains("command_code=316"));
+    }
+
+    #[test]
+    fn test_disallowed_peer_is_refused() {
+        let transport = MockTransport::new();
+        let server = TcpServer::new("127.0.0.1:0".to_string(), Arc::new(TransactionStore::new()));
+        assert!(server.admit(&transport));
+
+        let before = cdde_metrics::REJECTED_CONNECTIONS_TOTAL.get();
+        let server = server.with_access_list(AccessList::parse("10.0.0.0/8", "").unwrap());
+        assert!(!server.admit(&transport));
+        assert!(cdde_metrics::REJECTED_CONNECTIONS_TOTAL.get() > before);
+
+        let server =
+            server.with_access_list(AccessList::parse("127.0.0.0/8", "127.0.0.1").unwrap());
+        assert!(!server.admit(&transport));
+    }
+
+    #[tokio::test]
+    async fn test_request_with_malformed_avp_is_answered() {
+        let mut bytes = request(5).serialize();
+        // A trailing AVP declaring more data than the message holds
+        bytes.extend_from_slice(&[0, 0, 1, 1, 0x40, 0, 0, 64]);
+        let length = (bytes.len() as u32).to_be_bytes();
+        bytes[1..4].copy_from_slice(&length[1..]);
+
+        let transport = MockTransport::new().with_read(bytes);
+        let written = transport.written();
+        let server = TcpServer::new("127.0.0.1:0".to_string(), Arc::new(TransactionStore::new()));
+        server.handle_connection(transport, 1).await.unwrap();
+
+        let answers = written_answers(&written.lock().unwrap());
+        assert_eq!(answers.len(), 1);
+        let answer = &answers[0];
+        assert_eq!(answer.header.hop_by_hop_id, 5);
+        assert!(!answer.header.is_request());
+        assert_eq!(answer.header.flags & FLAG_ERROR, 0);
+        assert_eq!(
+            answer.find_avp(AVP_RESULT_CODE).unwrap().data,
+            DEFAULT_MALFORMED_RESULT_CODE.to_be_bytes()
+        );
+        assert_eq!(
+            answer.find_avp(AVP_SESSION_ID).unwrap().data,
+            b"mme1;framing"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_oversized_message_closes_connection() {
+        // Only the header arrives; waiting for the body would hang
+        let header = cdde_core::DiameterHeader {
+            length: 0x00FF_FFFF,
+            ..request(1).header
+        }
+        .serialize();
+        let transport = MockTransport::new()
+            .with_read(header)
+            .with_read(vec![0; 64]);
+        let written = transport.written();
+        let server = TcpServer::new("127.0.0.1:0".to_string(), Arc::new(TransactionStore::new()));
+
+        let result = server.handle_connection(transport, 1).await;
+        assert!(matches!(
+            result,
+            Err(CddeError::MessageTooLarge {
+                declared: 0x00FF_FFFF,
+                limit: DEFAULT_MAX_MESSAGE_SIZE
+            })
+        ));
+        assert!(written.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_request_turned_away_by_actor_skips_dcr() {
+        use crate::actor::{SessionAction, SessionActor};
+        use cdde_test_support::MockDcr;
+
+        let dcr = MockDcr::echo();
+        let (dcr_addr, dcr_handle) = dcr.spawn().await;
+
+        // The actor takes no requests at all
+        let (actor_tx, actor_rx) = mpsc::channel(16);
+        let (action_tx, mut action_rx) = mpsc::channel(16);
+        tokio::spawn(
+            SessionActor::new(SessionConfig::default(), actor_rx, action_tx)
+                .with_max_pending_per_connection(0)
+                .run(),
+        );
+        let connections = Arc::new(ConnectionRegistry::new());
+        let outbound = connections.clone();
+        tokio::spawn(async move {
+            while let Some(SessionAction::Reply { conn_id, packet }) = action_rx.recv().await {
+                outbound.deliver(conn_id, packet);
+            }
+        });
+
+        let server = TcpServer::new("127.0.0.1:0".to_string(), Arc::new(TransactionStore::new()))
+            .with_dcr_endpoint(format!("http://{dcr_addr}"))
+            .with_session_actor(actor_tx, connections);
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut client = tokio::net::TcpStream::connect(listener.local_addr().unwrap())
+            .await
+            .unwrap();
+        let (socket, _) = listener.accept().await.unwrap();
+        let connection = tokio::spawn(async move { server.handle_connection(socket, 4).await });
+
+        client.write_all(&request(1).serialize()).await.unwrap();
+        let mut buffer = [0u8; 1024];
+        let n = tokio::time::timeout(Duration::from_secs(5), client.read(&mut buffer))
+            .await
+            .unwrap()
+            .unwrap();
+        let answer = DiameterPacket::parse(&buffer[..n]).unwrap();
+        assert_eq!(answer.header.hop_by_hop_id, 1);
+        assert_eq!(
+            answer.find_avp(AVP_RESULT_CODE).unwrap().data,
+            crate::answer::RESULT_TOO_BUSY.to_be_bytes()
+        );
+        assert_eq!(dcr.calls(), 0);
+
+        drop(client);
+        connection.await.unwrap().unwrap();
+        dcr_handle.abort();
+    }
+
+    #[tokio::test]
+    async fn test_half_open_probe_turned_away_by_actor_is_not_consumed() {
+        use cdde_test_support::MockDcr;
+
+        let dcr = MockDcr::echo();
+        let (dcr_addr, dcr_handle) = dcr.spawn().await;
+
+        // Actor stand-in turning the first request away and taking the rest
+        let (actor_tx, mut actor_rx) = mpsc::channel(16);
+        let connections = Arc::new(ConnectionRegistry::new());
+        let outbound = connections.clone();
+        tokio::spawn(async move {
+            let mut first = true;
+            while let Some(message) = actor_rx.recv().await {
+                match message {
+                    ActorMessage::IngressRequest { admitted, .. } => {
+                        let _ = admitted.send(!first);
+                        first = false;
+                    }
+                    ActorMessage::Answer { conn_id, packet } => {
+                        outbound.deliver(conn_id, packet);
+                    }
+                    ActorMessage::Flush { .. } => {}
+                }
+            }
+        });
+
+        let server = TcpServer::new("127.0.0.1:0".to_string(), Arc::new(TransactionStore::new()))
+            .with_dcr_endpoint(format!("http://{dcr_addr}"))
+            .with_breaker_config(BreakerConfig {
+                failure_threshold: 1,
+                cool_down: Duration::ZERO,
+            })
+            .with_session_actor(actor_tx, connections);
+        // The breaker is open and lets the next call through as a probe
+        server.breaker().record_failure();
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut client = tokio::net::TcpStream::connect(listener.local_addr().unwrap())
+            .await
+            .unwrap();
+        let (socket, _) = listener.accept().await.unwrap();
+        let connection = tokio::spawn(async move { server.handle_connection(socket, 4).await });
+
+        let mut requests = request(1).serialize();
+        requests.extend(request(2).serialize());
+        client.write_all(&requests).await.unwrap();
+        let mut buffer = [0u8; 1024];
+        let n = tokio::time::timeout(Duration::from_secs(5), client.read(&mut buffer))
+            .await
+            .unwrap()
+            .unwrap();
+
+        // The second request is the probe and reaches the DCR
+        let answer = DiameterPacket::parse(&buffer[..n]).unwrap();
+        assert_eq!(answer.header.hop_by_hop_id, 2);
+        assert_eq!(dcr.calls(), 1);
+
+        drop(client);
+        connection.await.unwrap().unwrap();
+        dcr_handle.abort();
+    }
+
+    #[tokio::test]
+    async fn test_hung_peer_holds_up_neither_the_connection_nor_its_answer() {
+        use cdde_test_support::{reply, MockDcr};
+
+        /// Peer that never answers
+        struct HungForwarder;
+
+        #[async_trait::async_trait]
+        impl PeerForwarder for HungForwarder {
+            async fn forward(&self, _peer: &str, _payload: Vec<u8>) -> Result<Vec<u8>> {
+                std::future::pending().await
+            }
+        }
+
+        // Request 1 is forwarded to the hung peer, request 2 answered by the DCR
+        let (dcr_addr, dcr_handle) = MockDcr::new(|request| {
+            let mut packet = DiameterPacket::parse(&request.raw_payload).unwrap();
+            if packet.header.hop_by_hop_id == 1 {
+                return Ok(cdde_proto::DiameterPacketAction {
+                    action_type: cdde_proto::ActionType::Forward as i32,
+                    target_host_name: "hss1.example.com".to_string(),
+                    ..reply(packet.serialize())
+                });
+            }
+            packet.header.flags = 0x40;
+            Ok(reply(packet.serialize()))
+        })
+        .spawn()
+        .await;
+
+        let server = TcpServer::new("127.0.0.1:0".to_string(), Arc::new(TransactionStore::new()))
+            .with_dcr_endpoint(format!("http://{dcr_addr}"))
+            .with_session_config(SessionConfig {
+                answer_timeout: Duration::from_millis(200),
+                ..Default::default()
+            })
+            .with_forwarder(Arc::new(HungForwarder));
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut client = tokio::net::TcpStream::connect(listener.local_addr().unwrap())
+            .await
+            .unwrap();
+        let (socket, _) = listener.accept().await.unwrap();
+        let connection = tokio::spawn(async move { server.handle_connection(socket, 3).await });
+
+        let mut requests = request(1).serialize();
+        requests.extend(request(2).serialize());
+        client.write_all(&requests).await.unwrap();
+
+        let mut frames = FrameAccumulator::new();
+        let mut answers = Vec::new();
+        let mut buffer = [0u8; 1024];
+        while answers.len() < 2 {
+            let n = tokio::time::timeout(Duration::from_secs(5), client.read(&mut buffer))
+                .await
+                .unwrap()
+                .unwrap();
+            assert!(n > 0, "connection closed before both answers");
+            frames.extend(&buffer[..n]);
+            while let Some(frame) = frames.next_frame().unwrap() {
+                answers.push(DiameterPacket::parse(&frame).unwrap());
+            }
+        }
+
+        // The second request is answered while the first waits on its peer
+        assert_eq!(answers[0].header.hop_by_hop_id, 2);
+        assert_eq!(answers[1].header.hop_by_hop_id, 1);
+        assert_eq!(
+            answers[1].find_avp(AVP_RESULT_CODE).unwrap().data,
+            RESULT_UNABLE_TO_DELIVER.to_be_bytes()
+        );
 
-        // This will process one packet and then "close" (read returns 0)
-        // We just want to ensure it doesn't panic
-        let _result = TcpServer::handle_connection(transport, store).await;
-        // It might return Ok or error depending on how the mock loop behaves with 0 read
-        // In our mock, poll_read puts data once. Next call?
-        // Actually our mock keeps putting data forever if we don't clear it.
-        // Let's improve mock if needed, but for now just checking compilation and basic structure.
+        drop(client);
+        connection.await.unwrap().unwrap();
+        dcr_handle.abort();
     }
 }
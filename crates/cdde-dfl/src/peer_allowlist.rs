@@ -0,0 +1,83 @@
+use cdde_core::cms::fetch_json;
+use cdde_core::Result;
+use serde::Deserialize;
+use std::collections::HashMap;
+
+/// Peers allowed to complete a capabilities exchange with the DFL
+///
+/// Diameter identities compare case-insensitively.
+#[derive(Debug, Clone, Default)]
+pub struct PeerAllowlist {
+    /// Origin-Host -> Origin-Realm
+    peers: HashMap<String, String>,
+}
+
+impl PeerAllowlist {
+    /// Build the allowlist from (Origin-Host, Origin-Realm) pairs
+    pub fn new<I, H, R>(peers: I) -> Self
+    where
+        I: IntoIterator<Item = (H, R)>,
+        H: AsRef<str>,
+        R: AsRef<str>,
+    {
+        Self {
+            peers: peers
+                .into_iter()
+                .map(|(host, realm)| {
+                    (
+                        host.as_ref().to_ascii_lowercase(),
+                        realm.as_ref().to_ascii_lowercase(),
+                    )
+                })
+                .collect(),
+        }
+    }
+
+    /// Check if a peer is known with this host and realm
+    pub fn allows(&self, origin_host: &str, origin_realm: &str) -> bool {
+        self.peers
+            .get(&origin_host.to_ascii_lowercase())
+            .is_some_and(|realm| realm.eq_ignore_ascii_case(origin_realm))
+    }
+
+    /// Number of known peers
+    pub fn len(&self) -> usize {
+        self.peers.len()
+    }
+
+    /// Check if no peer is known
+    pub fn is_empty(&self) -> bool {
+        self.peers.is_empty()
+    }
+}
+
+/// Fields of the CMS peer resource used by the DFL
+#[derive(Debug, Deserialize)]
+struct KnownPeer {
+    hostname: String,
+    realm: String,
+}
+
+/// Fetch the peers configured in the CMS
+pub async fn fetch_peer_allowlist(cms_url: &str) -> Result<PeerAllowlist> {
+    let peers: Vec<KnownPeer> = fetch_json(cms_url, "/api/v1/peers", "peer list").await?;
+
+    Ok(PeerAllowlist::new(
+        peers.into_iter().map(|peer| (peer.hostname, peer.realm)),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_allows_known_host_and_realm() {
+        let allowlist = PeerAllowlist::new([("mme1.example.com", "example.com")]);
+
+        assert!(allowlist.allows("mme1.example.com", "example.com"));
+        assert!(allowlist.allows("MME1.Example.com", "EXAMPLE.COM"));
+        assert!(!allowlist.allows("mme1.example.com", "other.net"));
+        assert!(!allowlist.allows("mme2.example.com", "example.com"));
+    }
+}
@@ -0,0 +1,116 @@
+use cdde_core::PeerHealthRegistry;
+use cdde_proto::routing_update_service_server::RoutingUpdateService;
+use cdde_proto::{PeerStatus, PeerStatusRequest, UpdateResponse};
+use std::sync::Arc;
+use tonic::{Request, Response, Status};
+use tracing::info;
+
+/// Receives peer status notifications from the DPA
+pub struct PeerStatusService {
+    health: Arc<PeerHealthRegistry>,
+}
+
+impl PeerStatusService {
+    /// Create a handler updating the given health registry
+    pub fn new(health: Arc<PeerHealthRegistry>) -> Self {
+        Self { health }
+    }
+}
+
+#[tonic::async_trait]
+impl RoutingUpdateService for PeerStatusService {
+    async fn update_peer_status(
+        &self,
+        request: Request<PeerStatusRequest>,
+    ) -> Result<Response<UpdateResponse>, Status> {
+        let request = request.into_inner();
+        let status = PeerStatus::try_from(request.current_status)
+            .map_err(|_| Status::invalid_argument("unknown peer status"))?;
+
+        info!(
+            peer = %request.peer_node_id,
+            status = ?status,
+            virtual_routers = ?request.virtual_router_ids,
+            "Peer status update"
+        );
+        self.health
+            .set_down(&request.peer_node_id, status == PeerStatus::Down);
+        self.health
+            .set_draining(&request.peer_node_id, status == PeerStatus::Draining);
+
+        Ok(Response::new(UpdateResponse {
+            success: true,
+            message: String::new(),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cdde_core::HealthThresholds;
+
+    fn request(status: PeerStatus) -> Request<PeerStatusRequest> {
+        Request::new(PeerStatusRequest {
+            peer_node_id: "hss01.example.com".to_string(),
+            current_status: status as i32,
+            virtual_router_ids: vec!["vr001".to_string()],
+        })
+    }
+
+    #[tokio::test]
+    async fn test_down_notification_marks_peer_down() {
+        let health = Arc::new(PeerHealthRegistry::new(HealthThresholds::default()));
+        let service = PeerStatusService::new(health.clone());
+
+        let response = service
+            .update_peer_status(request(PeerStatus::Down))
+            .await
+            .unwrap();
+        assert!(response.into_inner().success);
+        assert!(health.is_down("hss01.example.com"));
+
+        service
+            .update_peer_status(request(PeerStatus::Up))
+            .await
+            .unwrap();
+        assert!(!health.is_down("hss01.example.com"));
+    }
+
+    #[tokio::test]
+    async fn test_draining_notification_marks_peer_draining() {
+        let health = Arc::new(PeerHealthRegistry::new(HealthThresholds::default()));
+        let service = PeerStatusService::new(health.clone());
+
+        service
+            .update_peer_status(request(PeerStatus::Draining))
+            .await
+            .unwrap();
+        assert!(health.is_draining("hss01.example.com"));
+        assert!(!health.is_down("hss01.example.com"));
+
+        service
+            .update_peer_status(request(PeerStatus::Down))
+            .await
+            .unwrap();
+        assert!(!health.is_draining("hss01.example.com"));
+        assert!(health.is_down("hss01.example.com"));
+    }
+
+    #[tokio::test]
+    async fn test_unknown_status_is_rejected() {
+        let service = PeerStatusService::new(Arc::new(PeerHealthRegistry::new(
+            HealthThresholds::default(),
+        )));
+
+        let status = service
+            .update_peer_status(Request::new(PeerStatusRequest {
+                peer_node_id: "hss01.example.com".to_string(),
+                current_status: 42,
+                virtual_router_ids: vec![],
+            }))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::InvalidArgument);
+    }
+}
@@ -0,0 +1,92 @@
+//! Snapshots of pending transactions, kept across DFL restarts
+//!
+//! Client connections do not survive a restart, so the transactions that
+//! were waiting on them cannot be answered; they are re-armed so that each
+//! one is reported when its deadline passes instead of being forgotten.
+
+use cdde_core::{CddeError, Result};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Pending transaction as written to a snapshot
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PersistedTransaction {
+    pub connection_id: u64,
+    pub hop_by_hop_id: u32,
+    pub command_code: u32,
+    pub end_to_end_id: u32,
+    pub session_id: String,
+
+    /// Wall-clock deadline, in milliseconds since the Unix epoch
+    pub deadline_unix_ms: u64,
+}
+
+impl PersistedTransaction {
+    /// Convert a monotonic deadline to wall-clock time
+    pub fn deadline_to_unix_ms(deadline: Instant) -> u64 {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        (SystemTime::now() + remaining)
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64
+    }
+
+    /// Time left until the deadline, zero once it has passed
+    pub fn remaining(&self) -> Duration {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64;
+        Duration::from_millis(self.deadline_unix_ms.saturating_sub(now))
+    }
+}
+
+/// Write a snapshot, replacing the previous one atomically
+pub fn save_snapshot(path: &Path, entries: &[PersistedTransaction]) -> Result<()> {
+    let json = serde_json::to_vec(entries)
+        .map_err(|e| CddeError::InternalError(format!("Failed to encode snapshot: {e}")))?;
+    let tmp = path.with_extension("tmp");
+    std::fs::write(&tmp, json)?;
+    std::fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+/// Read a snapshot; a missing file means nothing was pending
+pub fn load_snapshot(path: &Path) -> Result<Vec<PersistedTransaction>> {
+    let json = match std::fs::read(path) {
+        Ok(json) => json,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(e.into()),
+    };
+    serde_json::from_slice(&json)
+        .map_err(|e| CddeError::ConfigError(format!("Invalid transaction snapshot: {e}")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_snapshot_round_trip() {
+        let path = std::env::temp_dir().join(format!("cdde-dfl-snapshot-{}", std::process::id()));
+        assert!(load_snapshot(&path).unwrap().is_empty());
+
+        let entries = vec![PersistedTransaction {
+            connection_id: 7,
+            hop_by_hop_id: 42,
+            command_code: 318,
+            end_to_end_id: 99,
+            session_id: "s1".to_string(),
+            deadline_unix_ms: PersistedTransaction::deadline_to_unix_ms(
+                Instant::now() + Duration::from_secs(10),
+            ),
+        }];
+        save_snapshot(&path, &entries).unwrap();
+        let loaded = load_snapshot(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded, entries);
+        assert!(loaded[0].remaining() > Duration::from_secs(9));
+    }
+}
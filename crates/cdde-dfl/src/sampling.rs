@@ -0,0 +1,60 @@
+/// Picks the transactions traced verbosely end-to-end
+///
+/// Sampled transactions get a trace id, which is handed to the DCR in the
+/// `trace_id` field so both sides log them in full.
+#[derive(Debug, Clone, Default)]
+pub struct TraceSampler {
+    rate: f64,
+}
+
+impl TraceSampler {
+    /// Sample the given fraction of transactions, clamped to `0.0..=1.0`
+    pub fn new(rate: f64) -> Self {
+        let rate = if rate.is_nan() {
+            0.0
+        } else {
+            rate.clamp(0.0, 1.0)
+        };
+        Self { rate }
+    }
+
+    /// Fraction of transactions sampled
+    pub fn rate(&self) -> f64 {
+        self.rate
+    }
+
+    /// Trace id for a sampled transaction, `None` for the rest
+    pub fn sample(&self) -> Option<String> {
+        if self.rate > 0.0 && rand::random::<f64>() < self.rate {
+            Some(format!("{:016x}", rand::random::<u64>()))
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sample_everything_or_nothing() {
+        let always = TraceSampler::new(1.0);
+        for _ in 0..1000 {
+            let trace_id = always.sample().expect("Message not sampled at rate 1.0");
+            assert_eq!(trace_id.len(), 16);
+        }
+
+        let never = TraceSampler::new(0.0);
+        assert!((0..1000).all(|_| never.sample().is_none()));
+        assert!(TraceSampler::default().sample().is_none());
+    }
+
+    #[test]
+    fn test_rate_is_clamped() {
+        assert_eq!(TraceSampler::new(5.0).rate(), 1.0);
+        assert_eq!(TraceSampler::new(-1.0).rate(), 0.0);
+        assert_eq!(TraceSampler::new(f64::NAN).rate(), 0.0);
+        assert_eq!(TraceSampler::new(0.001).rate(), 0.001);
+    }
+}
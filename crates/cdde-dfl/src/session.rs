@@ -1,6 +1,57 @@
-use std::time::Instant;
+use cdde_core::codes::{
+    AUTH_SESSION_NO_STATE_MAINTAINED, AVP_AUTH_SESSION_STATE, CMD_SESSION_TERMINATION,
+};
+use cdde_core::DiameterPacket;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
 use tokio_util::time::delay_queue::Key;
 
+/// Timeouts applied to transactions handled by the DFL
+#[derive(Debug, Clone)]
+pub struct SessionConfig {
+    /// Overall lifetime of a transaction in the session store
+    pub timeout_duration: Duration,
+
+    /// Time allowed for the first answer before replying UNABLE_TO_DELIVER
+    pub answer_timeout: Duration,
+
+    /// Answers arriving later than this are logged as slow
+    pub slow_threshold: Duration,
+
+    /// Answer timeouts configured per Virtual Router in the CMS
+    pub vr_answer_timeouts: HashMap<String, Duration>,
+}
+
+impl SessionConfig {
+    /// Answer timeout, never longer than the overall session timeout
+    pub fn effective_answer_timeout(&self) -> Duration {
+        self.answer_timeout.min(self.timeout_duration)
+    }
+
+    /// Answer timeout for a request of the given Virtual Router
+    ///
+    /// Untagged requests and unknown Virtual Routers use the default answer
+    /// timeout; either way it is capped by the session timeout.
+    pub fn answer_timeout_for(&self, vr_id: Option<&str>) -> Duration {
+        vr_id
+            .and_then(|vr_id| self.vr_answer_timeouts.get(vr_id))
+            .copied()
+            .unwrap_or(self.answer_timeout)
+            .min(self.timeout_duration)
+    }
+}
+
+impl Default for SessionConfig {
+    fn default() -> Self {
+        Self {
+            timeout_duration: Duration::from_secs(30),
+            answer_timeout: Duration::from_secs(5),
+            slow_threshold: Duration::from_secs(1),
+            vr_answer_timeouts: HashMap::new(),
+        }
+    }
+}
+
 /// Transaction context for session management
 #[derive(Debug, Clone)]
 pub struct TransactionContext {
@@ -21,6 +72,9 @@ pub struct TransactionContext {
 
     /// Ingress timestamp
     pub ingress_timestamp: Instant,
+
+    /// When the transaction times out, if it is timed out at all
+    pub deadline: Option<Instant>,
 }
 
 impl TransactionContext {
@@ -39,19 +93,46 @@ impl TransactionContext {
             original_end_to_end_id: end_to_end_id,
             session_id,
             ingress_timestamp: Instant::now(),
+            deadline: None,
         }
     }
 
+    /// Set when the transaction times out
+    pub fn with_deadline(mut self, deadline: Instant) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
     /// Calculate elapsed time since ingress
     pub fn elapsed(&self) -> std::time::Duration {
         self.ingress_timestamp.elapsed()
     }
 }
 
+/// Check if an answer ends its session
+///
+/// A Session-Termination-Answer always tears the session down; a session
+/// without maintained state (Auth-Session-State = NO_STATE_MAINTAINED) ends
+/// with its answer.
+pub fn ends_session(packet: &DiameterPacket) -> bool {
+    if packet.header.is_request() {
+        return false;
+    }
+
+    if packet.header.command_code == CMD_SESSION_TERMINATION {
+        return true;
+    }
+
+    packet
+        .find_avp(AVP_AUTH_SESSION_STATE)
+        .and_then(|avp| avp.data.as_slice().try_into().ok())
+        .map(u32::from_be_bytes)
+        == Some(AUTH_SESSION_NO_STATE_MAINTAINED)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::time::Duration;
     use tokio_util::time::DelayQueue;
 
     #[tokio::test]
@@ -77,4 +158,85 @@ mod tests {
         tokio::time::sleep(Duration::from_millis(10)).await;
         assert!(ctx.elapsed() >= Duration::from_millis(10));
     }
+
+    #[test]
+    fn test_answer_timeout_bounded_by_session_timeout() {
+        let config = SessionConfig {
+            timeout_duration: Duration::from_secs(1),
+            answer_timeout: Duration::from_secs(5),
+            slow_threshold: Duration::from_secs(1),
+            ..Default::default()
+        };
+        assert_eq!(config.effective_answer_timeout(), Duration::from_secs(1));
+
+        let config = SessionConfig::default();
+        assert_eq!(config.effective_answer_timeout(), Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_answer_timeout_for_virtual_router() {
+        let config = SessionConfig {
+            timeout_duration: Duration::from_secs(10),
+            vr_answer_timeouts: HashMap::from([
+                ("vr001".to_string(), Duration::from_secs(2)),
+                ("vr002".to_string(), Duration::from_secs(60)),
+            ]),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            config.answer_timeout_for(Some("vr001")),
+            Duration::from_secs(2)
+        );
+        assert_eq!(
+            config.answer_timeout_for(Some("vr002")),
+            Duration::from_secs(10)
+        );
+        assert_eq!(
+            config.answer_timeout_for(Some("vr999")),
+            Duration::from_secs(5)
+        );
+        assert_eq!(config.answer_timeout_for(None), Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_ends_session() {
+        use cdde_core::{DiameterAvp, DiameterHeader};
+
+        let packet = |flags: u8, command_code: u32, avps: Vec<DiameterAvp>| DiameterPacket {
+            header: DiameterHeader {
+                version: 1,
+                length: 20,
+                flags,
+                command_code,
+                application_id: 4,
+                hop_by_hop_id: 1,
+                end_to_end_id: 1,
+            },
+            avps,
+        };
+        let auth_session_state = |value: u32| DiameterAvp {
+            code: AVP_AUTH_SESSION_STATE,
+            flags: 0x40,
+            vendor_id: None,
+            data: value.to_be_bytes().to_vec(),
+        };
+
+        // STA ends the session, STR does not
+        assert!(ends_session(&packet(0x00, 275, vec![])));
+        assert!(!ends_session(&packet(0x80, 275, vec![])));
+
+        // Stateless sessions end with their answer
+        assert!(ends_session(&packet(
+            0x00,
+            272,
+            vec![auth_session_state(AUTH_SESSION_NO_STATE_MAINTAINED)]
+        )));
+        assert!(!ends_session(&packet(
+            0x00,
+            272,
+            vec![auth_session_state(0)]
+        )));
+        assert!(!ends_session(&packet(0x00, 272, vec![])));
+    }
 }
@@ -0,0 +1,159 @@
+use crate::store::TransactionStore;
+use dashmap::DashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::{debug, info};
+
+/// Settings of the idle session sweep
+#[derive(Debug, Clone, Copy)]
+pub struct SessionSweepConfig {
+    /// Sessions older than this are dropped
+    pub max_age: Duration,
+
+    /// How often sessions are checked
+    pub interval: Duration,
+}
+
+impl Default for SessionSweepConfig {
+    fn default() -> Self {
+        Self {
+            max_age: Duration::from_secs(24 * 3600),
+            interval: Duration::from_secs(60),
+        }
+    }
+}
+
+/// Application sessions seen by the DFL, by Session-Id
+///
+/// A session is tracked from its first request until a session
+/// termination ends it. Sessions whose client never terminates them are
+/// dropped by the sweep once they reach the maximum age.
+#[derive(Debug, Default)]
+pub struct SessionRegistry {
+    started: DashMap<String, Instant>,
+}
+
+impl SessionRegistry {
+    /// Create an empty registry
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Note a request of a session, tracking it from `now` if it is new
+    pub fn touch(&self, session_id: &str, now: Instant) {
+        if !self.started.contains_key(session_id) {
+            self.started.insert(session_id.to_string(), now);
+        }
+    }
+
+    /// Stop tracking a session, returning whether it was tracked
+    pub fn end(&self, session_id: &str) -> bool {
+        self.started.remove(session_id).is_some()
+    }
+
+    /// Number of tracked sessions
+    pub fn len(&self) -> usize {
+        self.started.len()
+    }
+
+    /// Check if no session is tracked
+    pub fn is_empty(&self) -> bool {
+        self.started.is_empty()
+    }
+
+    /// Stop tracking sessions started more than `max_age` before `now`
+    ///
+    /// Returns the Session-Ids of the expired sessions.
+    pub fn expire(&self, now: Instant, max_age: Duration) -> Vec<String> {
+        let expired: Vec<String> = self
+            .started
+            .iter()
+            .filter(|entry| now.saturating_duration_since(*entry.value()) > max_age)
+            .map(|entry| entry.key().clone())
+            .collect();
+        for session_id in &expired {
+            self.started.remove(session_id);
+        }
+        expired
+    }
+}
+
+/// Drop the sessions older than `max_age` at `now`, with their pending
+/// transactions
+///
+/// No STR is sent on behalf of the client; only the DFL's state is cleaned.
+/// Returns the number of sessions dropped.
+pub async fn sweep_sessions(
+    sessions: &SessionRegistry,
+    store: &TransactionStore,
+    max_age: Duration,
+    now: Instant,
+) -> usize {
+    let expired = sessions.expire(now, max_age);
+    for session_id in &expired {
+        let removed = store.remove_session(session_id).await;
+        debug!(
+            "Session {} exceeded {:?}, dropped with {} pending transactions",
+            session_id, max_age, removed
+        );
+    }
+    cdde_metrics::SESSIONS_SWEPT_TOTAL.inc_by(expired.len() as f64);
+    expired.len()
+}
+
+/// Sweep sessions periodically, forever
+pub async fn run_session_sweep(
+    sessions: Arc<SessionRegistry>,
+    store: Arc<TransactionStore>,
+    config: SessionSweepConfig,
+) {
+    let mut ticker = tokio::time::interval(config.interval);
+    loop {
+        ticker.tick().await;
+        let swept = sweep_sessions(&sessions, &store, config.max_age, Instant::now()).await;
+        if swept > 0 {
+            info!("Dropped {} sessions older than {:?}", swept, config.max_age);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_session_older_than_max_age_is_swept() {
+        let sessions = SessionRegistry::new();
+        let store = TransactionStore::new();
+        let start = Instant::now();
+        let max_age = Duration::from_secs(3600);
+
+        sessions.touch("mme1;old", start);
+        store
+            .insert(
+                1,
+                10,
+                272,
+                100,
+                "mme1;old".to_string(),
+                Duration::from_secs(30),
+            )
+            .await;
+        sessions.touch("mme1;new", start + Duration::from_secs(1800));
+        // Later requests do not make a session younger
+        sessions.touch("mme1;old", start + Duration::from_secs(3000));
+
+        let before = cdde_metrics::SESSIONS_SWEPT_TOTAL.get();
+        let later = start + Duration::from_secs(3601);
+        assert_eq!(sweep_sessions(&sessions, &store, max_age, later).await, 1);
+
+        assert_eq!(sessions.len(), 1);
+        assert!(!sessions.end("mme1;old"));
+        assert!(store.is_empty());
+        assert!(cdde_metrics::SESSIONS_SWEPT_TOTAL.get() > before);
+
+        // Terminated sessions are no longer tracked
+        assert!(sessions.end("mme1;new"));
+        assert!(sessions.is_empty());
+    }
+}
@@ -1,9 +1,10 @@
 use dashmap::DashMap;
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use tokio_stream::StreamExt;
 use tokio_util::time::{delay_queue::Key, DelayQueue};
 
+use crate::persistence::PersistedTransaction;
 use crate::session::TransactionContext;
 
 /// Transaction store using DashMap for concurrent access
@@ -48,7 +49,8 @@ impl TransactionStore {
             command_code,
             end_to_end_id,
             session_id,
-        );
+        )
+        .with_deadline(Instant::now() + timeout);
 
         // Store in map
         self.store.insert(key, context);
@@ -75,6 +77,26 @@ impl TransactionStore {
         }
     }
 
+    /// Remove every transaction belonging to a session
+    ///
+    /// Returns the number of transactions removed.
+    pub async fn remove_session(&self, session_id: &str) -> usize {
+        let keys: Vec<(u64, u32)> = self
+            .store
+            .iter()
+            .filter(|entry| entry.session_id == session_id)
+            .map(|entry| *entry.key())
+            .collect();
+
+        let mut removed = 0;
+        for (connection_id, hop_by_hop_id) in keys {
+            if self.remove(connection_id, hop_by_hop_id).await.is_some() {
+                removed += 1;
+            }
+        }
+        removed
+    }
+
     /// Get transaction without removing
     pub fn get(&self, connection_id: u64, hop_by_hop_id: u32) -> Option<TransactionContext> {
         let key = (connection_id, hop_by_hop_id);
@@ -92,9 +114,55 @@ impl TransactionStore {
     }
 
     /// Wait for next timeout
+    ///
+    /// The expired transaction is dropped from the store.
     pub async fn next_timeout(&self) -> Option<(u64, u32)> {
         let mut delay_queue = self.delay_queue.lock().await;
-        delay_queue.next().await.map(|expired| expired.into_inner())
+        let key = delay_queue
+            .next()
+            .await
+            .map(|expired| expired.into_inner())?;
+        self.store.remove(&key);
+        Some(key)
+    }
+
+    /// Pending transactions in a form that can be persisted
+    pub fn snapshot(&self) -> Vec<PersistedTransaction> {
+        self.store
+            .iter()
+            .filter_map(|entry| {
+                let (connection_id, hop_by_hop_id) = *entry.key();
+                Some(PersistedTransaction {
+                    connection_id,
+                    hop_by_hop_id,
+                    command_code: entry.original_command_code,
+                    end_to_end_id: entry.original_end_to_end_id,
+                    session_id: entry.session_id.clone(),
+                    deadline_unix_ms: PersistedTransaction::deadline_to_unix_ms(entry.deadline?),
+                })
+            })
+            .collect()
+    }
+
+    /// Rebuild a store from persisted transactions
+    ///
+    /// Each transaction times out at its original deadline; those already
+    /// past it expire immediately.
+    pub async fn restore(entries: Vec<PersistedTransaction>) -> Self {
+        let store = Self::new();
+        for entry in entries {
+            store
+                .insert(
+                    entry.connection_id,
+                    entry.hop_by_hop_id,
+                    entry.command_code,
+                    entry.end_to_end_id,
+                    entry.session_id.clone(),
+                    entry.remaining(),
+                )
+                .await;
+        }
+        store
     }
 }
 
@@ -169,4 +237,55 @@ mod tests {
         let expired = store.next_timeout().await.unwrap();
         assert_eq!(expired, (123, 456));
     }
+
+    #[tokio::test]
+    async fn test_remove_session() {
+        let store = TransactionStore::new();
+        let timeout = Duration::from_secs(5);
+
+        store.insert(1, 10, 275, 1, "s1".to_string(), timeout).await;
+        store.insert(2, 20, 272, 2, "s1".to_string(), timeout).await;
+        store.insert(1, 30, 272, 3, "s2".to_string(), timeout).await;
+
+        assert_eq!(store.remove_session("s1").await, 2);
+        assert_eq!(store.len(), 1);
+        assert!(store.get(1, 30).is_some());
+        assert_eq!(store.remove_session("s1").await, 0);
+    }
+
+    #[tokio::test]
+    async fn test_restore_rearms_timeouts() {
+        let store = TransactionStore::new();
+        store
+            .insert(
+                1,
+                10,
+                318,
+                100,
+                "s1".to_string(),
+                Duration::from_millis(100),
+            )
+            .await;
+        store
+            .insert(2, 20, 316, 200, "s2".to_string(), Duration::from_secs(30))
+            .await;
+
+        let mut snapshot = store.snapshot();
+        snapshot.sort_by_key(|entry| entry.connection_id);
+        assert_eq!(snapshot.len(), 2);
+        assert_eq!(snapshot[0].hop_by_hop_id, 10);
+        assert_eq!(snapshot[1].session_id, "s2");
+
+        let restored = TransactionStore::restore(snapshot).await;
+        assert_eq!(restored.len(), 2);
+        assert_eq!(restored.get(2, 20).unwrap().original_end_to_end_id, 200);
+
+        // The short transaction keeps its deadline and expires first
+        let expired = tokio::time::timeout(Duration::from_secs(2), restored.next_timeout())
+            .await
+            .expect("Restored transaction did not time out");
+        assert_eq!(expired, Some((1, 10)));
+        assert!(restored.get(1, 10).is_none());
+        assert_eq!(restored.len(), 1);
+    }
 }
@@ -0,0 +1,66 @@
+use cdde_core::cms::fetch_json;
+use cdde_core::Result;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::time::Duration;
+use tracing::warn;
+
+/// Fields of the CMS Virtual Router resource used by the DFL
+#[derive(Debug, Deserialize)]
+struct VirtualRouterTimeout {
+    id: String,
+    timeout_ms: i32,
+}
+
+/// Fetch the answer timeout of every Virtual Router configured in the CMS
+///
+/// Virtual Routers with a non-positive timeout are skipped so they fall back
+/// to the default answer timeout.
+pub async fn fetch_vr_timeouts(cms_url: &str) -> Result<HashMap<String, Duration>> {
+    let vrs: Vec<VirtualRouterTimeout> =
+        fetch_json(cms_url, "/api/v1/vrs", "Virtual Router list").await?;
+
+    Ok(vrs
+        .into_iter()
+        .filter_map(|vr| match u64::try_from(vr.timeout_ms) {
+            Ok(ms) if ms > 0 => Some((vr.id, Duration::from_millis(ms))),
+            _ => {
+                warn!(
+                    "Ignoring timeout {}ms of Virtual Router {}",
+                    vr.timeout_ms, vr.id
+                );
+                None
+            }
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cdde_test_support::MockCms;
+
+    #[tokio::test]
+    async fn test_fetch_vr_timeouts() {
+        let (url, _) = MockCms::new()
+            .with_json(
+                serde_json::json!([
+                    {"id": "vr001", "hostname": "dfl1", "realm": "example.com", "timeout_ms": 2000},
+                    {"id": "vr002", "hostname": "dfl2", "realm": "example.com", "timeout_ms": 500},
+                    {"id": "vr003", "hostname": "dfl3", "realm": "example.com", "timeout_ms": 0}
+                ])
+                .to_string(),
+            )
+            .spawn()
+            .await;
+
+        let timeouts = fetch_vr_timeouts(&url).await.unwrap();
+        assert_eq!(
+            timeouts,
+            HashMap::from([
+                ("vr001".to_string(), Duration::from_millis(2000)),
+                ("vr002".to_string(), Duration::from_millis(500)),
+            ])
+        );
+    }
+}
@@ -0,0 +1,60 @@
+//! Encoding of the Diameter `Address` data type (RFC 6733 section 4.3.1)
+//!
+//! A two-octet IANA address family followed by the address itself.
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+/// IANA address family number for IPv4
+pub const ADDRESS_FAMILY_IPV4: u16 = 1;
+
+/// IANA address family number for IPv6
+pub const ADDRESS_FAMILY_IPV6: u16 = 2;
+
+/// Encode an IP address as an `Address` AVP payload
+pub fn encode_address(ip: IpAddr) -> Vec<u8> {
+    match ip {
+        IpAddr::V4(ip) => [&ADDRESS_FAMILY_IPV4.to_be_bytes()[..], &ip.octets()].concat(),
+        IpAddr::V6(ip) => [&ADDRESS_FAMILY_IPV6.to_be_bytes()[..], &ip.octets()].concat(),
+    }
+}
+
+/// Decode an `Address` AVP payload holding an IP address
+///
+/// Returns `None` for other address families or a truncated payload.
+pub fn decode_address(data: &[u8]) -> Option<IpAddr> {
+    let (family, address) = data.split_first_chunk::<2>()?;
+    match u16::from_be_bytes(*family) {
+        ADDRESS_FAMILY_IPV4 => {
+            let octets: [u8; 4] = address.try_into().ok()?;
+            Some(IpAddr::V4(Ipv4Addr::from(octets)))
+        }
+        ADDRESS_FAMILY_IPV6 => {
+            let octets: [u8; 16] = address.try_into().ok()?;
+            Some(IpAddr::V6(Ipv6Addr::from(octets)))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_address_round_trip() {
+        let v4: IpAddr = "192.0.2.1".parse().unwrap();
+        assert_eq!(encode_address(v4), vec![0, 1, 192, 0, 2, 1]);
+        assert_eq!(decode_address(&encode_address(v4)), Some(v4));
+
+        let v6: IpAddr = "2001:db8::1".parse().unwrap();
+        assert_eq!(encode_address(v6).len(), 18);
+        assert_eq!(decode_address(&encode_address(v6)), Some(v6));
+    }
+
+    #[test]
+    fn test_decode_rejects_malformed_address() {
+        assert_eq!(decode_address(&[0, 1, 127, 0, 0]), None);
+        assert_eq!(decode_address(&[0, 8, 1, 2, 3, 4]), None);
+        assert_eq!(decode_address(&[0]), None);
+    }
+}
@@ -0,0 +1,52 @@
+//! Validate a dictionary XML file before uploading it to the CMS
+//!
+//! Usage: dict-validate <dictionary.xml>
+
+use cdde_diameter_dict::validate::validate_file;
+use cdde_diameter_dict::DictionaryManager;
+use std::process::ExitCode;
+
+fn main() -> ExitCode {
+    let Some(path) = std::env::args().nth(1) else {
+        eprintln!("Usage: dict-validate <dictionary.xml>");
+        return ExitCode::from(2);
+    };
+
+    let report = match validate_file(&path) {
+        Ok(report) => report,
+        Err(e) => {
+            eprintln!("error: {e}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    for avp in &report.avps {
+        let vendor = avp
+            .vendor_id
+            .map(|id| format!(" vendor {id}"))
+            .unwrap_or_default();
+        println!(
+            "{:>8} {} ({:?}){}",
+            avp.code, avp.name, avp.data_type, vendor
+        );
+    }
+    println!("{} AVPs parsed", report.avps.len());
+
+    for code in &report.duplicate_codes {
+        eprintln!("error: AVP code {code} is defined more than once");
+    }
+    for (name, data_type) in &report.unknown_types {
+        eprintln!("error: {name} has unknown data type {data_type}");
+    }
+    if !report.is_valid() {
+        return ExitCode::FAILURE;
+    }
+
+    // Make sure the file also loads the way the services will load it
+    if let Err(e) = DictionaryManager::new().load_from_file(&path) {
+        eprintln!("error: {e}");
+        return ExitCode::FAILURE;
+    }
+
+    ExitCode::SUCCESS
+}
@@ -1,3 +1,6 @@
+use crate::address::{decode_address, encode_address};
+use std::fmt::Write;
+use std::net::IpAddr;
 use thiserror::Error;
 
 /// AVP data type enumeration
@@ -57,6 +60,71 @@ pub enum ParseError {
 }
 
 impl AvpDataType {
+    /// Check if values of this type are numbers
+    pub fn is_numeric(&self) -> bool {
+        matches!(
+            self,
+            Self::Unsigned32
+                | Self::Unsigned64
+                | Self::Integer32
+                | Self::Integer64
+                | Self::Float32
+                | Self::Float64
+                | Self::Enumerated
+                | Self::Time
+        )
+    }
+
+    /// Encode a textual value into raw bytes according to data type
+    ///
+    /// Numeric types are parsed and encoded big-endian, Address takes an IP
+    /// address or hex, OctetString and Grouped take hex; all other types
+    /// carry the text as-is. This is the inverse of the `AvpValue` display.
+    pub fn encode(&self, value: &str) -> Result<Vec<u8>, ParseError> {
+        let value_trimmed = value.trim();
+
+        match self {
+            Self::Unsigned32 | Self::Time => Ok(value_trimmed
+                .parse::<u32>()
+                .map_err(invalid(*self, value))?
+                .to_be_bytes()
+                .to_vec()),
+            Self::Unsigned64 => Ok(value_trimmed
+                .parse::<u64>()
+                .map_err(invalid(*self, value))?
+                .to_be_bytes()
+                .to_vec()),
+            Self::Integer32 | Self::Enumerated => Ok(value_trimmed
+                .parse::<i32>()
+                .map_err(invalid(*self, value))?
+                .to_be_bytes()
+                .to_vec()),
+            Self::Integer64 => Ok(value_trimmed
+                .parse::<i64>()
+                .map_err(invalid(*self, value))?
+                .to_be_bytes()
+                .to_vec()),
+            Self::Float32 => Ok(value_trimmed
+                .parse::<f32>()
+                .map_err(invalid(*self, value))?
+                .to_be_bytes()
+                .to_vec()),
+            Self::Float64 => Ok(value_trimmed
+                .parse::<f64>()
+                .map_err(invalid(*self, value))?
+                .to_be_bytes()
+                .to_vec()),
+            Self::Address => match value_trimmed.parse::<IpAddr>() {
+                Ok(ip) => Ok(encode_address(ip)),
+                Err(_) => decode_hex(value_trimmed).map_err(invalid(*self, value)),
+            },
+            Self::OctetString | Self::Grouped => {
+                decode_hex(value_trimmed).map_err(invalid(*self, value))
+            }
+            _ => Ok(value.as_bytes().to_vec()),
+        }
+    }
+
     /// Parse raw bytes into AvpValue according to data type
     pub fn parse(&self, data: &[u8]) -> Result<AvpValue, ParseError> {
         match self {
@@ -153,6 +221,55 @@ impl AvpDataType {
     }
 }
 
+/// Error for a value that does not parse as `data_type`
+fn invalid<E>(data_type: AvpDataType, value: &str) -> impl FnOnce(E) -> ParseError + '_ {
+    move |_| ParseError::ParseError(format!("Invalid {data_type:?} value: {value}"))
+}
+
+/// Lowercase hex digits of `bytes`
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::new(), |mut hex, byte| {
+        let _ = write!(hex, "{byte:02x}");
+        hex
+    })
+}
+
+/// Bytes of a string of hex digit pairs
+fn decode_hex(hex: &str) -> Result<Vec<u8>, ()> {
+    if !hex.len().is_multiple_of(2) || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(());
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).map_err(|_| ()))
+        .collect()
+}
+
+impl std::fmt::Display for AvpValue {
+    /// Numbers in decimal, text as-is, IP addresses in their usual notation
+    /// and other binary values as hex, so `AvpDataType::encode` gets the
+    /// same bytes back
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Utf8String(s) | Self::DiameterIdentity(s) | Self::DiameterUri(s) => {
+                write!(f, "{s}")
+            }
+            Self::Unsigned32(v) | Self::Time(v) => write!(f, "{v}"),
+            Self::Unsigned64(v) => write!(f, "{v}"),
+            Self::Integer32(v) | Self::Enumerated(v) => write!(f, "{v}"),
+            Self::Integer64(v) => write!(f, "{v}"),
+            Self::Float32(v) => write!(f, "{v}"),
+            Self::Float64(v) => write!(f, "{v}"),
+            Self::Address(bytes) => match decode_address(bytes) {
+                Some(ip) => write!(f, "{ip}"),
+                None => write!(f, "{}", encode_hex(bytes)),
+            },
+            Self::OctetString(bytes) | Self::Grouped(bytes) => write!(f, "{}", encode_hex(bytes)),
+            Self::IpFilterRule(bytes) => write!(f, "{}", String::from_utf8_lossy(bytes)),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -194,4 +311,61 @@ mod tests {
 
         assert!(result.is_err());
     }
+
+    #[test]
+    fn test_encode_round_trip() {
+        let data = AvpDataType::Unsigned32.encode("2001").unwrap();
+        assert_eq!(data, vec![0x00, 0x00, 0x07, 0xD1]);
+
+        let value = AvpDataType::Unsigned32.parse(&data).unwrap();
+        assert_eq!(value.to_string(), "2001");
+
+        let data = AvpDataType::Integer32.encode("-1").unwrap();
+        assert_eq!(
+            AvpDataType::Integer32.parse(&data).unwrap().to_string(),
+            "-1"
+        );
+
+        assert_eq!(
+            AvpDataType::DiameterIdentity.encode("host").unwrap(),
+            b"host"
+        );
+        assert!(AvpDataType::Unsigned32.encode("abc").is_err());
+        assert!(AvpDataType::Float64.encode("abc").is_err());
+    }
+
+    #[test]
+    fn test_binary_values_round_trip() {
+        for (data_type, data) in [
+            (AvpDataType::Address, vec![0, 1, 192, 0, 2, 1]),
+            (
+                AvpDataType::Address,
+                encode_address("2001:db8::1".parse().unwrap()),
+            ),
+            // Address family other than IPv4 and IPv6
+            (AvpDataType::Address, vec![0, 8, 0xAB, 0xCD]),
+            (AvpDataType::OctetString, vec![0x12, 0xF4, 0x56, 0xFF]),
+            (
+                AvpDataType::Grouped,
+                vec![0, 0, 1, 10, 0x40, 0, 0, 12, 0, 0, 0, 1],
+            ),
+        ] {
+            let text = data_type.parse(&data).unwrap().to_string();
+            assert_eq!(
+                data_type.encode(&text).unwrap(),
+                data,
+                "{data_type:?} {text}"
+            );
+        }
+
+        let address = AvpDataType::Address.parse(&[0, 1, 192, 0, 2, 1]).unwrap();
+        assert_eq!(address.to_string(), "192.0.2.1");
+        let octets = AvpDataType::OctetString.parse(&[0x12, 0xF4]).unwrap();
+        assert_eq!(octets.to_string(), "12f4");
+
+        assert!(AvpDataType::Address.encode("not-an-address").is_err());
+        assert!(AvpDataType::OctetString.encode("12f").is_err());
+        assert!(AvpDataType::OctetString.encode("zz").is_err());
+        assert!(AvpDataType::OctetString.encode("+f").is_err());
+    }
 }
@@ -1,9 +1,12 @@
 // Diameter dictionary module
+pub mod address;
 pub mod data_type;
 pub mod manager;
 pub mod standard;
+pub mod validate;
 
 // Re-export commonly used types
 pub use data_type::{AvpDataType, AvpValue, ParseError};
-pub use manager::{AvpInfo, DictionaryManager};
+pub use manager::{AvpInfo, DictionaryManager, EvictionPolicy};
 pub use standard::StandardAvpCode;
+pub use validate::{validate_dictionary, DictionaryReport};
@@ -8,44 +8,158 @@ pub struct AvpInfo {
     pub name: String,
     pub data_type: AvpDataType,
     pub vendor_id: Option<u32>,
+    /// Symbolic names of Enumerated values, e.g. "INITIAL_REQUEST" => 1
+    pub enum_values: HashMap<String, i32>,
 }
 
 use quick_xml::de::from_str;
 use serde::Deserialize;
-use std::collections::HashMap;
-use std::sync::RwLock;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+use tracing::warn;
+
+/// What happens when loading a dictionary would exceed the entry limit
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EvictionPolicy {
+    /// Refuse the dictionary, keeping the loaded AVPs
+    Reject,
+    /// Remove the earliest loaded AVPs to make room
+    EvictOldest,
+}
+
+/// Dynamic AVP definition and the dictionary it was loaded from
+#[derive(Debug)]
+struct DynamicAvp {
+    info: AvpInfo,
+    /// Id given to `load_dictionary`, `None` for anonymous loads
+    source: Option<String>,
+    /// Load sequence number, lower is older
+    loaded: u64,
+}
+
+#[derive(Debug, Default)]
+struct DynamicAvps {
+    entries: HashMap<u32, DynamicAvp>,
+    next_load: u64,
+}
+
+impl DynamicAvps {
+    fn insert(&mut self, info: AvpInfo, source: Option<&str>) {
+        let loaded = self.next_load;
+        self.next_load += 1;
+        self.entries.insert(
+            info.code,
+            DynamicAvp {
+                info,
+                source: source.map(str::to_string),
+                loaded,
+            },
+        );
+    }
+
+    /// Remove the earliest loaded entry not in `keep`
+    fn evict_oldest(&mut self, keep: &HashSet<u32>) -> Option<u32> {
+        let code = self
+            .entries
+            .iter()
+            .filter(|(code, _)| !keep.contains(code))
+            .min_by_key(|(_, avp)| avp.loaded)
+            .map(|(code, _)| *code)?;
+        self.entries.remove(&code);
+        Some(code)
+    }
+}
 
 /// Dictionary manager for AVP lookup and parsing
 pub struct DictionaryManager {
-    dynamic_avps: RwLock<HashMap<u32, AvpInfo>>,
+    dynamic_avps: RwLock<DynamicAvps>,
+    max_entries: Option<(usize, EvictionPolicy)>,
 }
 
 #[derive(Debug, Deserialize)]
-struct DictionaryXml {
+pub(crate) struct DictionaryXml {
     #[serde(rename = "avp", default)]
-    avps: Vec<AvpXml>,
+    pub(crate) avps: Vec<AvpXml>,
 }
 
 #[derive(Debug, Deserialize)]
-struct AvpXml {
+pub(crate) struct AvpXml {
     #[serde(rename = "@name")]
-    name: String,
+    pub(crate) name: String,
     #[serde(rename = "@code")]
-    code: u32,
+    pub(crate) code: u32,
     #[serde(rename = "@type")]
-    data_type: String,
+    pub(crate) data_type: String,
     #[serde(rename = "@vendor-id")]
-    vendor_id: Option<u32>,
+    pub(crate) vendor_id: Option<u32>,
+    #[serde(rename = "enum", default)]
+    pub(crate) enums: Vec<EnumXml>,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct EnumXml {
+    #[serde(rename = "@name")]
+    pub(crate) name: String,
+    #[serde(rename = "@code")]
+    pub(crate) code: i32,
+}
+
+impl AvpXml {
+    /// Definition of the AVP once its data type is known
+    pub(crate) fn into_info(self, data_type: AvpDataType) -> AvpInfo {
+        AvpInfo {
+            code: self.code,
+            name: self.name,
+            data_type,
+            vendor_id: self.vendor_id,
+            enum_values: self
+                .enums
+                .into_iter()
+                .map(|value| (value.name, value.code))
+                .collect(),
+        }
+    }
+}
+
+/// Map a dictionary XML type name to its data type
+pub(crate) fn data_type_from_name(name: &str) -> Option<AvpDataType> {
+    let data_type = match name {
+        "OctetString" => AvpDataType::OctetString,
+        "Integer32" => AvpDataType::Integer32,
+        "Integer64" => AvpDataType::Integer64,
+        "Unsigned32" => AvpDataType::Unsigned32,
+        "Unsigned64" => AvpDataType::Unsigned64,
+        "Float32" => AvpDataType::Float32,
+        "Float64" => AvpDataType::Float64,
+        "Grouped" => AvpDataType::Grouped,
+        "Address" => AvpDataType::Address,
+        "Time" => AvpDataType::Time,
+        "UTF8String" => AvpDataType::Utf8String,
+        "DiameterIdentity" => AvpDataType::DiameterIdentity,
+        "DiameterURI" => AvpDataType::DiameterUri,
+        "Enumerated" => AvpDataType::Enumerated,
+        "IPFilterRule" => AvpDataType::IpFilterRule,
+        _ => return None,
+    };
+    Some(data_type)
 }
 
 impl DictionaryManager {
     /// Create new dictionary manager
     pub fn new() -> Self {
         Self {
-            dynamic_avps: RwLock::new(HashMap::new()),
+            dynamic_avps: RwLock::new(DynamicAvps::default()),
+            max_entries: None,
         }
     }
 
+    /// Limit the number of dynamic AVPs, applying `policy` to loads beyond it
+    pub fn with_max_entries(mut self, max_entries: usize, policy: EvictionPolicy) -> Self {
+        self.max_entries = Some((max_entries, policy));
+        self
+    }
+
     /// Lookup AVP information by code
     pub fn lookup(&self, code: u32) -> Option<AvpInfo> {
         // Try standard dictionary first
@@ -54,18 +168,29 @@ impl DictionaryManager {
                 code,
                 name: std_code.name().to_string(),
                 data_type: std_code.data_type(),
-                vendor_id: None,
+                vendor_id: std_code.vendor_id(),
+                enum_values: HashMap::new(),
             });
         }
 
         // Try dynamic dictionary
-        if let Ok(guard) = self.dynamic_avps.read() {
-            if let Some(info) = guard.get(&code) {
-                return Some(info.clone());
-            }
-        }
+        self.read_dynamic()
+            .entries
+            .get(&code)
+            .map(|avp| avp.info.clone())
+    }
+
+    /// Number of AVPs loaded from dynamic dictionaries
+    pub fn dynamic_len(&self) -> usize {
+        self.read_dynamic().entries.len()
+    }
 
-        None
+    /// Value of an Enumerated AVP's symbolic name, e.g. "INITIAL_REQUEST"
+    pub fn enum_value(&self, code: u32, name: &str) -> Option<i32> {
+        self.read_dynamic()
+            .entries
+            .get(&code)
+            .and_then(|avp| avp.info.enum_values.get(name).copied())
     }
 
     /// Parse AVP data
@@ -76,46 +201,138 @@ impl DictionaryManager {
     }
 
     /// Load dynamic dictionary from XML string
+    ///
+    /// The AVPs cannot be unloaded individually; use `load_dictionary` for that.
     pub fn load_dynamic_dictionary(&self, xml: &str) -> Result<(), String> {
-        let dict: DictionaryXml = from_str(xml).map_err(|e| e.to_string())?;
-
-        let mut guard = self
-            .dynamic_avps
-            .write()
-            .map_err(|_| "Lock poisoned".to_string())?;
-
-        for avp in dict.avps {
-            let data_type = match avp.data_type.as_str() {
-                "OctetString" => AvpDataType::OctetString,
-                "Integer32" => AvpDataType::Integer32,
-                "Integer64" => AvpDataType::Integer64,
-                "Unsigned32" => AvpDataType::Unsigned32,
-                "Unsigned64" => AvpDataType::Unsigned64,
-                "Float32" => AvpDataType::Float32,
-                "Float64" => AvpDataType::Float64,
-                "Grouped" => AvpDataType::Grouped,
-                "Address" => AvpDataType::Address,
-                "Time" => AvpDataType::Time,
-                "UTF8String" => AvpDataType::Utf8String,
-                "DiameterIdentity" => AvpDataType::DiameterIdentity,
-                "DiameterURI" => AvpDataType::DiameterUri,
-                "Enumerated" => AvpDataType::Enumerated,
-                "IPFilterRule" => AvpDataType::IpFilterRule,
-                _ => continue, // Skip unknown types or handle error
-            };
-
-            let info = AvpInfo {
-                code: avp.code,
-                name: avp.name,
-                data_type,
-                vendor_id: avp.vendor_id,
-            };
-
-            guard.insert(avp.code, info);
+        self.load(xml, None)
+    }
+
+    /// Load a dictionary whose AVPs can later be removed by its `id`
+    ///
+    /// An AVP already defined by another dictionary is taken over, so
+    /// unloading that other dictionary keeps it.
+    pub fn load_dictionary(&self, id: &str, xml: &str) -> Result<(), String> {
+        self.load(xml, Some(id))
+    }
+
+    /// Remove the AVPs loaded by dictionary `id`, returning how many were removed
+    pub fn unload_dictionary(&self, id: &str) -> usize {
+        let mut guard = self.write_dynamic();
+        let before = guard.entries.len();
+        guard
+            .entries
+            .retain(|_, avp| avp.source.as_deref() != Some(id));
+        before - guard.entries.len()
+    }
+
+    fn load(&self, xml: &str, source: Option<&str>) -> Result<(), String> {
+        let avps = parse_dictionary(xml)?;
+        let codes: HashSet<u32> = avps.iter().map(|info| info.code).collect();
+
+        let mut guard = self.write_dynamic();
+        if let Some((max_entries, policy)) = self.max_entries {
+            let added = codes
+                .iter()
+                .filter(|code| !guard.entries.contains_key(code))
+                .count();
+            let excess = (guard.entries.len() + added).saturating_sub(max_entries);
+            if excess > 0 {
+                if policy == EvictionPolicy::Reject || codes.len() > max_entries {
+                    return Err(format!(
+                        "Dictionary would exceed the limit of {max_entries} dynamic AVPs"
+                    ));
+                }
+                for _ in 0..excess {
+                    if let Some(code) = guard.evict_oldest(&codes) {
+                        warn!(
+                            "Evicted dynamic AVP {} to stay within {} entries",
+                            code, max_entries
+                        );
+                    }
+                }
+            }
+        }
+
+        for info in avps {
+            guard.insert(info, source);
+        }
+        Ok(())
+    }
+
+    /// Replace all dynamic AVPs with the ones defined in an XML string
+    ///
+    /// The new set is parsed before the swap, so lookups never see a mix of
+    /// the old and new dictionaries and a bad document leaves the old one in place.
+    pub fn replace_dynamic_dictionary(&self, xml: &str) -> Result<(), String> {
+        self.replace(xml, None)
+    }
+
+    /// Replace all dynamic AVPs with those of dictionary `id`
+    ///
+    /// As `replace_dynamic_dictionary`, but the AVPs can be removed again
+    /// with `unload_dictionary`.
+    pub fn replace_dictionary(&self, id: &str, xml: &str) -> Result<(), String> {
+        self.replace(xml, Some(id))
+    }
+
+    fn replace(&self, xml: &str, source: Option<&str>) -> Result<(), String> {
+        let mut avps = DynamicAvps::default();
+        for info in parse_dictionary(xml)? {
+            avps.insert(info, source);
+        }
+        if let Some((max_entries, _)) = self.max_entries {
+            if avps.entries.len() > max_entries {
+                return Err(format!(
+                    "Dictionary exceeds the limit of {max_entries} dynamic AVPs"
+                ));
+            }
         }
 
+        *self.write_dynamic() = avps;
         Ok(())
     }
+
+    /// Load dynamic dictionary from an XML file
+    pub fn load_from_file(&self, path: impl AsRef<Path>) -> Result<(), String> {
+        let path = path.as_ref();
+        let xml = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read {}: {e}", path.display()))?;
+        self.load_dynamic_dictionary(&xml)
+    }
+
+    /// Read the dynamic dictionary, recovering from a poisoned lock
+    ///
+    /// Entries are inserted one at a time, so a writer that panicked leaves
+    /// the map consistent and it is safe to keep using it.
+    fn read_dynamic(&self) -> RwLockReadGuard<'_, DynamicAvps> {
+        self.dynamic_avps.read().unwrap_or_else(|poisoned| {
+            warn!("Dynamic dictionary lock was poisoned, recovering");
+            self.dynamic_avps.clear_poison();
+            poisoned.into_inner()
+        })
+    }
+
+    /// Write the dynamic dictionary, recovering from a poisoned lock
+    fn write_dynamic(&self) -> RwLockWriteGuard<'_, DynamicAvps> {
+        self.dynamic_avps.write().unwrap_or_else(|poisoned| {
+            warn!("Dynamic dictionary lock was poisoned, recovering");
+            self.dynamic_avps.clear_poison();
+            poisoned.into_inner()
+        })
+    }
+}
+
+/// AVP definitions of an XML dictionary, skipping AVPs of unknown types
+fn parse_dictionary(xml: &str) -> Result<Vec<AvpInfo>, String> {
+    let dict: DictionaryXml = from_str(xml).map_err(|e| e.to_string())?;
+    Ok(dict
+        .avps
+        .into_iter()
+        .filter_map(|avp| {
+            let data_type = data_type_from_name(&avp.data_type)?;
+            Some(avp.into_info(data_type))
+        })
+        .collect())
 }
 
 impl Default for DictionaryManager {
@@ -186,4 +403,154 @@ mod tests {
         assert_eq!(info.data_type, AvpDataType::Unsigned32);
         assert_eq!(info.vendor_id, Some(9999));
     }
+
+    #[test]
+    fn test_lookup_survives_poisoned_lock() {
+        let manager = std::sync::Arc::new(DictionaryManager::new());
+        manager
+            .load_dynamic_dictionary(
+                r#"<dictionary><avp name="Custom" code="5000" type="UTF8String"/></dictionary>"#,
+            )
+            .unwrap();
+
+        // A thread panicking while holding the write lock poisons it
+        let poisoner = manager.clone();
+        let result = std::thread::spawn(move || {
+            let _guard = poisoner.dynamic_avps.write().unwrap();
+            panic!("writer crashed");
+        })
+        .join();
+        assert!(result.is_err());
+        assert!(manager.dynamic_avps.is_poisoned());
+
+        assert_eq!(manager.lookup(5000).unwrap().name, "Custom");
+        assert!(!manager.dynamic_avps.is_poisoned());
+
+        manager
+            .load_dynamic_dictionary(
+                r#"<dictionary><avp name="Other" code="5001" type="Unsigned32"/></dictionary>"#,
+            )
+            .unwrap();
+        assert_eq!(manager.lookup(5001).unwrap().name, "Other");
+    }
+
+    #[test]
+    fn test_replace_dynamic_dictionary() {
+        let manager = DictionaryManager::new();
+        manager
+            .load_dynamic_dictionary(
+                r#"<dictionary>
+                    <avp name="Old-AVP" code="10001" type="Unsigned32"/>
+                    <avp name="Dropped-AVP" code="10002" type="Unsigned32"/>
+                </dictionary>"#,
+            )
+            .unwrap();
+
+        manager
+            .replace_dynamic_dictionary(
+                r#"<dictionary><avp name="New-AVP" code="10001" type="UTF8String"/></dictionary>"#,
+            )
+            .unwrap();
+        assert_eq!(manager.lookup(10001).unwrap().name, "New-AVP");
+        assert!(manager.lookup(10002).is_none());
+
+        // A document that does not parse keeps the current definitions
+        assert!(manager.replace_dynamic_dictionary("<dictionary>").is_err());
+        assert_eq!(manager.lookup(10001).unwrap().name, "New-AVP");
+    }
+
+    #[test]
+    fn test_enum_value() {
+        let manager = DictionaryManager::new();
+        manager
+            .load_dynamic_dictionary(
+                r#"<dictionary>
+                    <avp name="CC-Request-Type" code="416" type="Enumerated">
+                        <enum name="INITIAL_REQUEST" code="1"/>
+                        <enum name="UPDATE_REQUEST" code="2"/>
+                    </avp>
+                </dictionary>"#,
+            )
+            .unwrap();
+
+        assert_eq!(manager.enum_value(416, "UPDATE_REQUEST"), Some(2));
+        assert_eq!(manager.enum_value(416, "EVENT_REQUEST"), None);
+        assert_eq!(manager.enum_value(264, "INITIAL_REQUEST"), None);
+    }
+
+    #[test]
+    fn test_unload_dictionary() {
+        let manager = DictionaryManager::new();
+        manager
+            .load_dictionary(
+                "vendor-a",
+                r#"<dictionary>
+                    <avp name="A-One" code="20001" type="Unsigned32"/>
+                    <avp name="Shared" code="20002" type="Unsigned32"/>
+                </dictionary>"#,
+            )
+            .unwrap();
+        manager
+            .load_dictionary(
+                "vendor-b",
+                r#"<dictionary><avp name="Shared" code="20002" type="UTF8String"/></dictionary>"#,
+            )
+            .unwrap();
+
+        assert_eq!(manager.unload_dictionary("vendor-a"), 1);
+        assert!(manager.lookup(20001).is_none());
+        // Taken over by vendor-b, so it survives vendor-a's removal
+        assert_eq!(
+            manager.lookup(20002).unwrap().data_type,
+            AvpDataType::Utf8String
+        );
+
+        assert_eq!(manager.unload_dictionary("vendor-b"), 1);
+        assert!(manager.lookup(20002).is_none());
+        assert_eq!(manager.unload_dictionary("vendor-b"), 0);
+    }
+
+    #[test]
+    fn test_max_entries() {
+        let dictionary = |codes: &[u32]| {
+            let avps: String = codes
+                .iter()
+                .map(|code| format!(r#"<avp name="AVP-{code}" code="{code}" type="Unsigned32"/>"#))
+                .collect();
+            format!("<dictionary>{avps}</dictionary>")
+        };
+
+        let manager = DictionaryManager::new().with_max_entries(2, EvictionPolicy::Reject);
+        manager
+            .load_dynamic_dictionary(&dictionary(&[30001, 30002]))
+            .unwrap();
+        // Redefining loaded AVPs needs no room
+        manager
+            .load_dynamic_dictionary(&dictionary(&[30002]))
+            .unwrap();
+        assert!(manager
+            .load_dynamic_dictionary(&dictionary(&[30003]))
+            .is_err());
+        assert!(manager.lookup(30003).is_none());
+        assert_eq!(manager.dynamic_len(), 2);
+
+        let manager = DictionaryManager::new().with_max_entries(2, EvictionPolicy::EvictOldest);
+        manager
+            .load_dynamic_dictionary(&dictionary(&[30001, 30002]))
+            .unwrap();
+        manager
+            .load_dynamic_dictionary(&dictionary(&[30003]))
+            .unwrap();
+        assert!(manager.lookup(30001).is_none());
+        assert!(manager.lookup(30002).is_some());
+        assert!(manager.lookup(30003).is_some());
+        // A dictionary larger than the limit never fits
+        assert!(manager
+            .load_dynamic_dictionary(&dictionary(&[30004, 30005, 30006]))
+            .is_err());
+        assert!(manager
+            .replace_dynamic_dictionary(&dictionary(&[30004, 30005, 30006]))
+            .is_err());
+        assert_eq!(manager.dynamic_len(), 2);
+    }
 }
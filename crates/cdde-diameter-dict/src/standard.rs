@@ -1,5 +1,8 @@
 use crate::data_type::AvpDataType;
 
+/// Vendor-Id of 3GPP
+const VENDOR_ID_3GPP: u32 = 10415;
+
 /// Standard AVP Code definitions from RFC 6733 and 3GPP specifications
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 #[repr(u32)]
@@ -132,6 +135,21 @@ impl StandardAvpCode {
             Self::EventTrigger => AvpDataType::Enumerated,
         }
     }
+
+    /// Get the Vendor-Id of vendor-specific AVPs
+    pub fn vendor_id(&self) -> Option<u32> {
+        match self {
+            Self::SubscriptionData
+            | Self::UlrFlags
+            | Self::UlaFlags
+            | Self::VisitedPlmnId
+            | Self::RequestedEutranAuthInfo
+            | Self::ChargingRuleInstall
+            | Self::ChargingRuleName
+            | Self::EventTrigger => Some(VENDOR_ID_3GPP),
+            _ => None,
+        }
+    }
 }
 
 #[cfg(test)]
@@ -168,4 +186,10 @@ mod tests {
             AvpDataType::Unsigned32
         );
     }
+
+    #[test]
+    fn test_vendor_id() {
+        assert_eq!(StandardAvpCode::OriginHost.vendor_id(), None);
+        assert_eq!(StandardAvpCode::VisitedPlmnId.vendor_id(), Some(10415));
+    }
 }
@@ -0,0 +1,90 @@
+use crate::manager::{data_type_from_name, AvpInfo, DictionaryXml};
+use quick_xml::de::from_str;
+use std::collections::HashSet;
+use std::path::Path;
+
+/// Result of checking a dictionary XML document
+#[derive(Debug, Default)]
+pub struct DictionaryReport {
+    /// AVPs with a known data type
+    pub avps: Vec<AvpInfo>,
+
+    /// Codes defined more than once
+    pub duplicate_codes: Vec<u32>,
+
+    /// AVPs whose data type is not recognized, as (name, type)
+    pub unknown_types: Vec<(String, String)>,
+}
+
+impl DictionaryReport {
+    /// Check if the dictionary can be loaded without losing AVPs
+    pub fn is_valid(&self) -> bool {
+        self.duplicate_codes.is_empty() && self.unknown_types.is_empty()
+    }
+}
+
+/// Check a dictionary XML document without loading it
+pub fn validate_dictionary(xml: &str) -> Result<DictionaryReport, String> {
+    let dict: DictionaryXml = from_str(xml).map_err(|e| e.to_string())?;
+
+    let mut report = DictionaryReport::default();
+    let mut seen = HashSet::new();
+
+    for avp in dict.avps {
+        if !seen.insert(avp.code) && !report.duplicate_codes.contains(&avp.code) {
+            report.duplicate_codes.push(avp.code);
+        }
+
+        match data_type_from_name(&avp.data_type) {
+            Some(data_type) => report.avps.push(avp.into_info(data_type)),
+            None => report.unknown_types.push((avp.name, avp.data_type)),
+        }
+    }
+
+    Ok(report)
+}
+
+/// Check a dictionary XML file without loading it
+pub fn validate_file(path: impl AsRef<Path>) -> Result<DictionaryReport, String> {
+    let path = path.as_ref();
+    let xml = std::fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read {}: {e}", path.display()))?;
+    validate_dictionary(&xml)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn testdata(name: &str) -> std::path::PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("testdata")
+            .join(name)
+    }
+
+    #[test]
+    fn test_valid_dictionary_file() {
+        let report = validate_file(testdata("valid.xml")).unwrap();
+
+        assert!(report.is_valid());
+        assert_eq!(report.avps.len(), 3);
+        assert_eq!(report.avps[0].name, "Subscription-Data");
+    }
+
+    #[test]
+    fn test_invalid_dictionary_file() {
+        let report = validate_file(testdata("invalid.xml")).unwrap();
+
+        assert!(!report.is_valid());
+        assert_eq!(report.duplicate_codes, vec![1400]);
+        assert_eq!(
+            report.unknown_types,
+            vec![("Broken-AVP".to_string(), "Unsigned128".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_missing_file() {
+        assert!(validate_file(testdata("missing.xml")).is_err());
+    }
+}
@@ -0,0 +1,105 @@
+use std::time::Duration;
+
+/// Delays between reconnection attempts to a peer
+///
+/// The delay starts at `initial` and is multiplied by `multiplier` after
+/// each failed attempt, up to `max`. Each delay is then shortened by a
+/// random share of at most `jitter`, so connectors of a bouncing peer do not
+/// all reconnect at the same moment. Only a connection that stays up for
+/// `reset_after` starts the delays over, so a peer that accepts the
+/// handshake and then drops the connection still backs off.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BackoffPolicy {
+    pub initial: Duration,
+    pub max: Duration,
+    pub multiplier: f64,
+    /// Fraction of the delay, from 0.0 to 1.0, that may be cut at random
+    pub jitter: f64,
+    /// How long a connection must last before the delay is reset
+    pub reset_after: Duration,
+}
+
+impl Default for BackoffPolicy {
+    fn default() -> Self {
+        Self {
+            initial: Duration::from_secs(5),
+            max: Duration::from_secs(60),
+            multiplier: 2.0,
+            jitter: 0.2,
+            reset_after: Duration::from_secs(60),
+        }
+    }
+}
+
+impl BackoffPolicy {
+    /// The same delay before every attempt
+    pub fn fixed(interval: Duration) -> Self {
+        Self {
+            initial: interval,
+            max: interval,
+            multiplier: 1.0,
+            jitter: 0.0,
+            reset_after: interval,
+        }
+    }
+
+    /// Delay before retrying after `attempt` consecutive failures, without jitter
+    pub fn base_delay(&self, attempt: u32) -> Duration {
+        let factor = self
+            .multiplier
+            .max(1.0)
+            .powi(attempt.min(i32::MAX as u32) as i32);
+        let delay = self.initial.as_secs_f64() * factor;
+        if delay.is_finite() && delay < self.max.as_secs_f64() {
+            Duration::from_secs_f64(delay)
+        } else {
+            self.max
+        }
+    }
+
+    /// Delay before retrying after `attempt` consecutive failures
+    pub fn delay(&self, attempt: u32) -> Duration {
+        self.delay_with(attempt, rand::random::<f64>())
+    }
+
+    /// Delay with the jitter drawn from `random`, in `[0, 1)`
+    pub fn delay_with(&self, attempt: u32, random: f64) -> Duration {
+        let cut = self.jitter.clamp(0.0, 1.0) * random.clamp(0.0, 1.0);
+        self.base_delay(attempt).mul_f64(1.0 - cut)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_delay_grows_and_is_bounded() {
+        let policy = BackoffPolicy {
+            initial: Duration::from_millis(100),
+            max: Duration::from_secs(1),
+            multiplier: 2.0,
+            jitter: 0.5,
+            reset_after: Duration::from_secs(1),
+        };
+
+        let delays: Vec<Duration> = (0..6).map(|attempt| policy.base_delay(attempt)).collect();
+        assert_eq!(
+            delays,
+            [100, 200, 400, 800, 1000, 1000].map(Duration::from_millis)
+        );
+        assert_eq!(policy.base_delay(u32::MAX), policy.max);
+
+        // Jitter only ever shortens the delay, by half at most
+        for attempt in 0..6 {
+            let base = policy.base_delay(attempt);
+            assert_eq!(policy.delay_with(attempt, 0.0), base);
+            assert_eq!(policy.delay_with(attempt, 1.0), base / 2);
+            let delay = policy.delay(attempt);
+            assert!(delay <= base && delay >= base / 2);
+        }
+
+        let fixed = BackoffPolicy::fixed(Duration::from_millis(50));
+        assert_eq!(fixed.delay(10), Duration::from_millis(50));
+    }
+}
@@ -1,45 +1,350 @@
-use cdde_core::{CddeError, Result, Transport};
+use crate::backoff::BackoffPolicy;
+use crate::event::{PeerEvent, PeerInfo};
+use crate::handle::{Forward, PeerHandle};
+use crate::ids::IdGenerator;
+use cdde_core::address::encode_address;
+use cdde_core::codes::{
+    AVP_DISCONNECT_CAUSE, AVP_HOST_IP_ADDRESS, AVP_ORIGIN_HOST, AVP_ORIGIN_REALM,
+    CMD_DEVICE_WATCHDOG, CMD_DISCONNECT_PEER, RESULT_SUCCESS, RESULT_UNABLE_TO_DELIVER,
+};
+use cdde_core::diameter::vendor_specific_app_id;
+use cdde_core::{
+    validate_command, CddeError, DiameterAvp, DiameterCodec, DiameterPacket, Result, Transport,
+};
+use futures::{SinkExt, StreamExt};
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr};
+use std::sync::atomic::{AtomicU32, Ordering};
 use std::time::Duration;
-use tokio::io::AsyncReadExt;
 use tokio::net::TcpStream;
+use tokio::sync::{mpsc, oneshot, watch, Mutex};
+use tokio::time::Instant;
+use tokio_util::codec::Framed;
 use tracing::{debug, error, info, warn};
 
+/// Peer connection split into Diameter messages
+type PeerStream<'a, T> = Framed<&'a mut T, DiameterCodec>;
+
+/// Default number of unanswered requests a peer may have
+pub const DEFAULT_MAX_OUTSTANDING: usize = 1000;
+
+/// Timers and limits applied to every peer connection
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConnectorSettings {
+    /// How long to wait for a CEA after sending the CER
+    pub cea_timeout: Duration,
+
+    /// How long the peer may stay silent; should exceed its watchdog interval
+    pub read_timeout: Duration,
+
+    /// How long a write may block before the connection is dropped
+    pub write_timeout: Duration,
+
+    /// Delays before reconnecting after a lost connection or failed attempt
+    pub reconnect_backoff: BackoffPolicy,
+
+    /// Shortest delay before reconnecting to a peer that disconnected with
+    /// DO_NOT_WANT_TO_TALK_TO_YOU
+    pub refused_reconnect_delay: Duration,
+
+    /// Forwarded requests a peer may have unanswered before forwards are refused
+    pub max_outstanding: usize,
+
+    /// Origin-Host of the CER, DWA and DPR we send
+    pub origin_host: String,
+
+    /// Origin-Realm of the CER, DWA and DPR we send
+    pub origin_realm: String,
+}
+
+impl Default for ConnectorSettings {
+    fn default() -> Self {
+        Self {
+            cea_timeout: Duration::from_secs(10),
+            read_timeout: Duration::from_secs(60),
+            write_timeout: Duration::from_secs(10),
+            reconnect_backoff: BackoffPolicy::default(),
+            refused_reconnect_delay: Duration::from_secs(600),
+            max_outstanding: DEFAULT_MAX_OUTSTANDING,
+            origin_host: "dpa.example.com".to_string(),
+            origin_realm: "example.com".to_string(),
+        }
+    }
+}
+
+impl ConnectorSettings {
+    /// Read overrides from CEA_TIMEOUT_MS, READ_TIMEOUT_MS, WRITE_TIMEOUT_MS,
+    /// RECONNECT_INTERVAL_MS, RECONNECT_MAX_MS, RECONNECT_MULTIPLIER,
+    /// RECONNECT_JITTER, RECONNECT_RESET_MS, RECONNECT_REFUSED_MS,
+    /// MAX_OUTSTANDING_REQUESTS, ORIGIN_HOST and ORIGIN_REALM
+    ///
+    /// Values that do not parse, including non-finite numbers, are ignored.
+    pub fn from_env() -> Self {
+        let millis = |name: &str| {
+            std::env::var(name)
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .map(Duration::from_millis)
+        };
+        let number = |name: &str| std::env::var(name).ok().and_then(|v| parse_finite(&v));
+        let defaults = Self::default();
+        let backoff = defaults.reconnect_backoff;
+        Self {
+            cea_timeout: millis("CEA_TIMEOUT_MS").unwrap_or(defaults.cea_timeout),
+            read_timeout: millis("READ_TIMEOUT_MS").unwrap_or(defaults.read_timeout),
+            write_timeout: millis("WRITE_TIMEOUT_MS").unwrap_or(defaults.write_timeout),
+            reconnect_backoff: BackoffPolicy {
+                initial: millis("RECONNECT_INTERVAL_MS").unwrap_or(backoff.initial),
+                max: millis("RECONNECT_MAX_MS").unwrap_or(backoff.max),
+                multiplier: number("RECONNECT_MULTIPLIER").unwrap_or(backoff.multiplier),
+                jitter: number("RECONNECT_JITTER").unwrap_or(backoff.jitter),
+                reset_after: millis("RECONNECT_RESET_MS").unwrap_or(backoff.reset_after),
+            },
+            refused_reconnect_delay: millis("RECONNECT_REFUSED_MS")
+                .unwrap_or(defaults.refused_reconnect_delay),
+            max_outstanding: std::env::var("MAX_OUTSTANDING_REQUESTS")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(defaults.max_outstanding),
+            origin_host: std::env::var("ORIGIN_HOST").unwrap_or(defaults.origin_host),
+            origin_realm: std::env::var("ORIGIN_REALM").unwrap_or(defaults.origin_realm),
+        }
+    }
+}
+
+/// Parse a finite number, rejecting NaN and infinities
+fn parse_finite(value: &str) -> Option<f64> {
+    value.parse::<f64>().ok().filter(|v| v.is_finite())
+}
+
 /// TCP Client for Diameter peer connections
 pub struct TcpClient {
     peer_addr: String,
-    reconnect_interval: Duration,
+    peer_host: Option<String>,
+    origin_host: String,
+    origin_realm: String,
+    host_ips: Vec<IpAddr>,
+    vendor_applications: Vec<(u32, u32)>,
+    backoff: BackoffPolicy,
+    // Connections lost or attempts failed since a connection last stayed up
+    failures: AtomicU32,
+    refused_reconnect_delay: Duration,
+    // Disconnect-Cause of the peer's last DPR, until the reconnect is scheduled
+    peer_disconnect_cause: std::sync::Mutex<Option<u32>>,
+    cea_timeout: Duration,
+    read_timeout: Duration,
+    write_timeout: Duration,
+    virtual_router_ids: Vec<String>,
+    events: Option<mpsc::Sender<PeerEvent>>,
+    handle: PeerHandle,
+    forwards: Mutex<mpsc::Receiver<Forward>>,
+    drain: watch::Receiver<Option<Duration>>,
 }
 
+/// Disconnect-Cause REBOOTING, sent when draining for an upgrade
+const DISCONNECT_CAUSE_REBOOTING: u32 = 0;
+
+/// Disconnect-Cause BUSY, asking for a reconnect only after a while
+const DISCONNECT_CAUSE_BUSY: u32 = 1;
+
+/// Disconnect-Cause DO_NOT_WANT_TO_TALK_TO_YOU, asking not to reconnect
+const DISCONNECT_CAUSE_DO_NOT_WANT_TO_TALK_TO_YOU: u32 = 2;
+
 impl TcpClient {
     /// Create new TCP client
     pub fn new(peer_addr: String) -> Self {
+        let (forwards_tx, forwards) = mpsc::channel(256);
+        let (drain_tx, drain) = watch::channel(None);
+        let settings = ConnectorSettings::default();
         Self {
             peer_addr,
-            reconnect_interval: Duration::from_secs(5),
+            peer_host: None,
+            origin_host: settings.origin_host,
+            origin_realm: settings.origin_realm,
+            host_ips: vec![IpAddr::V4(Ipv4Addr::LOCALHOST)],
+            vendor_applications: Vec::new(),
+            backoff: settings.reconnect_backoff,
+            failures: AtomicU32::new(0),
+            refused_reconnect_delay: settings.refused_reconnect_delay,
+            peer_disconnect_cause: std::sync::Mutex::new(None),
+            cea_timeout: settings.cea_timeout,
+            read_timeout: settings.read_timeout,
+            write_timeout: settings.write_timeout,
+            virtual_router_ids: Vec::new(),
+            events: None,
+            handle: PeerHandle::new(forwards_tx, drain_tx),
+            forwards: Mutex::new(forwards),
+            drain,
         }
     }
 
+    /// Handle for forwarding requests to this peer and draining it
+    pub fn handle(&self) -> PeerHandle {
+        self.handle.clone()
+    }
+
+    /// Apply all connection timers at once
+    pub fn with_settings(mut self, settings: &ConnectorSettings) -> Self {
+        self.cea_timeout = settings.cea_timeout;
+        self.read_timeout = settings.read_timeout;
+        self.write_timeout = settings.write_timeout;
+        self.backoff = settings.reconnect_backoff;
+        self.refused_reconnect_delay = settings.refused_reconnect_delay;
+        self.handle.set_max_outstanding(settings.max_outstanding);
+        self.origin_host = settings.origin_host.clone();
+        self.origin_realm = settings.origin_realm.clone();
+        self
+    }
+
+    /// Set the Origin-Host and Origin-Realm of the messages we send
+    pub fn with_identity(mut self, origin_host: String, origin_realm: String) -> Self {
+        self.origin_host = origin_host;
+        self.origin_realm = origin_realm;
+        self
+    }
+
+    /// Refuse forwards while the peer has this many requests unanswered
+    pub fn with_max_outstanding(mut self, max_outstanding: usize) -> Self {
+        self.handle.set_max_outstanding(max_outstanding);
+        self
+    }
+
+    /// Identify the peer by its configured host name until the CEA arrives
+    pub fn with_peer_host(mut self, hostname: String) -> Self {
+        self.peer_host = Some(hostname);
+        self
+    }
+
+    /// Set the local addresses advertised as Host-IP-Address in the CER
+    ///
+    /// Multihomed hosts list every address; an empty list is ignored since
+    /// the CER needs at least one.
+    pub fn with_host_ips(mut self, host_ips: Vec<IpAddr>) -> Self {
+        if !host_ips.is_empty() {
+            self.host_ips = host_ips;
+        }
+        self
+    }
+
+    /// Set the `(vendor id, application id)` pairs advertised in the CER
+    ///
+    /// Each becomes a Vendor-Specific-Application-Id.
+    pub fn with_vendor_applications(mut self, applications: Vec<(u32, u32)>) -> Self {
+        self.vendor_applications = applications;
+        self
+    }
+
+    /// Transport address of the peer
+    pub fn peer_addr(&self) -> &str {
+        &self.peer_addr
+    }
+
+    /// Set the virtual routers served through this peer
+    pub fn with_virtual_routers(mut self, virtual_router_ids: Vec<String>) -> Self {
+        self.virtual_router_ids = virtual_router_ids;
+        self
+    }
+
+    /// Report peer up/down events on a channel
+    pub fn with_event_sender(mut self, events: mpsc::Sender<PeerEvent>) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    /// Set how long to wait for a CEA after sending the CER
+    pub fn with_cea_timeout(mut self, timeout: Duration) -> Self {
+        self.cea_timeout = timeout;
+        self
+    }
+
+    /// Set how long the peer may stay silent before the connection is dropped
+    ///
+    /// Should exceed the peer's watchdog interval.
+    pub fn with_read_timeout(mut self, timeout: Duration) -> Self {
+        self.read_timeout = timeout;
+        self
+    }
+
+    /// Set how long a write may block before the connection is dropped
+    pub fn with_write_timeout(mut self, timeout: Duration) -> Self {
+        self.write_timeout = timeout;
+        self
+    }
+
+    /// Reconnect after a fixed delay instead of backing off
+    pub fn with_reconnect_interval(mut self, interval: Duration) -> Self {
+        self.backoff = BackoffPolicy::fixed(interval);
+        self
+    }
+
+    /// Set the delays before reconnecting
+    pub fn with_backoff(mut self, backoff: BackoffPolicy) -> Self {
+        self.backoff = backoff;
+        self
+    }
+
     /// Start connection loop
+    ///
+    /// Returns once a drain was requested and the connection has closed.
     pub async fn start(&self) {
         info!("Starting DPA connector to {}", self.peer_addr);
 
         loop {
+            if self.handle.is_draining() {
+                info!("Connector to {} drained", self.peer_addr);
+                return;
+            }
+
             match self.connect().await {
                 Ok(mut socket) => {
                     info!("Connected to {}", self.peer_addr);
-                    if let Err(e) = self.handle_connection(&mut socket).await {
-                        error!("Connection lost: {}", e);
+                    let mut peer = self.peer_info();
+                    let connected_at = Instant::now();
+                    let result = self.handle_connection(&mut socket, &mut peer).await;
+                    // Only a connection that lasted shows the peer has recovered
+                    if connected_at.elapsed() >= self.backoff.reset_after {
+                        self.failures.store(0, Ordering::Relaxed);
                     }
+                    let reason = match result {
+                        Err(CddeError::ConnectionClosed) => {
+                            warn!("Connection closed by {}", self.peer_addr);
+                            CddeError::ConnectionClosed.to_string()
+                        }
+                        Err(e) => {
+                            error!("Connection lost: {}", e);
+                            e.to_string()
+                        }
+                        Ok(()) if self.handle.is_draining() => "drained".to_string(),
+                        Ok(()) => "connection ended".to_string(),
+                    };
+                    self.notify(PeerEvent::PeerDown { peer, reason }).await;
                 }
                 Err(e) => {
-                    warn!(
-                        "Failed to connect to {}: {}. Retrying in {:?}...",
-                        self.peer_addr, e, self.reconnect_interval
-                    );
+                    warn!("Failed to connect to {}: {}", self.peer_addr, e);
                 }
             }
 
-            tokio::time::sleep(self.reconnect_interval).await;
+            let delay = self.reconnect_delay();
+            info!("Reconnecting to {} in {:?}", self.peer_addr, delay);
+            tokio::time::sleep(delay).await;
+        }
+    }
+
+    /// Delay before the next attempt, longer after each consecutive failure
+    ///
+    /// A peer that disconnected as BUSY is left alone for the longest
+    /// backoff delay, one that does not want to talk to us for the refused
+    /// reconnect delay.
+    fn reconnect_delay(&self) -> Duration {
+        let delay = self
+            .backoff
+            .delay(self.failures.fetch_add(1, Ordering::Relaxed));
+        match self.peer_disconnect_cause.lock().unwrap().take() {
+            Some(DISCONNECT_CAUSE_BUSY) => delay.max(self.backoff.max),
+            Some(DISCONNECT_CAUSE_DO_NOT_WANT_TO_TALK_TO_YOU) => {
+                delay.max(self.refused_reconnect_delay)
+            }
+            _ => delay,
         }
     }
 
@@ -49,50 +354,246 @@ impl TcpClient {
         Ok(stream)
     }
 
+    /// Peer identity before the capabilities exchange
+    pub fn peer_info(&self) -> PeerInfo {
+        PeerInfo {
+            peer_id: self
+                .peer_host
+                .clone()
+                .unwrap_or_else(|| self.peer_addr.clone()),
+            addr: self.peer_addr.clone(),
+            virtual_router_ids: self.virtual_router_ids.clone(),
+            host_ips: Vec::new(),
+        }
+    }
+
+    /// Send a peer event if anyone is listening
+    async fn notify(&self, event: PeerEvent) {
+        if let Some(events) = &self.events {
+            if events.send(event).await.is_err() {
+                debug!("Peer event receiver dropped");
+            }
+        }
+    }
+
     /// Handle connected session
-    async fn handle_connection<T: Transport>(&self, socket: &mut T) -> Result<()> {
+    async fn handle_connection<T: Transport>(
+        &self,
+        socket: &mut T,
+        peer: &mut PeerInfo,
+    ) -> Result<()> {
         info!("Starting handshake with {}", self.peer_addr);
-        self.send_cer(socket).await?;
-        self.receive_cea(socket).await?;
+        let mut stream = Framed::new(socket, DiameterCodec::new());
+        self.send_cer(&mut stream).await?;
+        let cea = tokio::time::timeout(self.cea_timeout, self.receive_cea(&mut stream))
+            .await
+            .map_err(|_| CddeError::HandshakeTimeout(self.cea_timeout.as_millis() as u64))??;
         info!("Handshake successful with {}", self.peer_addr);
 
-        let mut buffer = [0u8; 4096];
+        if let Some(origin_host) = cea.find_avp(AVP_ORIGIN_HOST) {
+            peer.peer_id = String::from_utf8_lossy(&origin_host.data).to_string();
+        }
+        peer.host_ips = cea.host_ip_addresses();
+        self.notify(PeerEvent::PeerUp(peer.clone())).await;
+        let _connected = Connected::new();
+
+        let mut forwards = self.forwards.lock().await;
+        let mut drain = self.drain.clone();
+        let mut outstanding = InFlight::new(&self.handle);
 
         loop {
-            let n = socket.read(&mut buffer).await?;
-            if n == 0 {
-                return Ok(());
+            let draining = *drain.borrow_and_update();
+            if let Some(deadline) = draining {
+                self.notify(PeerEvent::PeerDraining(peer.clone())).await;
+                // Requests queued before the drain was requested still go out
+                while let Ok(forward) = forwards.try_recv() {
+                    self.send_forward(&mut stream, forward, &mut outstanding)
+                        .await?;
+                }
+                return self
+                    .drain_connection(&mut stream, &mut outstanding, deadline)
+                    .await;
+            }
+
+            tokio::select! {
+                packet = self.read_packet(&mut stream) => {
+                    if !self.handle_packet(&mut stream, &packet?, &mut outstanding).await? {
+                        return Ok(());
+                    }
+                }
+                Some(forward) = forwards.recv() => {
+                    self.send_forward(&mut stream, forward, &mut outstanding).await?;
+                }
+                _ = drain.changed() => {}
             }
+        }
+    }
+
+    /// Handle one message received from the peer
+    ///
+    /// Answers go back to whoever forwarded the request. The DPA only relays
+    /// towards its peers, so requests a peer originates are answered with
+    /// DIAMETER_UNABLE_TO_DELIVER for the peer to route them elsewhere.
+    ///
+    /// Returns `false` once the peer asked to disconnect with a DPR, which
+    /// has been answered.
+    async fn handle_packet<T: Transport>(
+        &self,
+        stream: &mut PeerStream<'_, T>,
+        packet: &DiameterPacket,
+        outstanding: &mut InFlight<'_>,
+    ) -> Result<bool> {
+        let command_code = packet.header.command_code;
+        if command_code == CMD_DEVICE_WATCHDOG && packet.header.is_request() {
+            info!("Received DWR from {}", self.peer_addr);
+            self.send_dwa(stream, packet).await?;
+        } else if command_code == CMD_DEVICE_WATCHDOG {
+            debug!("Received DWA from {}", self.peer_addr);
+        } else if command_code == CMD_DISCONNECT_PEER && packet.header.is_request() {
+            let cause = packet
+                .find_avp(AVP_DISCONNECT_CAUSE)
+                .and_then(|avp| avp.data.get(..4))
+                .map(|data| u32::from_be_bytes([data[0], data[1], data[2], data[3]]));
+            info!(
+                "Received DPR from {} (Disconnect-Cause {:?})",
+                self.peer_addr, cause
+            );
+            *self.peer_disconnect_cause.lock().unwrap() = cause;
+            self.send_dpa(stream, packet).await?;
+            return Ok(false);
+        } else if packet.header.is_answer() && outstanding.complete(packet) {
+            debug!(
+                "Received answer {} from {}",
+                packet.header.hop_by_hop_id, self.peer_addr
+            );
+        } else if packet.header.is_request() {
+            warn!(
+                "Refusing request {} (Command Code {}) from {}",
+                packet.header.hop_by_hop_id, command_code, self.peer_addr
+            );
+            let answer = packet.clone().into_answer(
+                RESULT_UNABLE_TO_DELIVER,
+                &self.origin_host,
+                &self.origin_realm,
+            );
+            self.write_packet(stream, &answer).await?;
+        } else {
+            debug!(
+                "Dropping unexpected answer {} from {}",
+                packet.header.hop_by_hop_id, self.peer_addr
+            );
+        }
+        Ok(true)
+    }
+
+    /// Write a forwarded request and remember that it awaits an answer
+    async fn send_forward<T: Transport>(
+        &self,
+        stream: &mut PeerStream<'_, T>,
+        forward: Forward,
+        outstanding: &mut InFlight<'_>,
+    ) -> Result<()> {
+        // Tracked before writing so a failed write still frees its slot
+        outstanding.insert(forward.packet.header.hop_by_hop_id, forward.answer);
+        self.write_packet(stream, &forward.packet).await
+    }
+
+    /// Wait for outstanding answers, then disconnect with a DPR
+    async fn drain_connection<T: Transport>(
+        &self,
+        stream: &mut PeerStream<'_, T>,
+        outstanding: &mut InFlight<'_>,
+        deadline: Duration,
+    ) -> Result<()> {
+        info!(
+            "Draining connection to {} with {} outstanding requests (deadline {:?})",
+            self.peer_addr,
+            outstanding.len(),
+            deadline
+        );
 
-            // Try to parse packet
-            match cdde_core::DiameterPacket::parse(&buffer[..n]) {
+        let until = Instant::now() + deadline;
+        while !outstanding.is_empty() {
+            match tokio::time::timeout_at(until, self.read_packet(stream)).await {
                 Ok(packet) => {
-                    // Handle Device-Watchdog-Request (280)
-                    if packet.header.command_code == 280 && packet.header.is_request() {
-                        info!("Received DWR from {}", self.peer_addr);
-                        self.send_dwa(socket, &packet).await?;
-                    } else {
-                        debug!(
-                            "Received packet: Command Code {}",
-                            packet.header.command_code
-                        );
-                        // TODO: Forward other requests to DFL/DCR
+                    // A peer disconnecting first makes our DPR unnecessary
+                    if !self.handle_packet(stream, &packet?, outstanding).await? {
+                        return Ok(());
                     }
                 }
-                Err(e) => {
-                    error!("Failed to parse packet from {}: {}", self.peer_addr, e);
+                Err(_) => {
+                    warn!(
+                        "Drain deadline reached with {} requests outstanding on {}",
+                        outstanding.len(),
+                        self.peer_addr
+                    );
+                    break;
                 }
             }
         }
+
+        self.send_dpr(stream).await?;
+        match tokio::time::timeout(self.cea_timeout, self.receive_dpa(stream)).await {
+            Ok(Ok(())) => info!("Disconnected from {}", self.peer_addr),
+            Ok(Err(e)) => warn!("No DPA from {}: {}", self.peer_addr, e),
+            Err(_) => warn!("Timed out waiting for DPA from {}", self.peer_addr),
+        }
+        Ok(())
+    }
+
+    /// Read until the peer answers our DPR, servicing watchdogs meanwhile
+    async fn receive_dpa<T: Transport>(&self, stream: &mut PeerStream<'_, T>) -> Result<()> {
+        loop {
+            let packet = self.read_packet(stream).await?;
+            if packet.header.command_code == CMD_DISCONNECT_PEER && packet.header.is_answer() {
+                return Ok(());
+            }
+            if packet.header.command_code == 280 && packet.header.is_request() {
+                self.send_dwa(stream, &packet).await?;
+            }
+        }
+    }
+
+    /// Read the next message, giving up after the read timeout
+    ///
+    /// Messages that do not parse are logged and skipped; a message that
+    /// cannot be framed ends the connection.
+    async fn read_packet<T: Transport>(
+        &self,
+        stream: &mut PeerStream<'_, T>,
+    ) -> Result<DiameterPacket> {
+        let packet = loop {
+            let frame = tokio::time::timeout(self.read_timeout, stream.next())
+                .await
+                .map_err(|_| CddeError::ReadTimeout(self.read_timeout.as_millis() as u64))?
+                .ok_or(CddeError::ConnectionClosed)?
+                .inspect_err(|e| error!("Failed to frame packet from {}: {}", self.peer_addr, e))?;
+            match frame {
+                Ok(packet) => break packet,
+                Err(e) => error!("Skipping unparsable packet from {}: {}", self.peer_addr, e),
+            }
+        };
+        let peer = self.metrics_label();
+        cdde_metrics::PEER_BYTES_RECEIVED_TOTAL
+            .with_label_values(&[peer])
+            .inc_by(packet.header.length as f64);
+        cdde_metrics::PEER_MESSAGES_RECEIVED_TOTAL
+            .with_label_values(&[peer])
+            .inc();
+        Ok(packet)
+    }
+
+    /// Peer label of traffic metrics, stable across reconnections
+    fn metrics_label(&self) -> &str {
+        self.peer_host.as_deref().unwrap_or(&self.peer_addr)
     }
 
     async fn send_dwa<T: Transport>(
         &self,
-        socket: &mut T,
+        stream: &mut PeerStream<'_, T>,
         request: &cdde_core::DiameterPacket,
     ) -> Result<()> {
         use cdde_core::{DiameterAvp, DiameterHeader, DiameterPacket};
-        use tokio::io::AsyncWriteExt;
 
         let avps = vec![
             // Result-Code (268)
@@ -102,20 +603,8 @@ impl TcpClient {
                 vendor_id: None,
                 data: 2001u32.to_be_bytes().to_vec(), // DIAMETER_SUCCESS
             },
-            // Origin-Host (264)
-            DiameterAvp {
-                code: 264,
-                flags: 0x40,
-                vendor_id: None,
-                data: b"dpa.example.com".to_vec(),
-            },
-            // Origin-Realm (296)
-            DiameterAvp {
-                code: 296,
-                flags: 0x40,
-                vendor_id: None,
-                data: b"example.com".to_vec(),
-            },
+            self.origin_host_avp(),
+            self.origin_realm_avp(),
         ];
 
         let header = DiameterHeader {
@@ -129,39 +618,82 @@ impl TcpClient {
         };
 
         let packet = DiameterPacket { header, avps };
-        let bytes = packet.serialize();
-        socket.write_all(&bytes).await?;
+        self.write_packet(stream, &packet).await?;
 
         info!("Sent DWA to {}", self.peer_addr);
         Ok(())
     }
 
-    async fn send_cer<T: Transport>(&self, socket: &mut T) -> Result<()> {
-        use cdde_core::{DiameterAvp, DiameterHeader, DiameterPacket};
-        use tokio::io::AsyncWriteExt;
+    async fn send_dpa<T: Transport>(
+        &self,
+        stream: &mut PeerStream<'_, T>,
+        request: &DiameterPacket,
+    ) -> Result<()> {
+        use cdde_core::DiameterHeader;
 
         let avps = vec![
-            // Origin-Host (264)
             DiameterAvp {
-                code: 264,
-                flags: 0x40, // Mandatory
-                vendor_id: None,
-                data: b"dpa.example.com".to_vec(),
-            },
-            // Origin-Realm (296)
-            DiameterAvp {
-                code: 296,
+                code: 268,
                 flags: 0x40,
                 vendor_id: None,
-                data: b"example.com".to_vec(),
+                data: RESULT_SUCCESS.to_be_bytes().to_vec(),
             },
-            // Host-IP-Address (257) - simplified (127.0.0.1)
+            self.origin_host_avp(),
+            self.origin_realm_avp(),
+        ];
+        let header = DiameterHeader {
+            flags: 0, // Answer
+            ..request.header.clone()
+        };
+
+        self.write_packet(stream, &DiameterPacket { header, avps })
+            .await?;
+        info!("Sent DPA to {}", self.peer_addr);
+        Ok(())
+    }
+
+    async fn send_dpr<T: Transport>(&self, stream: &mut PeerStream<'_, T>) -> Result<()> {
+        use cdde_core::{DiameterAvp, DiameterHeader};
+
+        let avps = vec![
+            self.origin_host_avp(),
+            self.origin_realm_avp(),
             DiameterAvp {
-                code: 257,
+                code: AVP_DISCONNECT_CAUSE,
                 flags: 0x40,
                 vendor_id: None,
-                data: vec![0, 1, 127, 0, 0, 1],
+                data: DISCONNECT_CAUSE_REBOOTING.to_be_bytes().to_vec(),
             },
+        ];
+
+        let header = DiameterHeader {
+            version: 1,
+            length: 0,
+            flags: 0x80, // Request
+            command_code: CMD_DISCONNECT_PEER,
+            application_id: 0,
+            hop_by_hop_id: IdGenerator::global().next_hop_by_hop(),
+            end_to_end_id: IdGenerator::global().next_end_to_end(),
+        };
+
+        self.write_packet(stream, &DiameterPacket { header, avps })
+            .await?;
+        info!("Sent DPR to {}", self.peer_addr);
+        Ok(())
+    }
+
+    async fn send_cer<T: Transport>(&self, stream: &mut PeerStream<'_, T>) -> Result<()> {
+        use cdde_core::{DiameterAvp, DiameterHeader, DiameterPacket};
+
+        let mut avps = vec![self.origin_host_avp(), self.origin_realm_avp()];
+        // Host-IP-Address (257), one per local address
+        avps.extend(self.host_ips.iter().map(|ip| DiameterAvp {
+            code: AVP_HOST_IP_ADDRESS,
+            flags: 0x40,
+            vendor_id: None,
+            data: encode_address(*ip),
+        }));
+        avps.extend([
             // Vendor-Id (266)
             DiameterAvp {
                 code: 266,
@@ -176,7 +708,13 @@ impl TcpClient {
                 vendor_id: None,
                 data: b"CDDE-DPA".to_vec(),
             },
-        ];
+        ]);
+        // Vendor-Specific-Application-Id (260), one per application
+        avps.extend(
+            self.vendor_applications
+                .iter()
+                .map(|&(vendor_id, app_id)| vendor_specific_app_id(vendor_id, app_id)),
+        );
 
         let header = DiameterHeader {
             version: 1,
@@ -184,27 +722,59 @@ impl TcpClient {
             flags: 0x80, // Request
             command_code: 257,
             application_id: 0,
-            hop_by_hop_id: rand::random(),
-            end_to_end_id: rand::random(),
+            hop_by_hop_id: IdGenerator::global().next_hop_by_hop(),
+            end_to_end_id: IdGenerator::global().next_end_to_end(),
         };
 
         let packet = DiameterPacket { header, avps };
-        let bytes = packet.serialize();
-        socket.write_all(&bytes).await?;
+        self.write_packet(stream, &packet).await?;
 
         Ok(())
     }
 
-    async fn receive_cea<T: Transport>(&self, socket: &mut T) -> Result<()> {
-        use cdde_core::DiameterPacket;
+    fn origin_host_avp(&self) -> DiameterAvp {
+        DiameterAvp {
+            code: AVP_ORIGIN_HOST,
+            flags: 0x40, // Mandatory
+            vendor_id: None,
+            data: self.origin_host.as_bytes().to_vec(),
+        }
+    }
 
-        let mut buffer = [0u8; 4096];
-        let n = socket.read(&mut buffer).await?;
-        if n == 0 {
-            return Err(CddeError::ConnectionClosed);
+    fn origin_realm_avp(&self) -> DiameterAvp {
+        DiameterAvp {
+            code: AVP_ORIGIN_REALM,
+            flags: 0x40,
+            vendor_id: None,
+            data: self.origin_realm.as_bytes().to_vec(),
         }
+    }
 
-        let packet = DiameterPacket::parse(&buffer[..n])?;
+    /// Write a packet, giving up after the write timeout
+    async fn write_packet<T: Transport>(
+        &self,
+        stream: &mut PeerStream<'_, T>,
+        packet: &DiameterPacket,
+    ) -> Result<()> {
+        tokio::time::timeout(self.write_timeout, stream.send(packet.clone()))
+            .await
+            .map_err(|_| CddeError::WriteTimeout(self.write_timeout.as_millis() as u64))??;
+
+        let peer = self.metrics_label();
+        cdde_metrics::PEER_BYTES_SENT_TOTAL
+            .with_label_values(&[peer])
+            .inc_by(packet.encoded_len() as f64);
+        cdde_metrics::PEER_MESSAGES_SENT_TOTAL
+            .with_label_values(&[peer])
+            .inc();
+        Ok(())
+    }
+
+    async fn receive_cea<T: Transport>(
+        &self,
+        stream: &mut PeerStream<'_, T>,
+    ) -> Result<DiameterPacket> {
+        let packet = self.read_packet(stream).await?;
         if packet.header.command_code != 257 || packet.header.is_request() {
             return Err(CddeError::InvalidPacket("Expected CEA".to_string()));
         }
@@ -222,6 +792,842 @@ impl TcpClient {
             }
         }
 
-        Ok(())
+        if let Err(missing) = validate_command(&packet) {
+            warn!(
+                "CEA from {} is missing AVPs {:?}: {}",
+                self.peer_addr, missing, packet
+            );
+            return Err(CddeError::MissingAvp(missing[0]));
+        }
+
+        Ok(packet)
+    }
+}
+
+/// Counts a peer connection in the connection gauge while alive
+struct Connected;
+
+impl Connected {
+    fn new() -> Self {
+        cdde_metrics::PEER_CONNECTIONS.inc();
+        Self
+    }
+}
+
+impl Drop for Connected {
+    fn drop(&mut self) {
+        cdde_metrics::PEER_CONNECTIONS.dec();
+    }
+}
+
+/// Forwarded requests still waiting for an answer, by Hop-by-Hop id
+///
+/// Gives the handle's outstanding slots back as answers arrive, and those of
+/// requests left unanswered when the connection goes away. Waiters of
+/// unanswered requests see their answer channel close.
+struct InFlight<'a> {
+    ids: HashMap<u32, Option<oneshot::Sender<DiameterPacket>>>,
+    handle: &'a PeerHandle,
+}
+
+impl<'a> InFlight<'a> {
+    fn new(handle: &'a PeerHandle) -> Self {
+        Self {
+            ids: HashMap::new(),
+            handle,
+        }
+    }
+
+    fn insert(&mut self, hop_by_hop_id: u32, answer: Option<oneshot::Sender<DiameterPacket>>) {
+        // A retransmission reuses the slot of the original request
+        if self.ids.insert(hop_by_hop_id, answer).is_some() {
+            self.handle.release(1);
+        }
+    }
+
+    /// Hand an answer to its waiter, returning whether it was outstanding
+    fn complete(&mut self, answer: &DiameterPacket) -> bool {
+        let Some(waiter) = self.ids.remove(&answer.header.hop_by_hop_id) else {
+            return false;
+        };
+        self.handle.release(1);
+        if let Some(waiter) = waiter {
+            // The requester may have given up waiting
+            let _ = waiter.send(answer.clone());
+        }
+        true
+    }
+
+    fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+}
+
+impl Drop for InFlight<'_> {
+    fn drop(&mut self) {
+        self.handle.release(self.ids.len());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cdde_core::{DiameterAvp, DiameterHeader, DiameterPacket, FrameAccumulator};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    fn avp(code: u32, data: &[u8]) -> DiameterAvp {
+        DiameterAvp {
+            code,
+            flags: 0x40,
+            vendor_id: None,
+            data: data.to_vec(),
+        }
+    }
+
+    /// Successful CEA answering a CER
+    fn cea(cer: &DiameterPacket) -> DiameterPacket {
+        DiameterPacket {
+            header: DiameterHeader {
+                version: 1,
+                length: 0,
+                flags: 0,
+                command_code: 257,
+                application_id: 0,
+                hop_by_hop_id: cer.header.hop_by_hop_id,
+                end_to_end_id: cer.header.end_to_end_id,
+            },
+            avps: vec![
+                avp(268, &2001u32.to_be_bytes()),
+                avp(264, b"hss.example.com"),
+                avp(296, b"example.com"),
+                avp(257, &[0, 1, 127, 0, 0, 1]),
+                avp(266, &10415u32.to_be_bytes()),
+                avp(269, b"HSS"),
+            ],
+        }
+    }
+
+    #[tokio::test]
+    async fn test_handshake_times_out_without_cea() {
+        // Peer that accepts the connection but never answers the CER
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let peer = tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            tokio::time::sleep(Duration::from_secs(10)).await;
+            drop(socket);
+        });
+
+        let client = TcpClient::new(addr.to_string()).with_cea_timeout(Duration::from_millis(100));
+        let mut socket = client.connect().await.unwrap();
+
+        let result = tokio::time::timeout(
+            Duration::from_secs(2),
+            client.handle_connection(&mut socket, &mut client.peer_info()),
+        )
+        .await
+        .expect("Handshake did not time out");
+
+        let err = result.unwrap_err();
+        assert!(matches!(err, CddeError::HandshakeTimeout(100)));
+        assert!(err.is_retryable());
+
+        peer.abort();
+    }
+
+    #[tokio::test]
+    async fn test_handshake_counts_peer_traffic() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let peer = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut frames = FrameAccumulator::new();
+            let cer = read_packet(&mut socket, &mut frames).await;
+            let cea = cea(&cer).serialize();
+            socket.write_all(&cea).await.unwrap();
+            (cer.header.length as f64, cea.len() as f64, socket)
+        });
+
+        let (events_tx, mut events) = mpsc::channel(4);
+        let client = TcpClient::new(addr.to_string()).with_event_sender(events_tx);
+        let mut socket = client.connect().await.unwrap();
+        let connection = tokio::spawn(async move {
+            let mut peer = client.peer_info();
+            client.handle_connection(&mut socket, &mut peer).await
+        });
+        assert!(matches!(events.recv().await.unwrap(), PeerEvent::PeerUp(_)));
+
+        let (cer_len, cea_len, _socket) = peer.await.unwrap();
+        let addr = addr.to_string();
+        let label = [addr.as_str()];
+        assert_eq!(
+            cdde_metrics::PEER_BYTES_SENT_TOTAL
+                .with_label_values(&label)
+                .get(),
+            cer_len
+        );
+        assert_eq!(
+            cdde_metrics::PEER_BYTES_RECEIVED_TOTAL
+                .with_label_values(&label)
+                .get(),
+            cea_len
+        );
+        assert_eq!(
+            cdde_metrics::PEER_MESSAGES_SENT_TOTAL
+                .with_label_values(&label)
+                .get(),
+            1.0
+        );
+        assert_eq!(
+            cdde_metrics::PEER_MESSAGES_RECEIVED_TOTAL
+                .with_label_values(&label)
+                .get(),
+            1.0
+        );
+
+        connection.abort();
+    }
+
+    #[tokio::test]
+    async fn test_handshake_with_fragmented_cea() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let peer = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut buffer = [0u8; 4096];
+            let n = socket.read(&mut buffer).await.unwrap();
+            let cer = DiameterPacket::parse(&buffer[..n]).unwrap();
+
+            // A large CEA advertising many vendors
+            let mut cea = cea(&cer);
+            for vendor in 0..200u32 {
+                cea.avps.push(avp(265, &vendor.to_be_bytes()));
+            }
+            let cea = cea.serialize();
+
+            // Deliver the CEA in two separate reads
+            let (first, second) = cea.split_at(cea.len() / 2);
+            socket.write_all(first).await.unwrap();
+            socket.flush().await.unwrap();
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            socket.write_all(second).await.unwrap();
+
+            // The connector only answers DWRs once the handshake succeeded
+            let dwr = DiameterPacket {
+                header: DiameterHeader {
+                    version: 1,
+                    length: 0,
+                    flags: 0x80,
+                    command_code: 280,
+                    application_id: 0,
+                    hop_by_hop_id: 77,
+                    end_to_end_id: 78,
+                },
+                avps: vec![],
+            };
+            socket.write_all(&dwr.serialize()).await.unwrap();
+
+            let n = socket.read(&mut buffer).await.unwrap();
+            DiameterPacket::parse(&buffer[..n]).unwrap()
+        });
+
+        let (events_tx, mut events) = mpsc::channel(4);
+        let client = TcpClient::new(addr.to_string())
+            .with_cea_timeout(Duration::from_secs(2))
+            .with_virtual_routers(vec!["vr001".to_string()])
+            .with_event_sender(events_tx);
+        let mut socket = client.connect().await.unwrap();
+        let connection = tokio::spawn(async move {
+            let mut peer = client.peer_info();
+            client.handle_connection(&mut socket, &mut peer).await
+        });
+
+        let dwa = tokio::time::timeout(Duration::from_secs(5), peer)
+            .await
+            .expect("No DWA received")
+            .unwrap();
+        assert_eq!(dwa.header.command_code, 280);
+        assert!(dwa.header.is_answer());
+        assert_eq!(dwa.header.hop_by_hop_id, 77);
+
+        // The peer is identified by the CEA Origin-Host
+        match events.recv().await.unwrap() {
+            PeerEvent::PeerUp(peer) => {
+                assert_eq!(peer.peer_id, "hss.example.com");
+                assert_eq!(peer.addr, addr.to_string());
+                assert_eq!(peer.virtual_router_ids, vec!["vr001".to_string()]);
+            }
+            other => panic!("Unexpected event {other:?}"),
+        }
+
+        connection.abort();
+    }
+
+    #[tokio::test]
+    async fn test_silent_peer_triggers_reconnect() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        // Peer that completes the handshake and then goes quiet without closing
+        let peer = tokio::spawn(async move {
+            let mut connections = Vec::new();
+            for _ in 0..2 {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut buffer = [0u8; 4096];
+                let n = socket.read(&mut buffer).await.unwrap();
+                let cer = DiameterPacket::parse(&buffer[..n]).unwrap();
+
+                socket.write_all(&cea(&cer).serialize()).await.unwrap();
+                connections.push(socket);
+            }
+            connections
+        });
+
+        let (events_tx, mut events) = mpsc::channel(8);
+        let client = TcpClient::new(addr.to_string())
+            .with_read_timeout(Duration::from_millis(200))
+            .with_reconnect_interval(Duration::from_millis(50))
+            .with_event_sender(events_tx);
+        let connector = tokio::spawn(async move { client.start().await });
+
+        assert!(matches!(events.recv().await.unwrap(), PeerEvent::PeerUp(_)));
+        match tokio::time::timeout(Duration::from_secs(2), events.recv())
+            .await
+            .expect("Silent peer was not dropped")
+            .unwrap()
+        {
+            PeerEvent::PeerDown { reason, .. } => {
+                assert_eq!(reason, CddeError::ReadTimeout(200).to_string());
+            }
+            other => panic!("Unexpected event {other:?}"),
+        }
+
+        // The connector reconnects and completes a new handshake
+        tokio::time::timeout(Duration::from_secs(2), peer)
+            .await
+            .expect("No reconnection after the read timeout")
+            .unwrap();
+        assert!(matches!(events.recv().await.unwrap(), PeerEvent::PeerUp(_)));
+
+        connector.abort();
+    }
+
+    #[tokio::test]
+    async fn test_cea_missing_product_name_fails_handshake() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let peer = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut buffer = [0u8; 4096];
+            let n = socket.read(&mut buffer).await.unwrap();
+            let cer = DiameterPacket::parse(&buffer[..n]).unwrap();
+
+            let mut cea = cea(&cer);
+            cea.avps.retain(|avp| avp.code != 269);
+            socket.write_all(&cea.serialize()).await.unwrap();
+            socket
+        });
+
+        let client = TcpClient::new(addr.to_string());
+        let mut socket = client.connect().await.unwrap();
+        let result = tokio::time::timeout(
+            Duration::from_secs(2),
+            client.handle_connection(&mut socket, &mut client.peer_info()),
+        )
+        .await
+        .expect("Handshake did not fail");
+
+        assert!(matches!(result, Err(CddeError::MissingAvp(269))));
+        peer.await.unwrap();
+    }
+
+    /// Read the next complete message from a test peer's socket
+    async fn read_packet(socket: &mut TcpStream, frames: &mut FrameAccumulator) -> DiameterPacket {
+        let mut buffer = [0u8; 4096];
+        loop {
+            if let Some(frame) = frames.next_frame().unwrap() {
+                return DiameterPacket::parse(&frame).unwrap();
+            }
+            let n = socket.read(&mut buffer).await.unwrap();
+            assert!(n > 0, "Connector closed the connection");
+            frames.extend(&buffer[..n]);
+        }
+    }
+
+    fn request(hop_by_hop_id: u32) -> DiameterPacket {
+        DiameterPacket {
+            header: DiameterHeader {
+                version: 1,
+                length: 0,
+                flags: 0xC0,
+                command_code: 316,
+                application_id: 16777251,
+                hop_by_hop_id,
+                end_to_end_id: hop_by_hop_id,
+            },
+            avps: vec![avp(263, b"session;1")],
+        }
+    }
+
+    #[tokio::test]
+    async fn test_peer_dpr_is_answered_and_ends_connection() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let peer = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut frames = FrameAccumulator::new();
+            let cer = read_packet(&mut socket, &mut frames).await;
+            socket.write_all(&cea(&cer).serialize()).await.unwrap();
+
+            let message = |command_code, flags, hop_by_hop_id, avps| DiameterPacket {
+                header: DiameterHeader {
+                    version: 1,
+                    length: 0,
+                    flags,
+                    command_code,
+                    application_id: 0,
+                    hop_by_hop_id,
+                    end_to_end_id: hop_by_hop_id,
+                },
+                avps,
+            };
+            // A stray DWA is not mistaken for a request
+            let dwa = message(280, 0, 40, vec![avp(268, &2001u32.to_be_bytes())]);
+            socket.write_all(&dwa.serialize()).await.unwrap();
+
+            // The DPR arrives in two reads
+            let dpr = message(
+                CMD_DISCONNECT_PEER,
+                0x80,
+                41,
+                vec![
+                    avp(264, b"hss.example.com"),
+                    avp(296, b"example.com"),
+                    avp(AVP_DISCONNECT_CAUSE, &2u32.to_be_bytes()),
+                ],
+            )
+            .serialize();
+            let (first, second) = dpr.split_at(10);
+            socket.write_all(first).await.unwrap();
+            socket.flush().await.unwrap();
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            socket.write_all(second).await.unwrap();
+
+            read_packet(&mut socket, &mut frames).await
+        });
+
+        let client = TcpClient::new(addr.to_string());
+        let mut socket = client.connect().await.unwrap();
+        let result = tokio::time::timeout(
+            Duration::from_secs(5),
+            client.handle_connection(&mut socket, &mut client.peer_info()),
+        )
+        .await
+        .expect("Connection did not end after the DPR");
+        assert!(result.is_ok());
+
+        // DO_NOT_WANT_TO_TALK_TO_YOU holds off the reconnect
+        assert!(client.reconnect_delay() >= ConnectorSettings::default().refused_reconnect_delay);
+        assert!(client.reconnect_delay() < ConnectorSettings::default().refused_reconnect_delay);
+
+        let dpa = peer.await.unwrap();
+        assert_eq!(dpa.header.command_code, CMD_DISCONNECT_PEER);
+        assert!(dpa.header.is_answer());
+        assert_eq!(dpa.header.hop_by_hop_id, 41);
+        assert_eq!(dpa.find_avp(268).unwrap().data, 2001u32.to_be_bytes());
+    }
+
+    #[tokio::test]
+    async fn test_peer_request_is_refused() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let peer = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut frames = FrameAccumulator::new();
+            let cer = read_packet(&mut socket, &mut frames).await;
+            socket.write_all(&cea(&cer).serialize()).await.unwrap();
+
+            socket.write_all(&request(60).serialize()).await.unwrap();
+            read_packet(&mut socket, &mut frames).await
+        });
+
+        let client = TcpClient::new(addr.to_string());
+        let mut socket = client.connect().await.unwrap();
+        let connection = tokio::spawn(async move {
+            client
+                .handle_connection(&mut socket, &mut client.peer_info())
+                .await
+        });
+
+        let answer = tokio::time::timeout(Duration::from_secs(5), peer)
+            .await
+            .expect("Peer request was not answered")
+            .unwrap();
+        assert!(answer.header.is_answer());
+        assert_eq!(answer.header.hop_by_hop_id, 60);
+        assert_eq!(
+            answer.find_avp(268).unwrap().data,
+            RESULT_UNABLE_TO_DELIVER.to_be_bytes()
+        );
+        assert_eq!(answer.find_avp(263).unwrap().data, b"session;1");
+        assert!(!connection.is_finished());
+        connection.abort();
+    }
+
+    #[tokio::test]
+    async fn test_unparsable_message_is_skipped() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let peer = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut frames = FrameAccumulator::new();
+            let cer = read_packet(&mut socket, &mut frames).await;
+            socket.write_all(&cea(&cer).serialize()).await.unwrap();
+
+            // The AVP of this request claims more data than the message holds
+            let mut malformed = request(50).serialize();
+            malformed[27] = 0xFF;
+            socket.write_all(&malformed).await.unwrap();
+
+            let dwr = DiameterPacket {
+                header: DiameterHeader {
+                    version: 1,
+                    length: 0,
+                    flags: 0x80,
+                    command_code: 280,
+                    application_id: 0,
+                    hop_by_hop_id: 51,
+                    end_to_end_id: 51,
+                },
+                avps: vec![avp(264, b"hss.example.com"), avp(296, b"example.com")],
+            };
+            socket.write_all(&dwr.serialize()).await.unwrap();
+            read_packet(&mut socket, &mut frames).await
+        });
+
+        let client = TcpClient::new(addr.to_string());
+        let mut socket = client.connect().await.unwrap();
+        let connection = tokio::spawn(async move {
+            client
+                .handle_connection(&mut socket, &mut client.peer_info())
+                .await
+        });
+
+        // The connection survives the bad message and answers the next one
+        let dwa = tokio::time::timeout(Duration::from_secs(5), peer)
+            .await
+            .expect("No DWA after the unparsable message")
+            .unwrap();
+        assert_eq!(dwa.header.command_code, 280);
+        assert_eq!(dwa.header.hop_by_hop_id, 51);
+        assert!(!connection.is_finished());
+        connection.abort();
+    }
+
+    #[tokio::test]
+    async fn test_drain_rejects_forwards_and_sends_dpr() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (answer_tx, answer_rx) = tokio::sync::oneshot::channel::<()>();
+
+        let peer = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut frames = FrameAccumulator::new();
+            let cer = read_packet(&mut socket, &mut frames).await;
+            socket.write_all(&cea(&cer).serialize()).await.unwrap();
+
+            // Hold the answer to the in-flight request until told to release it
+            let forwarded = read_packet(&mut socket, &mut frames).await;
+            assert_eq!(forwarded.header.hop_by_hop_id, 500);
+            answer_rx.await.unwrap();
+            let mut answer = forwarded.clone();
+            answer.header.flags = 0x40;
+            answer.avps.push(avp(268, &2001u32.to_be_bytes()));
+            socket.write_all(&answer.serialize()).await.unwrap();
+
+            // Nothing else is forwarded: the next message is the DPR
+            let dpr = read_packet(&mut socket, &mut frames).await;
+            let mut dpa = dpr.clone();
+            dpa.header.flags = 0;
+            dpa.avps = vec![avp(268, &2001u32.to_be_bytes())];
+            socket.write_all(&dpa.serialize()).await.unwrap();
+            dpr
+        });
+
+        let (events_tx, mut events) = mpsc::channel(4);
+        let client = TcpClient::new(addr.to_string())
+            .with_reconnect_interval(Duration::from_millis(50))
+            .with_event_sender(events_tx);
+        let handle = client.handle();
+        let connector = tokio::spawn(async move { client.start().await });
+
+        assert!(matches!(events.recv().await.unwrap(), PeerEvent::PeerUp(_)));
+        handle.forward(request(500)).await.unwrap();
+
+        handle.drain(Duration::from_secs(5));
+        assert!(matches!(
+            events.recv().await.unwrap(),
+            PeerEvent::PeerDraining(_)
+        ));
+        assert!(handle.is_draining());
+        let rejected = handle.forward(request(501)).await.unwrap_err();
+        assert!(matches!(rejected, CddeError::PeerBusy(_)));
+        assert_eq!(rejected.to_result_code(), 3004);
+
+        // The DPR only goes out once the outstanding answer arrives
+        answer_tx.send(()).unwrap();
+        let dpr = tokio::time::timeout(Duration::from_secs(5), peer)
+            .await
+            .expect("Peer never received a DPR")
+            .unwrap();
+        assert_eq!(dpr.header.command_code, CMD_DISCONNECT_PEER);
+        assert!(dpr.header.is_request());
+        assert_eq!(
+            dpr.find_avp(AVP_DISCONNECT_CAUSE).unwrap().data,
+            DISCONNECT_CAUSE_REBOOTING.to_be_bytes()
+        );
+
+        // The drained connector stops instead of reconnecting
+        tokio::time::timeout(Duration::from_secs(5), connector)
+            .await
+            .expect("Connector kept running after the drain")
+            .unwrap();
+        match events.recv().await.unwrap() {
+            PeerEvent::PeerDown { reason, .. } => assert_eq!(reason, "drained"),
+            other => panic!("Unexpected event {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_request_waits_for_its_answer() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let peer = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut frames = FrameAccumulator::new();
+            let cer = read_packet(&mut socket, &mut frames).await;
+            socket.write_all(&cea(&cer).serialize()).await.unwrap();
+
+            let forwarded = read_packet(&mut socket, &mut frames).await;
+            let mut answer = forwarded.clone();
+            answer.header.flags = 0x40;
+            answer.avps.push(avp(268, &2001u32.to_be_bytes()));
+            socket.write_all(&answer.serialize()).await.unwrap();
+
+            // The second request is never answered
+            read_packet(&mut socket, &mut frames).await;
+            forwarded
+        });
+
+        let (events_tx, mut events) = mpsc::channel(4);
+        let client = TcpClient::new(addr.to_string())
+            .with_reconnect_interval(Duration::from_secs(60))
+            .with_event_sender(events_tx);
+        let handle = client.handle();
+        let connector = tokio::spawn(async move { client.start().await });
+        assert!(matches!(events.recv().await.unwrap(), PeerEvent::PeerUp(_)));
+
+        let answer = tokio::time::timeout(Duration::from_secs(5), handle.request(request(500)))
+            .await
+            .expect("No answer to the request")
+            .unwrap();
+        assert!(answer.header.is_answer());
+        assert_eq!(answer.header.hop_by_hop_id, 500);
+        assert_eq!(answer.find_avp(268).unwrap().data, 2001u32.to_be_bytes());
+        assert_eq!(handle.outstanding(), 0);
+
+        // The peer saw a Hop-by-Hop id of this connection
+        let unanswered = tokio::spawn({
+            let handle = handle.clone();
+            async move { handle.request(request(501)).await }
+        });
+        let forwarded = peer.await.unwrap();
+        assert_ne!(forwarded.header.hop_by_hop_id, 500);
+
+        // Losing the connection fails the requests still waiting
+        let result = tokio::time::timeout(Duration::from_secs(5), unanswered)
+            .await
+            .expect("Waiting request never failed")
+            .unwrap();
+        assert!(matches!(result, Err(CddeError::ConnectionClosed)));
+        assert_eq!(handle.outstanding(), 0);
+
+        connector.abort();
+    }
+
+    #[tokio::test]
+    async fn test_cer_advertises_every_host_ip() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let peer = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut frames = FrameAccumulator::new();
+            let cer = read_packet(&mut socket, &mut frames).await;
+
+            // A multihomed peer answers with two addresses as well
+            let mut cea = cea(&cer);
+            cea.avps.push(avp(
+                257,
+                &cdde_core::address::encode_address("2001:db8::2".parse().unwrap()),
+            ));
+            socket.write_all(&cea.serialize()).await.unwrap();
+            (cer, socket)
+        });
+
+        let host_ips: Vec<IpAddr> = vec![
+            "192.0.2.1".parse().unwrap(),
+            "198.51.100.1".parse().unwrap(),
+        ];
+        let (events_tx, mut events) = mpsc::channel(4);
+        let client = TcpClient::new(addr.to_string())
+            .with_identity(
+                "dpa01.epc.example.com".to_string(),
+                "epc.example.com".to_string(),
+            )
+            .with_host_ips(host_ips.clone())
+            .with_vendor_applications(vec![(10415, 16777251)])
+            .with_event_sender(events_tx);
+        let mut socket = client.connect().await.unwrap();
+        let connection = tokio::spawn(async move {
+            let mut peer = client.peer_info();
+            client.handle_connection(&mut socket, &mut peer).await
+        });
+
+        let (cer, _socket) = peer.await.unwrap();
+        assert_eq!(cer.find_all_avps(257).len(), 2);
+        assert_eq!(cer.host_ip_addresses(), host_ips);
+        assert!(validate_command(&cer).is_ok());
+        assert_eq!(cer.header.command_code, 257);
+        assert!(cer.header.is_request());
+        assert_eq!(cer.find_avp(264).unwrap().data, b"dpa01.epc.example.com");
+        assert_eq!(cer.find_avp(296).unwrap().data, b"epc.example.com");
+        let application = cer.find_avp(260).unwrap().parse_grouped().unwrap();
+        assert_eq!(application[1].data, 16777251u32.to_be_bytes());
+
+        match events.recv().await.unwrap() {
+            PeerEvent::PeerUp(peer) => assert_eq!(
+                peer.host_ips,
+                vec![
+                    "127.0.0.1".parse::<IpAddr>().unwrap(),
+                    "2001:db8::2".parse().unwrap()
+                ]
+            ),
+            other => panic!("Unexpected event {other:?}"),
+        }
+
+        connection.abort();
+    }
+
+    #[tokio::test]
+    async fn test_forwards_beyond_outstanding_cap_are_refused() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (answer_tx, answer_rx) = tokio::sync::oneshot::channel::<()>();
+
+        let peer = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut frames = FrameAccumulator::new();
+            let cer = read_packet(&mut socket, &mut frames).await;
+            socket.write_all(&cea(&cer).serialize()).await.unwrap();
+
+            let forwarded = read_packet(&mut socket, &mut frames).await;
+            answer_rx.await.unwrap();
+            let mut answer = forwarded.clone();
+            answer.header.flags = 0x40;
+            answer.avps.push(avp(268, &2001u32.to_be_bytes()));
+            socket.write_all(&answer.serialize()).await.unwrap();
+
+            // Once answered, the next forward is accepted again
+            read_packet(&mut socket, &mut frames).await
+        });
+
+        let (events_tx, mut events) = mpsc::channel(4);
+        let client = TcpClient::new(addr.to_string())
+            .with_max_outstanding(1)
+            .with_event_sender(events_tx);
+        let handle = client.handle();
+        let connector = tokio::spawn(async move { client.start().await });
+        assert!(matches!(events.recv().await.unwrap(), PeerEvent::PeerUp(_)));
+
+        handle.forward(request(500)).await.unwrap();
+        let rejected = handle.forward(request(501)).await.unwrap_err();
+        assert!(matches!(rejected, CddeError::PeerBusy(_)));
+        assert_eq!(rejected.to_result_code(), 3004);
+        assert_eq!(handle.outstanding(), 1);
+
+        answer_tx.send(()).unwrap();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while handle.outstanding() > 0 {
+            assert!(Instant::now() < deadline, "Answer never released its slot");
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        handle.forward(request(502)).await.unwrap();
+        let forwarded = tokio::time::timeout(Duration::from_secs(5), peer)
+            .await
+            .expect("Peer never received the second forward")
+            .unwrap();
+        assert_eq!(forwarded.header.hop_by_hop_id, 502);
+
+        connector.abort();
+    }
+
+    #[tokio::test]
+    async fn test_short_lived_connections_keep_backing_off() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        // A peer completing every handshake, then dropping the connection
+        tokio::spawn(async move {
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut frames = FrameAccumulator::new();
+                let cer = read_packet(&mut socket, &mut frames).await;
+                socket.write_all(&cea(&cer).serialize()).await.unwrap();
+            }
+        });
+
+        let client = std::sync::Arc::new(TcpClient::new(addr.to_string()).with_backoff(
+            BackoffPolicy {
+                initial: Duration::from_millis(10),
+                max: Duration::from_secs(1),
+                multiplier: 2.0,
+                jitter: 0.0,
+                reset_after: Duration::from_secs(3600),
+            },
+        ));
+        let connector = tokio::spawn({
+            let client = client.clone();
+            async move { client.start().await }
+        });
+
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while client.failures.load(Ordering::Relaxed) < 3 {
+                tokio::time::sleep(Duration::from_millis(5)).await;
+            }
+        })
+        .await
+        .expect("Failures were reset by the handshakes");
+        connector.abort();
+    }
+
+    #[test]
+    fn test_non_finite_numbers_are_rejected() {
+        assert_eq!(parse_finite("0.5"), Some(0.5));
+        assert_eq!(parse_finite("NaN"), None);
+        assert_eq!(parse_finite("inf"), None);
+        assert_eq!(parse_finite("fast"), None);
     }
 }
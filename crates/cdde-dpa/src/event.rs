@@ -0,0 +1,98 @@
+use cdde_proto::{PeerStatus, PeerStatusRequest};
+use std::net::IpAddr;
+
+/// Identity of a peer reported in peer events
+#[derive(Debug, Clone, PartialEq)]
+pub struct PeerInfo {
+    /// Peer node identifier (Origin-Host from the CEA, or the address)
+    pub peer_id: String,
+
+    /// Transport address of the peer
+    pub addr: String,
+
+    /// Virtual routers served through this peer
+    pub virtual_router_ids: Vec<String>,
+
+    /// Host-IP-Address values advertised in the CEA
+    pub host_ips: Vec<IpAddr>,
+}
+
+/// Peer status change emitted by the connector
+#[derive(Debug, Clone, PartialEq)]
+pub enum PeerEvent {
+    /// Capabilities exchange completed
+    PeerUp(PeerInfo),
+
+    /// Drain started, outstanding requests are still being answered
+    PeerDraining(PeerInfo),
+
+    /// Connection lost or handshake failed
+    PeerDown { peer: PeerInfo, reason: String },
+}
+
+impl PeerEvent {
+    /// Peer the event refers to
+    pub fn peer(&self) -> &PeerInfo {
+        match self {
+            Self::PeerUp(peer) | Self::PeerDraining(peer) | Self::PeerDown { peer, .. } => peer,
+        }
+    }
+
+    /// Convert into the DPA to DFL status notification
+    pub fn to_status_request(&self) -> PeerStatusRequest {
+        let status = match self {
+            Self::PeerUp(_) => PeerStatus::Up,
+            Self::PeerDraining(_) => PeerStatus::Draining,
+            Self::PeerDown { .. } => PeerStatus::Down,
+        };
+        let peer = self.peer();
+
+        PeerStatusRequest {
+            peer_node_id: peer.peer_id.clone(),
+            current_status: status as i32,
+            virtual_router_ids: peer.virtual_router_ids.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn peer() -> PeerInfo {
+        PeerInfo {
+            peer_id: "hss01.example.com".to_string(),
+            addr: "10.0.0.1:3868".to_string(),
+            virtual_router_ids: vec!["vr001".to_string()],
+            host_ips: vec![],
+        }
+    }
+
+    #[test]
+    fn test_peer_up_to_status_request() {
+        let request = PeerEvent::PeerUp(peer()).to_status_request();
+
+        assert_eq!(request.peer_node_id, "hss01.example.com");
+        assert_eq!(request.current_status(), PeerStatus::Up);
+        assert_eq!(request.virtual_router_ids, vec!["vr001".to_string()]);
+    }
+
+    #[test]
+    fn test_peer_down_to_status_request() {
+        let request = PeerEvent::PeerDown {
+            peer: peer(),
+            reason: "connection closed".to_string(),
+        }
+        .to_status_request();
+
+        assert_eq!(request.current_status(), PeerStatus::Down);
+    }
+
+    #[test]
+    fn test_peer_draining_to_status_request() {
+        let request = PeerEvent::PeerDraining(peer()).to_status_request();
+
+        assert_eq!(request.peer_node_id, "hss01.example.com");
+        assert_eq!(request.current_status(), PeerStatus::Draining);
+    }
+}
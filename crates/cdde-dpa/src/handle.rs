@@ -0,0 +1,129 @@
+use crate::ids::IdGenerator;
+use cdde_core::{CddeError, DiameterPacket, Result};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot, watch};
+
+/// Request queued for the connector
+pub(crate) struct Forward {
+    pub(crate) packet: DiameterPacket,
+    /// Receives the peer's answer, if anyone waits for it
+    pub(crate) answer: Option<oneshot::Sender<DiameterPacket>>,
+}
+
+/// Handle for sending requests through a connector and draining it
+///
+/// Once a drain is requested the connector takes no new forwards, waits for
+/// the answers it is still owed (up to the drain deadline), then sends a DPR
+/// and closes the connection without reconnecting.
+///
+/// Requests count as outstanding from the moment they are queued until the
+/// peer answers them or the connection carrying them goes away; beyond
+/// `max_outstanding` further forwards are refused.
+#[derive(Clone)]
+pub struct PeerHandle {
+    forwards: mpsc::Sender<Forward>,
+    drain: Arc<watch::Sender<Option<Duration>>>,
+    outstanding: Arc<AtomicUsize>,
+    max_outstanding: usize,
+}
+
+impl PeerHandle {
+    pub(crate) fn new(
+        forwards: mpsc::Sender<Forward>,
+        drain: watch::Sender<Option<Duration>>,
+    ) -> Self {
+        Self {
+            forwards,
+            drain: Arc::new(drain),
+            outstanding: Arc::new(AtomicUsize::new(0)),
+            max_outstanding: usize::MAX,
+        }
+    }
+
+    pub(crate) fn set_max_outstanding(&mut self, max_outstanding: usize) {
+        self.max_outstanding = max_outstanding;
+    }
+
+    /// Queue a request for the peer
+    ///
+    /// Fails with `PeerBusy` (DIAMETER_TOO_BUSY) while draining or when the
+    /// peer already has `max_outstanding` requests outstanding.
+    pub async fn forward(&self, packet: DiameterPacket) -> Result<()> {
+        self.queue(Forward {
+            packet,
+            answer: None,
+        })
+        .await
+    }
+
+    /// Send a request to the peer and wait for its answer
+    ///
+    /// The request goes out with a Hop-by-Hop id of this connection and the
+    /// answer comes back with the original one. Fails like `forward`, and
+    /// with `ConnectionClosed` if the connection goes away first.
+    pub async fn request(&self, mut packet: DiameterPacket) -> Result<DiameterPacket> {
+        let hop_by_hop_id = packet.header.hop_by_hop_id;
+        packet.header.hop_by_hop_id = IdGenerator::global().next_hop_by_hop();
+        let (answer_tx, answer_rx) = oneshot::channel();
+        self.queue(Forward {
+            packet,
+            answer: Some(answer_tx),
+        })
+        .await?;
+
+        let mut answer = answer_rx.await.map_err(|_| CddeError::ConnectionClosed)?;
+        answer.header.hop_by_hop_id = hop_by_hop_id;
+        Ok(answer)
+    }
+
+    async fn queue(&self, forward: Forward) -> Result<()> {
+        if self.is_draining() {
+            return Err(CddeError::PeerBusy("connection is draining".to_string()));
+        }
+        let reserved =
+            self.outstanding
+                .fetch_update(Ordering::AcqRel, Ordering::Acquire, |count| {
+                    (count < self.max_outstanding).then_some(count + 1)
+                });
+        if reserved.is_err() {
+            return Err(CddeError::PeerBusy(format!(
+                "{} requests outstanding",
+                self.max_outstanding
+            )));
+        }
+        self.forwards.send(forward).await.map_err(|_| {
+            self.release(1);
+            CddeError::ConnectionClosed
+        })
+    }
+
+    /// Number of forwarded requests not answered yet
+    pub fn outstanding(&self) -> usize {
+        self.outstanding.load(Ordering::Acquire)
+    }
+
+    /// Return slots of requests that were answered or abandoned
+    pub(crate) fn release(&self, count: usize) {
+        self.outstanding.fetch_sub(count, Ordering::AcqRel);
+    }
+
+    /// Stop taking forwards and disconnect once in-flight requests complete
+    ///
+    /// Requests still unanswered after `deadline` are abandoned.
+    pub fn drain(&self, deadline: Duration) {
+        self.drain.send_if_modified(|drain| {
+            if drain.is_some() {
+                return false;
+            }
+            *drain = Some(deadline);
+            true
+        });
+    }
+
+    /// Whether a drain was requested
+    pub fn is_draining(&self) -> bool {
+        self.drain.borrow().is_some()
+    }
+}
@@ -0,0 +1,88 @@
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::OnceLock;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Allocator for hop-by-hop and end-to-end identifiers of originated requests
+///
+/// Both counters increase monotonically and wrap around. Following RFC 6733
+/// the hop-by-hop counter starts at a random value, and the end-to-end
+/// counter starts with the low 12 bits of the current time in its high bits
+/// and a random value in its low 20 bits.
+#[derive(Debug)]
+pub struct IdGenerator {
+    hop_by_hop: AtomicU32,
+    end_to_end: AtomicU32,
+}
+
+impl IdGenerator {
+    /// Create a generator with randomized starting values
+    pub fn new() -> Self {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs() as u32)
+            .unwrap_or_default();
+        let end_to_end = (now << 20) | (rand::random::<u32>() & 0x000F_FFFF);
+
+        Self::with_start(rand::random(), end_to_end)
+    }
+
+    /// Create a generator with fixed starting values
+    pub fn with_start(hop_by_hop: u32, end_to_end: u32) -> Self {
+        Self {
+            hop_by_hop: AtomicU32::new(hop_by_hop),
+            end_to_end: AtomicU32::new(end_to_end),
+        }
+    }
+
+    /// Process-wide generator shared by all connections
+    pub fn global() -> &'static IdGenerator {
+        static GLOBAL: OnceLock<IdGenerator> = OnceLock::new();
+        GLOBAL.get_or_init(IdGenerator::new)
+    }
+
+    /// Allocate the next hop-by-hop identifier
+    pub fn next_hop_by_hop(&self) -> u32 {
+        self.hop_by_hop.fetch_add(1, Ordering::Relaxed)
+    }
+
+    /// Allocate the next end-to-end identifier
+    pub fn next_end_to_end(&self) -> u32 {
+        self.end_to_end.fetch_add(1, Ordering::Relaxed)
+    }
+}
+
+impl Default for IdGenerator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_sequential_ids_do_not_repeat() {
+        let ids = IdGenerator::with_start(u32::MAX - 5, 0);
+
+        let window: HashSet<u32> = (0..10_000).map(|_| ids.next_hop_by_hop()).collect();
+        assert_eq!(window.len(), 10_000);
+        assert!(window.contains(&u32::MAX));
+        assert!(window.contains(&0)); // Wrapped around
+    }
+
+    #[test]
+    fn test_start_is_randomized() {
+        let starts: HashSet<u32> = (0..8)
+            .map(|_| IdGenerator::new().next_hop_by_hop())
+            .collect();
+        assert!(starts.len() > 1);
+
+        // Eight equal draws of the random low 20 bits are practically impossible
+        let low_bits: HashSet<u32> = (0..8)
+            .map(|_| IdGenerator::new().next_end_to_end() & 0x000F_FFFF)
+            .collect();
+        assert!(low_bits.len() > 1);
+    }
+}
@@ -1,10 +1,30 @@
+mod backoff;
 mod connector;
+mod event;
+mod handle;
+mod ids;
+mod notifier;
+mod peers;
+mod relay;
 mod state_machine;
 
-pub use connector::TcpClient;
+pub use backoff::BackoffPolicy;
+pub use connector::{ConnectorSettings, TcpClient, DEFAULT_MAX_OUTSTANDING};
+pub use event::{PeerEvent, PeerInfo};
+pub use handle::PeerHandle;
+pub use ids::IdGenerator;
+pub use notifier::{DflNotifier, DEFAULT_DFL_STATUS_ENDPOINT};
+pub use peers::{connector_for, fetch_peers, load_connectors, CmsPeer};
+pub use relay::{PeerRelay, DEFAULT_RELAY_BIND_ADDR};
 pub use state_machine::PeerStateMachine;
 
-use tracing::info;
+use cdde_proto::peer_relay_service_server::PeerRelayServiceServer;
+use std::collections::HashMap;
+use std::time::Duration;
+use tracing::{error, info, warn};
+
+/// Default time allowed for outstanding answers when draining
+const DEFAULT_DRAIN_DEADLINE: Duration = Duration::from_secs(10);
 
 #[tokio::main]
 async fn main() {
@@ -24,16 +44,158 @@ async fn main() {
     let peer_addr = std::env::var("PEER_ADDR").unwrap_or_else(|_| "127.0.0.1:3868".to_string());
     let _fsm = PeerStateMachine::new(peer_addr.clone());
 
-    // Start Connector
-    let client = TcpClient::new(peer_addr);
+    let settings = ConnectorSettings::from_env();
+    let virtual_router_ids: Vec<String> = std::env::var("VIRTUAL_ROUTER_IDS")
+        .unwrap_or_default()
+        .split(',')
+        .map(|id| id.trim().to_string())
+        .filter(|id| !id.is_empty())
+        .collect();
+
+    // Connect to the peers configured in the CMS, or to PEER_ADDR alone
+    let clients = match std::env::var("CMS_URL") {
+        Ok(cms_url) => match load_connectors(&cms_url, &virtual_router_ids, &settings).await {
+            Ok(clients) => clients,
+            Err(e) => {
+                error!("Failed to load peers from the CMS at {}: {}", cms_url, e);
+                std::process::exit(1);
+            }
+        },
+        Err(_) => vec![TcpClient::new(peer_addr)
+            .with_settings(&settings)
+            .with_virtual_routers(virtual_router_ids)],
+    };
+
+    // Local addresses advertised in the CER, several when multihomed
+    let host_ips: Vec<std::net::IpAddr> = std::env::var("HOST_IP_ADDRESSES")
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|ip| !ip.is_empty())
+        .filter_map(|ip| match ip.parse() {
+            Ok(ip) => Some(ip),
+            Err(e) => {
+                warn!("Ignoring invalid host IP address {}: {}", ip, e);
+                None
+            }
+        })
+        .collect();
+
+    // Applications advertised in the CER, as vendor:application pairs
+    let vendor_applications: Vec<(u32, u32)> = std::env::var("VENDOR_APPLICATIONS")
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|pair| !pair.is_empty())
+        .filter_map(|pair| {
+            let parsed = pair
+                .split_once(':')
+                .and_then(|(vendor, app)| Some((vendor.parse().ok()?, app.parse().ok()?)));
+            if parsed.is_none() {
+                warn!("Ignoring invalid vendor application {}", pair);
+            }
+            parsed
+        })
+        .collect();
 
-    // Spawn client loop
+    // Report peer status changes to the DFL
+    let dfl_endpoint = std::env::var("DFL_STATUS_ENDPOINT")
+        .unwrap_or_else(|_| DEFAULT_DFL_STATUS_ENDPOINT.to_string());
+    let mut notifier = DflNotifier::new(dfl_endpoint);
+    // The CMS keeps the same view for the operations dashboard
+    let mut cms_notifier = std::env::var("CMS_STATUS_ENDPOINT")
+        .ok()
+        .map(DflNotifier::new);
+    // The DCR stops routing to draining peers
+    let mut dcr_notifier = std::env::var("DCR_STATUS_ENDPOINT")
+        .ok()
+        .map(DflNotifier::new);
+    let (events_tx, mut events) = tokio::sync::mpsc::channel::<PeerEvent>(64);
     tokio::spawn(async move {
-        client.start().await;
+        while let Some(event) = events.recv().await {
+            let status = event.to_status_request();
+            info!(
+                peer = %status.peer_node_id,
+                status = ?status.current_status(),
+                "Peer status changed"
+            );
+            if let Err(e) = notifier.notify(&event).await {
+                warn!("Failed to notify DFL of peer status: {}", e);
+            }
+            if let Some(cms_notifier) = cms_notifier.as_mut() {
+                if let Err(e) = cms_notifier.notify(&event).await {
+                    warn!("Failed to notify CMS of peer status: {}", e);
+                }
+            }
+            if let Some(dcr_notifier) = dcr_notifier.as_mut() {
+                if let Err(e) = dcr_notifier.notify(&event).await {
+                    warn!("Failed to notify DCR of peer status: {}", e);
+                }
+            }
+        }
     });
 
-    // Keep main alive
-    loop {
-        tokio::time::sleep(std::time::Duration::from_secs(60)).await;
+    // Spawn client loops
+    let mut handles = Vec::new();
+    let mut relayed = HashMap::new();
+    let mut connectors = Vec::new();
+    for client in clients {
+        let client = client
+            .with_host_ips(host_ips.clone())
+            .with_vendor_applications(vendor_applications.clone())
+            .with_event_sender(events_tx.clone());
+        handles.push(client.handle());
+        let peer_id = client.peer_info().peer_id;
+        if relayed.insert(peer_id.clone(), client.handle()).is_some() {
+            warn!(
+                "Peer {} is configured twice, relaying to the last one",
+                peer_id
+            );
+        }
+        connectors.push(tokio::spawn(async move {
+            client.start().await;
+        }));
+    }
+
+    // Requests from the DFL and DCR to the connected peers
+    let relay_addr =
+        std::env::var("RELAY_BIND_ADDR").unwrap_or_else(|_| DEFAULT_RELAY_BIND_ADDR.to_string());
+    match relay_addr.parse() {
+        Ok(addr) => {
+            info!("Starting peer relay service on {}", relay_addr);
+            tokio::spawn(async move {
+                if let Err(e) = tonic::transport::Server::builder()
+                    .add_service(PeerRelayServiceServer::new(PeerRelay::new(relayed)))
+                    .serve(addr)
+                    .await
+                {
+                    error!("Peer relay service error: {}", e);
+                }
+            });
+        }
+        Err(e) => error!("Invalid RELAY_BIND_ADDR {}: {}", relay_addr, e),
+    }
+
+    if let Err(e) = tokio::signal::ctrl_c().await {
+        error!("Failed to listen for shutdown: {}", e);
+    }
+
+    // Disconnect gracefully so the peer reroutes before we go away
+    let drain_deadline = std::env::var("DRAIN_DEADLINE_MS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .map(Duration::from_millis)
+        .unwrap_or(DEFAULT_DRAIN_DEADLINE);
+    info!(
+        "Shutdown requested, draining {} peer connections",
+        handles.len()
+    );
+    for handle in &handles {
+        handle.drain(drain_deadline);
+    }
+    for connector in connectors {
+        if let Err(e) = connector.await {
+            error!("Connector failed: {}", e);
+        }
     }
 }
@@ -0,0 +1,145 @@
+use crate::event::PeerEvent;
+use cdde_core::{CddeError, Result};
+use cdde_proto::routing_update_service_client::RoutingUpdateServiceClient;
+use cdde_proto::UpdateResponse;
+use tonic::transport::Channel;
+use tracing::debug;
+
+/// Default DFL peer status endpoint
+pub const DEFAULT_DFL_STATUS_ENDPOINT: &str = "http://[::1]:50052";
+
+/// Sends peer status changes to the DFL
+pub struct DflNotifier {
+    endpoint: String,
+    client: Option<RoutingUpdateServiceClient<Channel>>,
+}
+
+impl DflNotifier {
+    /// Create a notifier for the given DFL endpoint
+    ///
+    /// The connection is opened on the first notification.
+    pub fn new(endpoint: String) -> Self {
+        Self {
+            endpoint,
+            client: None,
+        }
+    }
+
+    /// Send the status update for a peer event
+    pub async fn notify(&mut self, event: &PeerEvent) -> Result<UpdateResponse> {
+        let client = match self.client.as_mut() {
+            Some(client) => client,
+            None => {
+                let client = RoutingUpdateServiceClient::connect(self.endpoint.clone())
+                    .await
+                    .map_err(|e| CddeError::NetworkError(e.to_string()))?;
+                self.client.insert(client)
+            }
+        };
+
+        let request = event.to_status_request();
+        debug!("Sending peer status for {}", request.peer_node_id);
+
+        match client.update_peer_status(request).await {
+            Ok(response) => Ok(response.into_inner()),
+            Err(status) => {
+                // Reconnect on the next notification
+                self.client = None;
+                Err(CddeError::NetworkError(status.to_string()))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::PeerInfo;
+    use cdde_proto::routing_update_service_server::{
+        RoutingUpdateService, RoutingUpdateServiceServer,
+    };
+    use cdde_proto::{PeerStatus, PeerStatusRequest};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+    use tonic::{Request, Response, Status};
+
+    /// DFL stand-in recording received notifications
+    struct Recorder {
+        received: Arc<Mutex<Vec<PeerStatusRequest>>>,
+    }
+
+    #[tonic::async_trait]
+    impl RoutingUpdateService for Recorder {
+        async fn update_peer_status(
+            &self,
+            request: Request<PeerStatusRequest>,
+        ) -> std::result::Result<Response<UpdateResponse>, Status> {
+            self.received.lock().unwrap().push(request.into_inner());
+            Ok(Response::new(UpdateResponse {
+                success: true,
+                message: String::new(),
+            }))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_notify_sends_peer_status() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        drop(listener);
+
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let service = Recorder {
+            received: received.clone(),
+        };
+        let server = tokio::spawn(async move {
+            tonic::transport::Server::builder()
+                .add_service(RoutingUpdateServiceServer::new(service))
+                .serve(addr)
+                .await
+                .unwrap();
+        });
+
+        // Wait for the DFL stand-in to start
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let mut notifier = DflNotifier::new(format!("http://{addr}"));
+        let event = PeerEvent::PeerDown {
+            peer: PeerInfo {
+                peer_id: "hss01.example.com".to_string(),
+                addr: "10.0.0.1:3868".to_string(),
+                virtual_router_ids: vec!["vr001".to_string()],
+                host_ips: vec![],
+            },
+            reason: "Connection closed".to_string(),
+        };
+        assert!(notifier.notify(&event).await.unwrap().success);
+
+        let received = received.lock().unwrap();
+        assert_eq!(received.len(), 1);
+        assert_eq!(received[0].peer_node_id, "hss01.example.com");
+        assert_eq!(received[0].current_status(), PeerStatus::Down);
+        assert_eq!(received[0].virtual_router_ids, vec!["vr001".to_string()]);
+
+        server.abort();
+    }
+
+    #[tokio::test]
+    async fn test_notify_unreachable_dfl() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        drop(listener);
+
+        let mut notifier = DflNotifier::new(format!("http://{addr}"));
+        let event = PeerEvent::PeerUp(PeerInfo {
+            peer_id: "hss01.example.com".to_string(),
+            addr: "10.0.0.1:3868".to_string(),
+            virtual_router_ids: vec![],
+            host_ips: vec![],
+        });
+        assert!(matches!(
+            notifier.notify(&event).await,
+            Err(CddeError::NetworkError(_))
+        ));
+    }
+}
@@ -0,0 +1,238 @@
+use crate::connector::{ConnectorSettings, TcpClient};
+use cdde_core::cms::fetch_json;
+use cdde_core::{CddeError, Result, TransportKind};
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::net::{IpAddr, SocketAddr};
+use tracing::{info, warn};
+
+/// Peer row as served by the CMS peer API
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct CmsPeer {
+    pub hostname: String,
+    pub realm: String,
+    pub ip_address: String,
+    pub port: i32,
+    #[serde(default)]
+    pub transport: TransportKind,
+}
+
+impl CmsPeer {
+    /// Address to connect to, bracketing IPv6 literals
+    ///
+    /// Fails if the port is not a valid TCP port.
+    pub fn addr(&self) -> Result<String> {
+        let port = u16::try_from(self.port).map_err(|_| {
+            CddeError::ConfigError(format!(
+                "Peer {} has invalid port {}",
+                self.hostname, self.port
+            ))
+        })?;
+        Ok(match self.ip_address.parse::<IpAddr>() {
+            Ok(ip) => SocketAddr::new(ip, port).to_string(),
+            Err(_) => format!("{}:{}", self.ip_address, port),
+        })
+    }
+}
+
+/// Build the connector for a CMS peer row
+///
+/// Only TCP connectors exist so far, so peers requiring SCTP are refused.
+pub fn connector_for(
+    peer: &CmsPeer,
+    virtual_router_ids: Vec<String>,
+    settings: &ConnectorSettings,
+) -> Result<TcpClient> {
+    match peer.transport {
+        TransportKind::Tcp => Ok(TcpClient::new(peer.addr()?)
+            .with_peer_host(peer.hostname.clone())
+            .with_virtual_routers(virtual_router_ids)
+            .with_settings(settings)),
+        TransportKind::Sctp => Err(CddeError::ConfigError(format!(
+            "Peer {} requires SCTP, which the DPA does not support",
+            peer.hostname
+        ))),
+    }
+}
+
+/// Fetch the peers configured in the CMS, optionally for one Virtual Router
+pub async fn fetch_peers(cms_url: &str, vr_id: Option<&str>) -> Result<Vec<CmsPeer>> {
+    let mut path = "/api/v1/peers".to_string();
+    if let Some(vr_id) = vr_id {
+        path.push_str("?vr_id=");
+        path.extend(utf8_percent_encode(vr_id, NON_ALPHANUMERIC));
+    }
+    fetch_json(cms_url, &path, "peer list").await
+}
+
+/// Create one connector per CMS peer serving the given Virtual Routers
+///
+/// With no Virtual Routers every configured peer is loaded. A peer serving
+/// several of the routers gets a single connector listing all of them.
+/// Peers the DPA cannot connect to, such as SCTP peers, are skipped with a
+/// warning so they do not keep the others from loading.
+pub async fn load_connectors(
+    cms_url: &str,
+    virtual_router_ids: &[String],
+    settings: &ConnectorSettings,
+) -> Result<Vec<TcpClient>> {
+    let mut peers: BTreeMap<String, (CmsPeer, Vec<String>)> = BTreeMap::new();
+
+    if virtual_router_ids.is_empty() {
+        for peer in fetch_peers(cms_url, None).await? {
+            peers.insert(peer.hostname.clone(), (peer, Vec::new()));
+        }
+    }
+    for vr_id in virtual_router_ids {
+        for peer in fetch_peers(cms_url, Some(vr_id)).await? {
+            peers
+                .entry(peer.hostname.clone())
+                .or_insert_with(|| (peer, Vec::new()))
+                .1
+                .push(vr_id.clone());
+        }
+    }
+
+    let connectors: Vec<TcpClient> = peers
+        .into_values()
+        .filter_map(
+            |(peer, vr_ids)| match connector_for(&peer, vr_ids, settings) {
+                Ok(connector) => Some(connector),
+                Err(e) => {
+                    warn!("Skipping peer {}: {}", peer.hostname, e);
+                    None
+                }
+            },
+        )
+        .collect();
+    info!("Loaded {} peers from the CMS", connectors.len());
+    Ok(connectors)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cdde_test_support::MockCms;
+    use std::time::Duration;
+
+    fn peer(hostname: &str, ip_address: &str, port: i32) -> CmsPeer {
+        CmsPeer {
+            hostname: hostname.to_string(),
+            realm: "example.com".to_string(),
+            ip_address: ip_address.to_string(),
+            port,
+            transport: TransportKind::Tcp,
+        }
+    }
+
+    #[test]
+    fn test_connector_from_peer_row() {
+        let settings = ConnectorSettings {
+            read_timeout: Duration::from_secs(90),
+            ..Default::default()
+        };
+        let client = connector_for(
+            &peer("hss1.example.com", "192.0.2.10", 3869),
+            vec!["vr001".to_string()],
+            &settings,
+        )
+        .unwrap();
+
+        assert_eq!(client.peer_addr(), "192.0.2.10:3869");
+        let info = client.peer_info();
+        assert_eq!(info.peer_id, "hss1.example.com");
+        assert_eq!(info.addr, "192.0.2.10:3869");
+        assert_eq!(info.virtual_router_ids, vec!["vr001".to_string()]);
+
+        let client = connector_for(&peer("hss2", "2001:db8::1", 3868), vec![], &settings).unwrap();
+        assert_eq!(client.peer_addr(), "[2001:db8::1]:3868");
+
+        // Ports out of range are refused rather than wrapped
+        for port in [65536 + 3868, -1] {
+            let err = connector_for(&peer("hss3", "192.0.2.11", port), vec![], &settings)
+                .err()
+                .unwrap();
+            assert!(matches!(err, CddeError::ConfigError(_)));
+        }
+    }
+
+    #[test]
+    fn test_peer_transport() {
+        // Rows from a CMS without the transport column are TCP peers
+        let row: CmsPeer = serde_json::from_str(
+            r#"{"hostname": "hss1", "realm": "example.com", "ip_address": "192.0.2.1", "port": 3868}"#,
+        )
+        .unwrap();
+        assert_eq!(row.transport, TransportKind::Tcp);
+        assert!(connector_for(&row, vec![], &ConnectorSettings::default()).is_ok());
+
+        let sctp = CmsPeer {
+            transport: TransportKind::Sctp,
+            ..peer("hss2", "192.0.2.2", 3868)
+        };
+        let err = connector_for(&sctp, vec![], &ConnectorSettings::default())
+            .err()
+            .unwrap();
+        assert!(matches!(err, CddeError::ConfigError(_)));
+    }
+
+    #[tokio::test]
+    async fn test_load_connectors_for_virtual_routers() {
+        let row = |hostname: &str, ip: &str| serde_json::json!({"hostname": hostname, "realm": "example.com", "ip_address": ip, "port": 3868});
+        let (url, server) = MockCms::new()
+            .with_json(
+                serde_json::json!([row("hss1", "192.0.2.1"), row("hss2", "192.0.2.2")]).to_string(),
+            )
+            .with_json(serde_json::json!([row("hss2", "192.0.2.2")]).to_string())
+            .spawn()
+            .await;
+
+        let clients = load_connectors(
+            &url,
+            &["vr001".to_string(), "vr 002".to_string()],
+            &ConnectorSettings::default(),
+        )
+        .await
+        .unwrap();
+
+        let peers: Vec<_> = clients.iter().map(|client| client.peer_info()).collect();
+        assert_eq!(peers.len(), 2);
+        assert_eq!(peers[0].peer_id, "hss1");
+        assert_eq!(peers[0].virtual_router_ids, vec!["vr001".to_string()]);
+        assert_eq!(peers[1].addr, "192.0.2.2:3868");
+        assert_eq!(
+            peers[1].virtual_router_ids,
+            vec!["vr001".to_string(), "vr 002".to_string()]
+        );
+
+        assert_eq!(
+            server.await.unwrap(),
+            vec![
+                "GET /api/v1/peers?vr_id=vr001 HTTP/1.1".to_string(),
+                "GET /api/v1/peers?vr_id=vr%20002 HTTP/1.1".to_string(),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_unsupported_peers_are_skipped() {
+        let (url, _) = MockCms::new()
+            .with_json(
+                serde_json::json!([
+                    {"hostname": "hss1", "realm": "example.com", "ip_address": "192.0.2.1", "port": 3868, "transport": "sctp"},
+                    {"hostname": "hss2", "realm": "example.com", "ip_address": "192.0.2.2", "port": 70000},
+                    {"hostname": "hss3", "realm": "example.com", "ip_address": "192.0.2.3", "port": 3868}
+                ])
+                .to_string(),
+            )
+            .spawn()
+            .await;
+
+        let clients = load_connectors(&url, &[], &ConnectorSettings::default())
+            .await
+            .unwrap();
+        assert_eq!(clients.len(), 1);
+        assert_eq!(clients[0].peer_info().peer_id, "hss3");
+    }
+}
@@ -0,0 +1,107 @@
+use crate::handle::PeerHandle;
+use cdde_core::{CddeError, DiameterPacket};
+use cdde_proto::peer_relay_service_server::PeerRelayService;
+use cdde_proto::{PeerForwardRequest, PeerForwardResponse};
+use std::collections::HashMap;
+use tonic::{Request, Response, Status};
+use tracing::debug;
+
+/// Default address the relay service listens on
+pub const DEFAULT_RELAY_BIND_ADDR: &str = "[::1]:50054";
+
+/// Relays requests from the DFL and DCR to the connected peers
+///
+/// Peers are addressed by the node id the DPA reports in its peer status
+/// updates before the capabilities exchange: the configured host name, or
+/// the transport address.
+pub struct PeerRelay {
+    peers: HashMap<String, PeerHandle>,
+}
+
+impl PeerRelay {
+    /// Create a relay over the handles of the running connectors
+    pub fn new(peers: HashMap<String, PeerHandle>) -> Self {
+        Self { peers }
+    }
+}
+
+#[tonic::async_trait]
+impl PeerRelayService for PeerRelay {
+    async fn forward(
+        &self,
+        request: Request<PeerForwardRequest>,
+    ) -> Result<Response<PeerForwardResponse>, Status> {
+        let request = request.into_inner();
+        let handle = self
+            .peers
+            .get(&request.peer_node_id)
+            .ok_or_else(|| Status::not_found(format!("unknown peer {}", request.peer_node_id)))?;
+        let packet = DiameterPacket::parse(&request.raw_payload)
+            .map_err(|e| Status::invalid_argument(e.to_string()))?;
+        debug!(
+            "Relaying request {} to {}",
+            packet.header.hop_by_hop_id, request.peer_node_id
+        );
+
+        let answer = handle.request(packet).await.map_err(|e| match e {
+            CddeError::PeerBusy(reason) => Status::resource_exhausted(reason),
+            e => Status::unavailable(e.to_string()),
+        })?;
+        Ok(Response::new(PeerForwardResponse {
+            raw_payload: answer.serialize(),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connector::TcpClient;
+    use cdde_core::DiameterHeader;
+
+    fn request(hop_by_hop_id: u32) -> DiameterPacket {
+        DiameterPacket {
+            header: DiameterHeader {
+                version: 1,
+                length: 0,
+                flags: 0x80,
+                command_code: 316,
+                application_id: 16777251,
+                hop_by_hop_id,
+                end_to_end_id: 7,
+            },
+            avps: vec![],
+        }
+    }
+
+    #[tokio::test]
+    async fn test_unknown_peer_is_not_found() {
+        let relay = PeerRelay::new(HashMap::new());
+
+        let status = relay
+            .forward(Request::new(PeerForwardRequest {
+                peer_node_id: "hss".to_string(),
+                raw_payload: request(42).serialize(),
+            }))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::NotFound);
+    }
+
+    #[tokio::test]
+    async fn test_draining_peer_is_exhausted() {
+        let client = TcpClient::new("127.0.0.1:1".to_string());
+        let handle = client.handle();
+        handle.drain(std::time::Duration::from_secs(1));
+        let relay = PeerRelay::new(HashMap::from([("hss".to_string(), handle)]));
+
+        let status = relay
+            .forward(Request::new(PeerForwardRequest {
+                peer_node_id: "hss".to_string(),
+                raw_payload: request(42).serialize(),
+            }))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::ResourceExhausted);
+    }
+}
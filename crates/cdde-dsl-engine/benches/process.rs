@@ -0,0 +1,50 @@
+use cdde_dsl_engine::{Action, Avp, Condition, Rule, RuleEngine};
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion};
+
+/// Rule set touching many different AVP codes
+fn engine() -> RuleEngine {
+    let rules = (0..50u32)
+        .map(|i| {
+            Rule::new(
+                10,
+                vec![Condition::AvpEquals {
+                    code: 1000 + i,
+                    value: "value".to_string(),
+                }],
+                vec![Action::ModifyAvp {
+                    code: 1000 + i,
+                    value: "rewritten".to_string(),
+                }],
+            )
+        })
+        .collect();
+
+    RuleEngine::new(rules)
+}
+
+fn avps() -> Vec<Avp> {
+    (0..100u32)
+        .map(|i| Avp {
+            code: 1000 + i,
+            value: "value".to_string(),
+        })
+        .collect()
+}
+
+fn process(c: &mut Criterion) {
+    let engine = engine();
+
+    c.bench_function("process 50 rules x 100 avps", |b| {
+        b.iter_batched(
+            avps,
+            |mut avps| {
+                engine.process(&mut avps).unwrap();
+                black_box(avps)
+            },
+            BatchSize::SmallInput,
+        )
+    });
+}
+
+criterion_group!(benches, process);
+criterion_main!(benches);
@@ -1,5 +1,9 @@
+use crate::index::AvpIndex;
 use crate::rule::{Action, Avp, Condition, Rule};
+use cdde_diameter_dict::{AvpDataType, DictionaryManager};
 use regex::Regex;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use thiserror::Error;
 
 /// Engine error
@@ -12,27 +16,100 @@ pub enum EngineError {
     AvpNotFound(u32),
 }
 
+/// Limits on the work done for a single packet
+///
+/// Unlimited by default.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ExecutionBudget {
+    /// Maximum number of rules evaluated
+    pub max_rules: Option<usize>,
+
+    /// Maximum time spent evaluating rules
+    pub deadline: Option<Duration>,
+}
+
+/// Summary of a [`RuleEngine::process`] run
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ProcessOutcome {
+    /// Rules whose conditions were evaluated
+    pub rules_evaluated: usize,
+
+    /// Processing stopped at the budget; the remaining rules were skipped
+    pub budget_exceeded: bool,
+}
+
 /// Rule execution engine
 pub struct RuleEngine {
     rules: Vec<Rule>,
+    dictionary: Arc<DictionaryManager>,
+    budget: ExecutionBudget,
 }
 
 impl RuleEngine {
     /// Create new engine with rules
-    pub fn new(mut rules: Vec<Rule>) -> Self {
-        // Sort by priority (lower number = higher priority)
-        rules.sort_by_key(|r| r.priority);
-        Self { rules }
+    ///
+    /// Rules run by ascending priority (lower number = higher priority).
+    /// Rules with equal priority run in the order they were given.
+    pub fn new(rules: Vec<Rule>) -> Self {
+        // Insertion order is the explicit tie-breaker
+        let mut indexed: Vec<(usize, Rule)> = rules.into_iter().enumerate().collect();
+        indexed.sort_unstable_by_key(|(position, rule)| (rule.priority, *position));
+        let rules = indexed.into_iter().map(|(_, rule)| rule).collect();
+
+        Self {
+            rules,
+            dictionary: Arc::new(DictionaryManager::new()),
+            budget: ExecutionBudget::default(),
+        }
+    }
+
+    /// Cap the work done per packet
+    pub fn with_budget(mut self, budget: ExecutionBudget) -> Self {
+        self.budget = budget;
+        self
+    }
+
+    /// Use a dictionary with additional (dynamic) AVP definitions
+    pub fn with_dictionary(mut self, dictionary: Arc<DictionaryManager>) -> Self {
+        self.dictionary = dictionary;
+        self
+    }
+
+    /// Get the dictionary used to type AVP values
+    pub fn dictionary(&self) -> &DictionaryManager {
+        &self.dictionary
     }
 
     /// Process packet AVPs with rules
-    pub fn process(&self, avps: &mut Vec<Avp>) -> Result<(), EngineError> {
+    ///
+    /// The AVPs are indexed by code once; the index is only rebuilt when an
+    /// action removes AVPs. When the execution budget runs out the remaining
+    /// rules are skipped and the AVPs are left as the applied rules made them.
+    pub fn process(&self, avps: &mut Vec<Avp>) -> Result<ProcessOutcome, EngineError> {
+        let mut index = AvpIndex::new(avps);
+        let started = Instant::now();
+        let mut outcome = ProcessOutcome::default();
+
         for rule in &self.rules {
-            if self.evaluate_conditions(&rule.conditions, avps)? {
-                self.execute_actions(&rule.actions, avps)?;
+            let out_of_rules = self
+                .budget
+                .max_rules
+                .is_some_and(|max| outcome.rules_evaluated >= max);
+            let out_of_time = self
+                .budget
+                .deadline
+                .is_some_and(|deadline| started.elapsed() >= deadline);
+            if out_of_rules || out_of_time {
+                outcome.budget_exceeded = true;
+                break;
+            }
+
+            outcome.rules_evaluated += 1;
+            if self.evaluate_conditions(&rule.conditions, avps, &index)? {
+                self.execute_actions(&rule.actions, avps, &mut index)?;
             }
         }
-        Ok(())
+        Ok(outcome)
     }
 
     /// Evaluate all conditions (AND logic)
@@ -40,9 +117,10 @@ impl RuleEngine {
         &self,
         conditions: &[Condition],
         avps: &[Avp],
+        index: &AvpIndex,
     ) -> Result<bool, EngineError> {
         for condition in conditions {
-            if !self.evaluate_condition(condition, avps)? {
+            if !self.evaluate_condition(condition, avps, index)? {
                 return Ok(false);
             }
         }
@@ -50,39 +128,96 @@ impl RuleEngine {
     }
 
     /// Evaluate single condition
-    fn evaluate_condition(&self, condition: &Condition, avps: &[Avp]) -> Result<bool, EngineError> {
+    fn evaluate_condition(
+        &self,
+        condition: &Condition,
+        avps: &[Avp],
+        index: &AvpIndex,
+    ) -> Result<bool, EngineError> {
         match condition {
-            Condition::AvpExists { code } => Ok(avps.iter().any(|avp| avp.code == *code)),
+            Condition::AvpExists { code } => Ok(index.contains(*code)),
 
-            Condition::AvpEquals { code, value } => Ok(avps
+            Condition::AvpEquals { code, value } => Ok(index
+                .positions(*code)
                 .iter()
-                .any(|avp| avp.code == *code && avp.value == *value)),
+                .any(|&i| self.values_equal(*code, &avps[i].value, value))),
 
             Condition::AvpMatches { code, pattern } => {
                 let regex =
                     Regex::new(pattern).map_err(|e| EngineError::InvalidRegex(e.to_string()))?;
 
-                Ok(avps
+                Ok(index
+                    .positions(*code)
                     .iter()
-                    .any(|avp| avp.code == *code && regex.is_match(&avp.value)))
+                    .any(|&i| regex.is_match(&avps[i].value)))
             }
 
             Condition::Always => Ok(true),
         }
     }
 
+    /// Compare two values of an AVP according to its data type
+    ///
+    /// Numeric AVPs compare by value (so "2001" equals "02001"), Enumerated
+    /// values may also be given by their dictionary name and Diameter
+    /// identities ignore case; everything else compares as strings.
+    fn values_equal(&self, code: u32, left: &str, right: &str) -> bool {
+        let data_type = self.dictionary.lookup(code).map(|info| info.data_type);
+
+        match data_type {
+            Some(AvpDataType::DiameterIdentity) => left.eq_ignore_ascii_case(right),
+            Some(AvpDataType::Enumerated) => {
+                let resolve = |value: &str| {
+                    let value = value.trim();
+                    self.dictionary
+                        .enum_value(code, value)
+                        .map(i128::from)
+                        .or_else(|| value.parse::<i128>().ok())
+                };
+                match (resolve(left), resolve(right)) {
+                    (Some(l), Some(r)) => l == r,
+                    _ => left == right,
+                }
+            }
+            Some(AvpDataType::Float32 | AvpDataType::Float64) => {
+                match (left.trim().parse::<f64>(), right.trim().parse::<f64>()) {
+                    (Ok(l), Ok(r)) => l == r,
+                    _ => left == right,
+                }
+            }
+            Some(t) if t.is_numeric() => {
+                match (left.trim().parse::<i128>(), right.trim().parse::<i128>()) {
+                    (Ok(l), Ok(r)) => l == r,
+                    _ => left == right,
+                }
+            }
+            _ => left == right,
+        }
+    }
+
     /// Execute all actions
-    fn execute_actions(&self, actions: &[Action], avps: &mut Vec<Avp>) -> Result<(), EngineError> {
+    fn execute_actions(
+        &self,
+        actions: &[Action],
+        avps: &mut Vec<Avp>,
+        index: &mut AvpIndex,
+    ) -> Result<(), EngineError> {
         for action in actions {
-            self.execute_action(action, avps)?;
+            self.execute_action(action, avps, index)?;
         }
         Ok(())
     }
 
-    /// Execute single action
-    fn execute_action(&self, action: &Action, avps: &mut Vec<Avp>) -> Result<(), EngineError> {
+    /// Execute single action, keeping the index in sync
+    fn execute_action(
+        &self,
+        action: &Action,
+        avps: &mut Vec<Avp>,
+        index: &mut AvpIndex,
+    ) -> Result<(), EngineError> {
         match action {
             Action::AddAvp { code, value } => {
+                index.push(*code, avps.len());
                 avps.push(Avp {
                     code: *code,
                     value: value.clone(),
@@ -90,19 +225,23 @@ impl RuleEngine {
             }
 
             Action::ModifyAvp { code, value } => {
-                if let Some(avp) = avps.iter_mut().find(|avp| avp.code == *code) {
-                    avp.value = value.clone();
+                if let Some(i) = index.first(*code) {
+                    avps[i].value = value.clone();
                 }
             }
 
             Action::RemoveAvp { code } => {
-                avps.retain(|avp| avp.code != *code);
+                if index.contains(*code) {
+                    avps.retain(|avp| avp.code != *code);
+                    *index = AvpIndex::new(avps);
+                }
             }
 
             Action::SetAvp { code, value } => {
-                if let Some(avp) = avps.iter_mut().find(|avp| avp.code == *code) {
-                    avp.value = value.clone();
+                if let Some(i) = index.first(*code) {
+                    avps[i].value = value.clone();
                 } else {
+                    index.push(*code, avps.len());
                     avps.push(Avp {
                         code: *code,
                         value: value.clone(),
@@ -127,7 +266,11 @@ mod tests {
         }];
 
         let result = engine
-            .evaluate_condition(&Condition::AvpExists { code: 264 }, &avps)
+            .evaluate_condition(
+                &Condition::AvpExists { code: 264 },
+                &avps,
+                &AvpIndex::new(&avps),
+            )
             .unwrap();
 
         assert!(result);
@@ -148,6 +291,7 @@ mod tests {
                     value: "test.host".to_string(),
                 },
                 &avps,
+                &AvpIndex::new(&avps),
             )
             .unwrap();
 
@@ -159,6 +303,7 @@ mod tests {
         let engine = RuleEngine::new(vec![]);
         let mut avps = vec![];
 
+        let mut index = AvpIndex::new(&avps);
         engine
             .execute_action(
                 &Action::AddAvp {
@@ -166,6 +311,7 @@ mod tests {
                     value: "user@realm".to_string(),
                 },
                 &mut avps,
+                &mut index,
             )
             .unwrap();
 
@@ -182,6 +328,7 @@ mod tests {
             value: "original.host".to_string(),
         }];
 
+        let mut index = AvpIndex::new(&avps);
         engine
             .execute_action(
                 &Action::ModifyAvp {
@@ -189,6 +336,7 @@ mod tests {
                     value: "modified.host".to_string(),
                 },
                 &mut avps,
+                &mut index,
             )
             .unwrap();
 
@@ -209,8 +357,9 @@ mod tests {
             },
         ];
 
+        let mut index = AvpIndex::new(&avps);
         engine
-            .execute_action(&Action::RemoveAvp { code: 264 }, &mut avps)
+            .execute_action(&Action::RemoveAvp { code: 264 }, &mut avps, &mut index)
             .unwrap();
 
         assert_eq!(avps.len(), 1);
@@ -239,4 +388,281 @@ mod tests {
         assert_eq!(avps.len(), 2);
         assert_eq!(avps[1].code, 1);
     }
+
+    #[test]
+    fn test_avp_equals_numeric_avp() {
+        let engine = RuleEngine::new(vec![]);
+        let dict = DictionaryManager::new();
+
+        // Result-Code carried as binary 2001
+        let avps = vec![Avp::from_raw(268, &2001u32.to_be_bytes(), &dict)];
+        let index = AvpIndex::new(&avps);
+
+        let condition = |value: &str| Condition::AvpEquals {
+            code: 268,
+            value: value.to_string(),
+        };
+
+        assert!(engine
+            .evaluate_condition(&condition("2001"), &avps, &index)
+            .unwrap());
+        assert!(engine
+            .evaluate_condition(&condition("02001"), &avps, &index)
+            .unwrap());
+        assert!(!engine
+            .evaluate_condition(&condition("5001"), &avps, &index)
+            .unwrap());
+    }
+
+    #[test]
+    fn test_avp_equals_identity_ignores_case() {
+        let engine = RuleEngine::new(vec![]);
+        // Destination-Realm as sent by a client
+        let avps = vec![Avp {
+            code: 283,
+            value: "EPC.Example.COM".to_string(),
+        }];
+        let index = AvpIndex::new(&avps);
+
+        let condition = |value: &str| Condition::AvpEquals {
+            code: 283,
+            value: value.to_string(),
+        };
+        assert!(engine
+            .evaluate_condition(&condition("epc.example.com"), &avps, &index)
+            .unwrap());
+        assert!(!engine
+            .evaluate_condition(&condition("ims.example.com"), &avps, &index)
+            .unwrap());
+    }
+
+    #[test]
+    fn test_avp_equals_enumerated_by_name() {
+        let dict = Arc::new(DictionaryManager::new());
+        dict.load_dynamic_dictionary(
+            r#"<dictionary>
+                <avp name="CC-Request-Type" code="416" type="Enumerated">
+                    <enum name="INITIAL_REQUEST" code="1"/>
+                    <enum name="TERMINATION_REQUEST" code="3"/>
+                </avp>
+            </dictionary>"#,
+        )
+        .unwrap();
+        let engine = RuleEngine::new(vec![]).with_dictionary(dict.clone());
+
+        let avps = vec![Avp::from_raw(416, &1u32.to_be_bytes(), &dict)];
+        let index = AvpIndex::new(&avps);
+
+        let condition = |value: &str| Condition::AvpEquals {
+            code: 416,
+            value: value.to_string(),
+        };
+
+        assert!(engine
+            .evaluate_condition(&condition("INITIAL_REQUEST"), &avps, &index)
+            .unwrap());
+        assert!(!engine
+            .evaluate_condition(&condition("TERMINATION_REQUEST"), &avps, &index)
+            .unwrap());
+        // Values without a name still compare numerically
+        assert!(engine
+            .evaluate_condition(&condition("01"), &avps, &index)
+            .unwrap());
+    }
+
+    /// Straightforward scan of the AVP list, used as the reference for the index
+    fn process_by_scan(engine: &RuleEngine, avps: &mut Vec<Avp>) {
+        for rule in &engine.rules {
+            let matched = rule.conditions.iter().all(|condition| match condition {
+                Condition::AvpExists { code } => avps.iter().any(|avp| avp.code == *code),
+                Condition::AvpEquals { code, value } => avps
+                    .iter()
+                    .any(|avp| avp.code == *code && engine.values_equal(*code, &avp.value, value)),
+                Condition::AvpMatches { code, pattern } => {
+                    let regex = Regex::new(pattern).unwrap();
+                    avps.iter()
+                        .any(|avp| avp.code == *code && regex.is_match(&avp.value))
+                }
+                Condition::Always => true,
+            });
+            if !matched {
+                continue;
+            }
+
+            for action in &rule.actions {
+                match action {
+                    Action::AddAvp { code, value } => avps.push(Avp {
+                        code: *code,
+                        value: value.clone(),
+                    }),
+                    Action::ModifyAvp { code, value } => {
+                        if let Some(avp) = avps.iter_mut().find(|avp| avp.code == *code) {
+                            avp.value = value.clone();
+                        }
+                    }
+                    Action::RemoveAvp { code } => avps.retain(|avp| avp.code != *code),
+                    Action::SetAvp { code, value } => {
+                        match avps.iter_mut().find(|avp| avp.code == *code) {
+                            Some(avp) => avp.value = value.clone(),
+                            None => avps.push(Avp {
+                                code: *code,
+                                value: value.clone(),
+                            }),
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_indexed_matches_scan_on_large_avp_set() {
+        let mut rules = Vec::new();
+        for i in 0..40u32 {
+            let code = 1000 + i % 25;
+            rules.push(Rule::new(
+                (i % 7) as u8,
+                vec![Condition::AvpEquals {
+                    code,
+                    value: format!("value-{}", i % 5),
+                }],
+                vec![
+                    Action::ModifyAvp {
+                        code: code + 1,
+                        value: format!("modified-{i}"),
+                    },
+                    Action::AddAvp {
+                        code: 2000 + i,
+                        value: format!("added-{i}"),
+                    },
+                ],
+            ));
+            rules.push(Rule::new(
+                (i % 5) as u8,
+                vec![
+                    Condition::AvpExists { code: 2000 + i / 2 },
+                    Condition::AvpMatches {
+                        code,
+                        pattern: "^value-[0-2]$".to_string(),
+                    },
+                ],
+                vec![
+                    Action::RemoveAvp { code: code + 2 },
+                    Action::SetAvp {
+                        code: 3000 + i % 3,
+                        value: format!("set-{i}"),
+                    },
+                ],
+            ));
+        }
+        let engine = RuleEngine::new(rules);
+
+        let avps: Vec<Avp> = (0..300u32)
+            .map(|i| Avp {
+                code: 1000 + i % 30,
+                value: format!("value-{}", i % 5),
+            })
+            .collect();
+
+        let mut indexed = avps.clone();
+        engine.process(&mut indexed).unwrap();
+
+        let mut scanned = avps;
+        process_by_scan(&engine, &mut scanned);
+
+        let pairs = |avps: &[Avp]| -> Vec<(u32, String)> {
+            avps.iter()
+                .map(|avp| (avp.code, avp.value.clone()))
+                .collect()
+        };
+        assert_eq!(pairs(&indexed), pairs(&scanned));
+    }
+
+    #[test]
+    fn test_budget_caps_rule_evaluation() {
+        let rules = (0..1000u32)
+            .map(|i| {
+                Rule::new(
+                    10,
+                    vec![Condition::Always],
+                    vec![Action::AddAvp {
+                        code: 5000 + i,
+                        value: "x".to_string(),
+                    }],
+                )
+            })
+            .collect();
+        let engine = RuleEngine::new(rules).with_budget(ExecutionBudget {
+            max_rules: Some(25),
+            ..Default::default()
+        });
+
+        let mut avps = vec![];
+        let outcome = engine.process(&mut avps).unwrap();
+
+        assert_eq!(
+            outcome,
+            ProcessOutcome {
+                rules_evaluated: 25,
+                budget_exceeded: true,
+            }
+        );
+        // The rules applied before the cut-off are kept
+        assert_eq!(avps.len(), 25);
+        assert_eq!(avps[24].code, 5024);
+    }
+
+    #[test]
+    fn test_budget_deadline_and_unlimited() {
+        let rules = || {
+            (0..50u32)
+                .map(|i| Rule::new(10, vec![Condition::AvpExists { code: i }], vec![]))
+                .collect()
+        };
+
+        let expired = RuleEngine::new(rules()).with_budget(ExecutionBudget {
+            deadline: Some(Duration::ZERO),
+            ..Default::default()
+        });
+        let outcome = expired.process(&mut vec![]).unwrap();
+        assert_eq!(outcome.rules_evaluated, 0);
+        assert!(outcome.budget_exceeded);
+
+        // A budget matching the rule count is not exceeded
+        let exact = RuleEngine::new(rules()).with_budget(ExecutionBudget {
+            max_rules: Some(50),
+            deadline: Some(Duration::from_secs(60)),
+        });
+        let outcome = exact.process(&mut vec![]).unwrap();
+        assert_eq!(outcome.rules_evaluated, 50);
+        assert!(!outcome.budget_exceeded);
+    }
+
+    #[test]
+    fn test_equal_priority_rules_run_in_given_order() {
+        let set = |priority: u8, value: &str| {
+            Rule::new(
+                priority,
+                vec![Condition::Always],
+                vec![Action::AddAvp {
+                    code: 1,
+                    value: value.to_string(),
+                }],
+            )
+        };
+        let engine = RuleEngine::new(vec![
+            set(20, "late"),
+            set(10, "first"),
+            set(10, "second"),
+            set(10, "third"),
+            set(5, "early"),
+        ]);
+
+        for _ in 0..10 {
+            let mut avps = vec![];
+            engine.process(&mut avps).unwrap();
+            let order: Vec<_> = avps.iter().map(|avp| avp.value.as_str()).collect();
+            assert_eq!(order, vec!["early", "first", "second", "third", "late"]);
+        }
+    }
 }
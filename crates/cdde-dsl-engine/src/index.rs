@@ -0,0 +1,65 @@
+use crate::rule::Avp;
+use std::collections::HashMap;
+
+/// Positions of AVPs by code within an AVP list
+///
+/// Built once per message so conditions and actions avoid scanning the
+/// whole list. Positions are kept in list order.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AvpIndex {
+    positions: HashMap<u32, Vec<usize>>,
+}
+
+impl AvpIndex {
+    /// Index an AVP list
+    pub fn new(avps: &[Avp]) -> Self {
+        let mut index = Self::default();
+        for (position, avp) in avps.iter().enumerate() {
+            index.push(avp.code, position);
+        }
+        index
+    }
+
+    /// Positions of all AVPs with a code
+    pub fn positions(&self, code: u32) -> &[usize] {
+        self.positions.get(&code).map(Vec::as_slice).unwrap_or(&[])
+    }
+
+    /// Position of the first AVP with a code
+    pub fn first(&self, code: u32) -> Option<usize> {
+        self.positions(code).first().copied()
+    }
+
+    /// Check if an AVP with a code is present
+    pub fn contains(&self, code: u32) -> bool {
+        !self.positions(code).is_empty()
+    }
+
+    /// Record an AVP appended at `position`
+    pub fn push(&mut self, code: u32, position: usize) {
+        self.positions.entry(code).or_default().push(position);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn avp(code: u32) -> Avp {
+        Avp {
+            code,
+            value: String::new(),
+        }
+    }
+
+    #[test]
+    fn test_index_positions() {
+        let avps = vec![avp(264), avp(296), avp(264)];
+        let index = AvpIndex::new(&avps);
+
+        assert_eq!(index.positions(264), &[0, 2]);
+        assert_eq!(index.first(296), Some(1));
+        assert!(!index.contains(1));
+        assert_eq!(index.first(1), None);
+    }
+}
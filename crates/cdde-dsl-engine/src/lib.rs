@@ -1,5 +1,7 @@
 pub mod engine;
+pub mod index;
 pub mod rule;
 
-pub use engine::{EngineError, RuleEngine};
+pub use engine::{EngineError, ExecutionBudget, ProcessOutcome, RuleEngine};
+pub use index::AvpIndex;
 pub use rule::{Action, Avp, Condition, Rule};
@@ -1,3 +1,4 @@
+use cdde_diameter_dict::DictionaryManager;
 use serde::{Deserialize, Serialize};
 
 /// Manipulation rule
@@ -49,6 +50,28 @@ pub struct Avp {
     pub value: String,
 }
 
+impl Avp {
+    /// Decode a raw AVP into its textual value using the dictionary
+    ///
+    /// Addresses read as IP addresses and other binary data as hex. Unknown
+    /// AVPs and undecodable data fall back to lossy UTF-8.
+    pub fn from_raw(code: u32, data: &[u8], dict: &DictionaryManager) -> Self {
+        let value = dict
+            .parse_avp(code, data)
+            .map(|value| value.to_string())
+            .unwrap_or_else(|_| String::from_utf8_lossy(data).to_string());
+
+        Self { code, value }
+    }
+
+    /// Encode the textual value back into raw AVP data using the dictionary
+    pub fn to_raw(&self, dict: &DictionaryManager) -> Vec<u8> {
+        dict.lookup(self.code)
+            .and_then(|info| info.data_type.encode(&self.value).ok())
+            .unwrap_or_else(|| self.value.as_bytes().to_vec())
+    }
+}
+
 impl Rule {
     /// Create new rule
     pub fn new(priority: u8, conditions: Vec<Condition>, actions: Vec<Action>) -> Self {
@@ -99,4 +122,29 @@ mod tests {
 
         assert_eq!(deserialized.priority, 10);
     }
+
+    #[test]
+    fn test_avp_raw_conversion() {
+        let dict = DictionaryManager::new();
+
+        let result_code = Avp::from_raw(268, &2001u32.to_be_bytes(), &dict);
+        assert_eq!(result_code.value, "2001");
+        assert_eq!(result_code.to_raw(&dict), 2001u32.to_be_bytes());
+
+        let origin_host = Avp::from_raw(264, b"test.host", &dict);
+        assert_eq!(origin_host.value, "test.host");
+        assert_eq!(origin_host.to_raw(&dict), b"test.host");
+
+        let unknown = Avp::from_raw(99999, b"raw", &dict);
+        assert_eq!(unknown.value, "raw");
+
+        // Binary types survive the round trip through text
+        let host_ip = Avp::from_raw(257, &[0, 1, 192, 0, 2, 1], &dict);
+        assert_eq!(host_ip.value, "192.0.2.1");
+        assert_eq!(host_ip.to_raw(&dict), [0, 1, 192, 0, 2, 1]);
+
+        let visited_plmn = Avp::from_raw(1407, &[0x12, 0xF4, 0x56], &dict);
+        assert_eq!(visited_plmn.value, "12f456");
+        assert_eq!(visited_plmn.to_raw(&dict), [0x12, 0xF4, 0x56]);
+    }
 }
@@ -1,6 +1,6 @@
 use lazy_static::lazy_static;
 use prometheus::{
-    Counter, Encoder, Histogram, HistogramOpts, IntGauge, Opts, Registry, TextEncoder,
+    Counter, CounterVec, Encoder, Histogram, HistogramOpts, IntGauge, Opts, Registry, TextEncoder,
 };
 
 lazy_static! {
@@ -24,6 +24,93 @@ lazy_static! {
     pub static ref ERRORS_TOTAL: Counter = Counter::with_opts(
         Opts::new("errors_total", "Total number of errors")
     ).unwrap();
+
+    pub static ref SLOW_TRANSACTIONS_TOTAL: Counter = Counter::with_opts(
+        Opts::new("slow_transactions_total", "Transactions answered later than the slow threshold")
+    ).unwrap();
+
+    pub static ref SESSIONS_SWEPT_TOTAL: Counter = Counter::with_opts(
+        Opts::new("sessions_swept_total", "Sessions dropped by the DFL for exceeding their maximum age")
+    ).unwrap();
+
+    pub static ref ANSWER_COMMAND_MISMATCH_TOTAL: Counter = Counter::with_opts(
+        Opts::new("answer_command_mismatch_total", "Answers discarded because their command code differs from the pending request's")
+    ).unwrap();
+
+    pub static ref REQUESTS_SHED_TOTAL: Counter = Counter::with_opts(
+        Opts::new("requests_shed_total", "Routing requests refused with RESOURCE_EXHAUSTED because the DCR was saturated")
+    ).unwrap();
+
+    pub static ref TRANSFORM_STAGE_TOTAL: CounterVec = CounterVec::new(
+        Opts::new("transform_stage_total", "Transform stage runs by outcome"),
+        &["stage", "outcome"]
+    ).unwrap();
+
+    pub static ref RULE_BUDGET_EXCEEDED_TOTAL: Counter = Counter::with_opts(
+        Opts::new("rule_budget_exceeded_total", "Packets whose manipulation rules were cut short by the execution budget")
+    ).unwrap();
+
+    pub static ref REJECTED_CONNECTIONS_TOTAL: Counter = Counter::with_opts(
+        Opts::new("rejected_connections_total", "Connections refused by the client access list")
+    ).unwrap();
+
+    pub static ref REALM_REQUESTS_TOTAL: CounterVec = CounterVec::new(
+        Opts::new("realm_requests_total", "Routed requests by Destination-Realm"),
+        &["realm"]
+    ).unwrap();
+
+    pub static ref MANIPULATION_BYPASSED_TOTAL: CounterVec = CounterVec::new(
+        Opts::new("manipulation_bypassed_total", "Requests routed without manipulation because no stage applies, by well-known application id or \"other\""),
+        &["application_id"]
+    ).unwrap();
+
+    pub static ref FORWARD_ATTEMPTS_TOTAL: CounterVec = CounterVec::new(
+        Opts::new("forward_attempts_total", "Deliveries of forwarded requests by pool, retries included"),
+        &["pool"]
+    ).unwrap();
+
+    pub static ref FORWARD_RETRIES_TOTAL: CounterVec = CounterVec::new(
+        Opts::new("forward_retries_total", "Forwarded requests retried on another peer by pool"),
+        &["pool"]
+    ).unwrap();
+
+    pub static ref FORWARD_RETRIES_EXHAUSTED_TOTAL: CounterVec = CounterVec::new(
+        Opts::new("forward_retries_exhausted_total", "Forwarded requests that failed on every attempted peer by pool"),
+        &["pool"]
+    ).unwrap();
+
+    pub static ref DEAD_LETTERS_TOTAL: CounterVec = CounterVec::new(
+        Opts::new("dead_letters_total", "Requests given up as undeliverable by reason"),
+        &["reason"]
+    ).unwrap();
+
+    pub static ref DEAD_LETTERS_SUPPRESSED_TOTAL: Counter = Counter::with_opts(
+        Opts::new("dead_letters_suppressed_total", "Dead-letter records dropped by the rate limit")
+    ).unwrap();
+
+    pub static ref PEER_CONNECTIONS: IntGauge = IntGauge::with_opts(
+        Opts::new("peer_connections", "Peer connections past the capabilities exchange")
+    ).unwrap();
+
+    pub static ref PEER_BYTES_SENT_TOTAL: CounterVec = CounterVec::new(
+        Opts::new("peer_bytes_sent_total", "Diameter bytes written to each peer"),
+        &["peer"]
+    ).unwrap();
+
+    pub static ref PEER_BYTES_RECEIVED_TOTAL: CounterVec = CounterVec::new(
+        Opts::new("peer_bytes_received_total", "Diameter bytes read from each peer"),
+        &["peer"]
+    ).unwrap();
+
+    pub static ref PEER_MESSAGES_SENT_TOTAL: CounterVec = CounterVec::new(
+        Opts::new("peer_messages_sent_total", "Diameter messages written to each peer"),
+        &["peer"]
+    ).unwrap();
+
+    pub static ref PEER_MESSAGES_RECEIVED_TOTAL: CounterVec = CounterVec::new(
+        Opts::new("peer_messages_received_total", "Diameter messages read from each peer"),
+        &["peer"]
+    ).unwrap();
 }
 
 /// Register all metrics with the global registry
@@ -36,6 +123,63 @@ pub fn register_metrics() {
         .register(Box::new(ACTIVE_CONNECTIONS.clone()))
         .unwrap();
     REGISTRY.register(Box::new(ERRORS_TOTAL.clone())).unwrap();
+    REGISTRY
+        .register(Box::new(SLOW_TRANSACTIONS_TOTAL.clone()))
+        .unwrap();
+    REGISTRY
+        .register(Box::new(SESSIONS_SWEPT_TOTAL.clone()))
+        .unwrap();
+    REGISTRY
+        .register(Box::new(ANSWER_COMMAND_MISMATCH_TOTAL.clone()))
+        .unwrap();
+    REGISTRY
+        .register(Box::new(REQUESTS_SHED_TOTAL.clone()))
+        .unwrap();
+    REGISTRY
+        .register(Box::new(TRANSFORM_STAGE_TOTAL.clone()))
+        .unwrap();
+    REGISTRY
+        .register(Box::new(REALM_REQUESTS_TOTAL.clone()))
+        .unwrap();
+    REGISTRY
+        .register(Box::new(REJECTED_CONNECTIONS_TOTAL.clone()))
+        .unwrap();
+    REGISTRY
+        .register(Box::new(RULE_BUDGET_EXCEEDED_TOTAL.clone()))
+        .unwrap();
+    REGISTRY
+        .register(Box::new(MANIPULATION_BYPASSED_TOTAL.clone()))
+        .unwrap();
+    REGISTRY
+        .register(Box::new(FORWARD_ATTEMPTS_TOTAL.clone()))
+        .unwrap();
+    REGISTRY
+        .register(Box::new(FORWARD_RETRIES_TOTAL.clone()))
+        .unwrap();
+    REGISTRY
+        .register(Box::new(FORWARD_RETRIES_EXHAUSTED_TOTAL.clone()))
+        .unwrap();
+    REGISTRY
+        .register(Box::new(DEAD_LETTERS_TOTAL.clone()))
+        .unwrap();
+    REGISTRY
+        .register(Box::new(DEAD_LETTERS_SUPPRESSED_TOTAL.clone()))
+        .unwrap();
+    REGISTRY
+        .register(Box::new(PEER_CONNECTIONS.clone()))
+        .unwrap();
+    REGISTRY
+        .register(Box::new(PEER_BYTES_SENT_TOTAL.clone()))
+        .unwrap();
+    REGISTRY
+        .register(Box::new(PEER_BYTES_RECEIVED_TOTAL.clone()))
+        .unwrap();
+    REGISTRY
+        .register(Box::new(PEER_MESSAGES_SENT_TOTAL.clone()))
+        .unwrap();
+    REGISTRY
+        .register(Box::new(PEER_MESSAGES_RECEIVED_TOTAL.clone()))
+        .unwrap();
 }
 
 /// Gather metrics in Prometheus text format
@@ -1,4 +1,43 @@
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    tonic_build::compile_protos("proto/cdde.proto")?;
+    // Diameter payloads are shared bytes, so a packet serialized with
+    // `DiameterPacket::to_bytes` is handed to gRPC without another copy
+    let mut builder = tonic_build::configure().bytes([
+        ".cdde.DiameterPacketRequest.raw_payload",
+        ".cdde.DiameterPacketAction.response_payload",
+    ]);
+
+    // The compact codec serializes the generated messages through serde
+    if std::env::var_os("CARGO_FEATURE_COMPACT_CODEC").is_some() {
+        builder = builder
+            .type_attribute(
+                "cdde.DiameterPacketRequest",
+                "#[derive(serde::Serialize, serde::Deserialize)]",
+            )
+            .type_attribute(
+                "cdde.DiameterPacketAction",
+                "#[derive(serde::Serialize, serde::Deserialize)]",
+            );
+    }
+
+    builder.compile(&["proto/cdde.proto", "proto/internal.proto"], &["proto"])?;
+
+    // The same routing call over the compact codec, on its own route so
+    // protobuf peers are not confused by it
+    if std::env::var_os("CARGO_FEATURE_COMPACT_CODEC").is_some() {
+        let compact_router = tonic_build::manual::Service::builder()
+            .name("CoreRouterService")
+            .package("cdde.compact")
+            .method(
+                tonic_build::manual::Method::builder()
+                    .name("process_packet")
+                    .route_name("ProcessPacket")
+                    .input_type("crate::DiameterPacketRequest")
+                    .output_type("crate::DiameterPacketAction")
+                    .codec_path("crate::codec::CompactCodec")
+                    .build(),
+            )
+            .build();
+        tonic_build::manual::Builder::new().compile(&[compact_router]);
+    }
     Ok(())
 }
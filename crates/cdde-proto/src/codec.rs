@@ -0,0 +1,203 @@
+//! Compact binary codec for the internal DFL <-> DCR messages.
+//!
+//! Only usable when both ends are CDDE components built with the
+//! `compact-codec` feature. The [`compact`](crate::compact) routing service
+//! carries the DFL's requests to the DCR with it.
+
+use crate::{DiameterPacketAction, DiameterPacketRequest};
+use bytes::{Buf, BufMut};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::marker::PhantomData;
+use tonic::codec::{Codec, DecodeBuf, Decoder, EncodeBuf, Encoder};
+use tonic::Status;
+
+/// Error returned by the compact codec
+pub type CodecError = bincode::Error;
+
+/// gRPC codec of the [`compact`](crate::compact) routing service
+pub struct CompactCodec<T, U>(PhantomData<(T, U)>);
+
+impl<T, U> Default for CompactCodec<T, U> {
+    fn default() -> Self {
+        Self(PhantomData)
+    }
+}
+
+impl<T, U> Codec for CompactCodec<T, U>
+where
+    T: Serialize + Send + 'static,
+    U: DeserializeOwned + Send + 'static,
+{
+    type Encode = T;
+    type Decode = U;
+    type Encoder = CompactEncoder<T>;
+    type Decoder = CompactDecoder<U>;
+
+    fn encoder(&mut self) -> Self::Encoder {
+        CompactEncoder(PhantomData)
+    }
+
+    fn decoder(&mut self) -> Self::Decoder {
+        CompactDecoder(PhantomData)
+    }
+}
+
+/// Writes gRPC messages with the compact codec
+pub struct CompactEncoder<T>(PhantomData<T>);
+
+impl<T: Serialize> Encoder for CompactEncoder<T> {
+    type Item = T;
+    type Error = Status;
+
+    fn encode(&mut self, item: T, dst: &mut EncodeBuf<'_>) -> Result<(), Status> {
+        bincode::serialize_into(dst.writer(), &item).map_err(|e| Status::internal(e.to_string()))
+    }
+}
+
+/// Reads gRPC messages written by [`CompactEncoder`]
+pub struct CompactDecoder<U>(PhantomData<U>);
+
+impl<U: DeserializeOwned> Decoder for CompactDecoder<U> {
+    type Item = U;
+    type Error = Status;
+
+    fn decode(&mut self, src: &mut DecodeBuf<'_>) -> Result<Option<U>, Status> {
+        bincode::deserialize_from(src.reader())
+            .map(Some)
+            .map_err(|e| Status::invalid_argument(e.to_string()))
+    }
+}
+
+/// Encode a packet request with the compact codec
+pub fn encode_request(request: &DiameterPacketRequest) -> Result<Vec<u8>, CodecError> {
+    bincode::serialize(request)
+}
+
+/// Decode a packet request encoded with [`encode_request`]
+pub fn decode_request(data: &[u8]) -> Result<DiameterPacketRequest, CodecError> {
+    bincode::deserialize(data)
+}
+
+/// Encode a packet action with the compact codec
+pub fn encode_action(action: &DiameterPacketAction) -> Result<Vec<u8>, CodecError> {
+    bincode::serialize(action)
+}
+
+/// Decode a packet action encoded with [`encode_action`]
+pub fn decode_action(data: &[u8]) -> Result<DiameterPacketAction, CodecError> {
+    bincode::deserialize(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compact::core_router_service_client::CoreRouterServiceClient;
+    use crate::compact::core_router_service_server::{CoreRouterService, CoreRouterServiceServer};
+    use crate::ActionType;
+    use std::time::Duration;
+    use tonic::{Request, Response};
+
+    /// DCR stand-in replying with the request payload
+    struct EchoRouter;
+
+    #[tonic::async_trait]
+    impl CoreRouterService for EchoRouter {
+        async fn process_packet(
+            &self,
+            request: Request<DiameterPacketRequest>,
+        ) -> Result<Response<DiameterPacketAction>, Status> {
+            let request = request.into_inner();
+            Ok(Response::new(DiameterPacketAction {
+                action_type: ActionType::Reply as i32,
+                response_payload: request.raw_payload,
+                original_connection_id: request.connection_id,
+                ..Default::default()
+            }))
+        }
+    }
+
+    #[test]
+    fn test_request_round_trip() {
+        let request = DiameterPacketRequest {
+            connection_id: 42,
+            vr_id: "vr-1".to_string(),
+            reception_timestamp: 1_700_000_000_000_000_000,
+            raw_payload: vec![0x01, 0x00, 0x00, 0x14, 0x80, 0x00, 0x01, 0x18].into(),
+            session_tx_id: 7,
+            trace_id: "4bf92f3577b34da6".to_string(),
+        };
+
+        let encoded = encode_request(&request).unwrap();
+        let decoded = decode_request(&encoded).unwrap();
+
+        assert_eq!(decoded, request);
+    }
+
+    #[test]
+    fn test_action_round_trip() {
+        let action = DiameterPacketAction {
+            action_type: ActionType::Forward as i32,
+            target_host_name: "hss1.example.com".to_string(),
+            response_payload: vec![1, 2, 3, 4].into(),
+            original_connection_id: 42,
+            candidate_peers: vec![
+                "hss1.example.com".to_string(),
+                "hss2.example.com".to_string(),
+            ],
+            result_code: 0,
+        };
+
+        let encoded = encode_action(&action).unwrap();
+        let decoded = decode_action(&encoded).unwrap();
+
+        assert_eq!(decoded, action);
+    }
+
+    #[test]
+    fn test_truncated_input_is_rejected() {
+        let request = DiameterPacketRequest {
+            connection_id: 1,
+            vr_id: "default".to_string(),
+            ..Default::default()
+        };
+
+        let encoded = encode_request(&request).unwrap();
+        assert!(decode_request(&encoded[..encoded.len() - 1]).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_routing_call_over_compact_service() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        drop(listener);
+        let server = tokio::spawn(async move {
+            tonic::transport::Server::builder()
+                .add_service(CoreRouterServiceServer::new(EchoRouter))
+                .serve(addr)
+                .await
+                .unwrap();
+        });
+
+        // Wait for the DCR stand-in to start
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let mut client = CoreRouterServiceClient::connect(format!("http://{addr}"))
+            .await
+            .unwrap();
+        let action = client
+            .process_packet(DiameterPacketRequest {
+                connection_id: 42,
+                raw_payload: vec![1, 2, 3].into(),
+                ..Default::default()
+            })
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(action.action_type(), ActionType::Reply);
+        assert_eq!(action.original_connection_id, 42);
+        assert_eq!(&action.response_payload[..], &[1, 2, 3]);
+
+        server.abort();
+    }
+}
@@ -1,5 +1,22 @@
 tonic::include_proto!("cdde");
 
+/// Streaming DFL <-> DCR pipeline, not served by the components yet
+pub mod internal {
+    tonic::include_proto!("cdde.internal");
+}
+
+#[cfg(feature = "compact-codec")]
+pub mod codec;
+
+/// The DFL <-> DCR routing service over the compact codec
+#[cfg(feature = "compact-codec")]
+pub mod compact {
+    include!(concat!(
+        env!("OUT_DIR"),
+        "/cdde.compact.CoreRouterService.rs"
+    ));
+}
+
 // Re-export ActionType for convenience if needed,
 // though it's now part of the generated module.
 // We can add helper methods here if necessary.
@@ -0,0 +1,57 @@
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+use tokio::task::JoinHandle;
+
+/// Stand-in for the CMS REST API serving canned responses
+///
+/// Each connection gets the next response, in the order they were added.
+#[derive(Debug, Clone, Default)]
+pub struct MockCms {
+    responses: Vec<(u16, String)>,
+}
+
+impl MockCms {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Answer the next request with `200 OK` and a JSON body
+    pub fn with_json(self, body: impl Into<String>) -> Self {
+        self.with_response(200, body)
+    }
+
+    /// Answer the next request with a status and a JSON body
+    pub fn with_response(mut self, status: u16, body: impl Into<String>) -> Self {
+        self.responses.push((status, body.into()));
+        self
+    }
+
+    /// Serve the responses on an ephemeral localhost port
+    ///
+    /// Returns the base URL and a handle resolving to the request line of
+    /// every request served, once all responses are sent.
+    pub async fn spawn(self) -> (String, JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let handle = tokio::spawn(async move {
+            let mut requests = Vec::new();
+            for (status, body) in self.responses {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut request = [0u8; 1024];
+                let n = socket.read(&mut request).await.unwrap();
+                let request = String::from_utf8_lossy(&request[..n]);
+                requests.push(request.lines().next().unwrap_or_default().to_string());
+
+                let response = format!(
+                    "HTTP/1.1 {status} X\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{body}",
+                    body.len()
+                );
+                socket.write_all(response.as_bytes()).await.unwrap();
+            }
+            requests
+        });
+
+        (format!("http://{addr}"), handle)
+    }
+}
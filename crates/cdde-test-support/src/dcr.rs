@@ -0,0 +1,126 @@
+use bytes::Bytes;
+use cdde_proto::core_router_service_server::{CoreRouterService, CoreRouterServiceServer};
+use cdde_proto::{ActionType, DiameterPacketAction, DiameterPacketRequest};
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio::task::JoinHandle;
+use tokio_stream::wrappers::TcpListenerStream;
+use tonic::{Request, Response, Status};
+
+type Responder =
+    dyn Fn(DiameterPacketRequest) -> Result<DiameterPacketAction, Box<Status>> + Send + Sync;
+
+/// Stand-in for the DCR answering every request with a programmable action
+///
+/// Clones share the responder and the record of received requests, so a
+/// test can keep one to inspect after spawning another.
+#[derive(Clone)]
+pub struct MockDcr {
+    responder: Arc<Responder>,
+    delay: Duration,
+    received: Arc<Mutex<Vec<DiameterPacketRequest>>>,
+}
+
+impl MockDcr {
+    /// DCR answering each request with what `responder` returns for it
+    ///
+    /// An error is returned to the client as the gRPC status.
+    pub fn new(
+        responder: impl Fn(DiameterPacketRequest) -> Result<DiameterPacketAction, Box<Status>>
+            + Send
+            + Sync
+            + 'static,
+    ) -> Self {
+        Self {
+            responder: Arc::new(responder),
+            delay: Duration::ZERO,
+            received: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    /// DCR replying with the request payload unchanged
+    pub fn echo() -> Self {
+        Self::new(|request| Ok(reply(request.raw_payload)))
+    }
+
+    /// Wait before answering each request
+    pub fn with_delay(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+
+    /// Number of requests received so far
+    pub fn calls(&self) -> usize {
+        self.received.lock().unwrap().len()
+    }
+
+    /// Requests received so far, oldest first
+    pub fn received(&self) -> Vec<DiameterPacketRequest> {
+        self.received.lock().unwrap().clone()
+    }
+
+    /// Serve the DCR on an ephemeral localhost port
+    ///
+    /// The port is listening when this returns.
+    pub async fn spawn(&self) -> (SocketAddr, JoinHandle<()>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let router = tonic::transport::Server::builder()
+            .add_service(CoreRouterServiceServer::new(self.clone()));
+        #[cfg(feature = "compact-codec")]
+        let router = router.add_service(
+            cdde_proto::compact::core_router_service_server::CoreRouterServiceServer::new(
+                self.clone(),
+            ),
+        );
+        let handle = tokio::spawn(async move {
+            router
+                .serve_with_incoming(TcpListenerStream::new(listener))
+                .await
+                .unwrap();
+        });
+        (addr, handle)
+    }
+}
+
+/// Action asking the DFL to send `payload` back to the client
+pub fn reply(payload: impl Into<Bytes>) -> DiameterPacketAction {
+    DiameterPacketAction {
+        action_type: ActionType::Reply as i32,
+        target_host_name: String::new(),
+        response_payload: payload.into(),
+        original_connection_id: 0,
+        candidate_peers: vec![],
+        result_code: 0,
+    }
+}
+
+#[tonic::async_trait]
+impl CoreRouterService for MockDcr {
+    async fn process_packet(
+        &self,
+        request: Request<DiameterPacketRequest>,
+    ) -> Result<Response<DiameterPacketAction>, Status> {
+        let request = request.into_inner();
+        self.received.lock().unwrap().push(request.clone());
+        if !self.delay.is_zero() {
+            tokio::time::sleep(self.delay).await;
+        }
+        (self.responder)(request)
+            .map(Response::new)
+            .map_err(|status| *status)
+    }
+}
+
+#[cfg(feature = "compact-codec")]
+#[tonic::async_trait]
+impl cdde_proto::compact::core_router_service_server::CoreRouterService for MockDcr {
+    async fn process_packet(
+        &self,
+        request: Request<DiameterPacketRequest>,
+    ) -> Result<Response<DiameterPacketAction>, Status> {
+        CoreRouterService::process_packet(self, request).await
+    }
+}
@@ -0,0 +1,15 @@
+//! Test doubles shared by the CDDE crates
+//!
+//! [`MockTransport`] stands in for a Diameter peer connection,
+//! [`MockDcr`] for the DCR's gRPC service and [`MockCms`] for the CMS
+//! REST API. [`LogBuffer`] captures log output.
+
+mod cms;
+mod dcr;
+mod logs;
+mod transport;
+
+pub use cms::MockCms;
+pub use dcr::{reply, MockDcr};
+pub use logs::LogBuffer;
+pub use transport::MockTransport;
@@ -0,0 +1,28 @@
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+
+/// In-memory log sink, shared by its clones
+///
+/// Hand a clone to a subscriber with
+/// `tracing_subscriber::fmt().with_writer(move || writer.clone())` and read
+/// what was logged with [`LogBuffer::contents`].
+#[derive(Clone, Default)]
+pub struct LogBuffer(Arc<Mutex<Vec<u8>>>);
+
+impl LogBuffer {
+    /// Everything written so far
+    pub fn contents(&self) -> String {
+        String::from_utf8_lossy(&self.0.lock().unwrap()).into_owned()
+    }
+}
+
+impl Write for LogBuffer {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
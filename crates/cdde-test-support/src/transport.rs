@@ -0,0 +1,130 @@
+use async_trait::async_trait;
+use cdde_core::{Result, Transport};
+use std::collections::VecDeque;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+/// In-memory transport with scripted reads and captured writes
+///
+/// Each scripted chunk is handed out by its own reads, split when the read
+/// buffer is smaller. Once every chunk is consumed, reads return EOF.
+pub struct MockTransport {
+    reads: VecDeque<Vec<u8>>,
+    written: Arc<Mutex<Vec<u8>>>,
+    peer_addr: SocketAddr,
+    local_addr: SocketAddr,
+}
+
+impl MockTransport {
+    /// Connection from 127.0.0.1:12345 to 127.0.0.1:3868 with nothing to read
+    pub fn new() -> Self {
+        Self {
+            reads: VecDeque::new(),
+            written: Arc::new(Mutex::new(Vec::new())),
+            peer_addr: SocketAddr::from((Ipv4Addr::LOCALHOST, 12345)),
+            local_addr: SocketAddr::from((Ipv4Addr::LOCALHOST, 3868)),
+        }
+    }
+
+    /// Queue bytes to be read after the ones already queued
+    pub fn with_read(mut self, bytes: impl Into<Vec<u8>>) -> Self {
+        self.reads.push_back(bytes.into());
+        self
+    }
+
+    /// Set the address the connection appears to come from
+    pub fn with_peer_addr(mut self, addr: SocketAddr) -> Self {
+        self.peer_addr = addr;
+        self
+    }
+
+    /// Bytes written to the transport, shared so they can be checked after
+    /// the transport was moved into the code under test
+    pub fn written(&self) -> Arc<Mutex<Vec<u8>>> {
+        self.written.clone()
+    }
+}
+
+impl Default for MockTransport {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AsyncRead for MockTransport {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        let me = self.get_mut();
+        let Some(chunk) = me.reads.front_mut() else {
+            return Poll::Ready(Ok(()));
+        };
+
+        let len = buf.remaining().min(chunk.len());
+        buf.put_slice(&chunk[..len]);
+        chunk.drain(..len);
+        if chunk.is_empty() {
+            me.reads.pop_front();
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl AsyncWrite for MockTransport {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        self.written.lock().unwrap().extend_from_slice(buf);
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[async_trait]
+impl Transport for MockTransport {
+    fn peer_addr(&self) -> Result<SocketAddr> {
+        Ok(self.peer_addr)
+    }
+
+    fn local_addr(&self) -> Result<SocketAddr> {
+        Ok(self.local_addr)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[tokio::test]
+    async fn test_scripted_reads_then_eof() {
+        let mut transport = MockTransport::new().with_read(*b"abc").with_read(*b"de");
+        let written = transport.written();
+
+        let mut buf = [0u8; 2];
+        assert_eq!(transport.read(&mut buf).await.unwrap(), 2);
+        assert_eq!(&buf, b"ab");
+        assert_eq!(transport.read(&mut buf).await.unwrap(), 1);
+        assert_eq!(&buf[..1], b"c");
+        assert_eq!(transport.read(&mut buf).await.unwrap(), 2);
+        assert_eq!(&buf, b"de");
+        assert_eq!(transport.read(&mut buf).await.unwrap(), 0);
+
+        transport.write_all(b"answer").await.unwrap();
+        assert_eq!(*written.lock().unwrap(), b"answer");
+    }
+}